@@ -17,19 +17,19 @@ pub(crate) enum Request<'a> {
     Flush,
 }
 
-pub(crate) fn parse_input(input: &str) -> IResult<&str, Option<Request>, VerboseError<&str>> {
+pub(crate) fn parse_input(input: &str) -> IResult<&str, Option<Request<'_>>, VerboseError<&str>> {
     alt((parse_set, parse_flush, parse_get, parse_delete, parse_exit))(input)
 }
 
-fn parse_flush(input: &str) -> IResult<&str, Option<Request>, VerboseError<&str>> {
+fn parse_flush(input: &str) -> IResult<&str, Option<Request<'_>>, VerboseError<&str>> {
     map(tag_no_case("flush"), |_| Some(Request::Flush))(input)
 }
 
-fn parse_exit(input: &str) -> IResult<&str, Option<Request>, VerboseError<&str>> {
+fn parse_exit(input: &str) -> IResult<&str, Option<Request<'_>>, VerboseError<&str>> {
     map(tag_no_case("exit"), |_| None)(input)
 }
 
-fn parse_get(input: &str) -> IResult<&str, Option<Request>, VerboseError<&str>> {
+fn parse_get(input: &str) -> IResult<&str, Option<Request<'_>>, VerboseError<&str>> {
     map(
         separated_pair(
             tag_no_case("get"),
@@ -43,7 +43,7 @@ fn parse_get(input: &str) -> IResult<&str, Option<Request>, VerboseError<&str>>
     )(input)
 }
 
-fn parse_delete(input: &str) -> IResult<&str, Option<Request>, VerboseError<&str>> {
+fn parse_delete(input: &str) -> IResult<&str, Option<Request<'_>>, VerboseError<&str>> {
     map(
         separated_pair(
             tag_no_case("delete"),
@@ -54,7 +54,7 @@ fn parse_delete(input: &str) -> IResult<&str, Option<Request>, VerboseError<&str
     )(input)
 }
 
-fn parse_set(input: &str) -> IResult<&str, Option<Request>, VerboseError<&str>> {
+fn parse_set(input: &str) -> IResult<&str, Option<Request<'_>>, VerboseError<&str>> {
     map(
         tuple((
             tag_no_case("set"),
@@ -46,7 +46,7 @@ async fn main() {
                         println!("{res:?}");
                     }
                     Request::Flush => {
-                        let res = client.flush().await;
+                        let res = client.flush_confirmed().await;
                         println!("{res:?}");
                     }
                 },
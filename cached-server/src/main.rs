@@ -41,5 +41,5 @@ async fn main() {
     let addr = format!("{}:{}", host, cli.port);
     let server = Server::new().bind(addr).await.unwrap();
     println!("Cached server running on {host}:{}", server.port());
-    server.run().await;
+    server.serve_forever().await;
 }
@@ -2,8 +2,9 @@ use cached::{Client, Server};
 use criterion::{criterion_group, criterion_main, Criterion};
 use futures::future::join_all;
 use rand::distributions::{Alphanumeric, DistString, Distribution, Uniform};
-use rand::{Rng, SeedableRng};
 use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::cell::Cell;
 use tokio::time::Instant;
 
 fn get_key(c: &mut Criterion) {
@@ -34,6 +35,16 @@ fn get_key(c: &mut Criterion) {
         b.to_async(&rt)
             .iter(|| async { client.get("hello".to_string()).await.unwrap() })
     });
+
+    // The buffer is handed from one iteration to the next, so it is only allocated once
+    let buf = Cell::new(Vec::new());
+    c.bench_function("get_into", |b| {
+        b.to_async(&rt).iter(|| async {
+            let mut value = buf.take();
+            client.get_into("hello", &mut value).await.unwrap();
+            buf.set(value);
+        })
+    });
 }
 
 fn get_same_key_in_parallel_single_client(c: &mut Criterion) {
@@ -144,7 +155,7 @@ async fn random_client_action<'a>(
             let _ = client.delete(key).await;
         }
         RandomAccessClientSetup::Flush => {
-            let _ = client.flush().await;
+            let _ = client.flush_confirmed().await;
         }
     };
 }
@@ -0,0 +1,87 @@
+use crate::primitives::{OpCode, StatusCode};
+use std::collections::hash_map::DefaultHasher;
+use std::fmt;
+use std::hash::Hasher;
+use std::io::Write;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Writes a line per request to the writer given to [`Server::access_log`](crate::Server::access_log).
+#[derive(Clone)]
+pub(crate) struct AccessLog {
+    writer: Arc<Mutex<Box<dyn Write + Send>>>,
+    plain_keys: bool,
+}
+
+impl fmt::Debug for AccessLog {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AccessLog")
+            .field("plain_keys", &self.plain_keys)
+            .finish_non_exhaustive()
+    }
+}
+
+/// What is logged about a request once it was answered.
+#[derive(Debug)]
+pub(crate) struct AccessLogEntry<'a> {
+    pub(crate) peer_addr: SocketAddr,
+    pub(crate) op_code: OpCode,
+    /// The first key of the request, if it had any that was valid UTF-8.
+    pub(crate) key: Option<&'a str>,
+    pub(crate) status: StatusCode,
+    pub(crate) bytes_received: u64,
+    pub(crate) bytes_sent: u64,
+    pub(crate) latency: Duration,
+}
+
+impl AccessLog {
+    pub(crate) fn new(writer: Box<dyn Write + Send>) -> Self {
+        Self {
+            writer: Arc::new(Mutex::new(writer)),
+            plain_keys: false,
+        }
+    }
+
+    pub(crate) fn with_plain_keys(mut self, plain_keys: bool) -> Self {
+        self.plain_keys = plain_keys;
+        self
+    }
+
+    /// Writes the line for `entry`, failing to write loses the line.
+    pub(crate) fn record(&self, entry: &AccessLogEntry<'_>) {
+        let line = self.format(entry);
+        let mut writer = self.writer.lock().unwrap_or_else(PoisonError::into_inner);
+        let _ = writer.write_all(line.as_bytes());
+    }
+
+    /// Formats `entry` as `key=value` pairs separated by spaces, ending in a newline.
+    fn format(&self, entry: &AccessLogEntry<'_>) -> String {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis();
+        let key = match entry.key {
+            None => "-".to_string(),
+            Some(key) if self.plain_keys => format!("{key:?}"),
+            Some(key) => hash_key(key),
+        };
+        format!(
+            "ts={timestamp} peer={} op={:?} key={key} status={:?} received={} sent={} latency_us={}\n",
+            entry.peer_addr,
+            entry.op_code,
+            entry.status,
+            entry.bytes_received,
+            entry.bytes_sent,
+            entry.latency.as_micros()
+        )
+    }
+}
+
+/// Hashes `key` the same way on every server, so the lines of a key can be told apart
+/// without revealing it.
+fn hash_key(key: &str) -> String {
+    let mut hasher = DefaultHasher::new();
+    hasher.write(key.as_bytes());
+    format!("{:016x}", hasher.finish())
+}
@@ -0,0 +1,192 @@
+use crate::client::ClientConnection;
+use crate::error::{ConnectionError, Error, Result};
+use async_trait::async_trait;
+use std::fmt::Debug;
+use std::io;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, PoisonError, RwLock, Weak};
+use std::time::Duration;
+use tokio::net::lookup_host;
+use tokio::spawn;
+use tokio::time::{interval, MissedTickBehavior};
+
+/// Resolves a name to the addresses of the servers behind it,
+/// see [`Client::balanced_with_resolver`](crate::Client::balanced_with_resolver).
+#[async_trait]
+pub trait Resolver: Debug + Send + Sync {
+    /// Returns the addresses `name` currently resolves to.
+    async fn resolve(&self, name: &str) -> io::Result<Vec<SocketAddr>>;
+}
+
+/// Resolves names of the form `host:port` via the system's DNS resolver.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DnsResolver;
+
+#[async_trait]
+impl Resolver for DnsResolver {
+    async fn resolve(&self, name: &str) -> io::Result<Vec<SocketAddr>> {
+        Ok(lookup_host(name).await?.collect())
+    }
+}
+
+/// A connection to every address a name resolves to, handing them out by key or in turns.
+#[derive(Debug)]
+pub(crate) struct Balancer {
+    connections: RwLock<Vec<ClientConnection>>,
+    next: AtomicUsize,
+}
+
+impl Balancer {
+    /// Connects to every address `name` resolves to, and resolves it again every `reresolve_every`
+    /// until the balancer is dropped.
+    ///
+    /// Addresses that can't be connected to are left out until the next resolution.
+    /// Fails if `name` can't be resolved or none of its addresses can be connected to.
+    pub(crate) async fn connect(
+        name: String,
+        resolver: Arc<dyn Resolver>,
+        reresolve_every: Duration,
+    ) -> Result<Arc<Self>> {
+        let addrs = resolver
+            .resolve(&name)
+            .await
+            .map_err(|e| Error::new_connection(ConnectionError::Io(e)))?;
+        let mut connections = vec![];
+        let mut last_error = Error::new_connection(ConnectionError::NoAddress);
+        for addr in addrs {
+            match ClientConnection::connect(addr).await {
+                Ok(conn) => connections.push(conn),
+                Err(e) => last_error = e,
+            }
+        }
+        if connections.is_empty() {
+            return Err(last_error);
+        }
+        let balancer = Arc::new(Self {
+            connections: RwLock::new(connections),
+            next: AtomicUsize::new(0),
+        });
+        spawn(Self::reresolve(
+            Arc::downgrade(&balancer),
+            name,
+            resolver,
+            reresolve_every,
+        ));
+        Ok(balancer)
+    }
+
+    /// Hands out the next open connection in turn, any connection if all of them are closed.
+    pub(crate) fn next(&self) -> ClientConnection {
+        let connections = self
+            .connections
+            .read()
+            .unwrap_or_else(PoisonError::into_inner);
+        let start = self.next.fetch_add(1, Ordering::Relaxed);
+        (0..connections.len())
+            .map(|offset| &connections[(start + offset) % connections.len()])
+            .find(|conn| !conn.is_closed())
+            .unwrap_or(&connections[start % connections.len()])
+            .clone()
+    }
+
+    /// Hands out the connection to the server `key` belongs to, picked by rendezvous hashing.
+    ///
+    /// A key stays with its server as long as the server is resolved and its connection open,
+    /// adding or removing other servers does not move it. Keys of a closed connection go to
+    /// the server they would belong to without it until it is reconnected.
+    pub(crate) fn for_key(&self, key: &[u8]) -> ClientConnection {
+        let connections = self
+            .connections
+            .read()
+            .unwrap_or_else(PoisonError::into_inner);
+        // Open connections outweigh closed ones
+        connections
+            .iter()
+            .max_by_key(|conn| (!conn.is_closed(), weight(key, conn.peer_addr())))
+            .expect("The balancer always holds a connection")
+            .clone()
+    }
+
+    /// Returns the connections currently balanced across.
+    pub(crate) fn connections(&self) -> Vec<ClientConnection> {
+        self.connections
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+
+    /// Picks up the addresses `name` resolves to by now, until the balancer is dropped.
+    ///
+    /// Connections to addresses that are gone are dropped once their requests in flight were
+    /// answered, closed connections to the remaining ones are reconnected.
+    /// A failed or empty resolution keeps the current connections.
+    async fn reresolve(
+        balancer: Weak<Self>,
+        name: String,
+        resolver: Arc<dyn Resolver>,
+        reresolve_every: Duration,
+    ) {
+        let mut ticker = interval(reresolve_every);
+        ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        // The first tick completes immediately, the name was just resolved.
+        ticker.tick().await;
+        loop {
+            ticker.tick().await;
+            let Some(current) = balancer.upgrade().map(|balancer| balancer.connections()) else {
+                return;
+            };
+            let addrs = match resolver.resolve(&name).await {
+                Ok(addrs) if !addrs.is_empty() => addrs,
+                _ => continue,
+            };
+            let mut connections = vec![];
+            for addr in addrs {
+                match current.iter().find(|conn| conn.peer_addr() == addr) {
+                    Some(conn) => {
+                        // Failing to reconnect leaves the connection closed until the next resolution
+                        let _ = conn.reconnect().await;
+                        connections.push(conn.clone());
+                    }
+                    None => {
+                        if let Ok(conn) = ClientConnection::connect(addr).await {
+                            connections.push(conn);
+                        }
+                    }
+                }
+            }
+            let Some(balancer) = balancer.upgrade() else {
+                return;
+            };
+            if !connections.is_empty() {
+                *balancer
+                    .connections
+                    .write()
+                    .unwrap_or_else(PoisonError::into_inner) = connections;
+            }
+        }
+    }
+}
+
+/// The weight of the server at `addr` for `key`, the heaviest server gets the key.
+///
+/// Spelled out rather than relying on [`std::hash::Hasher`] implementations, so clients built
+/// differently still agree on where a key belongs.
+fn weight(key: &[u8], addr: SocketAddr) -> u64 {
+    const FNV_OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+    const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+    let ip = match addr.ip() {
+        IpAddr::V4(ip) => ip.to_ipv6_mapped().octets(),
+        IpAddr::V6(ip) => ip.octets(),
+    };
+    let hash = key
+        .iter()
+        .chain(&ip)
+        .chain(&addr.port().to_be_bytes())
+        .fold(FNV_OFFSET_BASIS, |hash, byte| {
+            (hash ^ u64::from(*byte)).wrapping_mul(FNV_PRIME)
+        });
+    // Mixing the address, hashed last, into all bits of the weight
+    let hash = (hash ^ (hash >> 33)).wrapping_mul(0xff51_afd7_ed55_8ccd);
+    hash ^ (hash >> 33)
+}
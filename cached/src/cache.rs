@@ -0,0 +1,233 @@
+use crate::db::{default_shard_amount, MainDB};
+use crate::eviction::EvictionPolicy;
+use std::collections::hash_map::RandomState;
+use std::fmt;
+use std::hash::BuildHasher;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+
+/// The cache of the server for use in-process, without a server or client.
+///
+/// Values are sharded and expire or are evicted just like the ones stored by a [`Server`](crate::Server).
+/// Each shard is locked on its own, so the cache can be shared between threads.
+/// Cloning the cache is cheap, the clones share the same keys.
+///
+/// # Examples
+///
+/// ```
+/// use cached::Cache;
+///
+/// let cache = Cache::new(4);
+/// cache.set("foo", "bar", None);
+/// assert_eq!(cache.get("foo"), Some("bar".to_string()));
+///
+/// assert!(cache.delete("foo"));
+/// assert!(!cache.contains("foo"));
+/// ```
+#[derive(Clone)]
+pub struct Cache {
+    shards: Arc<[Mutex<MainDB>]>,
+    hasher: RandomState,
+}
+
+impl Cache {
+    /// Creates a cache with `shard_amount` shards, at least one shard is always created.
+    pub fn new(shard_amount: usize) -> Self {
+        Self::with_shards(shard_amount, MainDB::new)
+    }
+
+    /// Creates a cache holding at most about `max_entries` entries, evicting per `policy`,
+    /// see [`Server::max_entries`](crate::Server::max_entries).
+    ///
+    /// The limit is split evenly across the shards and enforced by each shard on its own,
+    /// so rounded up to a multiple of the shard amount.
+    pub fn with_max_entries(
+        shard_amount: usize,
+        max_entries: usize,
+        policy: EvictionPolicy,
+    ) -> Self {
+        let max_entries_per_shard = max_entries.div_ceil(shard_amount.max(1));
+        Self::with_shards(shard_amount, || {
+            MainDB::with_max_entries(max_entries_per_shard, policy)
+        })
+    }
+
+    fn with_shards(shard_amount: usize, new_shard: impl Fn() -> MainDB) -> Self {
+        Self {
+            shards: (0..shard_amount.max(1))
+                .map(|_| Mutex::new(new_shard()))
+                .collect(),
+            hasher: RandomState::new(),
+        }
+    }
+
+    /// Returns the value under `key`, `None` if there is none or its TTL has passed.
+    pub fn get(&self, key: &str) -> Option<String> {
+        self.shard_for(key)
+            .get(key)
+            .and_then(Result::ok)
+            .filter(|value| !value.negative)
+            .and_then(|value| String::from_utf8(value.value.into_vec()).ok())
+    }
+
+    /// Stores `value` under `key`, replacing what was stored there before.
+    ///
+    /// The value expires once the TTL in milliseconds since the unix epoch has passed,
+    /// a TTL in the past stores nothing.
+    pub fn set(
+        &self,
+        key: impl Into<String>,
+        value: impl Into<String>,
+        ttl_since_unix_epoch_in_millis: Option<u128>,
+    ) {
+        let key = key.into();
+        self.shard_for(&key).insert_with_flags(
+            key,
+            value.into().into_bytes(),
+            ttl_since_unix_epoch_in_millis,
+            0,
+        );
+    }
+
+    /// Removes `key` and returns whether a value was stored under it.
+    pub fn delete(&self, key: &str) -> bool {
+        self.shard_for(key).delete(key)
+    }
+
+    /// Returns whether a value is stored under `key` and its TTL has not passed.
+    pub fn contains(&self, key: &str) -> bool {
+        self.shard_for(key).contains_key(key)
+    }
+
+    /// Removes all keys, one shard after the other.
+    pub fn clear(&self) {
+        for shard in self.shards.iter() {
+            lock(shard).clear();
+        }
+    }
+
+    /// Removes all expired keys and returns them.
+    ///
+    /// Expired keys are never returned, but without a server sweeping them in the background
+    /// they only free their memory once accessed or swept here.
+    pub fn sweep_expired(&self) -> Vec<String> {
+        self.shards
+            .iter()
+            .flat_map(|shard| lock(shard).sweep_expired())
+            .collect()
+    }
+
+    fn shard_for(&self, key: &str) -> MutexGuard<'_, MainDB> {
+        let idx = self.hasher.hash_one(key) as usize % self.shards.len();
+        lock(&self.shards[idx])
+    }
+}
+
+impl Default for Cache {
+    /// Creates a cache with one shard per available core.
+    fn default() -> Self {
+        Self::new(default_shard_amount())
+    }
+}
+
+impl fmt::Debug for Cache {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Cache")
+            .field("shard_amount", &self.shards.len())
+            .finish_non_exhaustive()
+    }
+}
+
+/// Shards are left consistent between operations, so a panic while holding the lock doesn't matter.
+fn lock(shard: &Mutex<MainDB>) -> MutexGuard<'_, MainDB> {
+    shard.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::thread;
+    use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+    fn now_in_millis() -> u128 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_millis()
+    }
+
+    #[test]
+    fn test_values_can_be_set_replaced_and_deleted() {
+        let cache = Cache::new(4);
+        assert_eq!(cache.get("key"), None);
+        assert!(!cache.contains("key"));
+
+        cache.set("key", "value", None);
+        assert_eq!(cache.get("key"), Some("value".to_string()));
+        assert!(cache.contains("key"));
+
+        cache.set("key", "other", None);
+        assert_eq!(cache.get("key"), Some("other".to_string()));
+
+        assert!(cache.delete("key"));
+        assert!(!cache.delete("key"));
+        assert_eq!(cache.get("key"), None);
+    }
+
+    #[test]
+    fn test_clearing_removes_the_keys_of_all_shards() {
+        let cache = Cache::new(4);
+        for i in 0..100 {
+            cache.set(format!("key-{i}"), "value".to_string(), None);
+        }
+        cache.clear();
+        assert!((0..100).all(|i| !cache.contains(&format!("key-{i}"))));
+    }
+
+    #[test]
+    fn test_values_expire_with_their_ttl() {
+        let cache = Cache::new(1);
+        cache.set("past", "value", Some(now_in_millis() - 1));
+        assert!(!cache.contains("past"));
+
+        cache.set("expiring", "value", Some(now_in_millis() + 20));
+        cache.set("live", "value", Some(now_in_millis() + 60_000));
+        assert_eq!(cache.get("expiring"), Some("value".to_string()));
+        thread::sleep(Duration::from_millis(50));
+
+        assert_eq!(cache.sweep_expired(), vec!["expiring".to_string()]);
+        assert_eq!(cache.get("expiring"), None);
+        assert!(!cache.delete("expiring"));
+        assert!(cache.contains("live"));
+    }
+
+    #[test]
+    fn test_full_caches_evict_per_their_policy() {
+        let cache = Cache::with_max_entries(1, 2, EvictionPolicy::Lru);
+        cache.set("a", "value", None);
+        cache.set("b", "value", None);
+        // Makes "b" the least recently used entry
+        cache.get("a");
+        cache.set("c", "value", None);
+
+        assert!(cache.contains("a"));
+        assert!(!cache.contains("b"));
+        assert!(cache.contains("c"));
+    }
+
+    #[test]
+    fn test_clones_share_their_keys_across_threads() {
+        let cache = Cache::default();
+        let handles: Vec<_> = (0..4)
+            .map(|i| {
+                let cache = cache.clone();
+                thread::spawn(move || cache.set(format!("key-{i}"), i.to_string(), None))
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+        for i in 0..4 {
+            assert_eq!(cache.get(&format!("key-{i}")), Some(i.to_string()));
+        }
+    }
+}
@@ -0,0 +1,108 @@
+use crate::primitives::OpCode;
+use crate::protocol::{HTTP_FEATURE, RESP_FEATURE};
+
+/// The limits and features a server was configured with, obtained via
+/// [`Client::capabilities`](crate::Client::capabilities).
+///
+/// Knowing them lets clients validate requests before sending them.
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub struct ServerCapabilities {
+    pub(crate) max_key_length: u32,
+    pub(crate) max_value_length: u32,
+    pub(crate) max_entries: Option<u64>,
+    pub(crate) requires_flush_confirmation: bool,
+    pub(crate) validates_keys: bool,
+    pub(crate) allowed_opcodes: Vec<OpCode>,
+}
+
+impl ServerCapabilities {
+    /// The longest key the server accepts, in bytes.
+    pub fn max_key_length(&self) -> usize {
+        self.max_key_length as usize
+    }
+
+    /// The longest value the server accepts, in bytes.
+    pub fn max_value_length(&self) -> usize {
+        self.max_value_length as usize
+    }
+
+    /// The limit on the entries the server holds, `None` if it is unlimited,
+    /// see [`Server::max_entries`](crate::Server::max_entries).
+    pub fn max_entries(&self) -> Option<u64> {
+        self.max_entries
+    }
+
+    /// Whether flushing must be confirmed,
+    /// see [`Server::require_flush_confirmation`](crate::Server::require_flush_confirmation).
+    pub fn requires_flush_confirmation(&self) -> bool {
+        self.requires_flush_confirmation
+    }
+
+    /// Whether the server checks keys against a validator,
+    /// see [`Server::key_validator`](crate::Server::key_validator).
+    pub fn validates_keys(&self) -> bool {
+        self.validates_keys
+    }
+
+    /// The operations the server carries out, in the order of their op codes.
+    ///
+    /// Operations the client does not know about are left out.
+    pub fn allowed_opcodes(&self) -> &[OpCode] {
+        &self.allowed_opcodes
+    }
+
+    /// Returns whether the server carries out `op_code`.
+    pub fn allows(&self, op_code: OpCode) -> bool {
+        self.allowed_opcodes.contains(&op_code)
+    }
+}
+
+/// The version of a server and the optional features it was built with, obtained via
+/// [`Client::server_version`](crate::Client::server_version).
+///
+/// Lets clients gate their use of features on what the server offers.
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub struct ServerInfo {
+    pub(crate) version: String,
+    pub(crate) features: u8,
+}
+
+impl ServerInfo {
+    /// The info of the server built from this crate.
+    #[cfg(feature = "runtime")]
+    pub(crate) fn current() -> Self {
+        let mut features = 0;
+        if cfg!(feature = "http") {
+            features |= HTTP_FEATURE;
+        }
+        if cfg!(feature = "resp") {
+            features |= RESP_FEATURE;
+        }
+        Self {
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            features,
+        }
+    }
+
+    /// The version of the crate the server was built from.
+    pub fn version(&self) -> &str {
+        &self.version
+    }
+
+    /// The optional features the server was built with, see [`HTTP_FEATURE`] and [`RESP_FEATURE`].
+    ///
+    /// Bits of features the client does not know about are kept.
+    pub fn features(&self) -> u8 {
+        self.features
+    }
+
+    /// Whether the server was built with the `http` feature, so it can serve HTTP as well.
+    pub fn has_http(&self) -> bool {
+        self.features & HTTP_FEATURE != 0
+    }
+
+    /// Whether the server was built with the `resp` feature, so it can speak the Redis protocol as well.
+    pub fn has_resp(&self) -> bool {
+        self.features & RESP_FEATURE != 0
+    }
+}
@@ -1,28 +1,120 @@
-use crate::connection::Connection;
-use crate::domain::{Key, Value};
-use crate::error::{ClientError, ConnectionError};
+use crate::balance::{Balancer, DnsResolver, Resolver};
+use crate::capabilities::{ServerCapabilities, ServerInfo};
+use crate::connection::{self, decode_response, set_request_id, total_frame_length, Connection};
+use crate::domain::{Key, TtlState, Value, MAX_VALUE_LENGTH};
+use crate::error::{ClientError, ConnectionError, FrameError, ParseError};
 use crate::error::{Error, Result};
-use crate::request::Request;
-use crate::response::{Response, ResponseBody, ResponseGet};
-use crate::StatusCode;
+use crate::eviction::{EvictionBatch, EvictionReason, EvictionStream, EVICTION_CHANNEL_CAPACITY};
+use crate::key_info::KeyInfo;
+use crate::metrics::ConnectionStats;
+use crate::protocol::{HEADER_SIZE, MAX_KEY_LENGTH};
+use crate::request::{encoded_entries_length, ChunkStep, Request};
+use crate::response::{Response, ResponseBody, ResponseGet, ResponseStatus};
+use crate::retry::RetryPolicy;
+use crate::{OpCode, StatusCode};
+use bytes::Bytes;
+use std::collections::HashMap;
 use std::fmt::Debug;
+use std::future::Future;
+use std::net::SocketAddr;
+use std::sync::{Arc, OnceLock, PoisonError, RwLock};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::io::{AsyncRead, AsyncReadExt};
 use tokio::net::{TcpStream, ToSocketAddrs};
 use tokio::spawn;
 use tokio::sync::mpsc;
 use tokio::sync::oneshot;
+use tokio::time::{sleep, sleep_until, timeout, timeout_at, Instant};
 #[cfg(feature = "tracing")]
 use tracing::instrument;
 
+/// How long [`Client::connect_ready`] waits before retrying after the first failed attempt,
+/// doubled for every further failed attempt.
+static MIN_CONNECT_READY_DELAY: Duration = Duration::from_millis(10);
+static MAX_CONNECT_READY_DELAY: Duration = Duration::from_millis(100);
+/// How long an [`EvictionStream`] waits before subscribing again after losing its connection,
+/// doubled for every further failed attempt.
+static MIN_RESUBSCRIBE_DELAY: Duration = Duration::from_millis(10);
+static MAX_RESUBSCRIBE_DELAY: Duration = Duration::from_secs(1);
+/// How many bytes of a value [`Client::set_from_reader`] reads and sends at a time, 64KiB.
+static CHUNK_LENGTH: usize = 64 * 1024;
+
 #[derive(Debug)]
 struct RequestResponder {
     request: Request,
     responder: oneshot::Sender<Result<Response>>,
 }
 
+/// A request handed over to a connection, awaiting its response.
+#[derive(Debug)]
+struct Submitted {
+    conn: ClientConnection,
+    receiver: oneshot::Receiver<Result<Response>>,
+}
+
+#[derive(Debug)]
+struct FrameResponder {
+    frame: Bytes,
+    responder: oneshot::Sender<Result<Bytes>>,
+}
+
+/// Who awaits the response to a request in flight.
+#[derive(Debug)]
+enum Responder {
+    Response(oneshot::Sender<Result<Response>>),
+    /// Awaits the response frame as it was sent, tagged with the id the caller gave the request.
+    Frame {
+        request_id: u32,
+        responder: oneshot::Sender<Result<Bytes>>,
+    },
+}
+
+impl Responder {
+    fn send_error(self, e: Error) {
+        match self {
+            Self::Response(responder) => {
+                let _ = responder.send(Err(e));
+            }
+            Self::Frame { responder, .. } => {
+                let _ = responder.send(Err(e));
+            }
+        }
+    }
+}
+
+/// What the background task of a connection is asked to do.
+#[derive(Debug)]
+enum Command {
+    Request(RequestResponder),
+    /// Send a request frame that was encoded elsewhere.
+    RequestFrame(FrameResponder),
+    /// Replace the TCP stream with a fresh one.
+    Renew(oneshot::Sender<Result<()>>),
+    /// Ping the server whenever the connection was idle for the given interval.
+    Keepalive(Duration, oneshot::Sender<()>),
+}
+
 /// A  connection
 #[derive(Debug, Clone)]
 pub struct ClientConnection {
-    sender: mpsc::Sender<RequestResponder>,
+    // Swapped for a fresh task by `Self::reconnect`
+    task: Arc<RwLock<ConnectionTask>>,
+    peer_addr: SocketAddr,
+    // The capabilities of the server last obtained by any client of the connection
+    capabilities: Arc<RwLock<Option<ServerCapabilities>>>,
+}
+
+/// The handle to the background task driving the stream of a connection.
+#[derive(Debug, Clone)]
+struct ConnectionTask {
+    sender: mpsc::Sender<Command>,
+    closed_reason: Arc<OnceLock<Arc<Error>>>,
+}
+
+impl ConnectionTask {
+    fn is_closed(&self) -> bool {
+        self.closed_reason.get().is_some() || self.sender.is_closed()
+    }
 }
 
 impl ClientConnection {
@@ -30,73 +122,111 @@ impl ClientConnection {
     /// Create a new client connection.
     ///
     /// Panics if cannot connect to addr.
+    ///
+    /// If sending a request or receiving its response fails, the connection is closed for good
+    /// and all subsequent requests report the error that caused it.
     pub async fn new<A: ToSocketAddrs>(addr: A) -> Self {
-        let (tx, mut rx) = mpsc::channel::<RequestResponder>(32);
-        let stream = TcpStream::connect(addr).await.unwrap();
-        let mut conn = Connection::new(stream);
-        // TODO when does this shutdown?
-        spawn(async move {
-            while let Some(request_responder) = rx.recv().await {
-                let responder = request_responder.responder;
-                let res = conn.send_request(request_responder.request).await;
-                let _ = responder.send(res);
-            }
-        });
-        Self { sender: tx }
+        Self::connect(addr).await.unwrap()
     }
 
-    fn get(&self) -> mpsc::Sender<RequestResponder> {
-        self.sender.clone()
+    /// Like [`Self::new`], but returns the error instead of panicking if it cannot connect.
+    pub(crate) async fn connect<A: ToSocketAddrs>(addr: A) -> Result<Self> {
+        let stream = TcpStream::connect(addr)
+            .await
+            .map_err(|e| Error::new_connection(ConnectionError::Io(e)))?;
+        Self::from_stream(stream)
     }
-}
-
-/// A client to communicate with the cached server.
-#[derive(Debug, Clone)]
-pub struct Client {
-    conn: mpsc::Sender<RequestResponder>,
-}
 
-impl Client {
-    /// Create a new client connecting to a server at `addr`.
+    /// Creates a connection over a blocking stream that was connected elsewhere,
+    /// e.g. in synchronous code or after a handshake with a proxy.
     ///
-    /// Panics if it cannot connect to addr.
+    /// The stream is switched to non-blocking mode. [`Self::renew`] connects to the same peer again.
+    /// Must be called from within a tokio runtime.
     ///
     /// # Examples
     ///
     /// ```
+    /// use cached::ClientConnection;
     /// use cached::Client;
     /// # use cached::Server;
     /// # use cached::Error;
-    /// use cached::StatusCode;
-    /// use cached::ClientConnection;
     ///
     /// # #[tokio::main]
     /// # async fn main() -> Result<(), Error> {
     /// # let server = Server::new().bind("127.0.0.1:0").await.unwrap();
     /// # let port = server.port();
     /// # tokio::spawn(async { server.run().await;});
-    /// let client = Client::new(format!("127.0.0.1:{port}")).await;
+    /// let stream = std::net::TcpStream::connect(format!("127.0.0.1:{port}")).unwrap();
+    /// let conn = ClientConnection::from_std_stream(stream)?;
+    /// let client = Client::with_connection(&conn);
+    /// client.set("foo", "bar", None).await?;
     /// # Ok(())
     /// # }
     /// ```
-    pub async fn new<A: ToSocketAddrs>(addr: A) -> Self {
-        let conn = ClientConnection::new(addr).await;
-        Self::with_connection(&conn)
+    pub fn from_std_stream(stream: std::net::TcpStream) -> Result<Self> {
+        stream
+            .set_nonblocking(true)
+            .map_err(|e| Error::new_connection(ConnectionError::Io(e)))?;
+        let stream = TcpStream::from_std(stream)
+            .map_err(|e| Error::new_connection(ConnectionError::Io(e)))?;
+        Self::from_stream(stream)
     }
 
-    /// Creates a new client using an existing connection.
+    fn from_stream(stream: TcpStream) -> Result<Self> {
+        // Renewing connects to the server this stream connected to, without resolving `addr` again
+        let peer_addr = stream
+            .peer_addr()
+            .map_err(|e| Error::new_connection(ConnectionError::Io(e)))?;
+        Ok(Self {
+            task: Arc::new(RwLock::new(Self::spawn(stream, peer_addr))),
+            peer_addr,
+            capabilities: Arc::new(RwLock::new(None)),
+        })
+    }
+
+    fn spawn(stream: TcpStream, peer_addr: SocketAddr) -> ConnectionTask {
+        let (tx, rx) = mpsc::channel::<Command>(32);
+        let closed_reason = Arc::new(OnceLock::new());
+        spawn(Self::run(
+            Connection::new(stream),
+            peer_addr,
+            rx,
+            Arc::clone(&closed_reason),
+        ));
+        ConnectionTask {
+            sender: tx,
+            closed_reason,
+        }
+    }
+
+    pub(crate) fn peer_addr(&self) -> SocketAddr {
+        self.peer_addr
+    }
+
+    /// Whether the connection was closed, e.g. by an error, and needs to [reconnect](Self::reconnect).
+    pub(crate) fn is_closed(&self) -> bool {
+        self.task().is_closed()
+    }
+
+    fn task(&self) -> ConnectionTask {
+        self.task
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+
+    /// Replaces a connection that was closed by an error with a fresh one to the same server.
     ///
-    /// This is useful for creating multiple clients that communicate with the server
-    /// via the same TCP connection.
+    /// All clients using the connection carry on with the new one.
+    /// An open connection is left as it is, see [`Self::renew`] to replace its stream.
     ///
     /// # Examples
     ///
     /// ```
+    /// use cached::ClientConnection;
     /// use cached::Client;
     /// # use cached::Server;
     /// # use cached::Error;
-    /// use cached::StatusCode;
-    /// use cached::ClientConnection;
     ///
     /// # #[tokio::main]
     /// # async fn main() -> Result<(), Error> {
@@ -104,162 +234,382 @@ impl Client {
     /// # let port = server.port();
     /// # tokio::spawn(async { server.run().await;});
     /// let conn = ClientConnection::new(format!("127.0.0.1:{port}")).await;
-    ///
-    /// let client_1 = Client::with_connection(&conn);
-    /// let response = client_1.set("foo", "bar", None).await.unwrap();
-    /// assert_eq!(response, StatusCode::Ok);
-    ///
-    /// let client_2 = Client::with_connection(&conn);
-    /// let response = client_2.get("foo").await.unwrap();
-    /// assert_eq!(response.status(), StatusCode::Ok);
+    /// let client = Client::with_connection(&conn);
+    /// if client.get("foo").await.is_err() {
+    ///     conn.reconnect().await?;
+    /// }
     /// # Ok(())
     /// # }
     /// ```
-    pub fn with_connection(conn: &ClientConnection) -> Self {
-        Self { conn: conn.get() }
+    #[cfg_attr(feature = "tracing", instrument(skip(self)))]
+    pub async fn reconnect(&self) -> Result<()> {
+        if !self.task().is_closed() {
+            return Ok(());
+        }
+        let stream = TcpStream::connect(self.peer_addr)
+            .await
+            .map_err(|e| Error::new_connection(ConnectionError::Io(e)))?;
+        let fresh = Self::spawn(stream, self.peer_addr);
+        let mut task = self.task.write().unwrap_or_else(PoisonError::into_inner);
+        // Unless another client reconnected in the meantime
+        if task.is_closed() {
+            *task = fresh;
+        }
+        Ok(())
     }
 
-    /// Gets a value by its key from the server.
+    /// Replaces the TCP stream of the connection with a freshly established one.
+    ///
+    /// All clients using the connection carry on with the new stream.
+    /// Requests submitted before renewing are still answered over the old stream,
+    /// which is only closed once all of their responses arrived.
+    /// Requests submitted afterwards wait for the new stream.
+    ///
+    /// If the new stream cannot be established, the error is returned and the old stream is kept.
+    /// A connection that was closed by an error cannot be renewed, see [`Self::reconnect`].
     ///
     /// # Examples
     ///
     /// ```
+    /// use cached::ClientConnection;
     /// use cached::Client;
     /// # use cached::Server;
     /// # use cached::Error;
-    /// use cached::StatusCode;
     ///
     /// # #[tokio::main]
     /// # async fn main() -> Result<(), Error> {
     /// # let server = Server::new().bind("127.0.0.1:0").await.unwrap();
     /// # let port = server.port();
     /// # tokio::spawn(async { server.run().await;});
-    /// let client = Client::new(format!("127.0.0.1:{port}")).await;
-    /// client.set("foo", "bar", None).await.unwrap();
-    ///
-    /// let response = client.get("foo").await.unwrap();
-    /// assert_eq!(response.status(), StatusCode::Ok);
-    /// assert_eq!(response.value().unwrap(), "bar");
-    /// assert!(response.ttl_since_unix_epoch_in_millis().is_none());
+    /// let conn = ClientConnection::new(format!("127.0.0.1:{port}")).await;
+    /// let client = Client::with_connection(&conn);
+    /// client.set("foo", "bar", None).await?;
     ///
-    /// let response = client.get("something else").await.unwrap();
-    /// assert_eq!(response.status(), StatusCode::KeyNotFound);
-    /// assert!(response.value().is_none());
-    /// assert!(response.ttl_since_unix_epoch_in_millis().is_none());
+    /// conn.renew().await?;
+    /// assert_eq!(client.get("foo").await?.value(), Some(b"bar".as_slice()));
     /// # Ok(())
     /// # }
     /// ```
     #[cfg_attr(feature = "tracing", instrument(skip(self)))]
-    pub async fn get<S>(&self, key: S) -> Result<ResponseGet>
-    where
-        S: Into<String>,
-        S: Debug,
-    {
-        let key = Key::parse(key.into())?;
-        let request = Request::Get(key);
-        let response = self.handle_request(request).await?;
-        if let ResponseBody::Get(maybe_value) = response.body {
-            let (value, ttl) = maybe_value.map_or((None, None), |value| {
-                (
-                    Some(value.value.into_inner()),
-                    value.ttl_since_unix_epoch_in_millis,
-                )
-            });
-            Ok(ResponseGet::new(response.status, value, ttl))
-        } else {
-            Err(Error::new_client(ClientError::ExpectedValue))
-        }
+    pub async fn renew(&self) -> Result<()> {
+        let (tx, rx) = oneshot::channel();
+        self.task()
+            .sender
+            .send(Command::Renew(tx))
+            .await
+            .map_err(|_| self.connection_error(ConnectionError::Send))?;
+        rx.await
+            .map_err(|_| self.connection_error(ConnectionError::Receive))?
     }
 
-    /// Sets a value for the given key with an optional expiry time.
-    /// Existing values for the key are not overwritten.
+    /// Pings the server whenever no request was sent and no response arrived for `interval`.
     ///
-    /// The expiry time must be set as Unix epoch in milliseconds.
-    /// The server will not return a value for expired keys.
+    /// This keeps the state of NATs and firewalls along the way alive,
+    /// and notices a dead stream before a request is sent over it.
+    /// If the ping is not answered within `interval`, or the server closes the stream
+    /// while no request is in flight, the stream is replaced with a fresh one like [`Self::renew`] does.
+    /// Only if that fails as well, the connection is closed for good.
     ///
     /// # Examples
     ///
     /// ```
+    /// use cached::ClientConnection;
     /// use cached::Client;
+    /// use std::time::Duration;
     /// # use cached::Server;
     /// # use cached::Error;
-    /// use cached::StatusCode;
     ///
     /// # #[tokio::main]
     /// # async fn main() -> Result<(), Error> {
     /// # let server = Server::new().bind("127.0.0.1:0").await.unwrap();
     /// # let port = server.port();
     /// # tokio::spawn(async { server.run().await;});
-    /// let client = Client::new(format!("127.0.0.1:{port}")).await;
-    /// let response = client.set("foo", "bar", None).await;
-    /// assert_eq!(response.unwrap(), StatusCode::Ok);
+    /// let conn = ClientConnection::new(format!("127.0.0.1:{port}")).await;
+    /// conn.keepalive_interval(Duration::from_secs(30)).await?;
+    /// let client = Client::with_connection(&conn);
+    /// client.set("foo", "bar", None).await?;
     /// # Ok(())
     /// # }
     /// ```
     #[cfg_attr(feature = "tracing", instrument(skip(self)))]
-    pub async fn set<S>(
-        &self,
-        key: S,
-        value: S,
-        ttl_since_unix_epoch_in_millis: Option<u128>,
-    ) -> Result<StatusCode>
-    where
-        S: Into<String>,
-        S: Debug,
-    {
-        let key = Key::parse(key.into())?;
-        let value = Value::parse(value.into())?;
-        let request = Request::Set {
-            key,
-            value,
-            ttl_since_unix_epoch_in_millis,
-        };
-        let response = self.handle_request(request).await?;
-        Ok(response.status)
+    pub async fn keepalive_interval(&self, interval: Duration) -> Result<()> {
+        let (tx, rx) = oneshot::channel();
+        self.task()
+            .sender
+            .send(Command::Keepalive(interval, tx))
+            .await
+            .map_err(|_| self.connection_error(ConnectionError::Send))?;
+        rx.await
+            .map_err(|_| self.connection_error(ConnectionError::Receive))
     }
 
-    /// Deletes a key with its value from the cache.
+    /// Sends a request frame that was encoded elsewhere and returns the response frame as it was received.
+    ///
+    /// This suits proxies, which pass frames through without decoding them.
+    /// The request id in `frame` is replaced for sending, and restored in the returned response,
+    /// so the caller may pick any id. The frame is otherwise sent as it is,
+    /// a malformed frame makes the server close the connection for all clients using it.
+    ///
+    /// Fails without sending anything if the total frame length in the header does not match `frame`.
     ///
     /// # Examples
     ///
     /// ```
-    /// use cached::Client;
+    /// use bytes::BytesMut;
+    /// use cached::protocol::{Frame, OpCode};
+    /// use cached::ClientConnection;
     /// # use cached::Server;
     /// # use cached::Error;
-    /// use cached::StatusCode;
     ///
     /// # #[tokio::main]
     /// # async fn main() -> Result<(), Error> {
     /// # let server = Server::new().bind("127.0.0.1:0").await.unwrap();
     /// # let port = server.port();
     /// # tokio::spawn(async { server.run().await;});
-    /// let client = Client::new(format!("127.0.0.1:{port}")).await;
-    /// client.set("foo", "bar", None).await.unwrap();
-    ///
-    /// let response = client.get("foo").await.unwrap();
-    /// assert_eq!(response.status(), StatusCode::Ok);
-    ///
-    /// let response = client.delete("foo").await.unwrap();
-    /// assert_eq!(response, StatusCode::Ok);
+    /// let conn = ClientConnection::new(format!("127.0.0.1:{port}")).await;
+    /// let mut request = Frame::new(OpCode::Get, 7);
+    /// request.key = Some("foo".to_string());
+    /// let mut frame = BytesMut::new();
+    /// request.encode_request(&mut frame)?;
     ///
-    /// let response = client.get("foo").await.unwrap();
-    /// assert_eq!(response.status(), StatusCode::KeyNotFound);
+    /// let response = conn.send_raw(frame.freeze()).await?;
+    /// let (response, _) = Frame::decode_response(&response)?.unwrap();
+    /// assert_eq!(response.request_id, 7);
     /// # Ok(())
     /// # }
     /// ```
     #[cfg_attr(feature = "tracing", instrument(skip(self)))]
-    pub async fn delete<S>(&self, key: S) -> Result<StatusCode>
-    where
-        S: Into<String>,
-        S: Debug,
-    {
-        let key = Key::parse(key.into())?;
-        let request = Request::Delete(key);
-        let response = self.handle_request(request).await?;
-        Ok(response.status)
+    pub async fn send_raw(&self, frame: Bytes) -> Result<Bytes> {
+        if frame.len() < HEADER_SIZE as usize
+            || total_frame_length(&frame).map(|length| length as usize) != Some(frame.len())
+        {
+            return Err(Error::new_frame(FrameError::InvalidLength));
+        }
+        let (tx, rx) = oneshot::channel();
+        self.task()
+            .sender
+            .send(Command::RequestFrame(FrameResponder {
+                frame,
+                responder: tx,
+            }))
+            .await
+            .map_err(|_| self.connection_error(ConnectionError::Send))?;
+        rx.await
+            .map_err(|_| self.connection_error(ConnectionError::Receive))?
     }
 
-    /// Clears the entire cache.
+    /// Writes requests in the order they were submitted, which is the order the server applies
+    /// them in (see [`Client`]'s ordering guarantees), and hands every response to the
+    /// sender of the request with the same id, no matter in which order the responses arrive.
+    async fn run(
+        mut conn: Connection,
+        peer_addr: SocketAddr,
+        mut rx: mpsc::Receiver<Command>,
+        closed_reason: Arc<OnceLock<Arc<Error>>>,
+    ) {
+        let mut in_flight: HashMap<u32, Responder> = HashMap::new();
+        let mut next_request_id: u32 = 0;
+        let mut accepting_requests = true;
+        let mut failed_renewal = None;
+        let mut keepalive: Option<Duration> = None;
+        let mut last_activity = Instant::now();
+        let error = loop {
+            if !accepting_requests && in_flight.is_empty() {
+                return;
+            }
+            tokio::select! {
+                maybe_command = rx.recv(), if accepting_requests => match maybe_command {
+                    Some(Command::Request(RequestResponder { request, responder })) => {
+                        let request_id = next_request_id;
+                        next_request_id = next_request_id.wrapping_add(1);
+                        let previous = in_flight.insert(request_id, Responder::Response(responder));
+                        debug_assert!(previous.is_none(), "request id {request_id} is still in flight");
+                        if let Err(e) = conn.write_request(request_id, request).await {
+                            break e;
+                        }
+                        last_activity = Instant::now();
+                    }
+                    Some(Command::RequestFrame(FrameResponder { frame, responder })) => {
+                        let request_id = next_request_id;
+                        next_request_id = next_request_id.wrapping_add(1);
+                        let responder = Responder::Frame {
+                            request_id: connection::request_id(&frame),
+                            responder,
+                        };
+                        let previous = in_flight.insert(request_id, responder);
+                        debug_assert!(previous.is_none(), "request id {request_id} is still in flight");
+                        if let Err(e) = conn.write_request_frame(request_id, &frame).await {
+                            break e;
+                        }
+                        last_activity = Instant::now();
+                    }
+                    Some(Command::Renew(responder)) => {
+                        // The responses to requests sent so far can only arrive over the old stream
+                        if let Err(e) = Self::await_in_flight(&mut conn, &mut in_flight).await {
+                            failed_renewal = Some(responder);
+                            break e;
+                        }
+                        match TcpStream::connect(peer_addr).await {
+                            Ok(stream) => {
+                                conn = Connection::new(stream);
+                                let _ = responder.send(Ok(()));
+                            }
+                            Err(e) => {
+                                let _ =
+                                    responder.send(Err(Error::new_connection(ConnectionError::Io(e))));
+                            }
+                        }
+                    }
+                    Some(Command::Keepalive(interval, responder)) => {
+                        keepalive = Some(interval);
+                        last_activity = Instant::now();
+                        let _ = responder.send(());
+                    }
+                    None => {
+                        // All clients are gone, only wait for the outstanding responses
+                        accepting_requests = false;
+                    }
+                },
+                response = conn.read_response_frame() => {
+                    if let Err(e) = Self::dispatch_response(response, &mut in_flight) {
+                        // Without requests in flight, nothing is lost by moving on to a new stream
+                        if keepalive.is_none() || !in_flight.is_empty() {
+                            break e;
+                        }
+                        match TcpStream::connect(peer_addr).await {
+                            Ok(stream) => conn = Connection::new(stream),
+                            Err(_) => break e,
+                        }
+                    }
+                    last_activity = Instant::now();
+                }
+                _ = sleep_until(last_activity + keepalive.unwrap_or_default()),
+                    if keepalive.is_some() && accepting_requests && in_flight.is_empty() =>
+                {
+                    let request_id = next_request_id;
+                    next_request_id = next_request_id.wrapping_add(1);
+                    let interval = keepalive.unwrap_or_default();
+                    if let Err(e) = Self::ping(&mut conn, request_id, interval).await {
+                        match TcpStream::connect(peer_addr).await {
+                            Ok(stream) => conn = Connection::new(stream),
+                            Err(_) => break e,
+                        }
+                    }
+                    last_activity = Instant::now();
+                }
+            }
+        };
+        // We cannot tell how much of a frame made it over the wire,
+        // so the stream cannot be used for any further requests.
+        let reason = Arc::clone(closed_reason.get_or_init(|| Arc::new(error)));
+        for (_, responder) in in_flight.drain() {
+            responder.send_error(Error::new_connection(ConnectionError::ClosedWhileAwaiting(
+                Arc::clone(&reason),
+            )));
+        }
+        if let Some(responder) = failed_renewal {
+            let _ = responder.send(Err(Error::new_connection(ConnectionError::Closed(reason))));
+        }
+    }
+
+    /// Sends a request that every server answers and waits up to `timeout_after` for its response.
+    async fn ping(conn: &mut Connection, request_id: u32, timeout_after: Duration) -> Result<()> {
+        conn.write_request(request_id, Request::Capabilities)
+            .await?;
+        match timeout(timeout_after, conn.read_response()).await {
+            Ok(Ok(Some((id, _)))) if id == request_id => Ok(()),
+            Ok(Ok(Some((id, _)))) => {
+                Err(Error::new_connection(ConnectionError::UnknownRequestId(id)))
+            }
+            Ok(Ok(None)) => Err(Error::new_connection(ConnectionError::ReadResponse)),
+            Ok(Err(e)) => Err(e),
+            Err(_) => Err(Error::new_connection(ConnectionError::PingTimedOut)),
+        }
+    }
+
+    /// Reads responses until every request in flight has been answered.
+    async fn await_in_flight(
+        conn: &mut Connection,
+        in_flight: &mut HashMap<u32, Responder>,
+    ) -> Result<()> {
+        while !in_flight.is_empty() {
+            Self::dispatch_response(conn.read_response_frame().await, in_flight)?;
+        }
+        Ok(())
+    }
+
+    /// Hands the response to the sender of the request it answers.
+    ///
+    /// A response to a request that is not in flight means the peer got the ids mixed up,
+    /// and later responses might end up with the wrong sender, so the connection is closed.
+    /// So does a response that cannot be decoded, its request is left in flight to learn why.
+    fn dispatch_response(
+        response: Result<Option<(u32, Bytes)>>,
+        in_flight: &mut HashMap<u32, Responder>,
+    ) -> Result<()> {
+        match response? {
+            Some((request_id, frame)) => {
+                let responder = in_flight.remove(&request_id).ok_or_else(|| {
+                    Error::new_connection(ConnectionError::UnknownRequestId(request_id))
+                })?;
+                match responder {
+                    Responder::Response(responder) => match decode_response(&frame) {
+                        Ok(response) => {
+                            let _ = responder.send(Ok(response));
+                        }
+                        Err(e) => {
+                            in_flight.insert(request_id, Responder::Response(responder));
+                            return Err(e);
+                        }
+                    },
+                    Responder::Frame {
+                        request_id,
+                        responder,
+                    } => {
+                        let mut frame = frame.to_vec();
+                        set_request_id(&mut frame, request_id);
+                        let _ = responder.send(Ok(frame.into()));
+                    }
+                }
+                Ok(())
+            }
+            None => Err(Error::new_connection(ConnectionError::ReadResponse)),
+        }
+    }
+
+    /// Maps a failure to talk to the background task to the error that closed the connection,
+    /// falling back to `fallback` if the connection was not closed by an error.
+    fn connection_error(&self, fallback: ConnectionError) -> Error {
+        match self.task().closed_reason.get() {
+            Some(reason) => Error::new_connection(ConnectionError::Closed(Arc::clone(reason))),
+            None => Error::new_connection(fallback),
+        }
+    }
+}
+
+/// A client to communicate with the cached server.
+///
+/// # Ordering
+///
+/// Requests sent over the same connection are applied by the server in the order they were
+/// submitted, and a request is submitted when the future of its call is polled for the first time.
+/// So two calls awaited one after the other are applied in program order, and so are calls
+/// running concurrently within one task, e.g. via `tokio::join!`, in the order they are listed.
+/// A request is only answered once it was applied, so once a call returned,
+/// its effects are visible to all later requests, on any connection.
+/// There is no ordering between calls made from different tasks that run at the same time.
+#[derive(Debug, Clone)]
+pub struct Client {
+    // Only used if the client doesn't balance across several servers
+    conn: ClientConnection,
+    // Hands out the connection for each request if the client balances across several servers
+    balancer: Option<Arc<Balancer>>,
+}
+
+impl Client {
+    /// Create a new client connecting to a server at `addr`.
+    ///
+    /// Panics if it cannot connect to addr.
     ///
     /// # Examples
     ///
@@ -268,6 +618,7 @@ impl Client {
     /// # use cached::Server;
     /// # use cached::Error;
     /// use cached::StatusCode;
+    /// use cached::ClientConnection;
     ///
     /// # #[tokio::main]
     /// # async fn main() -> Result<(), Error> {
@@ -275,36 +626,2789 @@ impl Client {
     /// # let port = server.port();
     /// # tokio::spawn(async { server.run().await;});
     /// let client = Client::new(format!("127.0.0.1:{port}")).await;
-    /// client.set("foo", "bar", None).await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn new<A: ToSocketAddrs>(addr: A) -> Self {
+        let conn = ClientConnection::new(addr).await;
+        Self::with_connection(&conn)
+    }
+
+    /// Creates a new client over a blocking stream that was connected elsewhere,
+    /// see [`ClientConnection::from_std_stream`].
+    pub fn from_std_stream(stream: std::net::TcpStream) -> Result<Self> {
+        Ok(Self::with_connection(&ClientConnection::from_std_stream(
+            stream,
+        )?))
+    }
+
+    /// Creates a new client connecting to a server at `addr` once the server answers requests,
+    /// retrying for up to `timeout_after`.
     ///
-    /// let response = client.get("foo").await?;
-    /// assert_eq!(response.status(), StatusCode::Ok);
+    /// Each attempt connects and asks the server for its [`capabilities`](Client::capabilities).
+    /// Attempts that fail, e.g. as the server is not listening yet, are retried after
+    /// a delay of 10ms doubling up to 100ms. Once `timeout_after` elapsed, the attempts are given up.
     ///
-    /// let response = client.flush().await?;
-    /// assert_eq!(response, StatusCode::Ok);
+    /// # Examples
     ///
-    /// let response = client.get("foo").await?;
-    /// assert_eq!(response.status(), StatusCode::KeyNotFound);
+    /// ```
+    /// use cached::Client;
+    /// # use cached::Server;
+    /// # use cached::Error;
+    /// use std::time::Duration;
+    ///
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Error> {
+    /// # let server = Server::new().bind("127.0.0.1:0").await.unwrap();
+    /// # let port = server.port();
+    /// # tokio::spawn(async { server.run().await;});
+    /// let client = Client::connect_ready(format!("127.0.0.1:{port}"), Duration::from_secs(1)).await?;
+    /// client.set("foo", "bar", None).await?;
     /// # Ok(())
     /// # }
     /// ```
-    #[cfg_attr(feature = "tracing", instrument(skip(self)))]
-    pub async fn flush(&self) -> Result<StatusCode> {
-        let request = Request::Flush;
-        let response = self.handle_request(request).await?;
-        Ok(response.status)
+    #[cfg_attr(feature = "tracing", instrument(skip(addr)))]
+    pub async fn connect_ready<A: ToSocketAddrs + Clone>(
+        addr: A,
+        timeout_after: Duration,
+    ) -> Result<Self> {
+        let deadline = Instant::now() + timeout_after;
+        let mut delay = MIN_CONNECT_READY_DELAY;
+        loop {
+            let attempt = async {
+                let client = Self::with_connection(&ClientConnection::connect(addr.clone()).await?);
+                client.capabilities().await?;
+                Ok::<_, Error>(client)
+            };
+            if let Ok(Ok(client)) = timeout_at(deadline, attempt).await {
+                return Ok(client);
+            }
+            if Instant::now() + delay >= deadline {
+                return Err(Error::new_connection(ConnectionError::NotReady));
+            }
+            sleep(delay).await;
+            delay = (delay * 2).min(MAX_CONNECT_READY_DELAY);
+        }
     }
 
-    async fn handle_request(&self, request: Request) -> Result<Response> {
-        let (tx, rx) = oneshot::channel();
-        self.conn
-            .send(RequestResponder {
-                request,
-                responder: tx,
-            })
-            .await
-            .map_err(|_| Error::new_connection(ConnectionError::Send))?;
-        rx.await
-            .map_err(|_| Error::new_connection(ConnectionError::Receive))?
+    /// Creates a new client using an existing connection.
+    ///
+    /// This is useful for creating multiple clients that communicate with the server
+    /// via the same TCP connection.
+    ///
+    /// Sharing a connection is safe no matter in which order the server answers:
+    /// every request is tagged with an id unique among the requests in flight on the connection,
+    /// and each response is handed to the client that sent the request with the same id.
+    /// A response carrying an id that is not in flight closes the connection
+    /// rather than being handed to the wrong client.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use cached::Client;
+    /// # use cached::Server;
+    /// # use cached::Error;
+    /// use cached::StatusCode;
+    /// use cached::ClientConnection;
+    ///
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Error> {
+    /// # let server = Server::new().bind("127.0.0.1:0").await.unwrap();
+    /// # let port = server.port();
+    /// # tokio::spawn(async { server.run().await;});
+    /// let conn = ClientConnection::new(format!("127.0.0.1:{port}")).await;
+    ///
+    /// let client_1 = Client::with_connection(&conn);
+    /// let response = client_1.set("foo", "bar", None).await.unwrap();
+    /// assert_eq!(response, StatusCode::Ok);
+    ///
+    /// let client_2 = Client::with_connection(&conn);
+    /// let response = client_2.get("foo").await.unwrap();
+    /// assert_eq!(response.status(), StatusCode::Ok);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn with_connection(conn: &ClientConnection) -> Self {
+        Self {
+            conn: conn.clone(),
+            balancer: None,
+        }
+    }
+
+    /// Creates a new client balancing its requests across all servers `name` resolves to via DNS,
+    /// see [`Client::balanced_with_resolver`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use cached::Client;
+    /// # use cached::Server;
+    /// # use cached::Error;
+    /// use std::time::Duration;
+    ///
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Error> {
+    /// # let server = Server::new().bind("127.0.0.1:0").await.unwrap();
+    /// # let port = server.port();
+    /// # tokio::spawn(async { server.run().await;});
+    /// let client = Client::balanced(format!("localhost:{port}"), Duration::from_secs(30)).await?;
+    /// client.set("foo", "bar", None).await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn balanced<S: Into<String>>(name: S, reresolve_every: Duration) -> Result<Self> {
+        Self::balanced_with_resolver(name, Arc::new(DnsResolver), reresolve_every).await
+    }
+
+    /// Creates a new client connecting to every address `resolver` resolves `name` to,
+    /// sending each request to the server its key belongs to.
+    ///
+    /// The name is resolved again every `reresolve_every` to connect to new addresses and drop
+    /// the connections to addresses that are gone. Closed connections are skipped and reconnected
+    /// on the next resolution. Addresses that can't be connected to are tried again then as well.
+    /// Fails if `name` can't be resolved or none of its addresses can be connected to.
+    ///
+    /// Each key belongs to one of the servers, so reading a key finds what was written to it
+    /// as long as its server stays resolved and reachable. Requests for several keys go to the
+    /// server of their first key, requests without keys take turns across the servers.
+    /// Requests are only ordered against each other if they are sent to the same server.
+    /// [`Client::renew_connection`] renews the connections to all servers, while
+    /// [`Client::subscribe_evictions`] subscribes to one of them in turn, like requests without keys.
+    pub async fn balanced_with_resolver<S: Into<String>>(
+        name: S,
+        resolver: Arc<dyn Resolver>,
+        reresolve_every: Duration,
+    ) -> Result<Self> {
+        let balancer = Balancer::connect(name.into(), resolver, reresolve_every).await?;
+        Ok(Self {
+            conn: balancer.next(),
+            balancer: Some(balancer),
+        })
+    }
+
+    /// Replaces the TCP stream of the client's connection with a fresh one,
+    /// for all clients sharing the connection.
+    ///
+    /// A balanced client renews its connections to all servers, failing with the first error.
+    /// See [`ClientConnection::renew`].
+    #[cfg_attr(feature = "tracing", instrument(skip(self)))]
+    pub async fn renew_connection(&self) -> Result<()> {
+        for conn in self.connections() {
+            conn.renew().await?;
+        }
+        Ok(())
+    }
+
+    /// Returns a view of the client whose methods setting values let them
+    /// expire after `ttl` unless a TTL is given explicitly.
+    ///
+    /// The view shares the connection of the client, which is available via
+    /// [`ClientWithDefaultTtl::client`] for everything else.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use cached::Client;
+    /// # use cached::Server;
+    /// # use cached::Error;
+    /// use std::time::Duration;
+    ///
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Error> {
+    /// # let server = Server::new().bind("127.0.0.1:0").await.unwrap();
+    /// # let port = server.port();
+    /// # tokio::spawn(async { server.run().await;});
+    /// let client = Client::new(format!("127.0.0.1:{port}")).await;
+    /// let expiring = client.with_default_ttl(Duration::from_secs(5 * 60));
+    /// expiring.set("foo", "bar", None).await?;
+    ///
+    /// let response = expiring.client().get("foo").await?;
+    /// assert!(response.ttl_since_unix_epoch_in_millis().is_some());
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn with_default_ttl(&self, ttl: Duration) -> ClientWithDefaultTtl {
+        ClientWithDefaultTtl {
+            client: self.clone(),
+            default_ttl: ttl,
+        }
+    }
+
+    /// Gets a value by its key from the server.
+    ///
+    /// Keys remembered as missing via [`Client::set_negative`] are answered with
+    /// [`StatusCode::NegativeCached`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use cached::Client;
+    /// # use cached::Server;
+    /// # use cached::Error;
+    /// use cached::{StatusCode, TtlState};
+    ///
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Error> {
+    /// # let server = Server::new().bind("127.0.0.1:0").await.unwrap();
+    /// # let port = server.port();
+    /// # tokio::spawn(async { server.run().await;});
+    /// let client = Client::new(format!("127.0.0.1:{port}")).await;
+    /// client.set("foo", "bar", None).await.unwrap();
+    ///
+    /// let response = client.get("foo").await.unwrap();
+    /// assert_eq!(response.status(), StatusCode::Ok);
+    /// assert_eq!(response.value().unwrap(), b"bar");
+    /// assert_eq!(response.ttl(), TtlState::NoTtl);
+    ///
+    /// let response = client.get("something else").await.unwrap();
+    /// assert_eq!(response.status(), StatusCode::KeyNotFound);
+    /// assert!(response.value().is_none());
+    /// assert_eq!(response.ttl(), TtlState::Unknown);
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[cfg_attr(feature = "tracing", instrument(skip(self)))]
+    pub async fn get<S>(&self, key: S) -> Result<ResponseGet>
+    where
+        S: Into<String>,
+        S: Debug,
+    {
+        let key = Key::parse(key.into())?;
+        let request = Request::Get(key);
+        let response = self.handle_request(request).await?;
+        into_response_get(response)
+    }
+
+    /// Gets the value for `key`, or `default` if there is none.
+    ///
+    /// Keys that were never set, expired or are remembered as missing all fall back to `default`.
+    /// Any other status is returned as an error, and so is a value that is not valid UTF-8.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use cached::Client;
+    /// # use cached::Server;
+    /// # use cached::Error;
+    ///
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Error> {
+    /// # let server = Server::new().bind("127.0.0.1:0").await.unwrap();
+    /// # let port = server.port();
+    /// # tokio::spawn(async { server.run().await;});
+    /// let client = Client::new(format!("127.0.0.1:{port}")).await;
+    /// client.set("foo", "bar", None).await?;
+    ///
+    /// assert_eq!(client.get_or_default("foo", "baz".to_string()).await?, "bar");
+    /// assert_eq!(client.get_or_default("something else", "baz".to_string()).await?, "baz");
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[cfg_attr(feature = "tracing", instrument(skip(self)))]
+    pub async fn get_or_default<S>(&self, key: S, default: String) -> Result<String>
+    where
+        S: Into<String>,
+        S: Debug,
+    {
+        let response = self.get(key).await?;
+        match response.status() {
+            StatusCode::Ok => response.ok_or_not_found(),
+            StatusCode::KeyNotFound | StatusCode::NegativeCached => Ok(default),
+            status => Err(Error::new_client(ClientError::Status(status))),
+        }
+    }
+
+    /// Gets a value like [`Client::get`], but writes it into `buf` instead of allocating
+    /// a response for it, and returns the status.
+    ///
+    /// `buf` is cleared first and only holds the value if the status is [`StatusCode::Ok`].
+    /// Reusing `buf` across calls saves allocating for every value once it has grown large enough,
+    /// while the request itself and its response frame are still allocated.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use cached::Client;
+    /// # use cached::Server;
+    /// # use cached::Error;
+    /// use cached::StatusCode;
+    ///
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Error> {
+    /// # let server = Server::new().bind("127.0.0.1:0").await.unwrap();
+    /// # let port = server.port();
+    /// # tokio::spawn(async { server.run().await;});
+    /// let client = Client::new(format!("127.0.0.1:{port}")).await;
+    /// client.set("foo", "bar", None).await?;
+    ///
+    /// let mut buf = Vec::new();
+    /// assert_eq!(client.get_into("foo", &mut buf).await?, StatusCode::Ok);
+    /// assert_eq!(buf, b"bar");
+    ///
+    /// assert_eq!(client.get_into("something else", &mut buf).await?, StatusCode::KeyNotFound);
+    /// assert!(buf.is_empty());
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[cfg_attr(feature = "tracing", instrument(skip(self, buf)))]
+    pub async fn get_into<S>(&self, key: S, buf: &mut Vec<u8>) -> Result<StatusCode>
+    where
+        S: Into<String>,
+        S: Debug,
+    {
+        let key = Key::parse(key.into())?;
+        let response = self.handle_request(Request::Get(key)).await?;
+        copy_value_into(response, buf)
+    }
+
+    /// Gets the values for several keys from the server.
+    ///
+    /// All requests are written to the connection without waiting for the responses in between,
+    /// so this costs about one round trip instead of one per key.
+    /// Responses are matched to their requests by id, the server may answer them in any order.
+    /// The results are returned in the order of `keys`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use cached::Client;
+    /// # use cached::Server;
+    /// # use cached::Error;
+    /// use cached::StatusCode;
+    ///
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Error> {
+    /// # let server = Server::new().bind("127.0.0.1:0").await.unwrap();
+    /// # let port = server.port();
+    /// # tokio::spawn(async { server.run().await;});
+    /// let client = Client::new(format!("127.0.0.1:{port}")).await;
+    /// client.set("foo", "bar", None).await?;
+    ///
+    /// let responses = client.pipeline_get(["foo", "something else"]).await?;
+    /// assert_eq!(responses[0].0, "foo");
+    /// assert_eq!(responses[0].1.value().unwrap(), b"bar");
+    /// assert_eq!(responses[1].0, "something else");
+    /// assert_eq!(responses[1].1.status(), StatusCode::KeyNotFound);
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[cfg_attr(feature = "tracing", instrument(skip(self, keys)))]
+    pub async fn pipeline_get<I, S>(&self, keys: I) -> Result<Vec<(String, ResponseGet)>>
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        let mut pending = vec![];
+        for key in keys {
+            let key = key.into();
+            let request = Request::Get(Key::parse(key.clone())?);
+            pending.push((key, self.submit_request(request).await?));
+        }
+        let mut responses = Vec::with_capacity(pending.len());
+        for (key, receiver) in pending {
+            let response = self.await_response(receiver).await?;
+            responses.push((key, into_response_get(response)?));
+        }
+        Ok(responses)
+    }
+
+    /// Gets the values of several keys like [`Client::pipeline_get`], keyed by their key.
+    ///
+    /// Keys that are not found are left out of the map.
+    /// Fails on any other status and on values that are not valid UTF-8.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use cached::Client;
+    /// # use cached::Server;
+    /// # use cached::Error;
+    ///
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Error> {
+    /// # let server = Server::new().bind("127.0.0.1:0").await.unwrap();
+    /// # let port = server.port();
+    /// # tokio::spawn(async { server.run().await;});
+    /// let client = Client::new(format!("127.0.0.1:{port}")).await;
+    /// client.set("foo", "bar", None).await?;
+    ///
+    /// let values = client.get_map(["foo", "something else"]).await?;
+    /// assert_eq!(values.len(), 1);
+    /// assert_eq!(values["foo"], "bar");
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[cfg_attr(feature = "tracing", instrument(skip(self, keys)))]
+    pub async fn get_map<I, S>(&self, keys: I) -> Result<HashMap<String, String>>
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        let mut values = HashMap::new();
+        for (key, response) in self.pipeline_get(keys).await? {
+            if response.status() != StatusCode::KeyNotFound {
+                values.insert(key, response.ok_or_not_found()?);
+            }
+        }
+        Ok(values)
+    }
+
+    /// Checks for several keys whether they exist on the server, in a single round trip.
+    ///
+    /// No values are transferred, expired keys are reported as not existing.
+    /// The results are returned in the order of `keys`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use cached::Client;
+    /// # use cached::Server;
+    /// # use cached::Error;
+    ///
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Error> {
+    /// # let server = Server::new().bind("127.0.0.1:0").await.unwrap();
+    /// # let port = server.port();
+    /// # tokio::spawn(async { server.run().await;});
+    /// let client = Client::new(format!("127.0.0.1:{port}")).await;
+    /// client.set("foo", "bar", None).await?;
+    ///
+    /// let exists = client.exists_many(["foo", "something else"]).await?;
+    /// assert_eq!(exists, vec![true, false]);
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[cfg_attr(feature = "tracing", instrument(skip(self, keys)))]
+    pub async fn exists_many<I, S>(&self, keys: I) -> Result<Vec<bool>>
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        let keys = keys
+            .into_iter()
+            .map(|key| Key::parse(key.into()))
+            .collect::<Result<Vec<_>>>()?;
+        let requested_keys = keys.len();
+        let request = Request::ExistsMany(keys);
+        let response = self.handle_request(request).await?;
+        match (response.status, response.body) {
+            (StatusCode::Ok, ResponseBody::ExistsMany(exists))
+                if exists.len() == requested_keys =>
+            {
+                Ok(exists)
+            }
+            (StatusCode::Ok, _) => Err(Error::new_client(ClientError::UnexpectedResponse)),
+            (status, _) => Err(Error::new_client(ClientError::Status(status))),
+        }
+    }
+
+    /// Returns all keys matching the glob `pattern`, sorted.
+    ///
+    /// In the pattern, `*` matches any sequence of characters and `?` matches a single character.
+    /// Expired keys are left out, and so are the values.
+    ///
+    /// # Performance
+    ///
+    /// The server checks every key it holds against the pattern, which takes time proportional
+    /// to the size of the whole cache and holds up the other requests to each shard while it is scanned.
+    /// Fails with [`StatusCode::ValueTooLarge`] if the matching keys do not fit into a single response.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use cached::Client;
+    /// # use cached::Server;
+    /// # use cached::Error;
+    ///
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Error> {
+    /// # let server = Server::new().bind("127.0.0.1:0").await.unwrap();
+    /// # let port = server.port();
+    /// # tokio::spawn(async { server.run().await;});
+    /// let client = Client::new(format!("127.0.0.1:{port}")).await;
+    /// client.set("user:1:session", "a", None).await?;
+    /// client.set("user:2:session", "b", None).await?;
+    /// client.set("user:2:profile", "c", None).await?;
+    ///
+    /// let keys = client.keys_matching("user:*:session").await?;
+    /// assert_eq!(keys, vec!["user:1:session", "user:2:session"]);
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[cfg_attr(feature = "tracing", instrument(skip(self)))]
+    pub async fn keys_matching<S>(&self, pattern: S) -> Result<Vec<String>>
+    where
+        S: Into<String>,
+        S: Debug,
+    {
+        let pattern = Key::parse(pattern.into())?;
+        let response = self.handle_request(Request::KeysGlob(pattern)).await?;
+        match (response.status, response.body) {
+            (StatusCode::Ok, ResponseBody::KeysGlob(keys)) => {
+                Ok(keys.into_iter().map(Key::into_inner).collect())
+            }
+            (StatusCode::Ok, _) => Err(Error::new_client(ClientError::UnexpectedResponse)),
+            (status, _) => Err(Error::new_client(ClientError::Status(status))),
+        }
+    }
+
+    /// Sets a value for the given key with an optional expiry time.
+    /// Existing values for the key are not overwritten.
+    ///
+    /// The value may be any bytes, e.g. a compressed or serialized payload,
+    /// see [`ResponseGet::value`] to get it back as such.
+    /// The expiry time must be set as Unix epoch in milliseconds.
+    /// The server will not return a value for expired keys.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use cached::Client;
+    /// # use cached::Server;
+    /// # use cached::Error;
+    /// use cached::StatusCode;
+    ///
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Error> {
+    /// # let server = Server::new().bind("127.0.0.1:0").await.unwrap();
+    /// # let port = server.port();
+    /// # tokio::spawn(async { server.run().await;});
+    /// let client = Client::new(format!("127.0.0.1:{port}")).await;
+    /// let response = client.set("foo", "bar", None).await;
+    /// assert_eq!(response.unwrap(), StatusCode::Ok);
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[cfg_attr(feature = "tracing", instrument(skip(self)))]
+    pub async fn set<S, V>(
+        &self,
+        key: S,
+        value: V,
+        ttl_since_unix_epoch_in_millis: Option<u128>,
+    ) -> Result<StatusCode>
+    where
+        S: Into<String>,
+        S: Debug,
+        V: Into<Vec<u8>>,
+        V: Debug,
+    {
+        self.set_with_flags(key, value, ttl_since_unix_epoch_in_millis, 0)
+            .await
+    }
+
+    /// Sets a value like [`Client::set`], storing `flags` alongside it.
+    ///
+    /// The server keeps the flags as they are and returns them when getting the value,
+    /// see [`ResponseGet::flags`], e.g. to tag how the value is encoded.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use cached::Client;
+    /// # use cached::Server;
+    /// # use cached::Error;
+    ///
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Error> {
+    /// # let server = Server::new().bind("127.0.0.1:0").await.unwrap();
+    /// # let port = server.port();
+    /// # tokio::spawn(async { server.run().await;});
+    /// const JSON: u32 = 1;
+    ///
+    /// let client = Client::new(format!("127.0.0.1:{port}")).await;
+    /// client.set_with_flags("foo", r#"{"bar":1}"#, None, JSON).await?;
+    ///
+    /// let response = client.get("foo").await?;
+    /// assert_eq!(response.flags(), JSON);
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[cfg_attr(feature = "tracing", instrument(skip(self)))]
+    pub async fn set_with_flags<S, V>(
+        &self,
+        key: S,
+        value: V,
+        ttl_since_unix_epoch_in_millis: Option<u128>,
+        flags: u32,
+    ) -> Result<StatusCode>
+    where
+        S: Into<String>,
+        S: Debug,
+        V: Into<Vec<u8>>,
+        V: Debug,
+    {
+        let key = Key::parse(key.into())?;
+        let value = Value::parse(value.into())?;
+        let request = Request::Set {
+            key,
+            value,
+            ttl_since_unix_epoch_in_millis,
+            flags,
+        };
+        let response = self.handle_request(request).await?;
+        Ok(response.status)
+    }
+
+    /// Sets a value like [`Client::set`], streaming it from `reader`, e.g. a file.
+    ///
+    /// The value is sent in chunks as it is read, holding no more than a single chunk at a time,
+    /// see [`OpCode::SetChunk`]. The server sets it once the last chunk arrived, without flags.
+    /// If reading fails, the server discards the chunks sent so far and the key is left as it was.
+    /// Values longer than `MAX_VALUE_LENGTH` are discarded the same way and answered with
+    /// [`StatusCode::ValueTooLarge`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use cached::Client;
+    /// # use cached::Server;
+    /// # use cached::Error;
+    ///
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Error> {
+    /// # let server = Server::new().bind("127.0.0.1:0").await.unwrap();
+    /// # let port = server.port();
+    /// # tokio::spawn(async { server.run().await;});
+    /// let client = Client::new(format!("127.0.0.1:{port}")).await;
+    /// client.set_from_reader("foo", "bar".as_bytes(), None).await?;
+    ///
+    /// assert_eq!(client.get("foo").await?.value(), Some(b"bar".as_slice()));
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[cfg_attr(feature = "tracing", instrument(skip(self, reader)))]
+    pub async fn set_from_reader<S, R>(
+        &self,
+        key: S,
+        reader: R,
+        ttl_since_unix_epoch_in_millis: Option<u128>,
+    ) -> Result<StatusCode>
+    where
+        S: Into<String>,
+        S: Debug,
+        R: AsyncRead + Unpin,
+    {
+        let key = Key::parse(key.into())?;
+        let mut reader = reader;
+        // The server collects the chunks per connection
+        let conn = self.connection_for(Some(&key));
+        loop {
+            let mut chunk = Vec::with_capacity(CHUNK_LENGTH);
+            let read = (&mut reader)
+                .take(CHUNK_LENGTH as u64)
+                .read_to_end(&mut chunk)
+                .await;
+            if let Err(e) = read {
+                let abort = Request::SetChunk {
+                    key,
+                    chunk: None,
+                    ttl_since_unix_epoch_in_millis,
+                    step: ChunkStep::Abort,
+                };
+                // The connection closing discards the chunks as well
+                let _ = self.send_chunk(&conn, abort).await;
+                return Err(Error::new_connection(ConnectionError::Io(e)));
+            }
+            // A chunk coming up short means the reader is done
+            let step = if chunk.len() < CHUNK_LENGTH {
+                ChunkStep::Last
+            } else {
+                ChunkStep::More
+            };
+            let request = Request::SetChunk {
+                key: key.clone(),
+                chunk: (!chunk.is_empty())
+                    .then(|| Value::parse(chunk))
+                    .transpose()?,
+                ttl_since_unix_epoch_in_millis,
+                step,
+            };
+            let status = self.send_chunk(&conn, request).await?;
+            if status != StatusCode::Ok || matches!(step, ChunkStep::Last) {
+                return Ok(status);
+            }
+        }
+    }
+
+    /// Sends a chunk of a value streamed by [`Client::set_from_reader`] over `conn`,
+    /// waiting for its status before the next one is read.
+    async fn send_chunk(&self, conn: &ClientConnection, request: Request) -> Result<StatusCode> {
+        let submitted = self.submit_request_on(conn.clone(), request).await?;
+        Ok(self.await_response(submitted).await?.status)
+    }
+
+    /// Sets a value like [`Client::set`], returning the whole response instead of just its status.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use cached::Client;
+    /// # use cached::Server;
+    /// # use cached::Error;
+    /// use cached::{OpCode, StatusCode};
+    ///
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Error> {
+    /// # let server = Server::new().bind("127.0.0.1:0").await.unwrap();
+    /// # let port = server.port();
+    /// # tokio::spawn(async { server.run().await;});
+    /// let client = Client::new(format!("127.0.0.1:{port}")).await;
+    /// let response = client.set_response("foo", "bar", None).await?;
+    /// assert_eq!(response.status(), StatusCode::Ok);
+    /// assert_eq!(response.op_code(), OpCode::Set);
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[cfg_attr(feature = "tracing", instrument(skip(self)))]
+    pub async fn set_response<S, V>(
+        &self,
+        key: S,
+        value: V,
+        ttl_since_unix_epoch_in_millis: Option<u128>,
+    ) -> Result<ResponseStatus>
+    where
+        S: Into<String>,
+        S: Debug,
+        V: Into<Vec<u8>>,
+        V: Debug,
+    {
+        let key = Key::parse(key.into())?;
+        let value = Value::parse(value.into())?;
+        let request = Request::Set {
+            key,
+            value,
+            ttl_since_unix_epoch_in_millis,
+            flags: 0,
+        };
+        let response = self.handle_request(request).await?;
+        Ok(ResponseStatus::from(&response))
+    }
+
+    /// Sets a value like [`Client::set`], expiring it at `expires_at`.
+    ///
+    /// An `expires_at` in the past is still sent, the server answers [`StatusCode::Ok`]
+    /// without storing the value then, as for any TTL in the past.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use cached::Client;
+    /// # use cached::Server;
+    /// # use cached::Error;
+    /// use std::time::{Duration, SystemTime};
+    ///
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Error> {
+    /// # let server = Server::new().bind("127.0.0.1:0").await.unwrap();
+    /// # let port = server.port();
+    /// # tokio::spawn(async { server.run().await;});
+    /// let client = Client::new(format!("127.0.0.1:{port}")).await;
+    /// let in_a_minute = SystemTime::now() + Duration::from_secs(60);
+    /// client.set_until("foo", "bar", in_a_minute).await?;
+    ///
+    /// let response = client.get("foo").await?;
+    /// assert!(response.remaining_ttl().unwrap() <= Duration::from_secs(60));
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[cfg_attr(feature = "tracing", instrument(skip(self)))]
+    pub async fn set_until<S, V>(
+        &self,
+        key: S,
+        value: V,
+        expires_at: SystemTime,
+    ) -> Result<StatusCode>
+    where
+        S: Into<String>,
+        S: Debug,
+        V: Into<Vec<u8>>,
+        V: Debug,
+    {
+        // Times before the unix epoch have passed as much as the epoch itself
+        let ttl_since_unix_epoch_in_millis = expires_at
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis();
+        self.set(key, value, Some(ttl_since_unix_epoch_in_millis))
+            .await
+    }
+
+    /// Sets a value like [`Client::set`], but fails unless the server answers [`StatusCode::Ok`].
+    ///
+    /// The status the server answered with is available via [`Error::status`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use cached::Client;
+    /// # use cached::Server;
+    /// # use cached::Error;
+    /// use cached::StatusCode;
+    ///
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Error> {
+    /// # let server = Server::new().bind("127.0.0.1:0").await.unwrap();
+    /// # let port = server.port();
+    /// # tokio::spawn(async { server.run().await;});
+    /// let client = Client::new(format!("127.0.0.1:{port}")).await;
+    /// client.set_checked("foo", "bar", None).await?;
+    ///
+    /// let error = client.set_checked("foo", "bar", None).await.unwrap_err();
+    /// assert_eq!(error.status(), Some(StatusCode::KeyExists));
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[cfg_attr(feature = "tracing", instrument(skip(self)))]
+    pub async fn set_checked<S, V>(
+        &self,
+        key: S,
+        value: V,
+        ttl_since_unix_epoch_in_millis: Option<u128>,
+    ) -> Result<()>
+    where
+        S: Into<String>,
+        S: Debug,
+        V: Into<Vec<u8>>,
+        V: Debug,
+    {
+        ensure_ok(self.set(key, value, ttl_since_unix_epoch_in_millis).await?)
+    }
+
+    /// Sets several values in one go, like [`Client::set`] for each of them.
+    ///
+    /// The requests are written back to back without waiting for the responses in between.
+    /// Each entry is reported on its own in the order of `entries`,
+    /// so entries that can't be set don't abort the rest of the batch.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use cached::Client;
+    /// # use cached::Server;
+    /// # use cached::Error;
+    /// use cached::StatusCode;
+    ///
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Error> {
+    /// # let server = Server::new().bind("127.0.0.1:0").await.unwrap();
+    /// # let port = server.port();
+    /// # tokio::spawn(async { server.run().await;});
+    /// let client = Client::new(format!("127.0.0.1:{port}")).await;
+    /// client.set("foo", "bar", None).await?;
+    ///
+    /// let results = client
+    ///     .set_many_pipelined([("foo", "baz"), ("something else", "baz")], None)
+    ///     .await;
+    /// assert_eq!(results[0].0, "foo");
+    /// assert_eq!(results[0].1.as_ref().unwrap(), &StatusCode::KeyExists);
+    /// assert_eq!(results[1].0, "something else");
+    /// assert_eq!(results[1].1.as_ref().unwrap(), &StatusCode::Ok);
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[cfg_attr(feature = "tracing", instrument(skip(self, entries)))]
+    pub async fn set_many_pipelined<I, S>(
+        &self,
+        entries: I,
+        ttl_since_unix_epoch_in_millis: Option<u128>,
+    ) -> Vec<(String, Result<StatusCode>)>
+    where
+        I: IntoIterator<Item = (S, S)>,
+        S: Into<String>,
+    {
+        let mut pending = vec![];
+        for (key, value) in entries {
+            let key = key.into();
+            let receiver = match (Key::parse(key.clone()), Value::parse(value.into())) {
+                (Ok(key), Ok(value)) => {
+                    let request = Request::Set {
+                        key,
+                        value,
+                        ttl_since_unix_epoch_in_millis,
+                        flags: 0,
+                    };
+                    self.submit_request(request).await
+                }
+                (Err(e), _) | (_, Err(e)) => Err(e),
+            };
+            pending.push((key, receiver));
+        }
+        let mut results = Vec::with_capacity(pending.len());
+        for (key, receiver) in pending {
+            let result = match receiver {
+                Ok(receiver) => self
+                    .await_response(receiver)
+                    .await
+                    .map(|response| response.status),
+                Err(e) => Err(e),
+            };
+            results.push((key, result));
+        }
+        results
+    }
+
+    /// Sets several values sharing the same TTL in a single request.
+    ///
+    /// Unlike [`Client::set_many_pipelined`], the TTL is sent once for all entries,
+    /// which must fit into a single frame together.
+    /// Like [`Client::set`], keys that exist already are left as they are.
+    /// The status of each entry is returned in the order of `entries`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use cached::Client;
+    /// # use cached::Server;
+    /// # use cached::Error;
+    /// use cached::StatusCode;
+    ///
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Error> {
+    /// # let server = Server::new().bind("127.0.0.1:0").await.unwrap();
+    /// # let port = server.port();
+    /// # tokio::spawn(async { server.run().await;});
+    /// let client = Client::new(format!("127.0.0.1:{port}")).await;
+    /// client.set("foo", "bar", None).await?;
+    ///
+    /// let statuses = client
+    ///     .set_many_ttl([("foo", "baz"), ("something else", "baz")], None)
+    ///     .await?;
+    /// assert_eq!(statuses, vec![StatusCode::KeyExists, StatusCode::Ok]);
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[cfg_attr(feature = "tracing", instrument(skip(self, entries)))]
+    pub async fn set_many_ttl<I, S>(
+        &self,
+        entries: I,
+        ttl_since_unix_epoch_in_millis: Option<u128>,
+    ) -> Result<Vec<StatusCode>>
+    where
+        I: IntoIterator<Item = (S, S)>,
+        S: Into<String>,
+    {
+        let mut keys = vec![];
+        let mut values = vec![];
+        for (key, value) in entries {
+            keys.push(Key::parse(key.into())?);
+            values.push(Value::parse(value.into())?);
+        }
+        // Checked here, the connection would give up on a frame it can't encode
+        if encoded_entries_length(&keys, &values) > MAX_VALUE_LENGTH as usize {
+            return Err(Error::new_parse(ParseError::ValueTooLong));
+        }
+        let requested_entries = keys.len();
+        let request = Request::SetMany {
+            keys,
+            values,
+            ttl_since_unix_epoch_in_millis,
+        };
+        let response = self.handle_request(request).await?;
+        match (response.status, response.body) {
+            (StatusCode::Ok, ResponseBody::SetMany(statuses))
+                if statuses.len() == requested_entries =>
+            {
+                Ok(statuses)
+            }
+            (StatusCode::Ok, _) => Err(Error::new_client(ClientError::UnexpectedResponse)),
+            (status, _) => Err(Error::new_client(ClientError::Status(status))),
+        }
+    }
+
+    /// Applies `ops` one after the other, undoing the ones applied so far as soon as one fails.
+    ///
+    /// An operation fails unless the server answers [`StatusCode::Ok`], e.g. setting a key that exists
+    /// already fails with [`StatusCode::KeyExists`]. The operations applied before are then undone in
+    /// reverse order: keys that were set are deleted, and deleted keys are set again with the value,
+    /// TTL and flags they held. Undoing is best-effort, if it fails itself it is skipped, and the error
+    /// of the failed operation is returned either way.
+    ///
+    /// The batch is not isolated from other clients. They see each operation as soon as it is applied,
+    /// and may change the keys of the batch before it is undone. A key deleted by the batch is only
+    /// restored if nobody set it meanwhile, while a key set by the batch is deleted in any case.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use cached::{BatchOp, Client};
+    /// # use cached::Server;
+    /// # use cached::Error;
+    /// use cached::StatusCode;
+    ///
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Error> {
+    /// # let server = Server::new().bind("127.0.0.1:0").await.unwrap();
+    /// # let port = server.port();
+    /// # tokio::spawn(async { server.run().await;});
+    /// let client = Client::new(format!("127.0.0.1:{port}")).await;
+    /// client.set("taken", "bar", None).await?;
+    ///
+    /// let error = client
+    ///     .batch_transaction([
+    ///         BatchOp::set("foo", "bar", None),
+    ///         BatchOp::set("taken", "baz", None),
+    ///     ])
+    ///     .await
+    ///     .unwrap_err();
+    /// assert_eq!(error.status(), Some(StatusCode::KeyExists));
+    /// assert_eq!(client.get("foo").await?.status(), StatusCode::KeyNotFound);
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[cfg_attr(feature = "tracing", instrument(skip(self, ops)))]
+    pub async fn batch_transaction<I>(&self, ops: I) -> Result<()>
+    where
+        I: IntoIterator<Item = BatchOp>,
+    {
+        let mut journal = vec![];
+        for op in ops {
+            match self.apply_undoable(op).await {
+                Ok(undo) => journal.push(undo),
+                Err(e) => {
+                    for undo in journal.into_iter().rev() {
+                        let _ = self.undo(undo).await;
+                    }
+                    return Err(e);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Applies `op` and returns how to undo it.
+    async fn apply_undoable(&self, op: BatchOp) -> Result<Undo> {
+        match op {
+            BatchOp::Set {
+                key,
+                value,
+                ttl_since_unix_epoch_in_millis,
+            } => {
+                ensure_ok(
+                    self.set(key.clone(), value, ttl_since_unix_epoch_in_millis)
+                        .await?,
+                )?;
+                Ok(Undo::Delete(key))
+            }
+            BatchOp::Delete { key } => {
+                let previous = self.get(key.clone()).await?;
+                ensure_ok(previous.status())?;
+                ensure_ok(self.delete(key.clone()).await?)?;
+                Ok(Undo::Restore {
+                    key,
+                    ttl_since_unix_epoch_in_millis: previous.ttl_since_unix_epoch_in_millis(),
+                    flags: previous.flags(),
+                    value: previous
+                        .into_value()
+                        .ok_or_else(|| Error::new_client(ClientError::ExpectedValue))?,
+                })
+            }
+        }
+    }
+
+    async fn undo(&self, undo: Undo) -> Result<()> {
+        match undo {
+            Undo::Delete(key) => ensure_ok(self.delete(key).await?),
+            Undo::Restore {
+                key,
+                value,
+                ttl_since_unix_epoch_in_millis,
+                flags,
+            } => ensure_ok(
+                self.set_with_flags(key, value, ttl_since_unix_epoch_in_millis, flags)
+                    .await?,
+            ),
+        }
+    }
+
+    /// Pushes an item to the front of the list stored under the key.
+    ///
+    /// The list is created if the key does not exist yet.
+    /// Together with [`Client::pop`] this forms a queue, items are popped in the order they were pushed in.
+    /// Fails with [`StatusCode::WrongType`] if the key holds a plain value
+    /// and with [`StatusCode::ValueTooLarge`] once the list would exceed the maximum value size.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use cached::Client;
+    /// # use cached::Server;
+    /// # use cached::Error;
+    /// use cached::StatusCode;
+    ///
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Error> {
+    /// # let server = Server::new().bind("127.0.0.1:0").await.unwrap();
+    /// # let port = server.port();
+    /// # tokio::spawn(async { server.run().await;});
+    /// let client = Client::new(format!("127.0.0.1:{port}")).await;
+    /// let response = client.push("jobs", "first").await?;
+    /// assert_eq!(response, StatusCode::Ok);
+    /// client.push("jobs", "second").await?;
+    ///
+    /// assert_eq!(client.pop("jobs").await?.unwrap(), "first");
+    /// assert_eq!(client.pop("jobs").await?.unwrap(), "second");
+    /// assert!(client.pop("jobs").await?.is_none());
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[cfg_attr(feature = "tracing", instrument(skip(self)))]
+    pub async fn push<S>(&self, key: S, item: S) -> Result<StatusCode>
+    where
+        S: Into<String>,
+        S: Debug,
+    {
+        let key = Key::parse(key.into())?;
+        let item = Value::parse(item.into())?;
+        let request = Request::LPush { key, item };
+        let response = self.handle_request(request).await?;
+        Ok(response.status)
+    }
+
+    /// Pops an item from the back of the list stored under the key.
+    ///
+    /// Returns `None` if there is no list under the key, lists are removed once their last item was popped.
+    /// Fails if the key holds a plain value.
+    /// See [`Client::push`] for an example.
+    #[cfg_attr(feature = "tracing", instrument(skip(self)))]
+    pub async fn pop<S>(&self, key: S) -> Result<Option<String>>
+    where
+        S: Into<String>,
+        S: Debug,
+    {
+        let key = Key::parse(key.into())?;
+        let request = Request::RPop(key);
+        let response = self.handle_request(request).await?;
+        match (response.status, response.body) {
+            (StatusCode::Ok, ResponseBody::RPop(Some(item))) => Ok(Some(item.into_string()?)),
+            (StatusCode::KeyNotFound, ResponseBody::RPop(None)) => Ok(None),
+            (StatusCode::Ok | StatusCode::KeyNotFound, _) => {
+                Err(Error::new_client(ClientError::UnexpectedResponse))
+            }
+            (status, _) => Err(Error::new_client(ClientError::Status(status))),
+        }
+    }
+
+    /// Adds a member to the set stored under the key.
+    ///
+    /// The set is created if the key does not exist yet.
+    /// Returns whether the member was newly added, adding an existing member again changes nothing.
+    /// Fails if the key holds something other than a set.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use cached::Client;
+    /// # use cached::Server;
+    /// # use cached::Error;
+    ///
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Error> {
+    /// # let server = Server::new().bind("127.0.0.1:0").await.unwrap();
+    /// # let port = server.port();
+    /// # tokio::spawn(async { server.run().await;});
+    /// let client = Client::new(format!("127.0.0.1:{port}")).await;
+    /// assert!(client.set_add("seen", "foo").await?);
+    /// assert!(!client.set_add("seen", "foo").await?);
+    ///
+    /// assert!(client.set_contains("seen", "foo").await?);
+    /// assert!(!client.set_contains("seen", "bar").await?);
+    ///
+    /// assert!(client.set_remove("seen", "foo").await?);
+    /// assert!(!client.set_contains("seen", "foo").await?);
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[cfg_attr(feature = "tracing", instrument(skip(self)))]
+    pub async fn set_add<S>(&self, key: S, member: S) -> Result<bool>
+    where
+        S: Into<String>,
+        S: Debug,
+    {
+        let key = Key::parse(key.into())?;
+        let member = Value::parse(member.into())?;
+        let response = self.handle_request(Request::SAdd { key, member }).await?;
+        into_membership(response, StatusCode::KeyExists)
+    }
+
+    /// Checks whether the member is part of the set stored under the key.
+    ///
+    /// Returns `false` if there is no set under the key.
+    /// Fails if the key holds something other than a set.
+    /// See [`Client::set_add`] for an example.
+    #[cfg_attr(feature = "tracing", instrument(skip(self)))]
+    pub async fn set_contains<S>(&self, key: S, member: S) -> Result<bool>
+    where
+        S: Into<String>,
+        S: Debug,
+    {
+        let key = Key::parse(key.into())?;
+        let member = Value::parse(member.into())?;
+        let response = self
+            .handle_request(Request::SIsMember { key, member })
+            .await?;
+        into_membership(response, StatusCode::KeyNotFound)
+    }
+
+    /// Removes the member from the set stored under the key, sets are removed once they are empty.
+    ///
+    /// Returns whether the member was present.
+    /// Fails if the key holds something other than a set.
+    /// See [`Client::set_add`] for an example.
+    #[cfg_attr(feature = "tracing", instrument(skip(self)))]
+    pub async fn set_remove<S>(&self, key: S, member: S) -> Result<bool>
+    where
+        S: Into<String>,
+        S: Debug,
+    {
+        let key = Key::parse(key.into())?;
+        let member = Value::parse(member.into())?;
+        let response = self.handle_request(Request::SRem { key, member }).await?;
+        into_membership(response, StatusCode::KeyNotFound)
+    }
+
+    /// Remembers the key as missing for `ttl`, so lookups at an expensive origin are not repeated.
+    ///
+    /// While the tombstone lives, [`Client::get`] answers [`StatusCode::NegativeCached`]
+    /// instead of [`StatusCode::KeyNotFound`].
+    /// Like values, tombstones do not replace existing values, [`StatusCode::KeyExists`] is
+    /// returned then. Setting a value replaces the tombstone, and it can be deleted like any key.
+    /// Fails without sending anything if `ttl` reaches beyond what [`SystemTime`] can represent.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use cached::Client;
+    /// # use cached::Server;
+    /// # use cached::Error;
+    /// use cached::StatusCode;
+    /// use std::time::Duration;
+    ///
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Error> {
+    /// # let server = Server::new().bind("127.0.0.1:0").await.unwrap();
+    /// # let port = server.port();
+    /// # tokio::spawn(async { server.run().await;});
+    /// let client = Client::new(format!("127.0.0.1:{port}")).await;
+    /// let status = client.set_negative("foo", Duration::from_secs(10)).await?;
+    /// assert_eq!(status, StatusCode::Ok);
+    ///
+    /// let response = client.get("foo").await?;
+    /// assert_eq!(response.status(), StatusCode::NegativeCached);
+    /// assert!(response.value().is_none());
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[cfg_attr(feature = "tracing", instrument(skip(self)))]
+    pub async fn set_negative<S>(&self, key: S, ttl: Duration) -> Result<StatusCode>
+    where
+        S: Into<String>,
+        S: Debug,
+    {
+        let key = Key::parse(key.into())?;
+        let ttl_since_unix_epoch_in_millis = expiry_after(ttl)?;
+        let request = Request::SetNegative {
+            key,
+            ttl_since_unix_epoch_in_millis: Some(ttl_since_unix_epoch_in_millis),
+        };
+        let response = self.handle_request(request).await?;
+        Ok(response.status)
+    }
+
+    /// Deletes a key with its value from the cache.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use cached::Client;
+    /// # use cached::Server;
+    /// # use cached::Error;
+    /// use cached::StatusCode;
+    ///
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Error> {
+    /// # let server = Server::new().bind("127.0.0.1:0").await.unwrap();
+    /// # let port = server.port();
+    /// # tokio::spawn(async { server.run().await;});
+    /// let client = Client::new(format!("127.0.0.1:{port}")).await;
+    /// client.set("foo", "bar", None).await.unwrap();
+    ///
+    /// let response = client.get("foo").await.unwrap();
+    /// assert_eq!(response.status(), StatusCode::Ok);
+    ///
+    /// let response = client.delete("foo").await.unwrap();
+    /// assert_eq!(response, StatusCode::Ok);
+    ///
+    /// let response = client.get("foo").await.unwrap();
+    /// assert_eq!(response.status(), StatusCode::KeyNotFound);
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[cfg_attr(feature = "tracing", instrument(skip(self)))]
+    pub async fn delete<S>(&self, key: S) -> Result<StatusCode>
+    where
+        S: Into<String>,
+        S: Debug,
+    {
+        let key = Key::parse(key.into())?;
+        let request = Request::Delete(key);
+        let response = self.handle_request(request).await?;
+        Ok(response.status)
+    }
+
+    /// Deletes a key like [`Client::delete`], returning the whole response instead of just its status.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use cached::Client;
+    /// # use cached::Server;
+    /// # use cached::Error;
+    /// use cached::{OpCode, StatusCode};
+    ///
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Error> {
+    /// # let server = Server::new().bind("127.0.0.1:0").await.unwrap();
+    /// # let port = server.port();
+    /// # tokio::spawn(async { server.run().await;});
+    /// let client = Client::new(format!("127.0.0.1:{port}")).await;
+    /// let response = client.delete_response("foo").await?;
+    /// assert_eq!(response.status(), StatusCode::KeyNotFound);
+    /// assert_eq!(response.op_code(), OpCode::Delete);
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[cfg_attr(feature = "tracing", instrument(skip(self)))]
+    pub async fn delete_response<S>(&self, key: S) -> Result<ResponseStatus>
+    where
+        S: Into<String>,
+        S: Debug,
+    {
+        let key = Key::parse(key.into())?;
+        let request = Request::Delete(key);
+        let response = self.handle_request(request).await?;
+        Ok(ResponseStatus::from(&response))
+    }
+
+    /// Deletes a key and returns the value it held, fetching and removing it all at once.
+    ///
+    /// Returns `None` if there was nothing to delete, keys remembered as missing count as nothing.
+    /// Fails, leaving the key in place, if the key holds a list or a set.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use cached::Client;
+    /// # use cached::Server;
+    /// # use cached::Error;
+    ///
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Error> {
+    /// # let server = Server::new().bind("127.0.0.1:0").await.unwrap();
+    /// # let port = server.port();
+    /// # tokio::spawn(async { server.run().await;});
+    /// let client = Client::new(format!("127.0.0.1:{port}")).await;
+    /// client.set("foo", "bar", None).await?;
+    ///
+    /// assert_eq!(client.delete_returning("foo").await?.unwrap(), "bar");
+    /// assert!(client.delete_returning("foo").await?.is_none());
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[cfg_attr(feature = "tracing", instrument(skip(self)))]
+    pub async fn delete_returning<S>(&self, key: S) -> Result<Option<String>>
+    where
+        S: Into<String>,
+        S: Debug,
+    {
+        let key = Key::parse(key.into())?;
+        let request = Request::DeleteReturning(key);
+        let response = self.handle_request(request).await?;
+        match (response.status, response.body) {
+            (StatusCode::Ok, ResponseBody::DeleteReturning(Some(value))) => {
+                Ok(Some(value.into_string()?))
+            }
+            (StatusCode::KeyNotFound, ResponseBody::DeleteReturning(None)) => Ok(None),
+            (StatusCode::Ok | StatusCode::KeyNotFound, _) => {
+                Err(Error::new_client(ClientError::UnexpectedResponse))
+            }
+            (status, _) => Err(Error::new_client(ClientError::Status(status))),
+        }
+    }
+
+    /// Deletes a key like [`Client::delete`], but fails unless the server answers [`StatusCode::Ok`].
+    ///
+    /// Deleting a key that does not exist fails with [`StatusCode::KeyNotFound`],
+    /// available via [`Error::status`].
+    #[cfg_attr(feature = "tracing", instrument(skip(self)))]
+    pub async fn delete_checked<S>(&self, key: S) -> Result<()>
+    where
+        S: Into<String>,
+        S: Debug,
+    {
+        ensure_ok(self.delete(key).await?)
+    }
+
+    /// Asks the server to clear the entire cache, without confirming it.
+    ///
+    /// Servers refuse this with [`StatusCode::OperationNotPermitted`] unless they were started
+    /// with [`Server::require_flush_confirmation`] turned off,
+    /// use [`Client::flush_confirmed`] to clear the cache.
+    ///
+    /// [`Server::require_flush_confirmation`]: crate::Server::require_flush_confirmation
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use cached::Client;
+    /// # use cached::Server;
+    /// # use cached::Error;
+    /// use cached::StatusCode;
+    ///
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Error> {
+    /// # let server = Server::new().bind("127.0.0.1:0").await.unwrap();
+    /// # let port = server.port();
+    /// # tokio::spawn(async { server.run().await;});
+    /// let client = Client::new(format!("127.0.0.1:{port}")).await;
+    /// client.set("foo", "bar", None).await?;
+    ///
+    /// # #[allow(deprecated)]
+    /// let response = client.flush().await?;
+    /// assert_eq!(response, StatusCode::OperationNotPermitted);
+    ///
+    /// let response = client.get("foo").await?;
+    /// assert_eq!(response.status(), StatusCode::Ok);
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[deprecated(note = "use flush_confirmed")]
+    #[cfg_attr(feature = "tracing", instrument(skip(self)))]
+    pub async fn flush(&self) -> Result<StatusCode> {
+        let request = Request::Flush { confirmed: false };
+        let response = self.handle_request(request).await?;
+        Ok(response.status)
+    }
+
+    /// Clears the entire cache.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use cached::Client;
+    /// # use cached::Server;
+    /// # use cached::Error;
+    /// use cached::StatusCode;
+    ///
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Error> {
+    /// # let server = Server::new().bind("127.0.0.1:0").await.unwrap();
+    /// # let port = server.port();
+    /// # tokio::spawn(async { server.run().await;});
+    /// let client = Client::new(format!("127.0.0.1:{port}")).await;
+    /// client.set("foo", "bar", None).await?;
+    ///
+    /// let response = client.get("foo").await?;
+    /// assert_eq!(response.status(), StatusCode::Ok);
+    ///
+    /// let response = client.flush_confirmed().await?;
+    /// assert_eq!(response, StatusCode::Ok);
+    ///
+    /// let response = client.get("foo").await?;
+    /// assert_eq!(response.status(), StatusCode::KeyNotFound);
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[cfg_attr(feature = "tracing", instrument(skip(self)))]
+    pub async fn flush_confirmed(&self) -> Result<StatusCode> {
+        let request = Request::Flush { confirmed: true };
+        let response = self.handle_request(request).await?;
+        Ok(response.status)
+    }
+
+    /// Clears the entire cache like [`Client::flush`], or [`Client::flush_confirmed`] if `confirmed`,
+    /// returning the whole response instead of just its status.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use cached::Client;
+    /// # use cached::Server;
+    /// # use cached::Error;
+    /// use cached::{OpCode, StatusCode};
+    ///
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Error> {
+    /// # let server = Server::new().bind("127.0.0.1:0").await.unwrap();
+    /// # let port = server.port();
+    /// # tokio::spawn(async { server.run().await;});
+    /// let client = Client::new(format!("127.0.0.1:{port}")).await;
+    /// let response = client.flush_response(false).await?;
+    /// assert_eq!(response.status(), StatusCode::OperationNotPermitted);
+    /// assert_eq!(response.op_code(), OpCode::Flush);
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[cfg_attr(feature = "tracing", instrument(skip(self)))]
+    pub async fn flush_response(&self, confirmed: bool) -> Result<ResponseStatus> {
+        let request = Request::Flush { confirmed };
+        let response = self.handle_request(request).await?;
+        Ok(ResponseStatus::from(&response))
+    }
+
+    /// Clears the entire cache like [`Client::flush_confirmed`], but fails unless the server answers
+    /// [`StatusCode::Ok`].
+    #[cfg_attr(feature = "tracing", instrument(skip(self)))]
+    pub async fn flush_checked(&self) -> Result<()> {
+        ensure_ok(self.flush_response(true).await?.status())
+    }
+
+    /// Removes the expired keys right away and returns how many there were.
+    ///
+    /// The server removes expired keys in the background anyway, see [`Server::sweep_interval`],
+    /// this reclaims their memory on demand. Live keys are left alone.
+    ///
+    /// [`Server::sweep_interval`]: crate::Server::sweep_interval
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use cached::Client;
+    /// # use cached::Server;
+    /// # use cached::Error;
+    /// use cached::StatusCode;
+    /// use std::time::{Duration, SystemTime};
+    ///
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Error> {
+    /// # let server = Server::new().bind("127.0.0.1:0").await.unwrap();
+    /// # let port = server.port();
+    /// # tokio::spawn(async { server.run().await;});
+    /// let client = Client::new(format!("127.0.0.1:{port}")).await;
+    /// let soon = SystemTime::now() + Duration::from_millis(10);
+    /// client.set_until("foo", "bar", soon).await?;
+    /// client.set("live", "bar", None).await?;
+    ///
+    /// tokio::time::sleep(Duration::from_millis(20)).await;
+    /// assert_eq!(client.flush_expired().await?, 1);
+    /// assert_eq!(client.get("live").await?.status(), StatusCode::Ok);
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[cfg_attr(feature = "tracing", instrument(skip(self)))]
+    pub async fn flush_expired(&self) -> Result<u64> {
+        let response = self.handle_request(Request::FlushExpired).await?;
+        match (response.status, response.body) {
+            (StatusCode::Ok, ResponseBody::FlushExpired(Some(removed))) => Ok(removed),
+            (StatusCode::Ok, _) => Err(Error::new_client(ClientError::UnexpectedResponse)),
+            (status, _) => Err(Error::new_client(ClientError::Status(status))),
+        }
+    }
+
+    /// Returns the stats the server keeps for the connection of the client.
+    ///
+    /// The stats cover all clients sharing the connection, but not this request.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use cached::Client;
+    /// # use cached::Server;
+    /// # use cached::Error;
+    ///
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Error> {
+    /// # let server = Server::new().bind("127.0.0.1:0").await.unwrap();
+    /// # let port = server.port();
+    /// # tokio::spawn(async { server.run().await;});
+    /// let client = Client::new(format!("127.0.0.1:{port}")).await;
+    /// client.set("foo", "bar", None).await?;
+    ///
+    /// let stats = client.connection_stats().await?;
+    /// assert_eq!(stats.handled_requests(), 1);
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[cfg_attr(feature = "tracing", instrument(skip(self)))]
+    pub async fn connection_stats(&self) -> Result<ConnectionStats> {
+        let response = self.handle_request(Request::ConnStats).await?;
+        match (response.status, response.body) {
+            (StatusCode::Ok, ResponseBody::ConnStats(Some(stats))) => Ok(stats),
+            (StatusCode::Ok, _) => Err(Error::new_client(ClientError::UnexpectedResponse)),
+            (status, _) => Err(Error::new_client(ClientError::Status(status))),
+        }
+    }
+
+    /// Gets the limits and features the server was configured with.
+    ///
+    /// They are remembered for all clients of the connection, see [`Client::validate`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use cached::{Client, OpCode};
+    /// # use cached::Server;
+    /// # use cached::Error;
+    ///
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Error> {
+    /// # let server = Server::new().bind("127.0.0.1:0").await.unwrap();
+    /// # let port = server.port();
+    /// # tokio::spawn(async { server.run().await;});
+    /// let client = Client::new(format!("127.0.0.1:{port}")).await;
+    ///
+    /// let capabilities = client.capabilities().await?;
+    /// assert_eq!(capabilities.max_value_length(), 1024 * 1024);
+    /// assert!(capabilities.allows(OpCode::Set));
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[cfg_attr(feature = "tracing", instrument(skip(self)))]
+    pub async fn capabilities(&self) -> Result<ServerCapabilities> {
+        let submitted = self.submit_request(Request::Capabilities).await?;
+        // Remembered for the connection to the server that answered
+        let conn = submitted.conn.clone();
+        let response = self.await_response(submitted).await?;
+        match (response.status, response.body) {
+            (StatusCode::Ok, ResponseBody::Capabilities(Some(capabilities))) => {
+                *conn
+                    .capabilities
+                    .write()
+                    .unwrap_or_else(PoisonError::into_inner) = Some(capabilities.clone());
+                Ok(capabilities)
+            }
+            (StatusCode::Ok, _) => Err(Error::new_client(ClientError::UnexpectedResponse)),
+            (status, _) => Err(Error::new_client(ClientError::Status(status))),
+        }
+    }
+
+    /// Returns the capabilities last obtained via [`Client::capabilities`] by any client of the
+    /// connection, `None` if they were never asked for.
+    ///
+    /// A balanced client returns those of the first of its current servers they are known for.
+    pub fn known_capabilities(&self) -> Option<ServerCapabilities> {
+        self.connections().iter().find_map(|conn| {
+            conn.capabilities
+                .read()
+                .unwrap_or_else(PoisonError::into_inner)
+                .clone()
+        })
+    }
+
+    /// Checks that the server would accept `op` without sending anything, see [`BatchOp::validate`].
+    ///
+    /// Uses the [known capabilities](Client::known_capabilities) of the server.
+    /// If none are known, only the limits of the protocol are checked.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use cached::{BatchOp, Client};
+    /// # use cached::Server;
+    /// # use cached::Error;
+    ///
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Error> {
+    /// # let server = Server::new().bind("127.0.0.1:0").await.unwrap();
+    /// # let port = server.port();
+    /// # tokio::spawn(async { server.run().await;});
+    /// let client = Client::new(format!("127.0.0.1:{port}")).await;
+    /// let capabilities = client.capabilities().await?;
+    ///
+    /// let too_long = "a".repeat(capabilities.max_value_length() + 1);
+    /// assert!(client.validate(&BatchOp::set("foo", too_long.as_str(), None)).is_err());
+    /// assert!(client.validate(&BatchOp::set("foo", "bar", None)).is_ok());
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn validate(&self, op: &BatchOp) -> Result<()> {
+        match self.known_capabilities() {
+            Some(capabilities) => op.validate(&capabilities),
+            None => op.check_lengths(MAX_KEY_LENGTH, MAX_VALUE_LENGTH as usize),
+        }
+    }
+
+    /// Returns the version of the server and the optional features it was built with.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use cached::Client;
+    /// # use cached::Server;
+    /// # use cached::Error;
+    ///
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Error> {
+    /// # let server = Server::new().bind("127.0.0.1:0").await.unwrap();
+    /// # let port = server.port();
+    /// # tokio::spawn(async { server.run().await;});
+    /// let client = Client::new(format!("127.0.0.1:{port}")).await;
+    ///
+    /// let info = client.server_version().await?;
+    /// assert!(!info.version().is_empty());
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[cfg_attr(feature = "tracing", instrument(skip(self)))]
+    pub async fn server_version(&self) -> Result<ServerInfo> {
+        let response = self.handle_request(Request::Version).await?;
+        match (response.status, response.body) {
+            (StatusCode::Ok, ResponseBody::Version(Some(info))) => Ok(info),
+            (StatusCode::Ok, _) => Err(Error::new_client(ClientError::UnexpectedResponse)),
+            (status, _) => Err(Error::new_client(ClientError::Status(status))),
+        }
+    }
+
+    /// Returns a random live key, `None` if there are none.
+    ///
+    /// The key is sampled from a random shard of the server, so this is approximate:
+    /// keys in shards holding fewer keys are more likely to be returned.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use cached::Client;
+    /// # use cached::Server;
+    /// # use cached::Error;
+    ///
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Error> {
+    /// # let server = Server::new().bind("127.0.0.1:0").await.unwrap();
+    /// # let port = server.port();
+    /// # tokio::spawn(async { server.run().await;});
+    /// let client = Client::new(format!("127.0.0.1:{port}")).await;
+    /// assert_eq!(client.random_key().await?, None);
+    ///
+    /// client.set("foo", "bar", None).await?;
+    /// assert_eq!(client.random_key().await?.as_deref(), Some("foo"));
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[cfg_attr(feature = "tracing", instrument(skip(self)))]
+    pub async fn random_key(&self) -> Result<Option<String>> {
+        let response = self.handle_request(Request::RandomKey).await?;
+        match (response.status, response.body) {
+            (StatusCode::Ok, ResponseBody::RandomKey(Some(key))) => Ok(Some(key.into_inner())),
+            (StatusCode::Ok, _) => Err(Error::new_client(ClientError::UnexpectedResponse)),
+            (StatusCode::KeyNotFound, _) => Ok(None),
+            (status, _) => Err(Error::new_client(ClientError::Status(status))),
+        }
+    }
+
+    /// Describes what the server stores under a key, `None` if there is nothing.
+    ///
+    /// Meant for debugging, inspecting a key does not count as an access to it for eviction.
+    /// Tombstones left by [`Client::set_negative`] are described as well.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use cached::Client;
+    /// # use cached::Server;
+    /// # use cached::Error;
+    ///
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Error> {
+    /// # let server = Server::new().bind("127.0.0.1:0").await.unwrap();
+    /// # let port = server.port();
+    /// # tokio::spawn(async { server.run().await;});
+    /// let client = Client::new(format!("127.0.0.1:{port}")).await;
+    /// assert_eq!(client.inspect("foo").await?, None);
+    ///
+    /// client.set("foo", "bar", None).await?;
+    /// let info = client.inspect("foo").await?.unwrap();
+    /// assert_eq!(info.value_length(), 3);
+    /// assert_eq!(info.ttl_since_unix_epoch_in_millis(), None);
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[cfg_attr(feature = "tracing", instrument(skip(self)))]
+    pub async fn inspect<S>(&self, key: S) -> Result<Option<KeyInfo>>
+    where
+        S: Into<String>,
+        S: Debug,
+    {
+        let key = Key::parse(key.into())?;
+        let response = self.handle_request(Request::Inspect(key)).await?;
+        match (response.status, response.body) {
+            (StatusCode::Ok, ResponseBody::Inspect(Some(info))) => Ok(Some(info)),
+            (StatusCode::Ok, _) => Err(Error::new_client(ClientError::UnexpectedResponse)),
+            (StatusCode::KeyNotFound, _) => Ok(None),
+            (status, _) => Err(Error::new_client(ClientError::Status(status))),
+        }
+    }
+
+    /// Adds `delta` to the integer stored under a key and returns the result.
+    ///
+    /// A missing key is set to `delta`. The value is stored as its decimal string, so it can be
+    /// read with [`Client::get`] as well, and keeps its TTL and flags.
+    /// Fails with [`StatusCode::InvalidValue`] if the value is not an integer, and with
+    /// [`StatusCode::OutOfRange`] if the result does not fit into an `i64`, leaving the value as it was.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use cached::Client;
+    /// # use cached::Server;
+    /// # use cached::Error;
+    ///
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Error> {
+    /// # let server = Server::new().bind("127.0.0.1:0").await.unwrap();
+    /// # let port = server.port();
+    /// # tokio::spawn(async { server.run().await;});
+    /// let client = Client::new(format!("127.0.0.1:{port}")).await;
+    /// assert_eq!(client.increment("visits", 5).await?, 5);
+    /// assert_eq!(client.increment("visits", 1).await?, 6);
+    /// assert_eq!(client.get("visits").await?.value(), Some(b"6".as_slice()));
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[cfg_attr(feature = "tracing", instrument(skip(self)))]
+    pub async fn increment<S>(&self, key: S, delta: i64) -> Result<i64>
+    where
+        S: Into<String>,
+        S: Debug,
+    {
+        let key = Key::parse(key.into())?;
+        let response = self
+            .handle_request(Request::Increment { key, delta })
+            .await?;
+        match (response.status, response.body) {
+            (StatusCode::Ok, ResponseBody::Increment(Some(counter))) => {
+                parse_counter(&counter.value)
+            }
+            (StatusCode::Ok, _) => Err(Error::new_client(ClientError::UnexpectedResponse)),
+            (status, _) => Err(Error::new_client(ClientError::Status(status))),
+        }
+    }
+
+    /// Subtracts `delta` from the integer stored under a key and returns the result,
+    /// like [`Client::increment`] does with `-delta`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use cached::Client;
+    /// # use cached::Server;
+    /// # use cached::Error;
+    ///
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Error> {
+    /// # let server = Server::new().bind("127.0.0.1:0").await.unwrap();
+    /// # let port = server.port();
+    /// # tokio::spawn(async { server.run().await;});
+    /// let client = Client::new(format!("127.0.0.1:{port}")).await;
+    /// client.set("stock", "10", None).await?;
+    /// assert_eq!(client.decrement("stock", 3).await?, 7);
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[cfg_attr(feature = "tracing", instrument(skip(self)))]
+    pub async fn decrement<S>(&self, key: S, delta: i64) -> Result<i64>
+    where
+        S: Into<String>,
+        S: Debug,
+    {
+        let key = Key::parse(key.into())?;
+        let response = self
+            .handle_request(Request::Decrement { key, delta })
+            .await?;
+        match (response.status, response.body) {
+            (StatusCode::Ok, ResponseBody::Decrement(Some(counter))) => {
+                parse_counter(&counter.value)
+            }
+            (StatusCode::Ok, _) => Err(Error::new_client(ClientError::UnexpectedResponse)),
+            (status, _) => Err(Error::new_client(ClientError::Status(status))),
+        }
+    }
+
+    /// Subscribes to the batches of expired keys the server removes, see
+    /// [`ServerHandle::subscribe_evictions`](crate::ServerHandle::subscribe_evictions).
+    ///
+    /// The subscription uses a connection of its own to the server of this client.
+    /// It survives that connection dropping by subscribing again, but batches removed in between
+    /// are lost, see [`EvictionStream`].
+    /// Fails if the first attempt to subscribe does.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use cached::Client;
+    /// use std::time::{Duration, SystemTime, UNIX_EPOCH};
+    /// # use cached::Server;
+    /// # use cached::Error;
+    ///
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Error> {
+    /// # let server = Server::new().bind("127.0.0.1:0").await.unwrap();
+    /// # let port = server.port();
+    /// # tokio::spawn(async { server.run().await;});
+    /// let client = Client::new(format!("127.0.0.1:{port}")).await;
+    /// let mut evictions = client.subscribe_evictions().await?;
+    ///
+    /// let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_millis();
+    /// client.set("foo", "bar", Some(now + 10)).await?;
+    /// tokio::time::sleep(Duration::from_millis(20)).await;
+    /// client.flush_expired().await?;
+    /// assert_eq!(evictions.recv().await.unwrap().keys, vec!["foo".to_string()]);
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[cfg_attr(feature = "tracing", instrument(skip(self)))]
+    pub async fn subscribe_evictions(&self) -> Result<EvictionStream> {
+        let peer_addr = self.connection_for(None).peer_addr;
+        let conn = subscribe_to_evictions(peer_addr).await?;
+        let (tx, rx) = mpsc::channel(EVICTION_CHANNEL_CAPACITY);
+        spawn(forward_evictions(conn, peer_addr, tx));
+        Ok(EvictionStream::new(rx))
+    }
+
+    /// Gets a value like [`Client::get`], retrying as long as it fails due to the connection
+    /// and `policy` allows, reconnecting before every retry.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use cached::{Client, RetryPolicy};
+    /// # use cached::Server;
+    /// # use cached::Error;
+    ///
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Error> {
+    /// # let server = Server::new().bind("127.0.0.1:0").await.unwrap();
+    /// # let port = server.port();
+    /// # tokio::spawn(async { server.run().await;});
+    /// let client = Client::new(format!("127.0.0.1:{port}")).await;
+    /// client.set("foo", "bar", None).await?;
+    ///
+    /// let response = client.get_retry("foo", RetryPolicy::default()).await?;
+    /// assert_eq!(response.value(), Some(b"bar".as_slice()));
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[cfg_attr(feature = "tracing", instrument(skip(self)))]
+    pub async fn get_retry<S>(&self, key: S, policy: RetryPolicy) -> Result<ResponseGet>
+    where
+        S: Into<String>,
+        S: Debug,
+    {
+        let key = key.into();
+        self.retrying(policy, || self.get(key.clone())).await
+    }
+
+    /// Deletes a value like [`Client::delete`], retrying like [`Client::get_retry`].
+    ///
+    /// If a delete was applied but its response was lost, the retry answers [`StatusCode::KeyNotFound`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use cached::{Client, RetryPolicy};
+    /// # use cached::Server;
+    /// # use cached::Error;
+    ///
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Error> {
+    /// # let server = Server::new().bind("127.0.0.1:0").await.unwrap();
+    /// # let port = server.port();
+    /// # tokio::spawn(async { server.run().await;});
+    /// let client = Client::new(format!("127.0.0.1:{port}")).await;
+    /// client.set("foo", "bar", None).await?;
+    ///
+    /// client.delete_retry("foo", RetryPolicy::default()).await?;
+    /// assert!(client.get("foo").await?.value().is_none());
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[cfg_attr(feature = "tracing", instrument(skip(self)))]
+    pub async fn delete_retry<S>(&self, key: S, policy: RetryPolicy) -> Result<StatusCode>
+    where
+        S: Into<String>,
+        S: Debug,
+    {
+        let key = key.into();
+        self.retrying(policy, || self.delete(key.clone())).await
+    }
+
+    /// Checks whether keys exist like [`Client::exists_many`], retrying like [`Client::get_retry`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use cached::{Client, RetryPolicy};
+    /// # use cached::Server;
+    /// # use cached::Error;
+    ///
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Error> {
+    /// # let server = Server::new().bind("127.0.0.1:0").await.unwrap();
+    /// # let port = server.port();
+    /// # tokio::spawn(async { server.run().await;});
+    /// let client = Client::new(format!("127.0.0.1:{port}")).await;
+    /// client.set("foo", "bar", None).await?;
+    ///
+    /// let exists = client.exists_many_retry(["foo", "baz"], RetryPolicy::default()).await?;
+    /// assert_eq!(exists, vec![true, false]);
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[cfg_attr(feature = "tracing", instrument(skip(self, keys)))]
+    pub async fn exists_many_retry<I, S>(&self, keys: I, policy: RetryPolicy) -> Result<Vec<bool>>
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        let keys: Vec<String> = keys.into_iter().map(Into::into).collect();
+        self.retrying(policy, || self.exists_many(keys.clone()))
+            .await
+    }
+
+    /// Sets a value like [`Client::set`], retrying like [`Client::get_retry`]
+    /// only if `policy` opts into it with [`RetryPolicy::retry_sets`].
+    ///
+    /// Sets are not idempotent: a set that was applied but whose response was lost
+    /// is refused with [`StatusCode::KeyExists`] when retried.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use cached::{Client, RetryPolicy};
+    /// # use cached::Server;
+    /// # use cached::Error;
+    ///
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Error> {
+    /// # let server = Server::new().bind("127.0.0.1:0").await.unwrap();
+    /// # let port = server.port();
+    /// # tokio::spawn(async { server.run().await;});
+    /// let client = Client::new(format!("127.0.0.1:{port}")).await;
+    /// let policy = RetryPolicy::default().retry_sets(true);
+    /// client.set_retry("foo", "bar", None, policy).await?;
+    /// assert_eq!(client.get("foo").await?.value(), Some(b"bar".as_slice()));
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[cfg_attr(feature = "tracing", instrument(skip(self)))]
+    pub async fn set_retry<S>(
+        &self,
+        key: S,
+        value: S,
+        ttl_since_unix_epoch_in_millis: Option<u128>,
+        policy: RetryPolicy,
+    ) -> Result<StatusCode>
+    where
+        S: Into<String>,
+        S: Debug,
+    {
+        let policy = if policy.retries_sets() {
+            policy
+        } else {
+            RetryPolicy::new(0)
+        };
+        let (key, value) = (key.into(), value.into());
+        self.retrying(policy, || {
+            self.set(key.clone(), value.clone(), ttl_since_unix_epoch_in_millis)
+        })
+        .await
+    }
+
+    /// Runs `attempt` until it succeeds, fails for another reason than the connection,
+    /// or `policy` gives up, reconnecting the closed connections before every retry.
+    async fn retrying<T, F, Fut>(&self, policy: RetryPolicy, mut attempt: F) -> Result<T>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T>>,
+    {
+        let mut retry = 0;
+        loop {
+            match attempt().await {
+                Err(e) if e.is_connection_error() && retry < policy.max_retries() => {
+                    sleep(policy.backoff_before(retry)).await;
+                    retry += 1;
+                    // Open connections are left alone, so this reconnects the one that failed.
+                    // Failing to reconnect fails the next attempt, which counts as a retry
+                    for conn in self.connections() {
+                        let _ = conn.reconnect().await;
+                    }
+                }
+                result => return result,
+            }
+        }
+    }
+
+    pub(crate) async fn handle_request(&self, request: Request) -> Result<Response> {
+        let submitted = self.submit_request(request).await?;
+        self.await_response(submitted).await
+    }
+
+    /// Returns the connection to send a request for `key` over, or for no key at all.
+    fn connection_for(&self, key: Option<&Key>) -> ClientConnection {
+        match (&self.balancer, key) {
+            (Some(balancer), Some(key)) => balancer.for_key(key.as_bytes()),
+            (Some(balancer), None) => balancer.next(),
+            (None, _) => self.conn.clone(),
+        }
+    }
+
+    /// Returns all connections the client currently sends requests over.
+    fn connections(&self) -> Vec<ClientConnection> {
+        match &self.balancer {
+            Some(balancer) => balancer.connections(),
+            None => vec![self.conn.clone()],
+        }
+    }
+
+    /// Hands the request over to the connection without waiting for the response.
+    async fn submit_request(&self, request: Request) -> Result<Submitted> {
+        let conn = self.connection_for(request.keys().first());
+        self.submit_request_on(conn, request).await
+    }
+
+    /// Hands the request over to `conn` like [`Client::submit_request`].
+    async fn submit_request_on(
+        &self,
+        conn: ClientConnection,
+        request: Request,
+    ) -> Result<Submitted> {
+        let (tx, rx) = oneshot::channel();
+        conn.task()
+            .sender
+            .send(Command::Request(RequestResponder {
+                request,
+                responder: tx,
+            }))
+            .await
+            .map_err(|_| conn.connection_error(ConnectionError::Send))?;
+        Ok(Submitted { conn, receiver: rx })
+    }
+
+    async fn await_response(&self, submitted: Submitted) -> Result<Response> {
+        // A responder is only dropped unanswered if its request was never written,
+        // the requests in flight when the connection closes are answered with an error
+        submitted
+            .receiver
+            .await
+            .map_err(|_| submitted.conn.connection_error(ConnectionError::Receive))?
+    }
+}
+
+/// An operation of a [`Client::batch_transaction`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BatchOp {
+    /// Sets a key that does not exist yet, like [`Client::set`].
+    Set {
+        key: String,
+        value: String,
+        ttl_since_unix_epoch_in_millis: Option<u128>,
+    },
+    /// Deletes a key that exists, like [`Client::delete`].
+    Delete { key: String },
+}
+
+impl BatchOp {
+    pub fn set<S: Into<String>>(
+        key: S,
+        value: S,
+        ttl_since_unix_epoch_in_millis: Option<u128>,
+    ) -> Self {
+        Self::Set {
+            key: key.into(),
+            value: value.into(),
+            ttl_since_unix_epoch_in_millis,
+        }
+    }
+
+    pub fn delete<S: Into<String>>(key: S) -> Self {
+        Self::Delete { key: key.into() }
+    }
+
+    /// Checks that a server with `capabilities` would accept the operation, without sending anything.
+    ///
+    /// Fails with [`StatusCode::OperationNotPermitted`] if the server does not carry out the operation,
+    /// and like the server would if the key is empty or the key or value is too long.
+    /// Keys the server might refuse by its [key validator](crate::Server::key_validator)
+    /// cannot be told apart locally and pass.
+    pub fn validate(&self, capabilities: &ServerCapabilities) -> Result<()> {
+        let op_code = match self {
+            Self::Set { .. } => OpCode::Set,
+            Self::Delete { .. } => OpCode::Delete,
+        };
+        if !capabilities.allows(op_code) {
+            return Err(Error::new_client(ClientError::Status(
+                StatusCode::OperationNotPermitted,
+            )));
+        }
+        self.check_lengths(
+            capabilities.max_key_length(),
+            capabilities.max_value_length(),
+        )
+    }
+
+    fn check_lengths(&self, max_key_length: usize, max_value_length: usize) -> Result<()> {
+        let (key, value) = match self {
+            Self::Set { key, value, .. } => (key, Some(value)),
+            Self::Delete { key } => (key, None),
+        };
+        if key.is_empty() {
+            return Err(Error::new_parse(ParseError::KeyEmpty));
+        }
+        if key.len() > max_key_length {
+            return Err(Error::new_parse(ParseError::KeyTooLong));
+        }
+        if value.is_some_and(|value| value.len() > max_value_length) {
+            return Err(Error::new_parse(ParseError::ValueTooLong));
+        }
+        Ok(())
+    }
+}
+
+/// Reverts a [`BatchOp`] that was applied.
+#[derive(Debug)]
+enum Undo {
+    Delete(String),
+    Restore {
+        key: String,
+        value: Vec<u8>,
+        ttl_since_unix_epoch_in_millis: Option<u128>,
+        flags: u32,
+    },
+}
+
+/// A [`Client`] that sets values with a default TTL, created by [`Client::with_default_ttl`].
+///
+/// Every method setting values applies the default TTL unless a TTL is given explicitly.
+/// The other methods of [`Client`] are available through [`ClientWithDefaultTtl::client`].
+#[derive(Debug, Clone)]
+pub struct ClientWithDefaultTtl {
+    client: Client,
+    default_ttl: Duration,
+}
+
+impl ClientWithDefaultTtl {
+    /// Returns the client the view was created from, which does not apply the default TTL.
+    pub fn client(&self) -> &Client {
+        &self.client
+    }
+
+    /// Sets a value like [`Client::set`], expiring it after the default TTL
+    /// unless `ttl_since_unix_epoch_in_millis` is given.
+    ///
+    /// The expiry time is computed from the default TTL when calling this.
+    /// Fails without sending anything if the default TTL reaches beyond what [`SystemTime`]
+    /// can represent.
+    #[cfg_attr(feature = "tracing", instrument(skip(self)))]
+    pub async fn set<S, V>(
+        &self,
+        key: S,
+        value: V,
+        ttl_since_unix_epoch_in_millis: Option<u128>,
+    ) -> Result<StatusCode>
+    where
+        S: Into<String>,
+        S: Debug,
+        V: Into<Vec<u8>>,
+        V: Debug,
+    {
+        let ttl = self.ttl_or_default(ttl_since_unix_epoch_in_millis)?;
+        self.client.set(key, value, Some(ttl)).await
+    }
+
+    /// Sets a value like [`Client::set_with_flags`], applying the default TTL like
+    /// [`ClientWithDefaultTtl::set`].
+    #[cfg_attr(feature = "tracing", instrument(skip(self)))]
+    pub async fn set_with_flags<S, V>(
+        &self,
+        key: S,
+        value: V,
+        ttl_since_unix_epoch_in_millis: Option<u128>,
+        flags: u32,
+    ) -> Result<StatusCode>
+    where
+        S: Into<String>,
+        S: Debug,
+        V: Into<Vec<u8>>,
+        V: Debug,
+    {
+        let ttl = self.ttl_or_default(ttl_since_unix_epoch_in_millis)?;
+        self.client
+            .set_with_flags(key, value, Some(ttl), flags)
+            .await
+    }
+
+    /// Sets a value like [`Client::set_from_reader`], applying the default TTL like
+    /// [`ClientWithDefaultTtl::set`].
+    #[cfg_attr(feature = "tracing", instrument(skip(self, reader)))]
+    pub async fn set_from_reader<S, R>(
+        &self,
+        key: S,
+        reader: R,
+        ttl_since_unix_epoch_in_millis: Option<u128>,
+    ) -> Result<StatusCode>
+    where
+        S: Into<String>,
+        S: Debug,
+        R: AsyncRead + Unpin,
+    {
+        let ttl = self.ttl_or_default(ttl_since_unix_epoch_in_millis)?;
+        self.client.set_from_reader(key, reader, Some(ttl)).await
+    }
+
+    /// Sets a value like [`Client::set_response`], applying the default TTL like
+    /// [`ClientWithDefaultTtl::set`].
+    #[cfg_attr(feature = "tracing", instrument(skip(self)))]
+    pub async fn set_response<S, V>(
+        &self,
+        key: S,
+        value: V,
+        ttl_since_unix_epoch_in_millis: Option<u128>,
+    ) -> Result<ResponseStatus>
+    where
+        S: Into<String>,
+        S: Debug,
+        V: Into<Vec<u8>>,
+        V: Debug,
+    {
+        let ttl = self.ttl_or_default(ttl_since_unix_epoch_in_millis)?;
+        self.client.set_response(key, value, Some(ttl)).await
+    }
+
+    /// Sets a value like [`Client::set_until`], the explicit expiry time replaces the default TTL.
+    #[cfg_attr(feature = "tracing", instrument(skip(self)))]
+    pub async fn set_until<S, V>(
+        &self,
+        key: S,
+        value: V,
+        expires_at: SystemTime,
+    ) -> Result<StatusCode>
+    where
+        S: Into<String>,
+        S: Debug,
+        V: Into<Vec<u8>>,
+        V: Debug,
+    {
+        self.client.set_until(key, value, expires_at).await
+    }
+
+    /// Sets a value like [`ClientWithDefaultTtl::set`], but fails unless the server answers
+    /// [`StatusCode::Ok`], see [`Client::set_checked`].
+    #[cfg_attr(feature = "tracing", instrument(skip(self)))]
+    pub async fn set_checked<S, V>(
+        &self,
+        key: S,
+        value: V,
+        ttl_since_unix_epoch_in_millis: Option<u128>,
+    ) -> Result<()>
+    where
+        S: Into<String>,
+        S: Debug,
+        V: Into<Vec<u8>>,
+        V: Debug,
+    {
+        ensure_ok(self.set(key, value, ttl_since_unix_epoch_in_millis).await?)
+    }
+
+    /// Sets several values like [`Client::set_many_pipelined`], applying the default TTL like
+    /// [`ClientWithDefaultTtl::set`].
+    ///
+    /// If the default TTL can't be applied, every entry fails without anything being sent.
+    #[cfg_attr(feature = "tracing", instrument(skip(self, entries)))]
+    pub async fn set_many_pipelined<I, S>(
+        &self,
+        entries: I,
+        ttl_since_unix_epoch_in_millis: Option<u128>,
+    ) -> Vec<(String, Result<StatusCode>)>
+    where
+        I: IntoIterator<Item = (S, S)>,
+        S: Into<String>,
+    {
+        match self.ttl_or_default(ttl_since_unix_epoch_in_millis) {
+            Ok(ttl) => self.client.set_many_pipelined(entries, Some(ttl)).await,
+            Err(_) => entries
+                .into_iter()
+                .map(|(key, _)| {
+                    (
+                        key.into(),
+                        Err(Error::new_client(ClientError::TtlOutOfRange)),
+                    )
+                })
+                .collect(),
+        }
+    }
+
+    /// Sets several values like [`Client::set_many_ttl`], applying the default TTL like
+    /// [`ClientWithDefaultTtl::set`].
+    #[cfg_attr(feature = "tracing", instrument(skip(self, entries)))]
+    pub async fn set_many_ttl<I, S>(
+        &self,
+        entries: I,
+        ttl_since_unix_epoch_in_millis: Option<u128>,
+    ) -> Result<Vec<StatusCode>>
+    where
+        I: IntoIterator<Item = (S, S)>,
+        S: Into<String>,
+    {
+        let ttl = self.ttl_or_default(ttl_since_unix_epoch_in_millis)?;
+        self.client.set_many_ttl(entries, Some(ttl)).await
+    }
+
+    /// Applies `ops` like [`Client::batch_transaction`], setting keys without a TTL with the
+    /// default TTL like [`ClientWithDefaultTtl::set`].
+    #[cfg_attr(feature = "tracing", instrument(skip(self, ops)))]
+    pub async fn batch_transaction<I>(&self, ops: I) -> Result<()>
+    where
+        I: IntoIterator<Item = BatchOp>,
+    {
+        let ops = ops
+            .into_iter()
+            .map(|op| match op {
+                BatchOp::Set {
+                    key,
+                    value,
+                    ttl_since_unix_epoch_in_millis,
+                } => Ok(BatchOp::Set {
+                    key,
+                    value,
+                    ttl_since_unix_epoch_in_millis: Some(
+                        self.ttl_or_default(ttl_since_unix_epoch_in_millis)?,
+                    ),
+                }),
+                op => Ok(op),
+            })
+            .collect::<Result<Vec<_>>>()?;
+        self.client.batch_transaction(ops).await
+    }
+
+    /// Sets a value like [`Client::set_retry`], applying the default TTL like
+    /// [`ClientWithDefaultTtl::set`].
+    ///
+    /// The expiry time is computed once, retries don't extend it.
+    #[cfg_attr(feature = "tracing", instrument(skip(self)))]
+    pub async fn set_retry<S>(
+        &self,
+        key: S,
+        value: S,
+        ttl_since_unix_epoch_in_millis: Option<u128>,
+        policy: RetryPolicy,
+    ) -> Result<StatusCode>
+    where
+        S: Into<String>,
+        S: Debug,
+    {
+        let ttl = self.ttl_or_default(ttl_since_unix_epoch_in_millis)?;
+        self.client.set_retry(key, value, Some(ttl), policy).await
+    }
+
+    /// Returns the TTL if given, otherwise when the default TTL starting now ends.
+    fn ttl_or_default(&self, ttl_since_unix_epoch_in_millis: Option<u128>) -> Result<u128> {
+        match ttl_since_unix_epoch_in_millis {
+            Some(ttl) => Ok(ttl),
+            None => expiry_after(self.default_ttl),
+        }
+    }
+}
+
+/// Returns when a TTL of `ttl` starting now ends, in milliseconds since the unix epoch.
+fn expiry_after(ttl: Duration) -> Result<u128> {
+    let expires_at = SystemTime::now()
+        .checked_add(ttl)
+        .ok_or_else(|| Error::new_client(ClientError::TtlOutOfRange))?;
+    Ok(expires_at
+        .duration_since(UNIX_EPOCH)
+        .expect("Time went backwards")
+        .as_millis())
+}
+
+fn ensure_ok(status: StatusCode) -> Result<()> {
+    match status {
+        StatusCode::Ok => Ok(()),
+        status => Err(Error::new_client(ClientError::Status(status))),
+    }
+}
+
+/// Counters are carried as their decimal string.
+fn parse_counter(value: &Value) -> Result<i64> {
+    std::str::from_utf8(value.as_bytes())
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .ok_or_else(|| Error::new_client(ClientError::UnexpectedResponse))
+}
+
+/// Set operations answer `Ok` for `true` and `false_status` for `false`.
+fn into_membership(response: Response, false_status: StatusCode) -> Result<bool> {
+    match response.status {
+        StatusCode::Ok => Ok(true),
+        status if status == false_status => Ok(false),
+        status => Err(Error::new_client(ClientError::Status(status))),
+    }
+}
+
+/// Copies the value of a GET response into `buf`, only allocating if `buf` is too small.
+fn copy_value_into(response: Response, buf: &mut Vec<u8>) -> Result<StatusCode> {
+    buf.clear();
+    match response.body {
+        ResponseBody::Get(maybe_value) => {
+            if let Some(value) = maybe_value {
+                buf.extend_from_slice(value.value.as_bytes());
+            }
+            Ok(response.status)
+        }
+        _ => Err(Error::new_client(ClientError::ExpectedValue)),
+    }
+}
+
+/// Connects to the server at `peer_addr` and subscribes to its evictions over the new connection.
+async fn subscribe_to_evictions(peer_addr: SocketAddr) -> Result<Connection> {
+    let stream = TcpStream::connect(peer_addr)
+        .await
+        .map_err(|e| Error::new_connection(ConnectionError::Io(e)))?;
+    let mut conn = Connection::new(stream);
+    conn.write_request(0, Request::SubscribeEvictions).await?;
+    match conn.read_response().await? {
+        Some((_, response)) => match (response.status, response.body) {
+            (StatusCode::Ok, ResponseBody::Evictions(_)) => Ok(conn),
+            (StatusCode::Ok, _) => Err(Error::new_client(ClientError::UnexpectedResponse)),
+            (status, _) => Err(Error::new_client(ClientError::Status(status))),
+        },
+        None => Err(Error::new_connection(ConnectionError::ReadResponse)),
+    }
+}
+
+/// Hands the batches arriving over `conn` to `tx`, subscribing again whenever the connection fails,
+/// until the [`EvictionStream`] receiving them is dropped.
+async fn forward_evictions(
+    mut conn: Connection,
+    peer_addr: SocketAddr,
+    tx: mpsc::Sender<EvictionBatch>,
+) {
+    loop {
+        let response = tokio::select! {
+            response = conn.read_response() => response,
+            _ = tx.closed() => return,
+        };
+        if let Ok(Some((_, response))) = response {
+            if let ResponseBody::Evictions(keys) = response.body {
+                let batch = EvictionBatch {
+                    keys: keys.into_iter().map(Key::into_inner).collect(),
+                    reason: EvictionReason::Expired,
+                };
+                if tx.send(batch).await.is_err() {
+                    return;
+                }
+            }
+            continue;
+        }
+        let mut delay = MIN_RESUBSCRIBE_DELAY;
+        conn = loop {
+            tokio::select! {
+                _ = sleep(delay) => {}
+                _ = tx.closed() => return,
+            }
+            match subscribe_to_evictions(peer_addr).await {
+                Ok(conn) => break conn,
+                Err(_) => delay = (delay * 2).min(MAX_RESUBSCRIBE_DELAY),
+            }
+        };
+    }
+}
+
+fn into_response_get(response: Response) -> Result<ResponseGet> {
+    if let ResponseBody::Get(maybe_value) = response.body {
+        let (value, ttl, flags, updated_at_in_millis) = match maybe_value {
+            Some(value) => (
+                Some(value.value.into_bytes()),
+                TtlState::from(value.ttl_since_unix_epoch_in_millis),
+                value.flags,
+                Some(value.updated_at_in_millis),
+            ),
+            None => (None, TtlState::Unknown, 0, None),
+        };
+        Ok(ResponseGet::new(response.status, value, ttl)
+            .with_flags(flags)
+            .with_updated_at(updated_at_in_millis))
+    } else {
+        Err(Error::new_client(ClientError::ExpectedValue))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::response::ResponseBodyGet;
+    use tokio::net::TcpListener;
+
+    #[tokio::test]
+    async fn test_pipelined_responses_are_matched_to_their_requests_by_id() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let keys: Vec<String> = (0..100).map(|i| format!("key-{i}")).collect();
+        let expected_requests = keys.len();
+        tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let mut conn = Connection::new(stream);
+            let mut requests = vec![];
+            while requests.len() < expected_requests {
+                requests.push(conn.read_request().await.unwrap().unwrap());
+            }
+            // Answer in the reverse order of how the requests came in
+            for (request_id, request) in requests.into_iter().rev() {
+                let Request::Get(key) = request else {
+                    panic!("Expected a GET request");
+                };
+                let value = Value::parse(format!("value of {key}")).unwrap();
+                let response = Response::new(
+                    StatusCode::Ok,
+                    ResponseBody::Get(Some(ResponseBodyGet {
+                        key,
+                        value,
+                        ttl_since_unix_epoch_in_millis: None,
+                        flags: 0,
+                        updated_at_in_millis: 0,
+                    })),
+                );
+                conn.write_response(request_id, response).await.unwrap();
+            }
+        });
+        let client = Client::new(address).await;
+
+        let responses = client.pipeline_get(keys.clone()).await.unwrap();
+
+        assert_eq!(responses.len(), keys.len());
+        for (key, (response_key, response)) in keys.iter().zip(responses) {
+            assert_eq!(key, &response_key);
+            assert_eq!(response.status(), StatusCode::Ok);
+            assert_eq!(response.value(), Some(format!("value of {key}").as_bytes()));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_checked_calls_fail_when_the_server_reports_an_internal_error() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let mut conn = Connection::new(stream);
+            while let Some((request_id, request)) = conn.read_request().await.unwrap() {
+                let body = match request {
+                    Request::Set { .. } => ResponseBody::Set,
+                    Request::Delete(_) => ResponseBody::Delete,
+                    Request::Flush { confirmed: true } => ResponseBody::Flush,
+                    _ => panic!("Unexpected request"),
+                };
+                let response = Response::new(StatusCode::InternalError, body);
+                conn.write_response(request_id, response).await.unwrap();
+            }
+        });
+        let client = Client::new(address).await;
+
+        // The unchecked calls report the status as success
+        assert_eq!(
+            client.set("ABC", "1234", None).await.unwrap(),
+            StatusCode::InternalError
+        );
+        let error = client.set_checked("ABC", "1234", None).await.unwrap_err();
+        assert_eq!(error.status(), Some(StatusCode::InternalError));
+        let error = client.delete_checked("ABC").await.unwrap_err();
+        assert_eq!(error.status(), Some(StatusCode::InternalError));
+        let error = client.flush_checked().await.unwrap_err();
+        assert_eq!(error.status(), Some(StatusCode::InternalError));
+    }
+
+    #[tokio::test]
+    async fn test_clients_sharing_a_connection_get_their_own_responses() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let mut conn = Connection::new(stream);
+            let mut requests = vec![];
+            while requests.len() < 4 {
+                requests.push(conn.read_request().await.unwrap().unwrap());
+            }
+            // Answer in the reverse order of how the requests came in
+            for (request_id, request) in requests.into_iter().rev() {
+                let Request::Get(key) = request else {
+                    panic!("Expected a GET request");
+                };
+                let response = Response::new(
+                    StatusCode::Ok,
+                    ResponseBody::Get(Some(ResponseBodyGet {
+                        value: Value::parse(format!("value of {key}")).unwrap(),
+                        key,
+                        ttl_since_unix_epoch_in_millis: None,
+                        flags: 0,
+                        updated_at_in_millis: 0,
+                    })),
+                );
+                conn.write_response(request_id, response).await.unwrap();
+            }
+        });
+        let conn = ClientConnection::new(address).await;
+        let client_1 = Client::with_connection(&conn);
+        let client_2 = Client::with_connection(&conn);
+
+        let (resp_1a, resp_2a, resp_1b, resp_2b) = tokio::join!(
+            client_1.get("1a"),
+            client_2.get("2a"),
+            client_1.get("1b"),
+            client_2.get("2b"),
+        );
+
+        assert_eq!(resp_1a.unwrap().value(), Some(b"value of 1a".as_slice()));
+        assert_eq!(resp_2a.unwrap().value(), Some(b"value of 2a".as_slice()));
+        assert_eq!(resp_1b.unwrap().value(), Some(b"value of 1b".as_slice()));
+        assert_eq!(resp_2b.unwrap().value(), Some(b"value of 2b".as_slice()));
+    }
+
+    #[tokio::test]
+    async fn test_responses_to_unknown_requests_close_the_connection() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let mut conn = Connection::new(stream);
+            let (request_id, _) = conn.read_request().await.unwrap().unwrap();
+            conn.write_response(request_id.wrapping_add(1), get_response("value"))
+                .await
+                .unwrap();
+            // Keep the stream open, the client has to close the connection on its own
+            let _ = conn.read_request().await;
+        });
+        let conn = ClientConnection::new(address).await;
+        let client_1 = Client::with_connection(&conn);
+        let client_2 = Client::with_connection(&conn);
+
+        let error = client_1.get("ABC").await.unwrap_err();
+        assert!(error.is_closed_while_awaiting());
+        assert!(error.to_string().contains("unknown request"), "{error}");
+        assert!(client_2.get("ABC").await.is_err());
+    }
+
+    fn get_response(value: &str) -> Response {
+        Response::new(
+            StatusCode::Ok,
+            ResponseBody::Get(Some(ResponseBodyGet {
+                key: Key::parse("ABC".to_string()).unwrap(),
+                value: Value::parse(value.to_string()).unwrap(),
+                ttl_since_unix_epoch_in_millis: None,
+                flags: 0,
+                updated_at_in_millis: 0,
+            })),
+        )
+    }
+
+    #[test]
+    #[ignore]
+    fn test_copying_values_into_a_warmed_buffer_does_not_allocate() {
+        let mut buf = Vec::new();
+        copy_value_into(get_response("a longer value"), &mut buf).unwrap();
+        let response = get_response("value");
+
+        let _profiler = dhat::Profiler::builder().testing().build();
+        let status = copy_value_into(response, &mut buf).unwrap();
+        let stats = dhat::HeapStats::get();
+        dhat::assert_eq!(stats.total_blocks, 0);
+
+        assert_eq!(status, StatusCode::Ok);
+        assert_eq!(buf, b"value");
     }
 }
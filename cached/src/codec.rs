@@ -0,0 +1,14 @@
+use std::fmt::Debug;
+
+/// Transforms values on their way into and out of the server's database,
+/// e.g. to keep them encrypted in memory, see [`Server::value_codec`](crate::Server::value_codec).
+///
+/// Only plain values are transformed, items of lists and members of sets are stored as they are.
+/// Values may be any bytes. Clients never see the stored form, they set and get values as usual.
+pub trait ValueCodec: Debug + Send + Sync {
+    /// Transforms `value` into the form it is stored in.
+    fn encode(&self, value: Vec<u8>) -> Vec<u8>;
+
+    /// Reverts [`ValueCodec::encode`], returning `None` if `stored` can't be decoded.
+    fn decode(&self, stored: Vec<u8>) -> Option<Vec<u8>>;
+}
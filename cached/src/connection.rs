@@ -1,51 +1,196 @@
-use crate::error::{ConnectionError, Error, Result};
+use crate::error::{ConnectionError, Error, FrameError, Result};
 use crate::frame::{RequestFrame, ResponseFrame};
+use crate::metrics::Metrics;
 use crate::parsing::{parse_request_frame, parse_response_frame};
+use crate::primitives::OpCode;
+use crate::protocol::{HEADER_SIZE, OP_CODE_OFFSET, REQUEST_ID_OFFSET, TOTAL_FRAME_LENGTH_OFFSET};
 use crate::request::Request;
 use crate::response::Response;
-use bytes::{Buf, BytesMut};
+use bytes::{Buf, Bytes, BytesMut};
 use nom::AsBytes;
 use std::fmt::Debug;
+use std::io;
+use std::sync::Arc;
+use std::time::Duration;
 use tokio::io::{AsyncReadExt, AsyncWriteExt, BufWriter};
 use tokio::net::TcpStream;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use tokio::time::{timeout_at, Instant};
 #[cfg(feature = "tracing")]
 use tracing::instrument;
 
+/// The capacity each buffer of a connection starts out with.
+const BUFFER_CAPACITY: usize = 8 * 1024;
+/// A buffer that grew beyond this for an oversized frame is shrunk back once the frame was handled,
+/// so a single large value doesn't inflate the connection's memory for good.
+const MAX_RETAINED_BUFFER_CAPACITY: usize = 4 * BUFFER_CAPACITY;
+
 #[derive(Debug)]
 pub(crate) struct Connection {
     stream: BufWriter<TcpStream>,
     buffer: BytesMut,
+    write_buffer: BytesMut,
+    // Bytes transferred since the last call to `take_transferred`
+    bytes_read: u64,
+    bytes_written: u64,
+    write_timeout: Option<Duration>,
+    frame_timeout: Option<Duration>,
+    // When the rest of the frame being received must have arrived, kept across cancelled reads
+    frame_deadline: Option<Instant>,
+    inflight_limit: Option<InflightLimit>,
+    // Held for the frame being read, handled or written, released before reading the next one
+    inflight: Option<OwnedSemaphorePermit>,
+    inflight_bytes: u32,
+}
+
+/// The bytes the connections of a server may hold at once, shared between them.
+///
+/// A connection holds the size of the request it is receiving until it answered it,
+/// and then the size of the response until it was written.
+#[derive(Debug, Clone)]
+pub(crate) struct InflightLimit {
+    permits: Arc<Semaphore>,
+    max_bytes: u32,
+    metrics: Arc<Metrics>,
+}
+
+impl InflightLimit {
+    /// A single frame larger than `max_bytes` holds all of them, so it is still let through eventually.
+    pub(crate) fn new(max_bytes: u64, metrics: Arc<Metrics>) -> Self {
+        let max_bytes = max_bytes
+            .clamp(1, u64::from(u32::MAX))
+            .min(Semaphore::MAX_PERMITS as u64) as u32;
+        Self {
+            permits: Arc::new(Semaphore::new(max_bytes as usize)),
+            max_bytes,
+            metrics,
+        }
+    }
 }
 
 impl Connection {
     pub fn new(socket: TcpStream) -> Self {
         Self {
             stream: BufWriter::new(socket),
-            buffer: BytesMut::with_capacity(8 * 1024),
+            buffer: BytesMut::with_capacity(BUFFER_CAPACITY),
+            write_buffer: BytesMut::with_capacity(BUFFER_CAPACITY),
+            bytes_read: 0,
+            bytes_written: 0,
+            write_timeout: None,
+            frame_timeout: None,
+            frame_deadline: None,
+            inflight_limit: None,
+            inflight: None,
+            inflight_bytes: 0,
         }
     }
 
-    #[cfg_attr(feature = "tracing", instrument(skip(self)))]
-    pub(crate) async fn send_request(&mut self, request: Request) -> Result<Response> {
-        self.write_request(request).await?;
-        match self.read_response().await? {
-            Some(response) => Ok(response),
-            None => Err(Error::new_connection(ConnectionError::ReadResponse)),
+    /// Waits for `inflight_limit` to have room for a frame before reading it on or writing it,
+    /// see [`InflightLimit`]. Frames are not limited by default.
+    pub(crate) fn with_inflight_limit(mut self, inflight_limit: Option<InflightLimit>) -> Self {
+        self.inflight_limit = inflight_limit;
+        self
+    }
+
+    /// Holds `bytes` of the inflight limit in total, waiting for other connections to release theirs.
+    async fn hold_inflight(&mut self, bytes: u32) -> Result<()> {
+        let Some(limit) = &self.inflight_limit else {
+            return Ok(());
+        };
+        let bytes = bytes.min(limit.max_bytes);
+        if bytes <= self.inflight_bytes {
+            return Ok(());
         }
+        let permit = limit
+            .permits
+            .clone()
+            .acquire_many_owned(bytes - self.inflight_bytes)
+            .await
+            .map_err(|e| Error::new_connection(ConnectionError::Acquire(e)))?;
+        limit
+            .metrics
+            .inflight_acquired(u64::from(bytes - self.inflight_bytes));
+        self.inflight_bytes = bytes;
+        match &mut self.inflight {
+            Some(held) => held.merge(permit),
+            None => self.inflight = Some(permit),
+        }
+        Ok(())
+    }
+
+    fn release_inflight(&mut self) {
+        if let (Some(limit), Some(_)) = (&self.inflight_limit, self.inflight.take()) {
+            limit
+                .metrics
+                .inflight_released(u64::from(std::mem::take(&mut self.inflight_bytes)));
+        }
+    }
+
+    /// Fails writes that take longer than `write_timeout` with [`ConnectionError::WriteTimedOut`],
+    /// so a peer that stopped reading can't stall the writer forever. Writes never time out by default.
+    pub(crate) fn with_write_timeout(mut self, write_timeout: Option<Duration>) -> Self {
+        self.write_timeout = write_timeout;
+        self
+    }
+
+    /// Returns the bytes read and written since the last call and resets the counts.
+    /// Fails reading a request with [`FrameError::Timeout`] if the rest of it does not arrive
+    /// within `frame_timeout` of its header, so a peer that stalls mid-frame does not hold up the connection.
+    ///
+    /// The time between frames is not limited.
+    pub(crate) fn with_frame_timeout(mut self, frame_timeout: Option<Duration>) -> Self {
+        self.frame_timeout = frame_timeout;
+        self
+    }
+
+    pub(crate) fn take_transferred(&mut self) -> (u64, u64) {
+        (
+            std::mem::take(&mut self.bytes_read),
+            std::mem::take(&mut self.bytes_written),
+        )
     }
 
     #[cfg_attr(feature = "tracing", instrument(skip(self)))]
-    pub(crate) async fn read_request(&mut self) -> Result<Option<Request>> {
+    /// Reads the next request together with the id the response must be tagged with.
+    pub(crate) async fn read_request(&mut self) -> Result<Option<(u32, Request)>> {
+        // The previous request was answered
+        self.release_inflight();
         loop {
-            if let Some(request) = read_request(&mut self.buffer)? {
-                return Ok(Some(request));
+            // Taken before the frame is consumed, as consuming it hides the capacity it used
+            let oversized = self.buffer.capacity() > MAX_RETAINED_BUFFER_CAPACITY;
+            match read_request(&mut self.buffer) {
+                Ok(None) => {}
+                read => {
+                    if oversized {
+                        shrink(&mut self.buffer);
+                    }
+                    self.frame_deadline = None;
+                    return read;
+                }
             }
-            if 0 == self
-                .stream
-                .read_buf(&mut self.buffer)
-                .await
-                .map_err(|_| Error::new_connection(ConnectionError::ReadResponse))?
-            {
+            // Receiving the rest of the frame only once there is room for it
+            if let Some(total_frame_length) = total_frame_length(&self.buffer) {
+                self.hold_inflight(total_frame_length).await?;
+                if let Some(frame_timeout) = self.frame_timeout {
+                    self.frame_deadline
+                        .get_or_insert_with(|| Instant::now() + frame_timeout);
+                }
+            }
+            let read = self.stream.read_buf(&mut self.buffer);
+            let read = match self.frame_deadline {
+                None => read.await,
+                Some(deadline) => match timeout_at(deadline, read).await {
+                    Ok(read) => read,
+                    Err(_) => {
+                        // The stalled frame is given up on, a later one gets a deadline of its own
+                        self.frame_deadline = None;
+                        return Err(Error::new_frame(FrameError::Timeout));
+                    }
+                },
+            };
+            let read = read.map_err(|_| Error::new_connection(ConnectionError::ReadResponse))?;
+            self.bytes_read += read as u64;
+            if 0 == read {
                 return if self.buffer.is_empty() {
                     Ok(None)
                 } else {
@@ -56,16 +201,36 @@ impl Connection {
     }
 
     #[cfg_attr(feature = "tracing", instrument(skip(self)))]
-    pub(crate) async fn read_response(&mut self) -> Result<Option<Response>> {
+    /// Reads the next response together with the id of the request it answers.
+    ///
+    /// This is cancel safe, no data is lost if the future is dropped while waiting for data.
+    pub(crate) async fn read_response(&mut self) -> Result<Option<(u32, Response)>> {
+        match self.read_response_frame().await? {
+            Some((request_id, frame)) => {
+                decode_response(&frame).map(|response| Some((request_id, response)))
+            }
+            None => Ok(None),
+        }
+    }
+
+    #[cfg_attr(feature = "tracing", instrument(skip(self)))]
+    /// Reads the next response frame as it was sent, together with the id of the request it answers.
+    ///
+    /// The frame is not decoded, see [`decode_response`]. This is cancel safe like [`Self::read_response`].
+    pub(crate) async fn read_response_frame(&mut self) -> Result<Option<(u32, Bytes)>> {
         loop {
-            if let Some(response) = read_response(&mut self.buffer)? {
-                return Ok(Some(response));
+            let oversized = self.buffer.capacity() > MAX_RETAINED_BUFFER_CAPACITY;
+            if let Some(frame) = split_frame(&mut self.buffer)? {
+                if oversized {
+                    shrink(&mut self.buffer);
+                }
+                return Ok(Some((request_id(&frame), frame)));
             }
             if 0 == self
                 .stream
                 .read_buf(&mut self.buffer)
                 .await
-                .map_err(|_| Error::new_connection(ConnectionError::ReadResponse))?
+                .map_err(|e| Error::new_connection(ConnectionError::Io(e)))?
             {
                 return if self.buffer.is_empty() {
                     Ok(None)
@@ -77,132 +242,173 @@ impl Connection {
     }
 
     #[cfg_attr(feature = "tracing", instrument(skip(self)))]
-    pub(crate) async fn write_request(&mut self, request: Request) -> Result<()> {
+    pub(crate) async fn write_request(&mut self, request_id: u32, request: Request) -> Result<()> {
         // TODO do we even need a Frame?
-        let frame = RequestFrame::try_from(request)?;
-        self.stream
-            .get_ref()
-            .writable()
-            .await
-            .map_err(|_| Error::new_connection(ConnectionError::Write))?;
-        self.stream
-            .write_u8(frame.header.op_code as u8)
-            .await
-            .map_err(|_| Error::new_connection(ConnectionError::Write))?;
-        // Padding byte
-        self.stream
-            .write_u8(0)
-            .await
-            .map_err(|_| Error::new_connection(ConnectionError::Write))?;
-        self.stream
-            .write_u8(frame.header.key_length)
-            .await
-            .map_err(|_| Error::new_connection(ConnectionError::Write))?;
-        self.stream
-            .write_u128(frame.header.ttl_since_unix_epoch_in_millis.into_inner())
-            .await
-            .map_err(|_| Error::new_connection(ConnectionError::Write))?;
-        self.stream
-            .write_u32(frame.header.total_frame_length)
-            .await
-            .map_err(|_| Error::new_connection(ConnectionError::Write))?;
-        if let Some(key) = frame.key {
-            self.stream
-                .write_all(key.as_bytes())
-                .await
-                .map_err(|_| Error::new_connection(ConnectionError::Write))?;
-        }
-        if let Some(value) = frame.value {
-            self.stream
-                .write_all(value.as_bytes())
-                .await
-                .map_err(|_| Error::new_connection(ConnectionError::Write))?;
-        }
-        self.stream
-            .flush()
-            .await
-            .map_err(|_| Error::new_connection(ConnectionError::Write))?;
-        Ok(())
+        let frame = RequestFrame::try_from(request)?.with_request_id(request_id);
+        self.write_buffer.clear();
+        frame.encode(&mut self.write_buffer);
+        self.write_frame().await
     }
 
     #[cfg_attr(feature = "tracing", instrument(skip(self)))]
-    pub(crate) async fn write_response(&mut self, response: Response) -> Result<()> {
+    /// Writes a request frame that was encoded elsewhere, tagged with `request_id` instead of the id it carries.
+    pub(crate) async fn write_request_frame(
+        &mut self,
+        request_id: u32,
+        frame: &[u8],
+    ) -> Result<()> {
+        self.write_buffer.clear();
+        self.write_buffer.extend_from_slice(frame);
+        set_request_id(&mut self.write_buffer, request_id);
+        self.write_frame().await
+    }
+
+    #[cfg_attr(feature = "tracing", instrument(skip(self)))]
+    pub(crate) async fn write_response(
+        &mut self,
+        request_id: u32,
+        response: Response,
+    ) -> Result<()> {
         // TODO do we even need a Frame?
-        let frame = ResponseFrame::try_from(response)?;
-        // TODO error conversion
-        self.stream
-            .get_ref()
-            .writable()
-            .await
-            .map_err(|_| Error::new_connection(ConnectionError::Write))?;
-        // TODO re-implement this elsewhere, the order etc is very specific to frame and should live there probably
-        self.stream
-            .write_u8(frame.header.op_code as u8)
-            .await
-            .map_err(|_| Error::new_connection(ConnectionError::Write))?;
-        self.stream
-            .write_u8(frame.header.status as u8)
-            .await
-            .map_err(|_| Error::new_connection(ConnectionError::Write))?;
-        self.stream
-            .write_u8(frame.header.key_length)
-            .await
-            .map_err(|_| Error::new_connection(ConnectionError::Write))?;
-        self.stream
-            .write_u128(frame.header.ttl_since_unix_epoch_in_millis.into_inner())
-            .await
-            .map_err(|_| Error::new_connection(ConnectionError::Write))?;
-        self.stream
-            .write_u32(frame.header.total_frame_length)
-            .await
-            .map_err(|_| Error::new_connection(ConnectionError::Write))?;
-        if let Some(key) = frame.key {
-            self.stream
-                .write_all(key.as_bytes())
-                .await
-                .map_err(|_| Error::new_connection(ConnectionError::Write))?;
-        }
-        if let Some(value) = frame.value {
-            self.stream
-                .write_all(value.as_bytes())
+        let frame = ResponseFrame::try_from(response)?.with_request_id(request_id);
+        // Nothing is held while waiting, so connections don't wait on each other's requests
+        self.release_inflight();
+        self.hold_inflight(frame.header.total_frame_length).await?;
+        self.write_buffer.clear();
+        frame.encode(&mut self.write_buffer);
+        self.write_frame().await
+    }
+
+    /// Writes the encoded frame in `write_buffer` to the stream.
+    ///
+    /// A frame that timed out may have been written partially, the connection is unusable afterwards.
+    async fn write_frame(&mut self) -> Result<()> {
+        let write = async {
+            self.stream.write_all(&self.write_buffer).await?;
+            self.stream.flush().await
+        };
+        let written = match self.write_timeout {
+            None => write.await,
+            Some(write_timeout) => tokio::time::timeout(write_timeout, write)
                 .await
-                .map_err(|_| Error::new_connection(ConnectionError::Write))?;
+                .map_err(|_| Error::new_connection(ConnectionError::WriteTimedOut))?,
+        };
+        written.map_err(|e| Error::new_connection(write_error(e)))?;
+        self.bytes_written += self.write_buffer.len() as u64;
+        if self.write_buffer.capacity() > MAX_RETAINED_BUFFER_CAPACITY {
+            self.write_buffer.clear();
+            shrink(&mut self.write_buffer);
         }
-        self.stream
-            .flush()
-            .await
-            .map_err(|_| Error::new_connection(ConnectionError::Write))?;
         Ok(())
     }
 }
 
-fn read_request(buffer: &mut BytesMut) -> Result<Option<Request>> {
+impl Drop for Connection {
+    fn drop(&mut self) {
+        self.release_inflight();
+    }
+}
+
+/// Moves what is left in `buffer` to a new one of the initial capacity, freeing the old one.
+fn shrink(buffer: &mut BytesMut) {
+    let mut shrunk = BytesMut::with_capacity(BUFFER_CAPACITY.max(buffer.len()));
+    shrunk.extend_from_slice(buffer);
+    *buffer = shrunk;
+}
+
+/// Returns the total length of the frame at the start of `buffer`, once its header arrived.
+pub(crate) fn total_frame_length(buffer: &[u8]) -> Option<u32> {
+    let bytes = buffer.get(TOTAL_FRAME_LENGTH_OFFSET..HEADER_SIZE as usize)?;
+    Some(u32::from_be_bytes(bytes.try_into().ok()?))
+}
+
+/// Tells a peer that went away apart from other IO errors.
+fn write_error(e: io::Error) -> ConnectionError {
+    match e.kind() {
+        io::ErrorKind::ConnectionReset
+        | io::ErrorKind::ConnectionAborted
+        | io::ErrorKind::BrokenPipe => ConnectionError::ResetByPeer,
+        _ => ConnectionError::Write(e),
+    }
+}
+
+/// Parses the request at the start of `buffer`, once all of its frame arrived.
+///
+/// A frame carrying a key that is not valid UTF-8 is skipped as a whole, failing with
+/// [`FrameError::InvalidKey`] so that the request can be answered and the connection kept.
+fn read_request(buffer: &mut BytesMut) -> Result<Option<(u32, Request)>> {
     match parse_request_frame(buffer.as_bytes()) {
         Err(e) if e.is_incomplete_frame() => Ok(None),
         Ok(request_frame) => {
             buffer.advance(request_frame.header.total_frame_length as usize);
-            Request::try_from(request_frame).map(Some)
+            let request_id = request_frame.header.request_id;
+            let op_code = request_frame.header.op_code;
+            Request::try_from(request_frame)
+                .map(|request| Some((request_id, request)))
+                .map_err(|e| reject_invalid_key(e, request_id, op_code))
         }
+        // Keys are only decoded once the whole frame arrived
+        Err(e) if e.is_key_not_utf8() => match split_frame(buffer)? {
+            Some(frame) => {
+                let op_code = OpCode::try_from(frame[OP_CODE_OFFSET])?;
+                Err(reject_invalid_key(e, request_id(&frame), op_code))
+            }
+            None => Err(e),
+        },
         Err(e) => Err(e),
     }
 }
 
-fn read_response(buffer: &mut BytesMut) -> Result<Option<Response>> {
-    match parse_response_frame(buffer.as_bytes()) {
-        Err(e) if e.is_incomplete_frame() => Ok(None),
-        Ok(response_frame) => {
-            buffer.advance(response_frame.header.total_frame_length as usize);
-            Response::try_from(response_frame).map(Some)
-        }
-        Err(e) => Err(e),
+fn reject_invalid_key(e: Error, request_id: u32, op_code: OpCode) -> Error {
+    if e.is_key_not_utf8() {
+        Error::new_frame(FrameError::InvalidKey {
+            request_id,
+            op_code,
+        })
+    } else {
+        e
     }
 }
 
+/// Splits off the frame at the start of `buffer` as it is, once all of it arrived.
+fn split_frame(buffer: &mut BytesMut) -> Result<Option<Bytes>> {
+    let Some(total_frame_length) = total_frame_length(buffer) else {
+        return Ok(None);
+    };
+    let total_frame_length = total_frame_length as usize;
+    if total_frame_length < HEADER_SIZE as usize {
+        return Err(Error::new_frame(FrameError::InvalidLength));
+    }
+    if buffer.len() < total_frame_length {
+        return Ok(None);
+    }
+    Ok(Some(buffer.split_to(total_frame_length).freeze()))
+}
+
+/// Decodes a whole response frame, as split off by [`Connection::read_response_frame`].
+pub(crate) fn decode_response(frame: &[u8]) -> Result<Response> {
+    parse_response_frame(frame).and_then(Response::try_from)
+}
+
+/// Returns the request id in the header of `frame`, which must hold at least the whole header.
+pub(crate) fn request_id(frame: &[u8]) -> u32 {
+    let bytes = &frame[REQUEST_ID_OFFSET..REQUEST_ID_OFFSET + 4];
+    u32::from_be_bytes(bytes.try_into().expect("the request id takes four bytes"))
+}
+
+/// Overwrites the request id in the header of `frame`, which must hold at least the whole header.
+pub(crate) fn set_request_id(frame: &mut [u8], request_id: u32) {
+    frame[REQUEST_ID_OFFSET..REQUEST_ID_OFFSET + 4].copy_from_slice(&request_id.to_be_bytes());
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
     use crate::domain::{Key, TTLSinceUnixEpochInMillis, Value};
-    use crate::primitives::OpCode;
+    use crate::error::ErrorInner;
+    use crate::primitives::{OpCode, StatusCode};
+    use crate::response::ResponseBody;
+    use tokio::net::TcpListener;
 
     #[global_allocator]
     static ALLOC: dhat::Alloc = dhat::Alloc;
@@ -211,7 +417,7 @@ mod test {
     #[ignore]
     fn test_parsing_request_frame_works() {
         let _profiler = dhat::Profiler::builder().testing().build();
-        let data = "\u{1}\0\u{3}\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\u{1e}ABC1234";
+        let data = "\u{1}\0\u{3}\0\0\0\0\0\0\0\u{1e}\0\0\0\0\0\0\0\0\0\0\0\0ABC1234";
         let bytes = data.as_bytes();
         // Get the baseline for setup
         let stats = dhat::HeapStats::get();
@@ -230,4 +436,124 @@ mod test {
         let expected_frame = RequestFrame::new(OpCode::Set, ttl, Some(key), Some(value));
         assert_eq!(parsed_frame, expected_frame.unwrap());
     }
+
+    /// Returns a connection to a peer that is connected, but has done nothing so far.
+    async fn connect() -> (Connection, TcpStream) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let peer = TcpStream::connect(listener.local_addr().unwrap())
+            .await
+            .unwrap();
+        let (stream, _) = listener.accept().await.unwrap();
+        (Connection::new(stream), peer)
+    }
+
+    /// Writes large responses until writing fails.
+    async fn write_until_failing(conn: &mut Connection) -> Error {
+        for request_id in 0..1000 {
+            let item = Value::parse(vec![b'a'; 512 * 1024]).unwrap();
+            let response = Response::new(StatusCode::Ok, ResponseBody::RPop(Some(item)));
+            if let Err(e) = conn.write_response(request_id, response).await {
+                return e;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(1)).await;
+        }
+        panic!("Writing never failed");
+    }
+
+    #[tokio::test]
+    async fn test_buffers_shrink_after_an_oversized_frame() {
+        let (mut conn, peer) = connect().await;
+        let mut peer = Connection::new(peer);
+        let large = Request::Set {
+            key: Key::parse("ABC".to_string()).unwrap(),
+            value: Value::parse("a".repeat(256 * 1024)).unwrap(),
+            ttl_since_unix_epoch_in_millis: None,
+            flags: 0,
+        };
+        peer.write_request(0, large).await.unwrap();
+        for request_id in 1..=3 {
+            let small = Request::Get(Key::parse("ABC".to_string()).unwrap());
+            peer.write_request(request_id, small).await.unwrap();
+        }
+
+        conn.read_request().await.unwrap().unwrap();
+        assert!(conn.buffer.capacity() <= MAX_RETAINED_BUFFER_CAPACITY);
+        for request_id in 1..=3 {
+            let (id, _) = conn.read_request().await.unwrap().unwrap();
+            assert_eq!(id, request_id);
+            assert!(conn.buffer.capacity() <= MAX_RETAINED_BUFFER_CAPACITY);
+        }
+
+        let item = Value::parse("a".repeat(256 * 1024)).unwrap();
+        let response = Response::new(StatusCode::Ok, ResponseBody::RPop(Some(item)));
+        conn.write_response(0, response).await.unwrap();
+        assert!(conn.write_buffer.capacity() <= MAX_RETAINED_BUFFER_CAPACITY);
+        let (_, response) = peer.read_response().await.unwrap().unwrap();
+        assert_eq!(response.status, StatusCode::Ok);
+        assert!(peer.buffer.capacity() <= MAX_RETAINED_BUFFER_CAPACITY);
+    }
+
+    #[tokio::test]
+    async fn test_writing_to_a_reset_connection_fails_as_reset_by_peer() {
+        let (mut conn, peer) = connect().await;
+        // Closing with a linger of zero resets the connection
+        socket2::SockRef::from(&peer)
+            .set_linger(Some(std::time::Duration::ZERO))
+            .unwrap();
+        drop(peer);
+
+        let e = write_until_failing(&mut conn).await;
+        assert!(
+            matches!(
+                e,
+                Error(ErrorInner::Connection(ConnectionError::ResetByPeer))
+            ),
+            "{e:?}"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_writing_to_a_peer_that_does_not_read_times_out() {
+        let (conn, _peer) = connect().await;
+        let mut conn = conn.with_write_timeout(Some(std::time::Duration::from_millis(50)));
+
+        let e = write_until_failing(&mut conn).await;
+        assert!(
+            matches!(
+                e,
+                Error(ErrorInner::Connection(ConnectionError::WriteTimedOut))
+            ),
+            "{e:?}"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_a_frame_stalling_after_its_header_times_out() {
+        let (conn, mut peer) = connect().await;
+        let mut conn = conn.with_frame_timeout(Some(std::time::Duration::from_millis(50)));
+        let mut header = vec![OpCode::Get as u8, 0, 3];
+        header.extend_from_slice(&0u32.to_be_bytes());
+        header.extend_from_slice(&100u32.to_be_bytes());
+        peer.write_all(&header).await.unwrap();
+
+        let e = conn.read_request().await.unwrap_err();
+        assert!(
+            matches!(e, Error(ErrorInner::Frame(FrameError::Timeout))),
+            "{e:?}"
+        );
+    }
+    #[tokio::test]
+    async fn test_a_frame_announcing_fewer_bytes_than_its_header_is_refused_right_away() {
+        let (mut conn, mut peer) = connect().await;
+        let mut header = vec![OpCode::Set as u8, 0, 3];
+        header.extend_from_slice(&0u32.to_be_bytes());
+        header.extend_from_slice(&4u32.to_be_bytes());
+        peer.write_all(&header).await.unwrap();
+
+        let e = conn.read_request().await.unwrap_err();
+        assert!(
+            matches!(e, Error(ErrorInner::Frame(FrameError::InvalidLength))),
+            "{e:?}"
+        );
+    }
 }
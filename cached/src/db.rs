@@ -1,36 +1,333 @@
+use crate::codec::ValueCodec;
+use crate::domain::MAX_VALUE_LENGTH;
+use crate::eviction::{EvictionBatch, EvictionIndex, EvictionPolicy, EvictionReason};
+use crate::glob;
+use crate::key_info::KeyInfo;
+use crate::metrics::Metrics;
+use crate::shutdown::Shutdown;
 use async_trait::async_trait;
-use std::collections::{HashMap, HashSet};
-use std::time::{SystemTime, UNIX_EPOCH};
+use bytes::Bytes;
+use std::collections::hash_map::RandomState;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::hash::BuildHasher;
+use std::num::NonZeroUsize;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::runtime::Handle;
+use tokio::sync::broadcast;
 use tokio::sync::mpsc;
 use tokio::sync::mpsc::Receiver;
 use tokio::sync::oneshot;
+use tokio::sync::RwLock;
+use tokio::task::JoinHandle;
+use tokio::time::Instant;
+#[cfg(feature = "tracing")]
+use tracing::debug;
 
+static DEFAULT_SHARD_AMOUNT: usize = 4;
+/// Values longer than this many bytes are shared with their readers unless configured otherwise.
+pub(crate) const DEFAULT_LARGE_VALUE_THRESHOLD: usize = 1024;
+
+/// One shard per available core, so shards rarely wait for each other.
+pub(crate) fn default_shard_amount() -> usize {
+    std::thread::available_parallelism().map_or(DEFAULT_SHARD_AMOUNT, NonZeroUsize::get)
+}
+
+/// Where a shard takes the current time from to tell whether keys expired.
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) enum Clock {
+    /// The system clock, which clients compute their TTLs from.
+    #[default]
+    System,
+    /// The system time when the clock was created, advanced by tokio's clock since then.
+    ///
+    /// Pausing and advancing tokio's clock, e.g. with `tokio::time::pause`, moves this clock alike.
+    Tokio {
+        created_at_millis: u128,
+        created: Instant,
+    },
+}
+
+impl Clock {
+    pub(crate) fn tokio() -> Self {
+        Self::Tokio {
+            created_at_millis: Self::System.now_in_millis(),
+            created: Instant::now(),
+        }
+    }
+
+    /// Returns the milliseconds since the unix epoch.
+    pub(crate) fn now_in_millis(&self) -> u128 {
+        match self {
+            Self::System => SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .expect("Time went backwards")
+                .as_millis(),
+            Self::Tokio {
+                created_at_millis,
+                created,
+            } => created_at_millis + created.elapsed().as_millis(),
+        }
+    }
+}
+
+/// The database, split into shards that each run on their own task.
+///
+/// Every key lives in exactly one shard, picked by its hash.
+/// A shard applies each request in full before taking the next one, so read-modify-write
+/// operations on a key, like pushing to a list, are serialized without any locks.
+///
+/// Flushing is the one operation spanning all shards. Every other request holds the flush barrier
+/// shared until its shard answered, and flushing holds it exclusively until all shards were cleared,
+/// so each request is applied either entirely before or entirely after a flush.
 #[derive(Debug, Clone)]
 pub(crate) struct Db {
-    request_sender: mpsc::Sender<DbRequestWithResponder>,
+    shards: Arc<[mpsc::Sender<DbRequestWithResponder>]>,
+    flush_barrier: Arc<RwLock<()>>,
+    hasher: RandomState,
+    // Applied by the callers rather than the shards, so encoding doesn't hold up other keys
+    value_codec: Option<Arc<dyn ValueCodec>>,
+}
+
+/// The tasks serving the shards of a [`Db`].
+///
+/// They finish once all handles to the database were dropped.
+#[derive(Debug)]
+pub(crate) struct DbTasks(Vec<JoinHandle<()>>);
+
+impl DbTasks {
+    /// Waits for all shard tasks to finish.
+    pub(crate) async fn join(self) {
+        for task in self.0 {
+            let _ = task.await;
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
 pub(crate) struct DbValue {
-    pub value: String,
+    pub value: StoredBytes,
     pub ttl_since_unix_epoch_in_millis: Option<u128>,
+    /// Whether the key is remembered as missing, the `value` is empty then.
+    pub negative: bool,
+    /// Stored with the value as they are, `0` for tombstones.
+    pub flags: u32,
+    /// When the value was last set, by the clock of the shard.
+    pub updated_at_in_millis: u128,
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub(crate) enum DbError {
+    /// The operation does not work on the kind of value stored under the key.
+    WrongType,
+    /// The operation would grow the value beyond `MAX_VALUE_LENGTH`.
+    ValueTooLarge,
+    /// The stored value could not be decoded by the [`ValueCodec`].
+    Undecodable,
+    /// The stored value is not an integer.
+    NotAnInteger,
+    /// The result would not fit into an integer.
+    OutOfRange,
+}
+
+/// A plain value as it is stored in a shard and handed out to readers, any bytes.
+///
+/// Values longer than the threshold of the shard are shared, so reading them only clones a reference.
+/// Shorter values are kept inline and copied by reads, as for them the copy costs less than sharing.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub(crate) enum StoredBytes {
+    Inline(Vec<u8>),
+    Shared(Bytes),
+}
+
+impl StoredBytes {
+    fn new(bytes: Vec<u8>, large_value_threshold: usize) -> Self {
+        if bytes.len() > large_value_threshold {
+            Self::Shared(Bytes::from(bytes))
+        } else {
+            Self::Inline(bytes)
+        }
+    }
+
+    pub(crate) fn as_bytes(&self) -> &[u8] {
+        match self {
+            Self::Inline(bytes) => bytes,
+            Self::Shared(bytes) => bytes,
+        }
+    }
+
+    /// Returns the bytes, copying shared ones.
+    pub(crate) fn into_vec(self) -> Vec<u8> {
+        match self {
+            Self::Inline(bytes) => bytes,
+            Self::Shared(bytes) => bytes.to_vec(),
+        }
+    }
+}
+
+/// Hands out the bytes without copying them.
+impl From<StoredBytes> for Bytes {
+    fn from(value: StoredBytes) -> Self {
+        match value {
+            StoredBytes::Inline(bytes) => Bytes::from(bytes),
+            StoredBytes::Shared(bytes) => bytes,
+        }
+    }
+}
+
+/// A value as it is stored in a shard.
+#[derive(Debug)]
+struct StoredValue {
+    data: Data,
+    ttl_since_unix_epoch_in_millis: Option<u128>,
+    flags: u32,
+    updated_at_in_millis: u128,
+}
+
+#[derive(Debug)]
+enum Data {
+    String(StoredBytes),
+    List(List),
+    Set(MemberSet),
+    /// A tombstone remembering that the key is missing at its origin.
+    ///
+    /// Tombstones are reported by `get` only, everything else treats the key as absent
+    /// and is free to replace the tombstone.
+    Negative,
+}
+
+/// A list of items, pushed to the front and popped from the back.
+///
+/// The items are kept as separate strings rather than encoded into a single one,
+/// so pushing and popping don't need to copy the other items.
+/// The summed up length of all items is bounded by `MAX_VALUE_LENGTH`, like any other value.
+#[derive(Debug, Default)]
+struct List {
+    items: VecDeque<String>,
+    bytes: usize,
+}
+
+/// A set of unique members.
+///
+/// The summed up length of all members is bounded by `MAX_VALUE_LENGTH`, like any other value.
+#[derive(Debug, Default)]
+struct MemberSet {
+    members: HashSet<String>,
+    bytes: usize,
+}
+
+impl MemberSet {
+    /// Returns whether the member was newly added.
+    fn add(&mut self, member: String) -> Result<bool, DbError> {
+        if self.members.contains(&member) {
+            return Ok(false);
+        }
+        if self.bytes + member.len() > MAX_VALUE_LENGTH as usize {
+            return Err(DbError::ValueTooLarge);
+        }
+        self.bytes += member.len();
+        Ok(self.members.insert(member))
+    }
+
+    /// Returns whether the member was present.
+    fn remove(&mut self, member: &str) -> bool {
+        let removed = self.members.remove(member);
+        if removed {
+            self.bytes -= member.len();
+        }
+        removed
+    }
+
+    fn is_empty(&self) -> bool {
+        self.members.is_empty()
+    }
+}
+
+impl List {
+    /// Returns the length of the list after pushing.
+    fn push_front(&mut self, item: String) -> Result<usize, DbError> {
+        if self.bytes + item.len() > MAX_VALUE_LENGTH as usize {
+            return Err(DbError::ValueTooLarge);
+        }
+        self.bytes += item.len();
+        self.items.push_front(item);
+        Ok(self.items.len())
+    }
+
+    fn pop_back(&mut self) -> Option<String> {
+        let item = self.items.pop_back()?;
+        self.bytes -= item.len();
+        Some(item)
+    }
+
+    fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
 }
 
 enum DbRequest {
     Get(String),
     Insert {
         key: String,
-        value: String,
+        value: Vec<u8>,
+        ttl: Option<u128>,
+        flags: u32,
+    },
+    InsertNegative {
+        key: String,
         ttl: Option<u128>,
     },
     Remove(String),
+    Take(String),
     ContainsKey(String),
+    PushFront {
+        key: String,
+        item: String,
+    },
+    PopBack(String),
+    SetAdd {
+        key: String,
+        member: String,
+    },
+    SetContains {
+        key: String,
+        member: String,
+    },
+    SetRemove {
+        key: String,
+        member: String,
+    },
     Clear,
+    KeysMatching(String),
+    /// Picks the live key at or after the position given by the seed.
+    RandomKey(u64),
+    Inspect(String),
+    /// Adds `delta` to the integer stored under the key, the shard decodes and encodes it with `codec`.
+    Increment {
+        key: String,
+        delta: i128,
+        codec: Option<Arc<dyn ValueCodec>>,
+    },
+    SweepExpired,
+    #[cfg(test)]
+    DebugTtlKeys,
 }
 
 enum DbResponse {
-    Get(DbValue),
+    Get(Result<DbValue, DbError>),
     ContainsKey(bool),
+    Removed(bool),
+    Take(Result<Option<StoredBytes>, DbError>),
+    PushFront(Result<usize, DbError>),
+    PopBack(Result<Option<String>, DbError>),
+    SetMembership(Result<bool, DbError>),
+    Keys(Vec<String>),
+    RandomKey(Option<String>),
+    Inspect(Option<KeyInfo>),
+    Increment(Result<DbValue, DbError>),
+    SweepExpired(Vec<String>),
+    #[cfg(test)]
+    DebugTtlKeys(Vec<String>),
 }
 
 struct DbRequestWithResponder {
@@ -38,105 +335,706 @@ struct DbRequestWithResponder {
     result_channel: oneshot::Sender<Option<DbResponse>>,
 }
 
-struct MainDB {
-    db: HashMap<String, DbValue>,
+/// A single shard of the database.
+///
+/// Shards are driven by their own task in a [`Db`], or locked one at a time by a [`Cache`](crate::Cache).
+pub(crate) struct MainDB {
+    db: HashMap<String, StoredValue>,
     keys_with_ttl: HashSet<String>,
+    capacity: Option<Capacity>,
+    max_ttl_keys: Option<usize>,
+    // How many keys with a TTL trigger the next forced sweep
+    ttl_keys_sweep_threshold: usize,
+    large_value_threshold: usize,
+    metrics: Option<Arc<Metrics>>,
+    clock: Clock,
+}
+
+/// The limit on the entries of a shard and what to evict once it is hit.
+#[derive(Debug)]
+struct Capacity {
+    max_entries: usize,
+    index: EvictionIndex,
 }
 
 impl MainDB {
-    fn new() -> Self {
+    pub(crate) fn new() -> Self {
         Self {
             db: HashMap::new(),
             keys_with_ttl: Default::default(),
+            capacity: None,
+            max_ttl_keys: None,
+            ttl_keys_sweep_threshold: usize::MAX,
+            large_value_threshold: DEFAULT_LARGE_VALUE_THRESHOLD,
+            metrics: None,
+            clock: Clock::System,
+        }
+    }
+
+    /// Shares values longer than `large_value_threshold` bytes with their readers, see [`StoredBytes`].
+    pub(crate) fn with_large_value_threshold(self, large_value_threshold: Option<usize>) -> Self {
+        Self {
+            large_value_threshold: large_value_threshold.unwrap_or(DEFAULT_LARGE_VALUE_THRESHOLD),
+            ..self
+        }
+    }
+
+    /// Records how long expired keys were kept past their expiry in `metrics`.
+    pub(crate) fn with_metrics(self, metrics: Option<Arc<Metrics>>) -> Self {
+        Self { metrics, ..self }
+    }
+
+    /// Tells whether keys expired by the time of `clock`.
+    pub(crate) fn with_clock(self, clock: Clock) -> Self {
+        Self { clock, ..self }
+    }
+
+    /// Sweeps the expired keys as soon as more than `max_ttl_keys` keys have a TTL.
+    ///
+    /// While most of them are live the sweeps are spaced out as the keys grow,
+    /// so the keys are not swept over and over again on every insert.
+    pub(crate) fn with_max_ttl_keys(self, max_ttl_keys: Option<usize>) -> Self {
+        Self {
+            max_ttl_keys,
+            ttl_keys_sweep_threshold: max_ttl_keys.unwrap_or(usize::MAX),
+            ..self
+        }
+    }
+
+    /// Creates a shard holding at most `max_entries` entries, tombstones included.
+    pub(crate) fn with_max_entries(max_entries: usize, policy: EvictionPolicy) -> Self {
+        Self {
+            capacity: Some(Capacity {
+                max_entries: max_entries.max(1),
+                index: EvictionIndex::new(policy),
+            }),
+            ..Self::new()
         }
     }
 
     fn handle_request(&mut self, request: DbRequest) -> Option<DbResponse> {
         match request {
             DbRequest::Get(key) => self.get(&key).map(DbResponse::Get),
-            DbRequest::Insert { key, value, ttl } => {
-                self.insert(key, value, ttl);
+            DbRequest::Insert {
+                key,
+                value,
+                ttl,
+                flags,
+            } => {
+                self.insert_with_flags(key, value, ttl, flags);
                 None
             }
-            DbRequest::ContainsKey(key) => {
-                Some(DbResponse::ContainsKey(self.db.contains_key(&key)))
-            }
-            DbRequest::Remove(key) => {
-                self.remove(&key);
+            DbRequest::InsertNegative { key, ttl } => {
+                self.insert_data(key, Data::Negative, ttl, 0);
                 None
             }
+            DbRequest::ContainsKey(key) => Some(DbResponse::ContainsKey(self.contains_key(&key))),
+            DbRequest::PushFront { key, item } => {
+                Some(DbResponse::PushFront(self.push_front(key, item)))
+            }
+            DbRequest::PopBack(key) => Some(DbResponse::PopBack(self.pop_back(&key))),
+            DbRequest::Take(key) => Some(DbResponse::Take(self.take(&key))),
+            DbRequest::SetAdd { key, member } => {
+                Some(DbResponse::SetMembership(self.set_add(key, member)))
+            }
+            DbRequest::SetContains { key, member } => {
+                Some(DbResponse::SetMembership(self.set_contains(&key, &member)))
+            }
+            DbRequest::SetRemove { key, member } => {
+                Some(DbResponse::SetMembership(self.set_remove(&key, &member)))
+            }
+            DbRequest::Remove(key) => Some(DbResponse::Removed(self.delete(&key))),
             DbRequest::Clear => {
                 self.clear();
                 None
             }
+            DbRequest::KeysMatching(pattern) => {
+                Some(DbResponse::Keys(self.keys_matching(&pattern)))
+            }
+            DbRequest::RandomKey(seed) => Some(DbResponse::RandomKey(self.random_key(seed))),
+            DbRequest::Inspect(key) => Some(DbResponse::Inspect(self.inspect(&key))),
+            DbRequest::Increment { key, delta, codec } => Some(DbResponse::Increment(
+                self.increment(key, delta, codec.as_deref()),
+            )),
+            DbRequest::SweepExpired => Some(DbResponse::SweepExpired(self.sweep_expired())),
+            #[cfg(test)]
+            DbRequest::DebugTtlKeys => Some(DbResponse::DebugTtlKeys(self.debug_ttl_keys())),
         }
     }
 
-    fn get(&mut self, key: &str) -> Option<DbValue> {
-        let maybe_value = self.db.get(key);
-        let maybe_ttl = maybe_value
-            .as_ref()
-            .and_then(|value| value.ttl_since_unix_epoch_in_millis);
+    pub(crate) fn get(&mut self, key: &str) -> Option<Result<DbValue, DbError>> {
+        self.live_value(key).map(|value| match &value.data {
+            Data::String(string) => Ok(DbValue {
+                value: string.clone(),
+                ttl_since_unix_epoch_in_millis: value.ttl_since_unix_epoch_in_millis,
+                negative: false,
+                flags: value.flags,
+                updated_at_in_millis: value.updated_at_in_millis,
+            }),
+            Data::Negative => Ok(DbValue {
+                value: StoredBytes::Inline(Vec::new()),
+                ttl_since_unix_epoch_in_millis: value.ttl_since_unix_epoch_in_millis,
+                negative: true,
+                flags: 0,
+                updated_at_in_millis: value.updated_at_in_millis,
+            }),
+            Data::List(_) | Data::Set(_) => Err(DbError::WrongType),
+        })
+    }
 
-        let ttl_has_expired = maybe_ttl
-            .map(|ttl| {
-                ttl < SystemTime::now()
-                    .duration_since(UNIX_EPOCH)
-                    .expect("Time went backwards")
-                    .as_millis()
-            })
-            .unwrap_or(false);
+    /// Tombstones do not count as the key being present.
+    pub(crate) fn contains_key(&mut self, key: &str) -> bool {
+        self.live_value(key)
+            .is_some_and(|value| !matches!(value.data, Data::Negative))
+    }
 
-        if ttl_has_expired {
-            self.db.remove(key);
-            self.keys_with_ttl.remove(key);
-            None
-        } else {
-            maybe_value.cloned()
+    /// Returns the value for `key` unless its TTL has expired, counting as an access to it.
+    fn live_value(&mut self, key: &str) -> Option<&StoredValue> {
+        self.remove_if_expired(key);
+        self.record_access(key);
+        self.db.get(key)
+    }
+
+    /// Returns the value for `key` unless its TTL has expired, counting as an access to it.
+    fn live_value_mut(&mut self, key: &str) -> Option<&mut StoredValue> {
+        self.remove_if_expired(key);
+        self.record_access(key);
+        self.db.get_mut(key)
+    }
+
+    fn record_access(&mut self, key: &str) {
+        if let Some(capacity) = &mut self.capacity {
+            capacity.index.accessed(key);
+        }
+    }
+
+    /// Stores `value` under `key`, evicting another entry first if the shard is full.
+    fn store(&mut self, key: String, value: StoredValue) {
+        if let Some(capacity) = &mut self.capacity {
+            if !self.db.contains_key(&key) && self.db.len() >= capacity.max_entries {
+                if let Some(victim) = capacity.index.victim() {
+                    #[cfg(feature = "tracing")]
+                    debug!("evicting {victim:?} to make room for {key:?}");
+                    self.remove(&victim);
+                }
+            }
+        }
+        if let Some(capacity) = &mut self.capacity {
+            capacity
+                .index
+                .inserted(&key, value.ttl_since_unix_epoch_in_millis);
         }
+        self.db.insert(key, value);
     }
 
+    fn remove_if_expired(&mut self, key: &str) {
+        let now = self.clock.now_in_millis();
+        let expired_at = self
+            .db
+            .get(key)
+            .and_then(|value| value.ttl_since_unix_epoch_in_millis)
+            .filter(|ttl| *ttl < now);
+        if let Some(expired_at) = expired_at {
+            self.reclaimed(expired_at, now);
+            self.remove(key);
+        }
+    }
+
+    /// Records that a key which expired at `expired_at` is removed at `now`.
+    fn reclaimed(&self, expired_at: u128, now: u128) {
+        if let Some(metrics) = &self.metrics {
+            let lag = u64::try_from(now - expired_at).unwrap_or(u64::MAX);
+            metrics.expired_key_reclaimed(Duration::from_millis(lag));
+        }
+    }
+
+    /// Pushes `item` to the front of the list under `key`, creating the list if necessary.
+    fn push_front(&mut self, key: String, item: String) -> Result<usize, DbError> {
+        if !self.contains_key(&key) {
+            self.store(
+                key.clone(),
+                StoredValue {
+                    data: Data::List(List::default()),
+                    ttl_since_unix_epoch_in_millis: None,
+                    flags: 0,
+                    updated_at_in_millis: self.clock.now_in_millis(),
+                },
+            );
+        }
+        match self.db.get_mut(&key).map(|value| &mut value.data) {
+            Some(Data::List(list)) => list.push_front(item),
+            _ => Err(DbError::WrongType),
+        }
+    }
+
+    /// Pops an item from the back of the list under `key`, the list is removed once it is empty.
+    fn pop_back(&mut self, key: &str) -> Result<Option<String>, DbError> {
+        let Some(value) = self.live_value_mut(key) else {
+            return Ok(None);
+        };
+        let list = match &mut value.data {
+            Data::List(list) => list,
+            Data::Negative => return Ok(None),
+            _ => return Err(DbError::WrongType),
+        };
+        let item = list.pop_back();
+        if list.is_empty() {
+            self.remove(key);
+        }
+        Ok(item)
+    }
+
+    /// Adds `member` to the set under `key`, creating the set if necessary.
+    ///
+    /// Returns whether the member was newly added.
+    fn set_add(&mut self, key: String, member: String) -> Result<bool, DbError> {
+        if !self.contains_key(&key) {
+            self.store(
+                key.clone(),
+                StoredValue {
+                    data: Data::Set(MemberSet::default()),
+                    ttl_since_unix_epoch_in_millis: None,
+                    flags: 0,
+                    updated_at_in_millis: self.clock.now_in_millis(),
+                },
+            );
+        }
+        match self.db.get_mut(&key).map(|value| &mut value.data) {
+            Some(Data::Set(set)) => set.add(member),
+            _ => Err(DbError::WrongType),
+        }
+    }
+
+    fn set_contains(&mut self, key: &str, member: &str) -> Result<bool, DbError> {
+        match self.live_value(key).map(|value| &value.data) {
+            None | Some(Data::Negative) => Ok(false),
+            Some(Data::Set(set)) => Ok(set.members.contains(member)),
+            Some(_) => Err(DbError::WrongType),
+        }
+    }
+
+    /// Removes `member` from the set under `key`, the set is removed once it is empty.
+    ///
+    /// Returns whether the member was present.
+    fn set_remove(&mut self, key: &str, member: &str) -> Result<bool, DbError> {
+        let Some(value) = self.live_value_mut(key) else {
+            return Ok(false);
+        };
+        let set = match &mut value.data {
+            Data::Set(set) => set,
+            Data::Negative => return Ok(false),
+            _ => return Err(DbError::WrongType),
+        };
+        let removed = set.remove(member);
+        if set.is_empty() {
+            self.remove(key);
+        }
+        Ok(removed)
+    }
+
+    /// Adds `delta` to the integer stored under `key` and returns the value as it is stored afterwards,
+    /// a missing key starts out at `0`.
+    ///
+    /// The value keeps its TTL and flags, the returned value is the plain decimal, not encoded by `codec`.
+    /// `delta` is wider than the integer, so that subtracting `i64::MIN` works if the result fits.
+    fn increment(
+        &mut self,
+        key: String,
+        delta: i128,
+        codec: Option<&dyn ValueCodec>,
+    ) -> Result<DbValue, DbError> {
+        let (current, ttl, flags) = match self.live_value(&key) {
+            None => (0, None, 0),
+            Some(value) => match &value.data {
+                Data::Negative => (0, None, 0),
+                Data::String(bytes) => {
+                    let bytes = match codec {
+                        None => bytes.as_bytes().to_vec(),
+                        Some(codec) => codec
+                            .decode(bytes.as_bytes().to_vec())
+                            .ok_or(DbError::Undecodable)?,
+                    };
+                    let current: i64 = std::str::from_utf8(&bytes)
+                        .ok()
+                        .and_then(|string| string.parse().ok())
+                        .ok_or(DbError::NotAnInteger)?;
+                    (current, value.ttl_since_unix_epoch_in_millis, value.flags)
+                }
+                Data::List(_) | Data::Set(_) => return Err(DbError::WrongType),
+            },
+        };
+        let new = i64::try_from(i128::from(current) + delta).map_err(|_| DbError::OutOfRange)?;
+        let plain = new.to_string().into_bytes();
+        let value = match codec {
+            None => plain.clone(),
+            Some(codec) => codec.encode(plain.clone()),
+        };
+        self.insert_with_flags(key.clone(), value, ttl, flags);
+        let updated_at_in_millis = self.db.get(&key).map_or_else(
+            || self.clock.now_in_millis(),
+            |value| value.updated_at_in_millis,
+        );
+        Ok(DbValue {
+            value: StoredBytes::Inline(plain),
+            ttl_since_unix_epoch_in_millis: ttl,
+            negative: false,
+            flags,
+            updated_at_in_millis,
+        })
+    }
+
+    #[cfg(test)]
     fn insert(&mut self, key: String, value: String, ttl_since_unix_epoch_in_millis: Option<u128>) {
+        self.insert_with_flags(key, value.into_bytes(), ttl_since_unix_epoch_in_millis, 0);
+    }
+
+    pub(crate) fn insert_with_flags(
+        &mut self,
+        key: String,
+        value: Vec<u8>,
+        ttl_since_unix_epoch_in_millis: Option<u128>,
+        flags: u32,
+    ) {
+        let value = StoredBytes::new(value, self.large_value_threshold);
+        self.insert_data(
+            key,
+            Data::String(value),
+            ttl_since_unix_epoch_in_millis,
+            flags,
+        );
+    }
+
+    fn insert_data(
+        &mut self,
+        key: String,
+        data: Data,
+        ttl_since_unix_epoch_in_millis: Option<u128>,
+        flags: u32,
+    ) {
+        let now = self.clock.now_in_millis();
         if let Some(ttl) = ttl_since_unix_epoch_in_millis {
-            if ttl
-                <= SystemTime::now()
-                    .duration_since(UNIX_EPOCH)
-                    .expect("Time went backwards")
-                    .as_millis()
-            {
+            if ttl <= now {
                 // TTL in the past, don't store anything
                 return;
             }
             self.keys_with_ttl.insert(key.clone());
+        } else {
+            // The key may have had a TTL before
+            self.keys_with_ttl.remove(&key);
         }
-        self.db.insert(
+        self.store(
             key,
-            DbValue {
-                value,
+            StoredValue {
+                data,
                 ttl_since_unix_epoch_in_millis,
+                flags,
+                updated_at_in_millis: now,
             },
         );
+        if self.keys_with_ttl.len() > self.ttl_keys_sweep_threshold {
+            self.sweep_expired();
+        }
+    }
+
+    /// Removes the key like [`MainDB::remove`], expired keys count as removed already.
+    pub(crate) fn delete(&mut self, key: &str) -> bool {
+        self.remove_if_expired(key);
+        self.remove(key)
     }
 
-    fn remove(&mut self, key: &str) {
-        self.db.remove(key);
+    /// Returns whether anything was stored under the key, tombstones included.
+    fn remove(&mut self, key: &str) -> bool {
         self.keys_with_ttl.remove(key);
+        if let Some(capacity) = &mut self.capacity {
+            capacity.index.removed(key);
+        }
+        self.db.remove(key).is_some()
     }
 
-    fn clear(&mut self) {
+    /// Removes the value under `key` and returns it, tombstones are removed but return nothing.
+    ///
+    /// Lists and sets are left in place.
+    fn take(&mut self, key: &str) -> Result<Option<StoredBytes>, DbError> {
+        self.remove_if_expired(key);
+        match self.db.get(key).map(|value| &value.data) {
+            None => return Ok(None),
+            Some(Data::List(_) | Data::Set(_)) => return Err(DbError::WrongType),
+            Some(Data::String(_) | Data::Negative) => {}
+        }
+        self.keys_with_ttl.remove(key);
+        if let Some(capacity) = &mut self.capacity {
+            capacity.index.removed(key);
+        }
+        match self.db.remove(key).map(|value| value.data) {
+            Some(Data::String(value)) => Ok(Some(value)),
+            _ => Ok(None),
+        }
+    }
+
+    pub(crate) fn clear(&mut self) {
         self.db.clear();
         self.keys_with_ttl.clear();
+        if let Some(capacity) = &mut self.capacity {
+            capacity.index.clear();
+        }
+    }
+
+    /// Returns the live keys matching the glob `pattern`, checking every key of the shard.
+    fn keys_matching(&self, pattern: &str) -> Vec<String> {
+        let now = self.clock.now_in_millis();
+        self.db
+            .iter()
+            .filter(|(_, value)| is_live(value, now))
+            .filter(|(key, _)| glob::matches(pattern, key))
+            .map(|(key, _)| key.clone())
+            .collect()
+    }
+
+    /// Returns the first live key from the position `seed` points at on, wrapping around.
+    ///
+    /// Keys following a run of expired ones are more likely to be picked.
+    fn random_key(&self, seed: u64) -> Option<String> {
+        if self.db.is_empty() {
+            return None;
+        }
+        let now = self.clock.now_in_millis();
+        let start = seed as usize % self.db.len();
+        self.db
+            .iter()
+            .skip(start)
+            .chain(self.db.iter().take(start))
+            .find(|(_, value)| is_live(value, now))
+            .map(|(key, _)| key.clone())
+    }
+
+    /// Describes the value under `key` without counting as an access to it.
+    ///
+    /// The shard is left for the caller to fill in, a shard does not know its own index.
+    fn inspect(&mut self, key: &str) -> Option<KeyInfo> {
+        self.remove_if_expired(key);
+        self.db.get(key).map(|value| {
+            let value_length = match &value.data {
+                Data::String(bytes) => bytes.as_bytes().len(),
+                Data::List(list) => list.items.len(),
+                Data::Set(set) => set.members.len(),
+                Data::Negative => 0,
+            };
+            KeyInfo {
+                value_length: u32::try_from(value_length).unwrap_or(u32::MAX),
+                ttl_since_unix_epoch_in_millis: value.ttl_since_unix_epoch_in_millis,
+                updated_at_in_millis: value.updated_at_in_millis,
+                flags: value.flags,
+                negative: matches!(value.data, Data::Negative),
+                shard: 0,
+            }
+        })
+    }
+
+    /// Removes all expired keys in one go and returns them.
+    pub(crate) fn sweep_expired(&mut self) -> Vec<String> {
+        let now = self.clock.now_in_millis();
+        let expired_keys: Vec<String> = self
+            .keys_with_ttl
+            .iter()
+            .filter(|key| {
+                self.db
+                    .get(*key)
+                    .and_then(|value| value.ttl_since_unix_epoch_in_millis)
+                    .is_none_or(|ttl| ttl < now)
+            })
+            .cloned()
+            .collect();
+        for key in &expired_keys {
+            if let Some(expired_at) = self
+                .db
+                .get(key)
+                .and_then(|value| value.ttl_since_unix_epoch_in_millis)
+            {
+                self.reclaimed(expired_at, now);
+            }
+            self.remove(key);
+        }
+        if let Some(max_ttl_keys) = self.max_ttl_keys {
+            self.ttl_keys_sweep_threshold = max_ttl_keys.max(self.keys_with_ttl.len() * 2);
+        }
+        expired_keys
+    }
+
+    /// Returns the keys tracked as having a TTL, sorted.
+    #[cfg(test)]
+    fn debug_ttl_keys(&self) -> Vec<String> {
+        let mut keys: Vec<String> = self.keys_with_ttl.iter().cloned().collect();
+        keys.sort();
+        keys
     }
 }
 
 impl Db {
-    pub(crate) fn new() -> Self {
-        let (tx, rx) = mpsc::channel::<DbRequestWithResponder>(32);
-        let main_db = MainDB::new();
-        tokio::spawn(Self::run(rx, main_db));
-        Self { request_sender: tx }
+    /// Creates a database with `shard_amount` shards, served by tasks on `runtime`.
+    ///
+    /// At least one shard is always created.
+    #[cfg(test)]
+    pub(crate) fn new(runtime: &Handle, shard_amount: usize) -> Self {
+        Self::with_limits(runtime, shard_amount, None, None, None, None, Clock::System).0
+    }
+
+    /// Creates a database holding at most about `max_entries` entries, evicting per their policy,
+    /// and sweeping once there are more than about `max_ttl_keys` keys with a TTL,
+    /// see [`MainDB::with_max_ttl_keys`].
+    ///
+    /// Both limits are split evenly across the shards and enforced by each shard on its own,
+    /// so rounded up to a multiple of the shard amount. Values longer than `large_value_threshold`
+    /// are shared with their readers, see [`StoredBytes`]. The shards record how long expired keys
+    /// were kept in `metrics`, and tell whether keys expired by the time of `clock`.
+    ///
+    /// Returns the tasks serving the shards along with the database, to wait for them to finish.
+    pub(crate) fn with_limits(
+        runtime: &Handle,
+        shard_amount: usize,
+        max_entries: Option<(usize, EvictionPolicy)>,
+        max_ttl_keys: Option<usize>,
+        large_value_threshold: Option<usize>,
+        metrics: Option<Arc<Metrics>>,
+        clock: Clock,
+    ) -> (Self, DbTasks) {
+        let per_shard = |limit: usize| limit.div_ceil(shard_amount.max(1));
+        let max_ttl_keys_per_shard = max_ttl_keys.map(per_shard);
+        Self::spawn_shards(runtime, shard_amount, || {
+            let shard = match max_entries {
+                None => MainDB::new(),
+                Some((max_entries, policy)) => {
+                    MainDB::with_max_entries(per_shard(max_entries), policy)
+                }
+            };
+            shard
+                .with_max_ttl_keys(max_ttl_keys_per_shard)
+                .with_large_value_threshold(large_value_threshold)
+                .with_metrics(metrics.clone())
+                .with_clock(clock)
+        })
+    }
+
+    fn spawn_shards(
+        runtime: &Handle,
+        shard_amount: usize,
+        new_shard: impl Fn() -> MainDB,
+    ) -> (Self, DbTasks) {
+        let (shards, tasks): (Vec<_>, Vec<_>) = (0..shard_amount.max(1))
+            .map(|_| {
+                let (tx, rx) = mpsc::channel::<DbRequestWithResponder>(32);
+                (tx, runtime.spawn(Self::run(rx, new_shard())))
+            })
+            .unzip();
+        let db = Self {
+            shards: shards.into(),
+            flush_barrier: Arc::new(RwLock::new(())),
+            hasher: RandomState::new(),
+            value_codec: None,
+        };
+        (db, DbTasks(tasks))
+    }
+
+    /// Stores plain values as encoded by `value_codec` and decodes them when they are read.
+    pub(crate) fn with_value_codec(self, value_codec: Option<Arc<dyn ValueCodec>>) -> Self {
+        Self {
+            value_codec,
+            ..self
+        }
+    }
+
+    /// Decodes a plain value read from a shard.
+    fn decode(&self, stored: StoredBytes) -> Result<StoredBytes, DbError> {
+        match &self.value_codec {
+            None => Ok(stored),
+            Some(codec) => codec
+                .decode(stored.into_vec())
+                .map(StoredBytes::Inline)
+                .ok_or(DbError::Undecodable),
+        }
+    }
+
+    pub(crate) fn shard_amount(&self) -> usize {
+        self.shards.len()
+    }
+
+    fn shard_for(&self, key: &str) -> &mpsc::Sender<DbRequestWithResponder> {
+        &self.shards[self.shard_index(key)]
+    }
+
+    fn shard_index(&self, key: &str) -> usize {
+        self.hasher.hash_one(key) as usize % self.shards.len()
+    }
+
+    /// Removes all expired keys from the shard at `idx` and returns them.
+    ///
+    /// The shard collects and removes its expired keys while handling a single request,
+    /// so live traffic to the shard is only held up once per sweep.
+    pub(crate) async fn sweep_shard(&self, idx: usize) -> Vec<String> {
+        let (tx, rx) = oneshot::channel::<Option<DbResponse>>();
+        let db_responder = DbRequestWithResponder {
+            request: DbRequest::SweepExpired,
+            result_channel: tx,
+        };
+        let _ = self.shards[idx].send(db_responder).await;
+        match rx.await {
+            Ok(Some(DbResponse::SweepExpired(removed))) => removed,
+            _ => vec![],
+        }
+    }
+
+    /// Returns the keys tracked as having a TTL across all shards, sorted.
+    #[cfg(test)]
+    pub(crate) async fn debug_ttl_keys(&self) -> Vec<String> {
+        let mut keys = vec![];
+        for shard in self.shards.iter() {
+            let (tx, rx) = oneshot::channel::<Option<DbResponse>>();
+            let db_responder = DbRequestWithResponder {
+                request: DbRequest::DebugTtlKeys,
+                result_channel: tx,
+            };
+            let _ = shard.send(db_responder).await;
+            if let Ok(Some(DbResponse::DebugTtlKeys(shard_keys))) = rx.await {
+                keys.extend(shard_keys);
+            }
+        }
+        keys.sort();
+        keys
+    }
+
+    /// Removes all expired keys, one shard after the other, and returns them.
+    pub(crate) async fn sweep_expired(&self) -> Vec<String> {
+        let mut removed = vec![];
+        for idx in 0..self.shard_amount() {
+            removed.extend(self.sweep_shard(idx).await);
+        }
+        removed
+    }
+
+    /// Sends the request to the shard and waits for its response, ordered against flushes.
+    async fn send(
+        &self,
+        shard: &mpsc::Sender<DbRequestWithResponder>,
+        request: DbRequest,
+    ) -> Option<DbResponse> {
+        let _flush_barrier = self.flush_barrier.read().await;
+        Self::send_unordered(shard, request).await
+    }
+
+    /// Sends the request to the shard and waits for its response, regardless of flushes.
+    async fn send_unordered(
+        shard: &mpsc::Sender<DbRequestWithResponder>,
+        request: DbRequest,
+    ) -> Option<DbResponse> {
+        let (tx, rx) = oneshot::channel::<Option<DbResponse>>();
+        let db_responder = DbRequestWithResponder {
+            request,
+            result_channel: tx,
+        };
+        let _ = shard.send(db_responder).await;
+        rx.await.ok().flatten()
     }
 
+    /// Serves the shard until all handles to the database were dropped.
     async fn run(mut rx: Receiver<DbRequestWithResponder>, mut main_db: MainDB) {
         while let Some(responder) = rx.recv().await {
             let response = main_db.handle_request(responder.request);
@@ -150,15 +1048,54 @@ impl Db {
 pub(crate) trait Database: Clone {
     type Output;
 
-    async fn insert(&self, key: String, value: String, ttl: Option<u128>);
+    async fn insert(&self, key: String, value: Vec<u8>, ttl: Option<u128>);
+
+    /// Stores `value` like [`Database::insert`], together with `flags` returned on getting it.
+    async fn insert_with_flags(&self, key: String, value: Vec<u8>, ttl: Option<u128>, flags: u32);
+
+    /// Stores a tombstone remembering that the key is missing, see [`DbValue::negative`].
+    async fn insert_negative(&self, key: String, ttl: Option<u128>);
 
-    async fn get(&self, key: &str) -> Option<Self::Output>;
+    async fn get(&self, key: &str) -> Option<Result<Self::Output, DbError>>;
 
-    async fn remove(&self, key: &str);
+    /// Returns whether anything was stored under the key.
+    async fn remove(&self, key: &str) -> bool;
+
+    /// Removes the value under `key` and returns it, all at once.
+    async fn take(&self, key: &str) -> Result<Option<StoredBytes>, DbError>;
 
     async fn contains_key(&self, key: &str) -> bool;
 
+    /// Pushes `item` to the front of the list under `key` and returns the new length of the list.
+    async fn push_front(&self, key: String, item: String) -> Result<usize, DbError>;
+
+    /// Pops an item from the back of the list under `key`.
+    async fn pop_back(&self, key: &str) -> Result<Option<String>, DbError>;
+
+    /// Adds `member` to the set under `key` and returns whether it was newly added.
+    async fn set_add(&self, key: String, member: String) -> Result<bool, DbError>;
+
+    async fn set_contains(&self, key: &str, member: String) -> Result<bool, DbError>;
+
+    /// Removes `member` from the set under `key` and returns whether it was present.
+    async fn set_remove(&self, key: &str, member: String) -> Result<bool, DbError>;
+
     async fn clear(&self);
+
+    /// Returns the keys matching the glob `pattern`, sorted.
+    async fn keys_matching(&self, pattern: &str) -> Vec<String>;
+
+    /// Returns a random live key, `None` if there are none.
+    async fn random_key(&self) -> Option<String>;
+
+    /// Describes the value under `key`, `None` if there is none.
+    async fn inspect(&self, key: &str) -> Option<KeyInfo>;
+
+    /// Adds `delta` to the integer stored under `key` and returns the value as it is stored afterwards,
+    /// all at once.
+    ///
+    /// Returns `None` if the value could not be reached, which only happens when shutting down.
+    async fn increment(&self, key: String, delta: i128) -> Option<Result<Self::Output, DbError>>;
 }
 
 #[async_trait]
@@ -168,74 +1105,338 @@ impl Database for Db {
     async fn insert(
         &self,
         key: String,
-        value: String,
+        value: Vec<u8>,
         ttl_since_unix_epoch_in_millis: Option<u128>,
     ) {
-        let (tx, _) = oneshot::channel::<Option<DbResponse>>();
-        let db_responder = DbRequestWithResponder {
-            request: DbRequest::Insert {
-                key,
-                value,
-                ttl: ttl_since_unix_epoch_in_millis,
-            },
-            result_channel: tx,
+        self.insert_with_flags(key, value, ttl_since_unix_epoch_in_millis, 0)
+            .await;
+    }
+
+    async fn insert_with_flags(
+        &self,
+        key: String,
+        value: Vec<u8>,
+        ttl_since_unix_epoch_in_millis: Option<u128>,
+        flags: u32,
+    ) {
+        let shard = self.shard_for(&key).clone();
+        let value = match &self.value_codec {
+            None => value,
+            Some(codec) => codec.encode(value),
         };
-        let _ = self.request_sender.send(db_responder).await;
+        let request = DbRequest::Insert {
+            key,
+            value,
+            ttl: ttl_since_unix_epoch_in_millis,
+            flags,
+        };
+        // Waiting for the shard means the value is visible to everyone once this returns
+        self.send(&shard, request).await;
+    }
+
+    async fn get(&self, key: &str) -> Option<Result<Self::Output, DbError>> {
+        let value = match self
+            .send(self.shard_for(key), DbRequest::Get(key.to_string()))
+            .await
+        {
+            Some(DbResponse::Get(value)) => value,
+            _ => return None,
+        };
+        Some(value.and_then(|value| {
+            if value.negative {
+                return Ok(value);
+            }
+            Ok(DbValue {
+                value: self.decode(value.value)?,
+                ..value
+            })
+        }))
+    }
+
+    async fn insert_negative(&self, key: String, ttl: Option<u128>) {
+        let shard = self.shard_for(&key).clone();
+        self.send(&shard, DbRequest::InsertNegative { key, ttl })
+            .await;
+    }
+
+    async fn remove(&self, key: &str) -> bool {
+        matches!(
+            self.send(self.shard_for(key), DbRequest::Remove(key.to_string()))
+                .await,
+            Some(DbResponse::Removed(true))
+        )
+    }
+
+    async fn take(&self, key: &str) -> Result<Option<StoredBytes>, DbError> {
+        match self
+            .send(self.shard_for(key), DbRequest::Take(key.to_string()))
+            .await
+        {
+            Some(DbResponse::Take(result)) => result?.map(|value| self.decode(value)).transpose(),
+            _ => Ok(None),
+        }
+    }
+
+    async fn contains_key(&self, key: &str) -> bool {
+        let request = DbRequest::ContainsKey(key.to_string());
+        matches!(
+            self.send(self.shard_for(key), request).await,
+            Some(DbResponse::ContainsKey(true))
+        )
+    }
+
+    async fn push_front(&self, key: String, item: String) -> Result<usize, DbError> {
+        let shard = self.shard_for(&key).clone();
+        match self.send(&shard, DbRequest::PushFront { key, item }).await {
+            Some(DbResponse::PushFront(result)) => result,
+            // The shard is gone, which only happens when shutting down
+            _ => Ok(0),
+        }
+    }
+
+    async fn pop_back(&self, key: &str) -> Result<Option<String>, DbError> {
+        let request = DbRequest::PopBack(key.to_string());
+        match self.send(self.shard_for(key), request).await {
+            Some(DbResponse::PopBack(result)) => result,
+            _ => Ok(None),
+        }
+    }
+
+    async fn set_add(&self, key: String, member: String) -> Result<bool, DbError> {
+        let shard = self.shard_for(&key).clone();
+        match self.send(&shard, DbRequest::SetAdd { key, member }).await {
+            Some(DbResponse::SetMembership(result)) => result,
+            _ => Ok(false),
+        }
+    }
+
+    async fn set_contains(&self, key: &str, member: String) -> Result<bool, DbError> {
+        let request = DbRequest::SetContains {
+            key: key.to_string(),
+            member,
+        };
+        match self.send(self.shard_for(key), request).await {
+            Some(DbResponse::SetMembership(result)) => result,
+            _ => Ok(false),
+        }
+    }
+
+    async fn set_remove(&self, key: &str, member: String) -> Result<bool, DbError> {
+        let request = DbRequest::SetRemove {
+            key: key.to_string(),
+            member,
+        };
+        match self.send(self.shard_for(key), request).await {
+            Some(DbResponse::SetMembership(result)) => result,
+            _ => Ok(false),
+        }
+    }
+
+    /// Waits for the requests already sent to the shards, and holds back new ones until all shards were cleared.
+    async fn clear(&self) {
+        let _flush_barrier = self.flush_barrier.write().await;
+        for shard in self.shards.iter() {
+            Self::send_unordered(shard, DbRequest::Clear).await;
+        }
+    }
+
+    /// Every shard scans all of its keys, one shard after the other.
+    async fn keys_matching(&self, pattern: &str) -> Vec<String> {
+        let mut keys = vec![];
+        for shard in self.shards.iter() {
+            let request = DbRequest::KeysMatching(pattern.to_string());
+            if let Some(DbResponse::Keys(shard_keys)) = self.send(shard, request).await {
+                keys.extend(shard_keys);
+            }
+        }
+        keys.sort();
+        keys
+    }
+
+    /// Samples a random shard, moving on to the next one while a shard has no live keys.
+    ///
+    /// This is approximate: each shard is about as likely to be sampled as any other regardless
+    /// of how many keys it holds, so keys in smaller shards are picked more often.
+    async fn random_key(&self) -> Option<String> {
+        let seed = RandomState::new().hash_one(());
+        let first = (seed % self.shards.len() as u64) as usize;
+        for idx in (first..self.shards.len()).chain(0..first) {
+            let request = DbRequest::RandomKey(seed);
+            if let Some(DbResponse::RandomKey(Some(key))) =
+                self.send(&self.shards[idx], request).await
+            {
+                return Some(key);
+            }
+        }
+        None
     }
 
-    async fn get(&self, key: &str) -> Option<Self::Output> {
-        let (tx, rx) = oneshot::channel::<Option<DbResponse>>();
-        let db_responder = DbRequestWithResponder {
-            request: DbRequest::Get(key.to_string()),
-            result_channel: tx,
-        };
-        let _ = self.request_sender.send(db_responder).await;
-        rx.await.ok().and_then(|v| match v {
-            Some(DbResponse::Get(value)) => Some(value),
+    async fn inspect(&self, key: &str) -> Option<KeyInfo> {
+        let idx = self.shard_index(key);
+        match self
+            .send(&self.shards[idx], DbRequest::Inspect(key.to_string()))
+            .await
+        {
+            Some(DbResponse::Inspect(info)) => info.map(|info| KeyInfo {
+                shard: u32::try_from(idx).unwrap_or(u32::MAX),
+                ..info
+            }),
             _ => None,
-        })
+        }
     }
 
-    async fn remove(&self, key: &str) {
-        let (tx, _) = oneshot::channel::<Option<DbResponse>>();
-        let db_responder = DbRequestWithResponder {
-            request: DbRequest::Remove(key.to_string()),
-            result_channel: tx,
+    async fn increment(&self, key: String, delta: i128) -> Option<Result<Self::Output, DbError>> {
+        let shard = self.shard_for(&key).clone();
+        let request = DbRequest::Increment {
+            key,
+            delta,
+            codec: self.value_codec.clone(),
         };
-        let _ = self.request_sender.send(db_responder).await;
+        match self.send(&shard, request).await {
+            Some(DbResponse::Increment(result)) => Some(result),
+            _ => None,
+        }
     }
+}
 
-    async fn contains_key(&self, key: &str) -> bool {
-        let (tx, rx) = oneshot::channel::<Option<DbResponse>>();
-        let db_responder = DbRequestWithResponder {
-            request: DbRequest::ContainsKey(key.to_string()),
-            result_channel: tx,
-        };
-        let _ = self.request_sender.send(db_responder).await;
-        rx.await.ok().map_or(false, |v| match v {
-            Some(DbResponse::ContainsKey(value)) => value,
-            _ => false,
-        })
+/// Whether `value` holds data that has not expired by `now`, tombstones do not count.
+fn is_live(value: &StoredValue, now: u128) -> bool {
+    !matches!(value.data, Data::Negative)
+        && value
+            .ttl_since_unix_epoch_in_millis
+            .is_none_or(|ttl| ttl >= now)
+}
+
+/// Periodically removes expired keys until the server shuts down.
+///
+/// The keys removed by a sweep are sent as a single batch, if anyone subscribed to them.
+pub(crate) async fn run_sweeper(
+    db: Db,
+    interval: Duration,
+    metrics: Arc<Metrics>,
+    evictions: broadcast::Sender<EvictionBatch>,
+    mut shutdown: Shutdown,
+) {
+    let mut ticker = tokio::time::interval(interval);
+    // The first tick completes immediately, there is nothing to sweep yet.
+    ticker.tick().await;
+    while !shutdown.is_shutdown() {
+        tokio::select! {
+            _ = ticker.tick() => {
+                let removed = db.sweep_expired().await;
+                #[cfg(feature = "tracing")]
+                debug!("Swept {} expired keys.", removed.len());
+                report_expired(removed, &metrics, &evictions);
+            }
+            _ = shutdown.recv() => {}
+        }
     }
+}
 
-    async fn clear(&self) {
-        let (tx, _) = oneshot::channel::<Option<DbResponse>>();
-        let db_responder = DbRequestWithResponder {
-            request: DbRequest::Clear,
-            result_channel: tx,
-        };
-        let _ = self.request_sender.send(db_responder).await;
+/// Counts the expired keys removed by a sweep as evicted
+/// and sends them as a single batch, if anyone subscribed to them.
+pub(crate) fn report_expired(
+    removed: Vec<String>,
+    metrics: &Metrics,
+    evictions: &broadcast::Sender<EvictionBatch>,
+) {
+    metrics.evicted(removed.len());
+    if !removed.is_empty() && evictions.receiver_count() > 0 {
+        let _ = evictions.send(EvictionBatch {
+            keys: removed,
+            reason: EvictionReason::Expired,
+        });
+    }
+}
+
+/// Seeds the database from lines of `key<TAB>value`, without TTLs.
+///
+/// Malformed lines are skipped, empty ones are ignored.
+/// Returns how many keys were seeded and how many lines were skipped.
+pub(crate) async fn warm(db: &Db, contents: &str) -> (usize, usize) {
+    let (mut seeded, mut skipped) = (0, 0);
+    for line in contents.lines().filter(|line| !line.is_empty()) {
+        if let Some((key, value)) = parse_warm_line(line) {
+            db.insert(key.to_string(), value.into(), None).await;
+            seeded += 1;
+        } else {
+            skipped += 1;
+        }
     }
+    (seeded, skipped)
+}
+
+fn parse_warm_line(line: &str) -> Option<(&str, &str)> {
+    let (key, value) = line.split_once('\t')?;
+    let key_is_valid = !key.is_empty() && key.len() <= u8::MAX as usize;
+    let value_is_valid = !value.is_empty() && value.len() <= MAX_VALUE_LENGTH as usize;
+    (key_is_valid && value_is_valid).then_some((key, value))
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
+    use rstest::rstest;
     use std::time::Duration;
 
+    #[test]
+    #[ignore]
+    fn test_reading_large_values_does_not_copy_them() {
+        let large_value = "x".repeat(10_000);
+        let mut db = MainDB::new().with_large_value_threshold(Some(1024));
+        db.insert("large".to_string(), large_value.clone(), None);
+        db.insert("small".to_string(), "small".to_string(), None);
+        // The buffer may only become shareable on the first read
+        db.get("large");
+
+        let _profiler = dhat::Profiler::builder().testing().build();
+        let value = db.get("large").unwrap().unwrap();
+        let stats = dhat::HeapStats::get();
+        dhat::assert_eq!(stats.total_blocks, 0);
+        assert_eq!(value.value.as_bytes(), large_value.as_bytes());
+
+        // Small values are copied
+        let value = db.get("small").unwrap().unwrap();
+        let stats = dhat::HeapStats::get();
+        dhat::assert_eq!(stats.total_blocks, 1);
+        dhat::assert_eq!(stats.total_bytes, 5);
+        assert_eq!(value.value, StoredBytes::Inline(b"small".to_vec()));
+    }
+
+    #[rstest]
+    #[case(1024, false)]
+    #[case(1023, true)]
+    fn test_values_longer_than_the_threshold_are_shared(
+        #[case] threshold: usize,
+        #[case] shared: bool,
+    ) {
+        let mut db = MainDB::new().with_large_value_threshold(Some(threshold));
+        db.insert("key".to_string(), "x".repeat(1024), None);
+        let value = db.get("key").unwrap().unwrap().value;
+        assert_eq!(matches!(value, StoredBytes::Shared(_)), shared);
+        assert_eq!(value.into_vec(), "x".repeat(1024).into_bytes());
+    }
+
+    #[tokio::test]
+    async fn test_shard_tasks_finish_once_all_handles_are_dropped() {
+        let (db, tasks) =
+            Db::with_limits(&Handle::current(), 4, None, None, None, None, Clock::System);
+        let handle = db.clone();
+        db.insert("key".to_string(), "value".into(), None).await;
+        drop(db);
+        // The remaining handle keeps the shards running
+        assert!(handle.contains_key("key").await);
+        assert!(tasks.0.iter().all(|task| !task.is_finished()));
+
+        drop(handle);
+        tokio::time::timeout(Duration::from_secs(5), tasks.join())
+            .await
+            .unwrap();
+    }
+
     #[tokio::test]
     async fn test_ttl_elapsed_does_not_return_value_from_db() {
-        let db = Db::new();
+        let db = Db::new(&Handle::current(), 4);
         let key = "Hello";
         let value = "World";
         let valid_until = SystemTime::now()
@@ -243,7 +1444,7 @@ mod test {
             .unwrap()
             .as_millis()
             + 1;
-        db.insert(key.to_string(), value.to_string(), Some(valid_until))
+        db.insert(key.to_string(), value.into(), Some(valid_until))
             .await;
 
         tokio::time::sleep(Duration::from_millis(10)).await;
@@ -264,16 +1465,16 @@ mod test {
         db.insert(key.to_string(), value.to_string(), Some(valid_until));
 
         // Ensure key is in main db and set of keys with TTL
-        assert!(db.db.get(key).is_some());
-        assert!(db.keys_with_ttl.get(key).is_some());
+        assert!(db.db.contains_key(key));
+        assert!(db.debug_ttl_keys().contains(&key.to_string()));
 
         tokio::time::sleep(Duration::from_millis(10)).await;
         // Must not return the key as its TTL expired already
         assert!(db.get(key).is_none());
 
         // Ensure everything is cleaned up
-        assert!(db.db.get(key).is_none());
-        assert!(db.keys_with_ttl.get(key).is_none());
+        assert!(!db.db.contains_key(key));
+        assert!(!db.debug_ttl_keys().contains(&key.to_string()));
     }
 
     #[tokio::test]
@@ -288,13 +1489,44 @@ mod test {
         db.insert(key.to_string(), value.to_string(), Some(valid_until_now));
 
         // Ensure key is in main db and set of keys with TTL
-        assert!(db.db.get(key).is_none());
-        assert!(db.keys_with_ttl.get(key).is_none());
+        assert!(!db.db.contains_key(key));
+        assert!(!db.debug_ttl_keys().contains(&key.to_string()));
+    }
+
+    /// Stores values reversed.
+    #[derive(Debug)]
+    struct Reversed;
+
+    impl ValueCodec for Reversed {
+        fn encode(&self, mut value: Vec<u8>) -> Vec<u8> {
+            value.reverse();
+            value
+        }
+
+        fn decode(&self, mut stored: Vec<u8>) -> Option<Vec<u8>> {
+            stored.reverse();
+            Some(stored)
+        }
+    }
+
+    #[tokio::test]
+    async fn test_values_are_stored_encoded_and_read_decoded() {
+        let db = Db::new(&Handle::current(), 4).with_value_codec(Some(Arc::new(Reversed)));
+        db.insert("Hello".to_string(), "World".into(), None).await;
+
+        // The same shards, without decoding
+        let stored = db.clone().with_value_codec(None);
+        let value = stored.get("Hello").await.unwrap().unwrap();
+        assert_eq!(value.value.as_bytes(), b"dlroW");
+        let value = db.get("Hello").await.unwrap().unwrap();
+        assert_eq!(value.value.as_bytes(), b"World");
+        let value = db.take("Hello").await.unwrap().unwrap();
+        assert_eq!(value.as_bytes(), b"World");
     }
 
     #[tokio::test]
     async fn test_ttl_in_future_returns_value_db() {
-        let db = Db::new();
+        let db = Db::new(&Handle::current(), 4);
         let key = "Hello";
         let value = "World";
         let valid_until_now = SystemTime::now()
@@ -302,7 +1534,7 @@ mod test {
             .unwrap()
             .as_millis()
             + 1;
-        db.insert(key.to_string(), value.to_string(), Some(valid_until_now))
+        db.insert(key.to_string(), value.into(), Some(valid_until_now))
             .await;
 
         // Must not return the key as its TTL expired already
@@ -322,15 +1554,15 @@ mod test {
         db.insert(key.to_string(), value.to_string(), Some(valid_until_now));
 
         // Ensure key is in main db and set of keys with TTL
-        assert!(db.db.get(key).is_some());
-        assert!(db.keys_with_ttl.get(key).is_some());
+        assert!(db.db.contains_key(key));
+        assert!(db.debug_ttl_keys().contains(&key.to_string()));
 
         // Must not return the key as its TTL expired already
         assert!(db.get(key).is_some());
 
         // Ensure everything is still present
-        assert!(db.db.get(key).is_some());
-        assert!(db.keys_with_ttl.get(key).is_some());
+        assert!(db.db.contains_key(key));
+        assert!(db.debug_ttl_keys().contains(&key.to_string()));
     }
 
     #[tokio::test]
@@ -346,40 +1578,433 @@ mod test {
         db.insert(key.to_string(), value.to_string(), Some(valid_until_now));
 
         // Ensure key is in main db and set of keys with TTL
-        assert!(db.db.get(key).is_some());
-        assert!(db.keys_with_ttl.get(key).is_some());
+        assert!(db.db.contains_key(key));
+        assert!(db.debug_ttl_keys().contains(&key.to_string()));
 
         db.remove(key);
 
         // Ensure everything is removed
-        assert!(db.db.get(key).is_none());
-        assert!(db.keys_with_ttl.get(key).is_none());
+        assert!(!db.db.contains_key(key));
+        assert!(!db.debug_ttl_keys().contains(&key.to_string()));
     }
 
     #[tokio::test]
     async fn test_contains_key_works() {
-        let db = Db::new();
+        let db = Db::new(&Handle::current(), 4);
         let key = "Hello";
         let value = "World";
-        db.insert(key.to_string(), value.to_string(), None).await;
+        db.insert(key.to_string(), value.into(), None).await;
 
         assert!(db.contains_key(key).await);
         db.remove(key).await;
         assert!(!db.contains_key(key).await);
     }
 
+    #[tokio::test]
+    async fn test_contains_key_ignores_expired_keys() {
+        let db = Db::new(&Handle::current(), 4);
+        let key = "Hello";
+        let value = "World";
+        let valid_until = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_millis()
+            + 1;
+        db.insert(key.to_string(), value.into(), Some(valid_until))
+            .await;
+
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        assert!(!db.contains_key(key).await);
+        assert!(db.debug_ttl_keys().await.is_empty());
+    }
+
+    fn stored_keys(db: &MainDB) -> Vec<&str> {
+        let mut keys: Vec<&str> = db.db.keys().map(String::as_str).collect();
+        keys.sort();
+        keys
+    }
+
+    fn fill(db: &mut MainDB, keys: &[&str]) {
+        for key in keys {
+            db.insert(key.to_string(), "value".to_string(), None);
+        }
+    }
+
+    #[test]
+    fn test_lru_evicts_the_least_recently_used_entry() {
+        let mut db = MainDB::with_max_entries(3, EvictionPolicy::Lru);
+        fill(&mut db, &["a", "b", "c"]);
+        db.get("a");
+
+        fill(&mut db, &["d"]);
+        assert_eq!(stored_keys(&db), vec!["a", "c", "d"]);
+    }
+
+    #[test]
+    fn test_lfu_evicts_the_least_frequently_used_entry() {
+        let mut db = MainDB::with_max_entries(3, EvictionPolicy::Lfu);
+        fill(&mut db, &["a", "b"]);
+        db.get("a");
+        db.get("b");
+        // `c` is the most recently used entry, but the least frequently used one
+        fill(&mut db, &["c"]);
+
+        fill(&mut db, &["d"]);
+        assert_eq!(stored_keys(&db), vec!["a", "b", "d"]);
+    }
+
+    #[test]
+    fn test_ttl_nearest_evicts_the_entry_expiring_next() {
+        let mut db = MainDB::with_max_entries(3, EvictionPolicy::TtlNearest);
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_millis();
+        db.insert("a".to_string(), "value".to_string(), Some(now + 60_000));
+        db.insert("b".to_string(), "value".to_string(), Some(now + 10_000));
+        db.insert("c".to_string(), "value".to_string(), None);
+
+        fill(&mut db, &["d"]);
+        assert_eq!(stored_keys(&db), vec!["a", "c", "d"]);
+    }
+
+    #[rstest]
+    #[case(EvictionPolicy::Lru)]
+    #[case(EvictionPolicy::Lfu)]
+    #[case(EvictionPolicy::Random)]
+    #[case(EvictionPolicy::TtlNearest)]
+    fn test_full_shard_evicts_one_entry_per_new_key(#[case] policy: EvictionPolicy) {
+        let mut db = MainDB::with_max_entries(3, policy);
+        fill(&mut db, &["a", "b", "c"]);
+        // Replacing an entry does not evict anything
+        fill(&mut db, &["a"]);
+        assert_eq!(stored_keys(&db), vec!["a", "b", "c"]);
+
+        db.push_front("list".to_string(), "1".to_string()).unwrap();
+        assert_eq!(db.db.len(), 3);
+        assert!(db.db.contains_key("list"));
+
+        // Removed keys free their slot
+        db.remove("list");
+        fill(&mut db, &["d"]);
+        assert_eq!(db.db.len(), 3);
+        assert!(db.db.contains_key("d"));
+    }
+
+    #[test]
+    fn test_list_pops_items_in_the_order_they_were_pushed_in() {
+        let mut db = MainDB::new();
+        assert_eq!(db.push_front("list".to_string(), "1".to_string()), Ok(1));
+        assert_eq!(db.push_front("list".to_string(), "2".to_string()), Ok(2));
+
+        assert_eq!(db.pop_back("list"), Ok(Some("1".to_string())));
+        assert_eq!(db.pop_back("list"), Ok(Some("2".to_string())));
+        // The list is removed once it is empty
+        assert!(!db.contains_key("list"));
+        assert_eq!(db.pop_back("list"), Ok(None));
+    }
+
+    #[test]
+    fn test_incrementing_keeps_the_ttl_and_flags_of_the_value() {
+        let mut db = MainDB::new();
+        let value = db.increment("missing".to_string(), -3, None).unwrap();
+        assert_eq!(value.value.as_bytes(), b"-3");
+        assert_eq!(value.ttl_since_unix_epoch_in_millis, None);
+
+        let valid_until = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_millis()
+            + 60_000;
+        db.insert_with_flags("counter".to_string(), "41".into(), Some(valid_until), 7);
+        let value = db.increment("counter".to_string(), 1, None).unwrap();
+        assert_eq!(value.value.as_bytes(), b"42");
+        assert_eq!(value.ttl_since_unix_epoch_in_millis, Some(valid_until));
+        assert_eq!(value.flags, 7);
+        let stored = db.get("counter").unwrap().unwrap();
+        assert_eq!(stored.value.as_bytes(), b"42");
+        assert_eq!(stored.ttl_since_unix_epoch_in_millis, Some(valid_until));
+        assert_eq!(stored.flags, 7);
+        assert_eq!(stored.updated_at_in_millis, value.updated_at_in_millis);
+
+        db.insert("text".to_string(), "abc".to_string(), None);
+        assert_eq!(
+            db.increment("text".to_string(), 1, None).unwrap_err(),
+            DbError::NotAnInteger
+        );
+        db.push_front("list".to_string(), "1".to_string()).unwrap();
+        assert_eq!(
+            db.increment("list".to_string(), 1, None).unwrap_err(),
+            DbError::WrongType
+        );
+    }
+
+    #[test]
+    fn test_incrementing_beyond_the_integer_is_out_of_range_and_changes_nothing() {
+        let mut db = MainDB::new();
+        db.insert("counter".to_string(), "42".to_string(), None);
+        assert_eq!(
+            db.increment("counter".to_string(), i128::from(i64::MAX), None)
+                .unwrap_err(),
+            DbError::OutOfRange
+        );
+        assert_eq!(db.get("counter").unwrap().unwrap().value.as_bytes(), b"42");
+
+        // Subtracting the smallest integer works as long as the result fits
+        db.insert("negative".to_string(), "-1".to_string(), None);
+        let value = db
+            .increment("negative".to_string(), -i128::from(i64::MIN), None)
+            .unwrap();
+        assert_eq!(value.value.as_bytes(), i64::MAX.to_string().as_bytes());
+    }
+
+    #[test]
+    fn test_taking_a_value_removes_it() {
+        let mut db = MainDB::new();
+        let valid_until = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_millis()
+            + 60_000;
+        db.insert("plain".to_string(), "value".to_string(), Some(valid_until));
+        db.insert_data("missing".to_string(), Data::Negative, None, 0);
+        db.push_front("list".to_string(), "1".to_string()).unwrap();
+
+        assert_eq!(
+            db.take("plain"),
+            Ok(Some(StoredBytes::Inline(b"value".to_vec())))
+        );
+        assert!(!db.db.contains_key("plain"));
+        assert!(db.debug_ttl_keys().is_empty());
+        assert_eq!(db.take("plain"), Ok(None));
+        // Tombstones are removed, but hold no value
+        assert_eq!(db.take("missing"), Ok(None));
+        assert!(!db.db.contains_key("missing"));
+        // Lists are left in place
+        assert_eq!(db.take("list"), Err(DbError::WrongType));
+        assert!(db.contains_key("list"));
+    }
+
+    #[test]
+    fn test_list_operations_on_plain_values_fail() {
+        let mut db = MainDB::new();
+        db.insert("plain".to_string(), "value".to_string(), None);
+        assert_eq!(
+            db.push_front("plain".to_string(), "1".to_string()),
+            Err(DbError::WrongType)
+        );
+        assert_eq!(db.pop_back("plain"), Err(DbError::WrongType));
+
+        db.push_front("list".to_string(), "1".to_string()).unwrap();
+        assert!(matches!(db.get("list"), Some(Err(DbError::WrongType))));
+    }
+
+    #[test]
+    fn test_list_is_bounded_by_max_value_length() {
+        let mut db = MainDB::new();
+        let item = "a".repeat(MAX_VALUE_LENGTH as usize / 2);
+        assert_eq!(db.push_front("list".to_string(), item.clone()), Ok(1));
+        assert_eq!(db.push_front("list".to_string(), item.clone()), Ok(2));
+        assert_eq!(
+            db.push_front("list".to_string(), "a".to_string()),
+            Err(DbError::ValueTooLarge)
+        );
+
+        // Popping makes room again
+        db.pop_back("list").unwrap();
+        assert_eq!(db.push_front("list".to_string(), "a".to_string()), Ok(2));
+    }
+
+    #[test]
+    fn test_adding_a_set_member_is_idempotent() {
+        let mut db = MainDB::new();
+        assert_eq!(db.set_add("set".to_string(), "a".to_string()), Ok(true));
+        assert_eq!(db.set_add("set".to_string(), "a".to_string()), Ok(false));
+        assert_eq!(db.set_add("set".to_string(), "b".to_string()), Ok(true));
+        assert_eq!(db.set_contains("set", "a"), Ok(true));
+        assert_eq!(db.set_contains("set", "b"), Ok(true));
+        assert_eq!(db.set_contains("set", "c"), Ok(false));
+        assert_eq!(db.set_contains("no set", "a"), Ok(false));
+    }
+
+    #[test]
+    fn test_removing_set_members_works() {
+        let mut db = MainDB::new();
+        db.set_add("set".to_string(), "a".to_string()).unwrap();
+        db.set_add("set".to_string(), "b".to_string()).unwrap();
+
+        assert_eq!(db.set_remove("set", "a"), Ok(true));
+        assert_eq!(db.set_remove("set", "a"), Ok(false));
+        assert_eq!(db.set_contains("set", "a"), Ok(false));
+        assert_eq!(db.set_remove("set", "b"), Ok(true));
+        // The set is removed once it is empty
+        assert!(!db.contains_key("set"));
+    }
+
+    #[test]
+    fn test_tombstones_are_only_reported_by_get() {
+        let mut db = MainDB::new();
+        let ttl = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_millis()
+            + 60_000;
+        db.insert_data("missing".to_string(), Data::Negative, Some(ttl), 0);
+
+        let value = db.get("missing").unwrap().unwrap();
+        assert!(value.negative);
+        assert_eq!(value.ttl_since_unix_epoch_in_millis, Some(ttl));
+        assert!(!db.contains_key("missing"));
+        assert_eq!(db.pop_back("missing"), Ok(None));
+        assert_eq!(db.set_contains("missing", "a"), Ok(false));
+        assert!(db.keys_matching("*").is_empty());
+
+        // Setting a value replaces the tombstone along with its TTL
+        db.insert("missing".to_string(), "found".to_string(), None);
+        let value = db.get("missing").unwrap().unwrap();
+        assert!(!value.negative);
+        assert_eq!(value.value.as_bytes(), b"found");
+        assert!(db.debug_ttl_keys().is_empty());
+    }
+
+    #[test]
+    fn test_values_keep_their_flags_until_replaced() {
+        let mut db = MainDB::new();
+        db.insert_with_flags("key".to_string(), "value".into(), None, 42);
+        assert_eq!(db.get("key").unwrap().unwrap().flags, 42);
+
+        db.insert("key".to_string(), "other".to_string(), None);
+        assert_eq!(db.get("key").unwrap().unwrap().flags, 0);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_overwriting_a_value_updates_when_it_was_set() {
+        let clock = Clock::tokio();
+        let mut db = MainDB::new().with_clock(clock);
+        db.insert("key".to_string(), "value".to_string(), None);
+        let set_at = db.get("key").unwrap().unwrap().updated_at_in_millis;
+        assert_eq!(set_at, clock.now_in_millis());
+
+        tokio::time::advance(Duration::from_secs(5)).await;
+        // Reading leaves it as it is
+        assert_eq!(db.get("key").unwrap().unwrap().updated_at_in_millis, set_at);
+        db.insert("key".to_string(), "other".to_string(), None);
+        let overwritten_at = db.get("key").unwrap().unwrap().updated_at_in_millis;
+        assert_eq!(overwritten_at, set_at + 5000);
+    }
+
+    #[test]
+    fn test_set_operations_on_other_values_fail() {
+        let mut db = MainDB::new();
+        db.insert("plain".to_string(), "value".to_string(), None);
+        db.push_front("list".to_string(), "1".to_string()).unwrap();
+        db.set_add("set".to_string(), "a".to_string()).unwrap();
+        for key in ["plain", "list"] {
+            assert_eq!(
+                db.set_add(key.to_string(), "a".to_string()),
+                Err(DbError::WrongType)
+            );
+            assert_eq!(db.set_contains(key, "a"), Err(DbError::WrongType));
+            assert_eq!(db.set_remove(key, "a"), Err(DbError::WrongType));
+        }
+        assert_eq!(
+            db.push_front("set".to_string(), "1".to_string()),
+            Err(DbError::WrongType)
+        );
+        assert!(matches!(db.get("set"), Some(Err(DbError::WrongType))));
+    }
+
+    #[tokio::test]
+    async fn test_keys_matching_collects_live_keys_from_all_shards() {
+        let db = Db::new(&Handle::current(), 4);
+        for key in [
+            "user:1:session",
+            "user:2:session",
+            "user:2:profile",
+            "other",
+        ] {
+            db.insert(key.to_string(), "value".into(), None).await;
+        }
+        db.push_front("user:3:session".to_string(), "item".to_string())
+            .await
+            .unwrap();
+        let expired = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_millis()
+            + 50;
+        db.insert("user:4:session".to_string(), "value".into(), Some(expired))
+            .await;
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        assert_eq!(
+            db.keys_matching("user:*:session").await,
+            vec!["user:1:session", "user:2:session", "user:3:session"]
+        );
+        assert_eq!(db.keys_matching("*").await.len(), 5);
+        assert!(db.keys_matching("nothing*").await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_random_key_only_returns_live_keys() {
+        let db = Db::new(&Handle::current(), 4);
+        assert_eq!(db.random_key().await, None);
+
+        let expired = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_millis()
+            + 50;
+        for idx in 0..20 {
+            db.insert(format!("expired:{idx}"), "value".into(), Some(expired))
+                .await;
+        }
+        db.insert("live".to_string(), "value".into(), None).await;
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        for _ in 0..20 {
+            assert_eq!(db.random_key().await.as_deref(), Some("live"));
+        }
+    }
+
     #[tokio::test]
     async fn test_clearing_db_works() {
-        let db = Db::new();
+        let db = Db::new(&Handle::current(), 4);
         let key = "Hello";
         let value = "World";
-        db.insert(key.to_string(), value.to_string(), None).await;
+        db.insert(key.to_string(), value.into(), None).await;
 
         assert!(db.contains_key(key).await);
         db.clear().await;
         assert!(!db.contains_key(key).await);
     }
 
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_sets_racing_a_flush_are_applied_entirely_before_or_after_it() {
+        let db = Db::new(&Handle::current(), 8);
+        let keys: Vec<String> = (0..1000).map(|i| format!("key{i}")).collect();
+        let writer = {
+            let db = db.clone();
+            let keys = keys.clone();
+            tokio::spawn(async move {
+                for key in keys {
+                    db.insert(key, "value".into(), None).await;
+                }
+            })
+        };
+        tokio::task::yield_now().await;
+        db.clear().await;
+        writer.await.unwrap();
+
+        // The sets are sequential, so those after the flush are a suffix of them
+        let mut survived = vec![];
+        for key in &keys {
+            survived.push(db.contains_key(key).await);
+        }
+        let first_survivor = survived.iter().position(|&s| s).unwrap_or(keys.len());
+        assert!(survived[first_survivor..].iter().all(|&s| s));
+    }
+
     #[tokio::test]
     async fn test_clearing_db_works_main_db() {
         let mut db = MainDB::new();
@@ -390,6 +2015,170 @@ mod test {
         assert!(db.db.contains_key(key));
         db.clear();
         assert_eq!(db.db.len(), 0);
-        assert_eq!(db.keys_with_ttl.len(), 0);
+        assert!(db.debug_ttl_keys().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_sweeping_removes_expired_keys_from_all_shards() {
+        let db = Db::new(&Handle::current(), 4);
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_millis();
+        for i in 0..100 {
+            db.insert(format!("expiring-{i}"), "value".into(), Some(now + 100))
+                .await;
+            db.insert(format!("live-{i}"), "value".into(), None).await;
+            db.insert(
+                format!("live-with-ttl-{i}"),
+                "value".into(),
+                Some(now + 60_000),
+            )
+            .await;
+        }
+        tokio::time::sleep(Duration::from_millis(150)).await;
+
+        assert_eq!(db.sweep_expired().await.len(), 100);
+
+        for i in 0..100 {
+            assert!(!db.contains_key(&format!("expiring-{i}")).await);
+            assert!(db.contains_key(&format!("live-{i}")).await);
+            assert!(db.contains_key(&format!("live-with-ttl-{i}")).await);
+        }
+        // Nothing left to sweep in any shard
+        for idx in 0..db.shard_amount() {
+            assert!(db.sweep_shard(idx).await.is_empty());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_sweeping_main_db_only_removes_expired_keys() {
+        let mut db = MainDB::new();
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_millis();
+        db.insert("expiring".to_string(), "value".to_string(), Some(now + 5));
+        db.insert("live".to_string(), "value".to_string(), Some(now + 60_000));
+        tokio::time::sleep(Duration::from_millis(10)).await;
+
+        assert_eq!(db.sweep_expired(), vec!["expiring".to_string()]);
+
+        assert!(!db.db.contains_key("expiring"));
+        assert!(db.db.contains_key("live"));
+        assert_eq!(db.debug_ttl_keys(), vec!["live".to_string()]);
+    }
+
+    #[tokio::test]
+    async fn test_removing_expired_keys_records_how_long_they_were_kept() {
+        let metrics = Arc::new(Metrics::default());
+        let mut db = MainDB::new().with_metrics(Some(metrics.clone()));
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_millis();
+        db.insert("accessed".to_string(), "value".to_string(), Some(now + 5));
+        db.insert("swept".to_string(), "value".to_string(), Some(now + 5));
+        db.insert("live".to_string(), "value".to_string(), Some(now + 60_000));
+        tokio::time::sleep(Duration::from_millis(30)).await;
+
+        assert!(db.get("accessed").is_none());
+        assert_eq!(metrics.snapshot().expired_keys_reclaimed(), 1);
+        assert_eq!(db.sweep_expired(), vec!["swept".to_string()]);
+        assert!(db.get("live").is_some());
+
+        let snapshot = metrics.snapshot();
+        assert_eq!(snapshot.expired_keys_reclaimed(), 2);
+        assert!(snapshot.expiry_lag_sum() >= Duration::from_millis(40));
+    }
+
+    #[tokio::test]
+    async fn test_exceeding_the_max_ttl_keys_sweeps_the_expired_ones() {
+        let mut db = MainDB::new().with_max_ttl_keys(Some(10));
+        for round in 0..5 {
+            let now = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap()
+                .as_millis();
+            for i in 0..10 {
+                db.insert(format!("{round}-{i}"), "value".to_string(), Some(now + 5));
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        // Every round pushed the expired keys of the previous one out
+        assert!(db.debug_ttl_keys().len() <= 10);
+
+        db.insert("no-ttl".to_string(), "value".to_string(), None);
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_millis();
+        db.insert("live".to_string(), "value".to_string(), Some(now + 60_000));
+        assert_eq!(db.debug_ttl_keys(), vec!["live".to_string()]);
+        assert!(db.db.contains_key("no-ttl"));
+    }
+
+    #[test]
+    fn test_live_ttl_keys_space_out_the_forced_sweeps() {
+        let mut db = MainDB::new().with_max_ttl_keys(Some(4));
+        let valid_until = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_millis()
+            + 60_000;
+        for i in 0..5 {
+            db.insert(i.to_string(), "value".to_string(), Some(valid_until));
+        }
+        assert_eq!(db.ttl_keys_sweep_threshold, 10);
+        for i in 5..100 {
+            db.insert(i.to_string(), "value".to_string(), Some(valid_until));
+        }
+        assert_eq!(db.debug_ttl_keys().len(), 100);
+        assert_eq!(db.ttl_keys_sweep_threshold, 190);
+    }
+
+    #[tokio::test]
+    async fn test_debug_ttl_keys_only_lists_keys_with_ttl() {
+        let db = Db::new(&Handle::current(), 4);
+        let valid_until = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_millis()
+            + 60_000;
+        for key in ["a", "b", "c"] {
+            db.insert(key.to_string(), "value".into(), Some(valid_until))
+                .await;
+        }
+        db.insert("no-ttl".to_string(), "value".into(), None).await;
+        assert_eq!(db.debug_ttl_keys().await, vec!["a", "b", "c"]);
+
+        db.remove("b").await;
+        assert_eq!(db.debug_ttl_keys().await, vec!["a", "c"]);
+
+        db.clear().await;
+        assert!(db.debug_ttl_keys().await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_warming_seeds_well_formed_lines_and_skips_the_rest() {
+        let db = Db::new(&Handle::current(), 4);
+        let long_key = "k".repeat(u8::MAX as usize + 1);
+        let contents = format!(
+            "foo\tbar\n\nno tab\n\tmissing key\nmissing value\t\n{long_key}\tvalue\ntabs\tin\tvalue\n"
+        );
+
+        assert_eq!(warm(&db, &contents).await, (2, 4));
+
+        assert_eq!(
+            db.get("foo").await.unwrap().unwrap().value.as_bytes(),
+            b"bar"
+        );
+        assert_eq!(
+            db.get("tabs").await.unwrap().unwrap().value.as_bytes(),
+            b"in\tvalue"
+        );
+        assert!(!db.contains_key("no tab").await);
+        assert!(!db.contains_key("missing value").await);
+        assert!(!db.contains_key(&long_key).await);
     }
 }
@@ -1,27 +1,63 @@
 use crate::error::ParseError;
 use crate::error::Result;
 use crate::Error;
+use bytes::Bytes;
 use std::fmt::{Display, Formatter};
 use std::ops::Deref;
 
-static NO_TTL_INDICATOR: u128 = 0;
-/// Value must not be greater than 1MB
-static MAX_VALUE_LENGTH: u32 = 1024 * 1024;
+pub(crate) use crate::protocol::MAX_VALUE_LENGTH;
+use crate::protocol::{MAX_KEY_LENGTH, NO_TTL as NO_TTL_INDICATOR};
+
+/// The TTL of a value as reported by the server, see `ResponseGet::ttl`.
+///
+/// Responses only carry a TTL along with a value, so no TTL at all can be told apart
+/// from a response that didn't say.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum TtlState {
+    /// The value never expires.
+    NoTtl,
+    /// The value expires at this many milliseconds since the unix epoch.
+    ExpiresAt(u128),
+    /// The response carried no value and so no TTL either, e.g. because the key was missing.
+    Unknown,
+}
+
+impl TtlState {
+    /// Returns when the value expires in milliseconds since the unix epoch,
+    /// `None` if it never expires or the TTL is unknown.
+    pub fn expires_at(&self) -> Option<u128> {
+        match self {
+            Self::ExpiresAt(ttl) => Some(*ttl),
+            Self::NoTtl | Self::Unknown => None,
+        }
+    }
+}
+
+impl From<Option<u128>> for TtlState {
+    /// Turns the TTL of a value into its state, a value without TTL never expires.
+    fn from(ttl_since_unix_epoch_in_millis: Option<u128>) -> Self {
+        match ttl_since_unix_epoch_in_millis {
+            Some(ttl) => Self::ExpiresAt(ttl),
+            None => Self::NoTtl,
+        }
+    }
+}
 
 #[derive(Debug, Copy, Clone)]
 #[cfg_attr(test, derive(PartialEq, Eq))]
-// A value of 0 means no TTL
-pub(crate) struct TTLSinceUnixEpochInMillis(u128);
+pub(crate) struct TTLSinceUnixEpochInMillis(Option<u64>);
 
+/// The value is transferred as raw bytes,
+/// it is only checked for valid UTF-8 where it is used as a string.
 #[derive(Debug, Eq, PartialEq)]
-pub(crate) struct Value(String);
+pub(crate) struct Value(Bytes);
 
-#[derive(Debug, Eq, PartialEq)]
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
 pub(crate) struct Key(String);
 
 impl Display for Value {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}", self.0)
+        write!(f, "{}", String::from_utf8_lossy(&self.0))
     }
 }
 
@@ -32,31 +68,44 @@ impl Display for Key {
 }
 
 impl Value {
-    pub(crate) fn parse(v: String) -> Result<Self> {
+    pub(crate) fn parse(v: impl Into<Bytes>) -> Result<Self> {
+        let v = v.into();
         if v.len() > MAX_VALUE_LENGTH as usize {
             return Err(Error::new_parse(ParseError::ValueTooLong));
         }
         Ok(Self(v))
     }
 
-    pub(crate) fn into_inner(self) -> String {
+    pub(crate) fn into_string(self) -> Result<String> {
+        String::from_utf8(self.0.to_vec()).map_err(|e| Error::new_parse(ParseError::String(e)))
+    }
+
+    pub(crate) fn into_bytes(self) -> Bytes {
         self.0
     }
 
+    /// Returns the bytes without copying them, the buffer is shared.
+    pub(crate) fn to_bytes(&self) -> Bytes {
+        self.0.clone()
+    }
+
     pub(crate) fn len(&self) -> u32 {
         // Guaranteed to not overflow because of MAX_VALUE_LENGTH used in `Self::parse`
         self.0.len() as u32
     }
 
     pub(crate) fn as_bytes(&self) -> &[u8] {
-        self.0.as_bytes()
+        &self.0
     }
 }
 
 impl Key {
+    /// Keys must not be empty, a key length of `0` on the wire stands for no key at all.
     pub(crate) fn parse(k: String) -> Result<Self> {
-        // Key must not be longer than u8::MAX
-        if k.len() > u8::MAX as usize {
+        if k.is_empty() {
+            return Err(Error::new_parse(ParseError::KeyEmpty));
+        }
+        if k.len() > MAX_KEY_LENGTH {
             return Err(Error::new_parse(ParseError::KeyTooLong));
         }
         Ok(Self(k))
@@ -67,7 +116,7 @@ impl Key {
     }
 
     pub(crate) fn len(&self) -> u8 {
-        // Guaranteed to not overflow because of u8::MAX used in `Self::parse`
+        // Guaranteed to not overflow because of MAX_KEY_LENGTH used in `Self::parse`
         self.0.len() as u8
     }
 
@@ -85,34 +134,29 @@ impl Deref for Key {
     }
 }
 
-// TODO Do we really need to implement Deref here?
-impl Deref for Value {
-    type Target = str;
-
-    fn deref(&self) -> &Self::Target {
-        &self.0
-    }
-}
-
 impl TTLSinceUnixEpochInMillis {
+    /// TTLs that can't be told apart from `NO_TTL_INDICATOR` on the wire
+    /// (about 584 million years from the epoch) are capped to the furthest one that can.
     pub(crate) fn parse(ttl: Option<u128>) -> Self {
-        ttl.map_or(Self(NO_TTL_INDICATOR), |ttl_since_unix_epoch_in_millis| {
-            if ttl_since_unix_epoch_in_millis == NO_TTL_INDICATOR {
-                Self(NO_TTL_INDICATOR)
-            } else {
-                Self(ttl_since_unix_epoch_in_millis)
-            }
-        })
+        Self(ttl.map(|ttl_since_unix_epoch_in_millis| {
+            u64::try_from(ttl_since_unix_epoch_in_millis)
+                .unwrap_or(u64::MAX)
+                .min(NO_TTL_INDICATOR - 1)
+        }))
     }
 
-    pub(crate) fn into_inner(self) -> u128 {
-        self.0
+    pub(crate) fn from_wire(ttl: u64) -> Self {
+        match ttl {
+            NO_TTL_INDICATOR => Self(None),
+            ttl => Self(Some(ttl)),
+        }
+    }
+
+    pub(crate) fn into_wire(self) -> u64 {
+        self.0.unwrap_or(NO_TTL_INDICATOR)
     }
 
     pub(crate) fn into_ttl(self) -> Option<u128> {
-        match self.0 {
-            0 => None,
-            ttl => Some(ttl),
-        }
+        self.0.map(u128::from)
     }
 }
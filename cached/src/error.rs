@@ -1,3 +1,5 @@
+use crate::{OpCode, StatusCode};
+use std::sync::Arc;
 use thiserror::Error;
 
 pub(crate) type Result<T> = std::result::Result<T, Error>;
@@ -35,9 +37,81 @@ impl Error {
         Self(e.into())
     }
 
+    /// Returns the status the server responded with, if the error is due to it.
+    pub fn status(&self) -> Option<StatusCode> {
+        match self {
+            Self(ErrorInner::Server(ClientError::Status(status))) => Some(*status),
+            _ => None,
+        }
+    }
+
+    /// Returns whether the connection closed while the request was awaiting its response.
+    ///
+    /// Such a request may or may not have been applied by the server,
+    /// so it is safe to retry on a new connection if it is idempotent, like getting or setting a value.
+    pub fn is_closed_while_awaiting(&self) -> bool {
+        matches!(
+            self,
+            Self(ErrorInner::Connection(
+                ConnectionError::ClosedWhileAwaiting(_)
+            ))
+        )
+    }
+
+    /// Returns whether the error is due to the connection rather than the request,
+    /// so the request may succeed once reconnected, see [`ClientConnection::reconnect`](crate::ClientConnection::reconnect).
+    pub fn is_connection_error(&self) -> bool {
+        matches!(self, Self(ErrorInner::Connection(_)))
+    }
+
+    /// Returns the kind of the IO error the error is due to, if any.
+    ///
+    /// Errors of requests that failed because their connection closed report what closed it.
+    pub fn io_error_kind(&self) -> Option<std::io::ErrorKind> {
+        match self {
+            Self(ErrorInner::Connection(ConnectionError::Io(e) | ConnectionError::Write(e))) => {
+                Some(e.kind())
+            }
+            Self(ErrorInner::Connection(
+                ConnectionError::Closed(reason) | ConnectionError::ClosedWhileAwaiting(reason),
+            )) => reason.io_error_kind(),
+            _ => None,
+        }
+    }
+
     pub(crate) fn is_incomplete_frame(&self) -> bool {
         matches!(self, Self(ErrorInner::Frame(FrameError::Incomplete)))
     }
+
+    pub(crate) fn is_key_not_utf8(&self) -> bool {
+        matches!(self, Self(ErrorInner::Parse(ParseError::KeyNotUtf8)))
+    }
+
+    /// Returns whether the error is due to what the peer sent or to the peer going away,
+    /// rather than to a bug, so the connection can simply be closed.
+    pub(crate) fn is_caused_by_peer(&self) -> bool {
+        matches!(
+            self,
+            Self(
+                ErrorInner::Frame(_)
+                    | ErrorInner::Parse(_)
+                    | ErrorInner::Connection(
+                        ConnectionError::ResetByPeer | ConnectionError::ReadResponse
+                    )
+            )
+        )
+    }
+
+    /// Returns the id and operation of the request, if the error is due to it carrying an invalid key.
+    pub(crate) fn invalid_key_request(&self) -> Option<(u32, OpCode)> {
+        match self {
+            Self(ErrorInner::Frame(FrameError::InvalidKey {
+                request_id,
+                op_code,
+            })) => Some((*request_id, *op_code)),
+            _ => None,
+        }
+    }
 }
 
 #[derive(Error, Debug)]
@@ -52,10 +126,16 @@ pub(crate) enum ParseError {
     UnexpectedKey,
     #[error("unexpected value")]
     UnexpectedValue,
+    #[error("key empty")]
+    KeyEmpty,
     #[error("key too long")]
     KeyTooLong,
+    #[error("key is not valid UTF-8")]
+    KeyNotUtf8,
     #[error("value too long")]
     ValueTooLong,
+    #[error("unknown chunk step")]
+    UnknownChunkStep,
     #[error(transparent)]
     String(#[from] std::string::FromUtf8Error),
     #[error("could not parse")]
@@ -70,28 +150,66 @@ pub(crate) enum FrameError {
     InvalidOpCode,
     #[error("invalid StatusCode")]
     InvalidStatusCode,
+    /// The total frame length in the header does not fit the frame.
+    #[error("invalid frame length")]
+    InvalidLength,
+    /// The rest of a frame did not arrive in time after its header.
+    #[error("timed out receiving frame")]
+    Timeout,
+    /// The frame arrived in full and was skipped, but a key of the request it carries is not valid UTF-8.
+    #[error("invalid key in request {request_id}")]
+    InvalidKey { request_id: u32, op_code: OpCode },
 }
 
 #[derive(Error, Debug)]
 pub(crate) enum ConnectionError {
     #[error("could not read response")]
     ReadResponse,
+    /// The peer answered a request that is not awaiting a response,
+    /// so responses can no longer be told apart.
+    #[error("received response to unknown request {0}")]
+    UnknownRequestId(u32),
     #[error("connection reset by peer")]
     ResetByPeer,
-    #[error("could not write")]
-    Write,
+    #[error("could not write: {0}")]
+    Write(std::io::Error),
+    /// The peer did not take the data in time, most likely it stopped reading.
+    #[error("timed out writing")]
+    WriteTimedOut,
+    /// The peer did not answer a keepalive ping within the keepalive interval.
+    #[error("timed out awaiting keepalive ping")]
+    PingTimedOut,
+    /// The server did not answer in time, see [`Client::connect_ready`](crate::Client::connect_ready).
+    #[error("server not ready in time")]
+    NotReady,
+    /// The name given to [`Client::balanced`](crate::Client::balanced) resolved to no address.
+    #[error("no address to connect to")]
+    NoAddress,
     #[error("could not send")]
     Send,
     #[error("could not receive")]
     Receive,
     #[error(transparent)]
     Io(#[from] std::io::Error),
+    #[cfg(feature = "runtime")]
     #[error(transparent)]
     Acquire(#[from] tokio::sync::AcquireError),
+    #[error("connection closed: {0}")]
+    Closed(Arc<Error>),
+    /// The connection closed after the request was submitted but before its response arrived,
+    /// the request may or may not have been applied.
+    #[error("connection closed while awaiting response: {0}")]
+    ClosedWhileAwaiting(Arc<Error>),
 }
 
 #[derive(Error, Debug)]
 pub(crate) enum ClientError {
     #[error("expected value")]
     ExpectedValue,
+    #[error("unexpected response")]
+    UnexpectedResponse,
+    #[error("server responded with: {0}")]
+    Status(StatusCode),
+    #[error("ttl out of range")]
+    TtlOutOfRange,
 }
@@ -0,0 +1,267 @@
+use futures_core::Stream;
+use std::collections::hash_map::RandomState;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::hash::BuildHasher;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use tokio::sync::broadcast;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::mpsc;
+
+/// How many batches a slow subscriber may fall behind before it misses some.
+pub(crate) const EVICTION_CHANNEL_CAPACITY: usize = 16;
+
+/// Why keys were evicted.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[non_exhaustive]
+pub enum EvictionReason {
+    /// The TTL of the keys elapsed.
+    Expired,
+}
+
+/// The keys evicted together, e.g. during a single sweep of expired keys.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct EvictionBatch {
+    pub keys: Vec<String>,
+    pub reason: EvictionReason,
+}
+
+/// Receives the eviction batches of a server, obtained via
+/// [`ServerHandle::subscribe_evictions`](crate::ServerHandle::subscribe_evictions).
+///
+/// Only batches emitted after subscribing are received.
+#[derive(Debug)]
+pub struct EvictionSubscriber {
+    receiver: broadcast::Receiver<EvictionBatch>,
+    missed_batches: u64,
+}
+
+impl EvictionSubscriber {
+    pub(crate) fn new(receiver: broadcast::Receiver<EvictionBatch>) -> Self {
+        Self {
+            receiver,
+            missed_batches: 0,
+        }
+    }
+
+    /// Waits for the next batch, `None` once the server and all of its handles are gone.
+    ///
+    /// A subscriber that falls behind by more than a few batches skips the oldest ones,
+    /// see [`EvictionSubscriber::missed_batches`].
+    pub async fn recv(&mut self) -> Option<EvictionBatch> {
+        loop {
+            match self.receiver.recv().await {
+                Ok(batch) => return Some(batch),
+                Err(RecvError::Lagged(missed)) => self.missed_batches += missed,
+                Err(RecvError::Closed) => return None,
+            }
+        }
+    }
+
+    /// Returns how many batches were skipped because the subscriber fell behind.
+    pub fn missed_batches(&self) -> u64 {
+        self.missed_batches
+    }
+}
+
+/// Receives the batches of keys a server expires over the network, obtained via
+/// [`Client::subscribe_evictions`](crate::Client::subscribe_evictions).
+///
+/// If the connection to the server drops, the stream connects and subscribes again in the background,
+/// waiting a little longer after every failed attempt, for as long as the stream is around.
+/// Batches removed while the stream is not subscribed are lost,
+/// so are those a slow consumer falls behind on by more than a few batches.
+#[derive(Debug)]
+pub struct EvictionStream {
+    receiver: mpsc::Receiver<EvictionBatch>,
+}
+
+impl EvictionStream {
+    pub(crate) fn new(receiver: mpsc::Receiver<EvictionBatch>) -> Self {
+        Self { receiver }
+    }
+
+    /// Waits for the next batch.
+    pub async fn recv(&mut self) -> Option<EvictionBatch> {
+        self.receiver.recv().await
+    }
+}
+
+impl Stream for EvictionStream {
+    type Item = EvictionBatch;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.receiver.poll_recv(cx)
+    }
+}
+
+/// Which entry a full shard evicts to make room for a new key,
+/// see [`Server::max_entries`](crate::Server::max_entries).
+///
+/// Once a limit is set, every policy keeps a copy of each key plus 48 bytes of usage per entry.
+/// The memory overhead listed for each policy comes on top of that.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum EvictionPolicy {
+    /// Evicts the least recently used entry.
+    ///
+    /// Orders the entries by their last access, another copy of each key plus 8 bytes per entry.
+    #[default]
+    Lru,
+    /// Evicts the least frequently used entry, the least recently used one among equally used entries.
+    ///
+    /// Orders the entries by their accesses, another copy of each key plus 16 bytes per entry.
+    Lfu,
+    /// Evicts an entry at random.
+    ///
+    /// There is no overhead, but picking the entry walks half of the shard on average.
+    Random,
+    /// Evicts the entry expiring next, entries without a TTL are only evicted, at random,
+    /// if no entry has a TTL.
+    ///
+    /// Orders the entries by their TTL, another copy of the key plus 16 bytes per entry with a TTL.
+    TtlNearest,
+}
+
+#[derive(Debug, Copy, Clone)]
+struct Usage {
+    last_access: u64,
+    accesses: u64,
+    ttl_since_unix_epoch_in_millis: Option<u128>,
+}
+
+/// Keeps track of the keys of a shard to pick the entry to evict according to the policy.
+#[derive(Debug)]
+pub(crate) struct EvictionIndex {
+    policy: EvictionPolicy,
+    usage: HashMap<String, Usage>,
+    // Only maintained for the policies that need them
+    by_recency: BTreeMap<u64, String>,
+    by_frequency: BTreeSet<(u64, u64, String)>,
+    by_expiry: BTreeSet<(u128, String)>,
+    clock: u64,
+    random: RandomState,
+}
+
+impl EvictionIndex {
+    pub(crate) fn new(policy: EvictionPolicy) -> Self {
+        Self {
+            policy,
+            usage: HashMap::new(),
+            by_recency: BTreeMap::new(),
+            by_frequency: BTreeSet::new(),
+            by_expiry: BTreeSet::new(),
+            clock: 0,
+            random: RandomState::new(),
+        }
+    }
+
+    /// Registers a key that was stored, replacing what was known about it before.
+    pub(crate) fn inserted(&mut self, key: &str, ttl_since_unix_epoch_in_millis: Option<u128>) {
+        self.removed(key);
+        let usage = Usage {
+            last_access: self.tick(),
+            accesses: 1,
+            ttl_since_unix_epoch_in_millis,
+        };
+        self.index(key, usage);
+        self.usage.insert(key.to_string(), usage);
+    }
+
+    /// Registers an access to a stored key.
+    pub(crate) fn accessed(&mut self, key: &str) {
+        let Some(usage) = self.usage.get(key).copied() else {
+            return;
+        };
+        self.unindex(key, usage);
+        let usage = Usage {
+            last_access: self.tick(),
+            accesses: usage.accesses.saturating_add(1),
+            ..usage
+        };
+        self.index(key, usage);
+        self.usage.insert(key.to_string(), usage);
+    }
+
+    pub(crate) fn removed(&mut self, key: &str) {
+        if let Some(usage) = self.usage.remove(key) {
+            self.unindex(key, usage);
+        }
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.usage.clear();
+        self.by_recency.clear();
+        self.by_frequency.clear();
+        self.by_expiry.clear();
+    }
+
+    /// Returns the key to evict next, `None` if no key is known.
+    pub(crate) fn victim(&mut self) -> Option<String> {
+        match self.policy {
+            EvictionPolicy::Lru => self.by_recency.values().next().cloned(),
+            EvictionPolicy::Lfu => self
+                .by_frequency
+                .iter()
+                .next()
+                .map(|(_, _, key)| key.clone()),
+            EvictionPolicy::Random => self.random_key(),
+            EvictionPolicy::TtlNearest => self
+                .by_expiry
+                .iter()
+                .next()
+                .map(|(_, key)| key.clone())
+                .or_else(|| self.random_key()),
+        }
+    }
+
+    fn random_key(&mut self) -> Option<String> {
+        if self.usage.is_empty() {
+            return None;
+        }
+        let tick = self.tick();
+        let idx = self.random.hash_one(tick) as usize % self.usage.len();
+        self.usage.keys().nth(idx).cloned()
+    }
+
+    fn tick(&mut self) -> u64 {
+        self.clock += 1;
+        self.clock
+    }
+
+    fn index(&mut self, key: &str, usage: Usage) {
+        match self.policy {
+            EvictionPolicy::Lru => {
+                self.by_recency.insert(usage.last_access, key.to_string());
+            }
+            EvictionPolicy::Lfu => {
+                self.by_frequency
+                    .insert((usage.accesses, usage.last_access, key.to_string()));
+            }
+            EvictionPolicy::TtlNearest => {
+                if let Some(ttl) = usage.ttl_since_unix_epoch_in_millis {
+                    self.by_expiry.insert((ttl, key.to_string()));
+                }
+            }
+            EvictionPolicy::Random => {}
+        }
+    }
+
+    fn unindex(&mut self, key: &str, usage: Usage) {
+        match self.policy {
+            EvictionPolicy::Lru => {
+                self.by_recency.remove(&usage.last_access);
+            }
+            EvictionPolicy::Lfu => {
+                self.by_frequency
+                    .remove(&(usage.accesses, usage.last_access, key.to_string()));
+            }
+            EvictionPolicy::TtlNearest => {
+                if let Some(ttl) = usage.ttl_since_unix_epoch_in_millis {
+                    self.by_expiry.remove(&(ttl, key.to_string()));
+                }
+            }
+            EvictionPolicy::Random => {}
+        }
+    }
+}
@@ -1,14 +1,26 @@
 use crate::error::{Error, FrameError, Result};
-use bytes::{Buf, Bytes};
+use bytes::{Buf, BufMut, Bytes, BytesMut};
 use std::fmt::Debug;
 
 use crate::domain::{Key, TTLSinceUnixEpochInMillis, Value};
 use crate::primitives::OpCode;
+use crate::protocol;
 use crate::StatusCode;
 
-static HEADER_SIZE_BYTES: u8 = 23;
+/// op code (1) + status or padding (1) + key length (1) + request id (4) + total frame length (4).
+///
+/// The fixed part of the header is followed by the TTL, flags and updated at fields for the frames that carry them only,
+/// see [`RequestHeader::size`] and [`ResponseHeader::size`].
+static HEADER_SIZE_BYTES: u8 = protocol::HEADER_SIZE;
+/// The TTL is transferred as `u64` milliseconds since the unix epoch.
+static TTL_SIZE_BYTES: u8 = protocol::TTL_SIZE;
+/// The flags are transferred as a `u32`.
+static FLAGS_SIZE_BYTES: u8 = protocol::FLAGS_SIZE;
+/// When the value was last set is transferred as `u64` milliseconds since the unix epoch.
+static UPDATED_AT_SIZE_BYTES: u8 = protocol::UPDATED_AT_SIZE;
 
 #[derive(Debug)]
+#[cfg_attr(test, derive(PartialEq, Eq))]
 pub(crate) struct ResponseFrame {
     pub header: ResponseHeader,
     pub key: Option<Key>,
@@ -27,7 +39,8 @@ impl ResponseFrame {
         let value_length = value.as_ref().map_or(0, |v| v.len());
         // TODO?
         // We're assuming no overflow here as value should be sufficiently smaller than u32:MAX - 2*u8::MAX
-        let total_frame_length = ResponseHeader::size() as u32 + key_length as u32 + value_length;
+        let total_frame_length =
+            ResponseHeader::size(op_code) as u32 + key_length as u32 + value_length;
         let header = ResponseHeader::new(
             op_code,
             status,
@@ -37,6 +50,49 @@ impl ResponseFrame {
         );
         Ok(Self { header, key, value })
     }
+
+    /// Tags the frame with the id of the request it answers.
+    pub(crate) fn with_request_id(mut self, request_id: u32) -> Self {
+        self.header.request_id = request_id;
+        self
+    }
+
+    /// Sets the flags stored with the value, they are only sent if the op code carries flags.
+    pub(crate) fn with_flags(mut self, flags: u32) -> Self {
+        self.header.flags = flags;
+        self
+    }
+
+    /// Sets when the value was last set, it is only sent if the op code carries it.
+    pub(crate) fn with_updated_at(mut self, updated_at_in_millis: u64) -> Self {
+        self.header.updated_at_in_millis = updated_at_in_millis;
+        self
+    }
+
+    /// Appends the frame in its wire format to `buf`.
+    pub(crate) fn encode(&self, buf: &mut BytesMut) {
+        buf.reserve(self.header.total_frame_length as usize);
+        buf.put_u8(self.header.op_code as u8);
+        buf.put_u8(self.header.status as u8);
+        buf.put_u8(self.header.key_length);
+        buf.put_u32(self.header.request_id);
+        buf.put_u32(self.header.total_frame_length);
+        if ResponseHeader::has_ttl(self.header.op_code) {
+            buf.put_u64(self.header.ttl_since_unix_epoch_in_millis.into_wire());
+        }
+        if ResponseHeader::has_flags(self.header.op_code) {
+            buf.put_u32(self.header.flags);
+        }
+        if ResponseHeader::has_updated_at(self.header.op_code) {
+            buf.put_u64(self.header.updated_at_in_millis);
+        }
+        if let Some(key) = &self.key {
+            buf.put_slice(key.as_bytes());
+        }
+        if let Some(value) = &self.value {
+            buf.put_slice(value.as_bytes());
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -58,7 +114,8 @@ impl RequestFrame {
         let value_length = value.as_ref().map_or(0, |v| v.len());
         // TODO?
         // We're assuming no overflow here as value should be sufficiently smaller than u32:MAX - 2*u8::MAX
-        let total_frame_length = ResponseHeader::size() as u32 + key_length as u32 + value_length;
+        let total_frame_length =
+            RequestHeader::size(op_code) as u32 + key_length as u32 + value_length;
         let header = RequestHeader::new(
             op_code,
             key_length,
@@ -67,6 +124,41 @@ impl RequestFrame {
         );
         Ok(Self { header, key, value })
     }
+
+    /// Tags the frame with an id the server echoes back in its response.
+    pub(crate) fn with_request_id(mut self, request_id: u32) -> Self {
+        self.header.request_id = request_id;
+        self
+    }
+
+    /// Sets the flags to store with the value, they are only sent if the op code carries flags.
+    pub(crate) fn with_flags(mut self, flags: u32) -> Self {
+        self.header.flags = flags;
+        self
+    }
+
+    /// Appends the frame in its wire format to `buf`.
+    pub(crate) fn encode(&self, buf: &mut BytesMut) {
+        buf.reserve(self.header.total_frame_length as usize);
+        buf.put_u8(self.header.op_code as u8);
+        // Padding byte
+        buf.put_u8(0);
+        buf.put_u8(self.header.key_length);
+        buf.put_u32(self.header.request_id);
+        buf.put_u32(self.header.total_frame_length);
+        if RequestHeader::has_ttl(self.header.op_code) {
+            buf.put_u64(self.header.ttl_since_unix_epoch_in_millis.into_wire());
+        }
+        if RequestHeader::has_flags(self.header.op_code) {
+            buf.put_u32(self.header.flags);
+        }
+        if let Some(key) = &self.key {
+            buf.put_slice(key.as_bytes());
+        }
+        if let Some(value) = &self.value {
+            buf.put_slice(value.as_bytes());
+        }
+    }
 }
 
 #[derive(Debug, Copy, Clone)]
@@ -74,7 +166,9 @@ impl RequestFrame {
 pub(crate) struct RequestHeader {
     pub op_code: OpCode,
     pub key_length: u8,
+    pub request_id: u32,
     pub ttl_since_unix_epoch_in_millis: TTLSinceUnixEpochInMillis,
+    pub flags: u32,
     pub total_frame_length: u32,
 }
 
@@ -88,13 +182,33 @@ impl RequestHeader {
         Self {
             op_code,
             key_length,
+            request_id: 0,
             ttl_since_unix_epoch_in_millis,
+            flags: 0,
             total_frame_length,
         }
     }
 
-    pub(crate) fn size() -> u8 {
-        HEADER_SIZE_BYTES
+    /// Only Set, SetNegative and SetMany requests carry a TTL.
+    pub(crate) fn has_ttl(op_code: OpCode) -> bool {
+        protocol::request_has_ttl(op_code)
+    }
+
+    /// Only Set requests carry flags.
+    pub(crate) fn has_flags(op_code: OpCode) -> bool {
+        protocol::request_has_flags(op_code)
+    }
+
+    /// The size of the header, including the TTL field if the op code carries one.
+    pub(crate) fn size(op_code: OpCode) -> u8 {
+        let mut size = HEADER_SIZE_BYTES;
+        if Self::has_ttl(op_code) {
+            size += TTL_SIZE_BYTES;
+        }
+        if Self::has_flags(op_code) {
+            size += FLAGS_SIZE_BYTES;
+        }
+        size
     }
 }
 
@@ -104,7 +218,10 @@ pub(crate) struct ResponseHeader {
     pub op_code: OpCode,
     pub status: StatusCode,
     pub key_length: u8,
+    pub request_id: u32,
     pub ttl_since_unix_epoch_in_millis: TTLSinceUnixEpochInMillis,
+    pub flags: u32,
+    pub updated_at_in_millis: u64,
     pub total_frame_length: u32,
 }
 
@@ -120,13 +237,42 @@ impl ResponseHeader {
             op_code,
             status,
             key_length,
+            request_id: 0,
             ttl_since_unix_epoch_in_millis,
+            flags: 0,
+            updated_at_in_millis: 0,
             total_frame_length,
         }
     }
 
-    pub(crate) fn size() -> u8 {
-        HEADER_SIZE_BYTES
+    /// Only Get responses carry a TTL.
+    pub(crate) fn has_ttl(op_code: OpCode) -> bool {
+        protocol::response_has_ttl(op_code)
+    }
+
+    /// Only Get responses carry flags.
+    pub(crate) fn has_flags(op_code: OpCode) -> bool {
+        protocol::response_has_flags(op_code)
+    }
+
+    /// Only Get responses carry when the value was last set.
+    pub(crate) fn has_updated_at(op_code: OpCode) -> bool {
+        protocol::response_has_updated_at(op_code)
+    }
+
+    /// The size of the header, including the TTL field if the op code carries one.
+    pub(crate) fn size(op_code: OpCode) -> u8 {
+        let mut size = HEADER_SIZE_BYTES;
+        if Self::has_ttl(op_code) {
+            size += TTL_SIZE_BYTES;
+        }
+        if Self::has_flags(op_code) {
+            size += FLAGS_SIZE_BYTES;
+        }
+        if Self::has_updated_at(op_code) {
+            size += UPDATED_AT_SIZE_BYTES;
+        }
+        size
     }
 }
 
@@ -140,14 +286,31 @@ impl TryFrom<Bytes> for RequestHeader {
         let op_code = OpCode::try_from(value.get_u8())?;
         let _ = value.get_u8();
         let key_length = value.get_u8();
-        let ttl_since_unix_epoch_in_millis =
-            TTLSinceUnixEpochInMillis::parse(Some(value.get_u128()));
+        let request_id = value.get_u32();
         let total_frame_length = value.get_u32();
+        let ttl_since_unix_epoch_in_millis = if Self::has_ttl(op_code) {
+            if value.remaining() < TTL_SIZE_BYTES as usize {
+                return Err(Error::new_frame(FrameError::Incomplete));
+            }
+            TTLSinceUnixEpochInMillis::from_wire(value.get_u64())
+        } else {
+            TTLSinceUnixEpochInMillis::parse(None)
+        };
+        let flags = if Self::has_flags(op_code) {
+            if value.remaining() < FLAGS_SIZE_BYTES as usize {
+                return Err(Error::new_frame(FrameError::Incomplete));
+            }
+            value.get_u32()
+        } else {
+            0
+        };
 
         Ok(Self {
             op_code,
             key_length,
+            request_id,
             ttl_since_unix_epoch_in_millis,
+            flags,
             total_frame_length,
         })
     }
@@ -163,15 +326,41 @@ impl TryFrom<Bytes> for ResponseHeader {
         let op_code = OpCode::try_from(value.get_u8())?;
         let status = StatusCode::try_from(value.get_u8())?;
         let key_length = value.get_u8();
-        let ttl_since_unix_epoch_in_millis =
-            TTLSinceUnixEpochInMillis::parse(Some(value.get_u128()));
+        let request_id = value.get_u32();
         let total_frame_length = value.get_u32();
+        let ttl_since_unix_epoch_in_millis = if Self::has_ttl(op_code) {
+            if value.remaining() < TTL_SIZE_BYTES as usize {
+                return Err(Error::new_frame(FrameError::Incomplete));
+            }
+            TTLSinceUnixEpochInMillis::from_wire(value.get_u64())
+        } else {
+            TTLSinceUnixEpochInMillis::parse(None)
+        };
+        let flags = if Self::has_flags(op_code) {
+            if value.remaining() < FLAGS_SIZE_BYTES as usize {
+                return Err(Error::new_frame(FrameError::Incomplete));
+            }
+            value.get_u32()
+        } else {
+            0
+        };
+        let updated_at_in_millis = if Self::has_updated_at(op_code) {
+            if value.remaining() < UPDATED_AT_SIZE_BYTES as usize {
+                return Err(Error::new_frame(FrameError::Incomplete));
+            }
+            value.get_u64()
+        } else {
+            0
+        };
 
         Ok(Self {
             op_code,
             status,
             key_length,
+            request_id,
             ttl_since_unix_epoch_in_millis,
+            flags,
+            updated_at_in_millis,
             total_frame_length,
         })
     }
@@ -182,6 +371,152 @@ mod test {
     use super::*;
     use crate::domain::{Key, Value};
     use crate::error::{ErrorInner, ParseError};
+    use crate::parsing::{parse_request_frame, parse_response_frame};
+    use rstest::rstest;
+
+    #[rstest]
+    #[case(OpCode::Set, 23)]
+    #[case(OpCode::SetNegative, 19)]
+    #[case(OpCode::Get, 11)]
+    #[case(OpCode::Delete, 11)]
+    #[case(OpCode::Flush, 11)]
+    fn test_request_header_size(#[case] op_code: OpCode, #[case] expected_size: u8) {
+        assert_eq!(RequestHeader::size(op_code), expected_size);
+    }
+
+    #[rstest]
+    #[case(OpCode::Set, 11)]
+    #[case(OpCode::Get, 31)]
+    #[case(OpCode::Delete, 11)]
+    #[case(OpCode::Flush, 11)]
+    fn test_response_header_size(#[case] op_code: OpCode, #[case] expected_size: u8) {
+        assert_eq!(ResponseHeader::size(op_code), expected_size);
+    }
+
+    #[rstest]
+    #[case(OpCode::Set, Some("ABC"), Some("1234"), 30)]
+    #[case(OpCode::Get, Some("ABC"), None, 14)]
+    #[case(OpCode::Delete, Some("ABC"), None, 14)]
+    #[case(OpCode::Flush, None, None, 11)]
+    fn test_encoded_request_frame_size(
+        #[case] op_code: OpCode,
+        #[case] key: Option<&str>,
+        #[case] value: Option<&str>,
+        #[case] expected_size: usize,
+    ) {
+        let key = key.map(|k| Key::parse(k.to_string()).unwrap());
+        let value = value.map(|v| Value::parse(v.to_string()).unwrap());
+        let frame =
+            RequestFrame::new(op_code, TTLSinceUnixEpochInMillis::parse(None), key, value).unwrap();
+        let mut buf = BytesMut::new();
+        frame.encode(&mut buf);
+        assert_eq!(buf.len(), expected_size);
+        assert_eq!(frame.header.total_frame_length as usize, expected_size);
+        assert_eq!(parse_request_frame(&buf).unwrap(), frame);
+    }
+
+    #[rstest]
+    #[case(OpCode::Set, StatusCode::Ok, None, None, 11)]
+    #[case(OpCode::Get, StatusCode::Ok, Some("ABC"), Some("1234"), 38)]
+    #[case(OpCode::Get, StatusCode::KeyNotFound, None, None, 31)]
+    #[case(OpCode::Delete, StatusCode::Ok, None, None, 11)]
+    #[case(OpCode::Flush, StatusCode::Ok, None, None, 11)]
+    fn test_encoded_response_frame_size(
+        #[case] op_code: OpCode,
+        #[case] status: StatusCode,
+        #[case] key: Option<&str>,
+        #[case] value: Option<&str>,
+        #[case] expected_size: usize,
+    ) {
+        let key = key.map(|k| Key::parse(k.to_string()).unwrap());
+        let value = value.map(|v| Value::parse(v.to_string()).unwrap());
+        let frame = ResponseFrame::new(
+            op_code,
+            status,
+            TTLSinceUnixEpochInMillis::parse(None),
+            key,
+            value,
+        )
+        .unwrap();
+        let mut buf = BytesMut::new();
+        frame.encode(&mut buf);
+        assert_eq!(buf.len(), expected_size);
+        assert_eq!(frame.header.total_frame_length as usize, expected_size);
+        assert_eq!(parse_response_frame(&buf).unwrap(), frame);
+    }
+
+    #[rstest]
+    #[case(None, None)]
+    // The unix epoch itself is a TTL like any other, distinct from no TTL
+    #[case(Some(0), Some(0))]
+    #[case(Some(1), Some(1))]
+    #[case(Some(1_700_000_000_000), Some(1_700_000_000_000))]
+    #[case(Some(u64::MAX as u128 - 1), Some(u64::MAX as u128 - 1))]
+    // TTLs that do not fit into the wire format are capped
+    #[case(Some(u64::MAX as u128), Some(u64::MAX as u128 - 1))]
+    #[case(Some(u128::MAX), Some(u64::MAX as u128 - 1))]
+    fn test_ttl_round_trips_through_request_frame(
+        #[case] ttl: Option<u128>,
+        #[case] expected_ttl: Option<u128>,
+    ) {
+        let key = Key::parse("ABC".to_string()).unwrap();
+        let value = Value::parse("1234".to_string()).unwrap();
+        let frame = RequestFrame::new(
+            OpCode::Set,
+            TTLSinceUnixEpochInMillis::parse(ttl),
+            Some(key),
+            Some(value),
+        )
+        .unwrap();
+        let mut buf = BytesMut::new();
+        frame.encode(&mut buf);
+        assert_eq!(buf.len(), frame.header.total_frame_length as usize);
+
+        let parsed_frame = parse_request_frame(&buf).unwrap();
+        assert_eq!(
+            parsed_frame
+                .header
+                .ttl_since_unix_epoch_in_millis
+                .into_ttl(),
+            expected_ttl
+        );
+    }
+
+    #[rstest]
+    #[case(None, None)]
+    #[case(Some(0), Some(0))]
+    #[case(Some(1), Some(1))]
+    #[case(Some(1_700_000_000_000), Some(1_700_000_000_000))]
+    #[case(Some(u128::MAX), Some(u64::MAX as u128 - 1))]
+    fn test_ttl_round_trips_through_response_frame(
+        #[case] ttl: Option<u128>,
+        #[case] expected_ttl: Option<u128>,
+    ) {
+        let key = Key::parse("ABC".to_string()).unwrap();
+        let value = Value::parse("1234".to_string()).unwrap();
+        let frame = ResponseFrame::new(
+            OpCode::Get,
+            StatusCode::Ok,
+            TTLSinceUnixEpochInMillis::parse(ttl),
+            Some(key),
+            Some(value),
+        )
+        .unwrap()
+        .with_request_id(42);
+        let mut buf = BytesMut::new();
+        frame.encode(&mut buf);
+        assert_eq!(buf.len(), frame.header.total_frame_length as usize);
+
+        let parsed_frame = parse_response_frame(&buf).unwrap();
+        assert_eq!(parsed_frame.header.request_id, 42);
+        assert_eq!(
+            parsed_frame
+                .header
+                .ttl_since_unix_epoch_in_millis
+                .into_ttl(),
+            expected_ttl
+        );
+    }
 
     #[test]
     fn test_parsing_request_with_valid_long_key_works() {
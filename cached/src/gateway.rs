@@ -0,0 +1,99 @@
+//! Serving the cache over protocols other than the wire protocol, see the `http` and `resp` features.
+use std::future::Future;
+use std::io;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::runtime::Handle;
+use tokio::sync::{broadcast, mpsc, OwnedSemaphorePermit, Semaphore};
+#[cfg(feature = "tracing")]
+use tracing::debug;
+
+use crate::server::Service;
+use crate::shutdown::Shutdown;
+
+/// How long to wait before accepting again after accepting a connection failed.
+const ACCEPT_BACKOFF: Duration = Duration::from_millis(10);
+
+/// Everything a gateway shares with the server it belongs to.
+#[derive(Debug)]
+pub(crate) struct Gateway {
+    pub(crate) service: Service,
+    pub(crate) connection_limit: Arc<Semaphore>,
+    pub(crate) notify_shutdown: broadcast::Receiver<()>,
+    pub(crate) shutdown_complete_tx: mpsc::Sender<()>,
+    pub(crate) runtime: Handle,
+}
+
+/// A connection accepted by a gateway, holding its share of the server until dropped.
+#[derive(Debug)]
+pub(crate) struct GatewayConnection {
+    pub(crate) stream: TcpStream,
+    pub(crate) service: Service,
+    pub(crate) shutdown: Shutdown,
+    _shutdown_complete: mpsc::Sender<()>,
+    _permit: OwnedSemaphorePermit,
+}
+
+impl Drop for GatewayConnection {
+    fn drop(&mut self) {
+        self.service.metrics().connection_closed();
+    }
+}
+
+/// Accepts connections on `listener` until `stop` resolves, serving each via `serve_connection`.
+///
+/// Gateway connections count towards the connection limit of the server like any other.
+pub(crate) async fn serve<F, Fut>(
+    listener: TcpListener,
+    gateway: Gateway,
+    stop: impl Future<Output = ()>,
+    serve_connection: F,
+) where
+    F: Fn(GatewayConnection) -> Fut,
+    Fut: Future<Output = io::Result<()>> + Send + 'static,
+{
+    tokio::pin!(stop);
+    loop {
+        let accepted = tokio::select! {
+            accepted = accept(&listener, &gateway.connection_limit) => accepted,
+            _ = &mut stop => return,
+        };
+        let Some((stream, permit)) = accepted else {
+            return;
+        };
+        gateway.service.metrics().connection_opened();
+        let conn = GatewayConnection {
+            stream,
+            service: gateway.service.clone(),
+            shutdown: Shutdown::new(gateway.notify_shutdown.resubscribe()),
+            _shutdown_complete: gateway.shutdown_complete_tx.clone(),
+            _permit: permit,
+        };
+        let served = serve_connection(conn);
+        gateway.runtime.spawn(async move {
+            if let Err(_e) = served.await {
+                #[cfg(feature = "tracing")]
+                debug!("Closing the gateway connection: {_e}");
+            }
+        });
+    }
+}
+
+async fn accept(
+    listener: &TcpListener,
+    connection_limit: &Arc<Semaphore>,
+) -> Option<(TcpStream, OwnedSemaphorePermit)> {
+    let permit = connection_limit.clone().acquire_owned().await.ok()?;
+    loop {
+        match listener.accept().await {
+            Ok((stream, _)) => return Some((stream, permit)),
+            // Keep serving the existing connections, like the server does
+            Err(_e) => {
+                #[cfg(feature = "tracing")]
+                debug!("Could not accept a gateway connection: {_e}");
+                tokio::time::sleep(ACCEPT_BACKOFF).await;
+            }
+        }
+    }
+}
@@ -0,0 +1,65 @@
+/// Returns whether `text` matches the glob `pattern` as a whole.
+///
+/// `*` matches any sequence of characters, including none, and `?` matches exactly one character.
+/// Every other character only matches itself, there is no escaping.
+pub(crate) fn matches(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    let (mut p, mut t) = (0, 0);
+    // Where the last `*` was seen and the text position it currently stands in for
+    let mut backtrack: Option<(usize, usize)> = None;
+    while t < text.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                backtrack = Some((p, t));
+                p += 1;
+            }
+            Some('?') => {
+                p += 1;
+                t += 1;
+            }
+            Some(c) if *c == text[t] => {
+                p += 1;
+                t += 1;
+            }
+            _ => match backtrack {
+                // Let the last `*` swallow one more character and try again
+                Some((star_p, star_t)) => {
+                    backtrack = Some((star_p, star_t + 1));
+                    p = star_p + 1;
+                    t = star_t + 1;
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rstest::rstest;
+
+    #[rstest]
+    #[case("user:*:session", "user:1:session", true)]
+    #[case("user:*:session", "user::session", true)]
+    #[case("user:*:session", "user:1:2:session", true)]
+    #[case("user:*:session", "user:1:sessions", false)]
+    #[case("user:*", "user:", true)]
+    #[case("user:*", "users", false)]
+    #[case("*", "", true)]
+    #[case("*", "anything", true)]
+    #[case("", "", true)]
+    #[case("", "a", false)]
+    #[case("a?c", "abc", true)]
+    #[case("a?c", "ac", false)]
+    #[case("?", "ü", true)]
+    #[case("*b*b", "abab", true)]
+    #[case("*b*b", "abba", false)]
+    #[case("exact", "exact", true)]
+    #[case("exact", "exactly", false)]
+    fn test_glob_matching(#[case] pattern: &str, #[case] text: &str, #[case] expected: bool) {
+        assert_eq!(matches(pattern, text), expected);
+    }
+}
@@ -0,0 +1,431 @@
+//! An HTTP/1.1 gateway to the cache, see [`Server::bind_http`](crate::Server::bind_http).
+//!
+//! Values are exposed under `/keys/{key}`, the key being percent-encoded:
+//! `GET` returns the value, `PUT` stores the body as the value and `DELETE` removes it.
+//! TTLs are given in the `X-Cached-Ttl` header, in milliseconds from now.
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio::time::Instant;
+
+use crate::domain::{Key, Value, MAX_VALUE_LENGTH};
+use crate::gateway::GatewayConnection;
+use crate::metrics::ConnectionStats;
+use crate::primitives::StatusCode;
+use crate::request::Request;
+use crate::response::{Response, ResponseBody};
+use bytes::{Buf, BytesMut};
+
+/// How long the request line and the headers of a request may be, together.
+const MAX_HEAD_LENGTH: usize = 8 * 1024;
+const KEYS_PATH: &str = "/keys/";
+const TTL_HEADER: &str = "x-cached-ttl";
+
+/// Serves HTTP requests on the connection until it is closed or the server shuts down.
+pub(crate) async fn serve_connection(conn: GatewayConnection) -> std::io::Result<()> {
+    HttpConnection {
+        conn,
+        buffer: BytesMut::with_capacity(4 * 1024),
+    }
+    .run()
+    .await
+}
+
+struct HttpConnection {
+    conn: GatewayConnection,
+    buffer: BytesMut,
+}
+
+/// A parsed request, the body is still in the buffer of the connection.
+#[derive(Debug, PartialEq, Eq)]
+struct Head {
+    method: String,
+    path: String,
+    content_length: usize,
+    ttl: Option<String>,
+    expects_continue: bool,
+    keep_alive: bool,
+}
+
+/// An answer to an HTTP request.
+#[derive(Debug, PartialEq, Eq)]
+struct HttpResponse {
+    code: u16,
+    headers: Vec<(&'static str, String)>,
+    body: Vec<u8>,
+}
+
+impl HttpResponse {
+    fn new(code: u16, body: impl Into<Vec<u8>>) -> Self {
+        Self {
+            code,
+            headers: vec![],
+            body: body.into(),
+        }
+    }
+
+    /// An answer carrying nothing but its code, describing the status in the body.
+    fn from_status(code: u16, status: StatusCode) -> Self {
+        Self::new(code, format!("{status}\n"))
+    }
+
+    fn with_header(mut self, name: &'static str, value: String) -> Self {
+        self.headers.push((name, value));
+        self
+    }
+
+    fn encode(&self, keep_alive: bool) -> Vec<u8> {
+        let mut encoded = format!("HTTP/1.1 {} {}\r\n", self.code, reason(self.code));
+        for (name, value) in &self.headers {
+            encoded.push_str(&format!("{name}: {value}\r\n"));
+        }
+        encoded.push_str(&format!("Content-Length: {}\r\n", self.body.len()));
+        if !keep_alive {
+            encoded.push_str("Connection: close\r\n");
+        }
+        encoded.push_str("\r\n");
+        let mut encoded = encoded.into_bytes();
+        encoded.extend_from_slice(&self.body);
+        encoded
+    }
+}
+
+impl HttpConnection {
+    async fn run(&mut self) -> std::io::Result<()> {
+        while !self.conn.shutdown.is_shutdown() {
+            let head = tokio::select! {
+                head = read_head(&mut self.conn.stream, &mut self.buffer) => head?,
+                _ = self.conn.shutdown.recv() => return Ok(()),
+            };
+            let head = match head {
+                Ok(Some(head)) => head,
+                Ok(None) => return Ok(()),
+                Err(response) => return self.write(&response, false).await,
+            };
+            if head.content_length > MAX_VALUE_LENGTH as usize {
+                let response = HttpResponse::from_status(413, StatusCode::ValueTooLarge);
+                return self.write(&response, false).await;
+            }
+            if head.expects_continue && head.content_length > 0 {
+                self.conn
+                    .stream
+                    .write_all(b"HTTP/1.1 100 Continue\r\n\r\n")
+                    .await?;
+            }
+            while self.buffer.len() < head.content_length {
+                if self.conn.stream.read_buf(&mut self.buffer).await? == 0 {
+                    return Ok(());
+                }
+            }
+            let body = self.buffer.split_to(head.content_length);
+            let started = Instant::now();
+            let response = self.handle(&head, &body).await;
+            self.write(&response, head.keep_alive).await?;
+            self.conn
+                .service
+                .metrics()
+                .request_handled(started.elapsed());
+            if !head.keep_alive {
+                return Ok(());
+            }
+        }
+        Ok(())
+    }
+
+    async fn write(&mut self, response: &HttpResponse, keep_alive: bool) -> std::io::Result<()> {
+        self.conn
+            .stream
+            .write_all(&response.encode(keep_alive))
+            .await?;
+        self.conn.stream.flush().await
+    }
+
+    async fn handle(&self, head: &Head, body: &[u8]) -> HttpResponse {
+        let request = match into_request(head, body) {
+            Ok(request) => request,
+            Err(response) => return response,
+        };
+        let response = self
+            .conn
+            .service
+            .handle_request(request, ConnectionStats::new())
+            .await;
+        into_http_response(response)
+    }
+}
+
+/// Reads the head of the next request, `None` if the client closed the connection in between requests.
+async fn read_head(
+    stream: &mut TcpStream,
+    buffer: &mut BytesMut,
+) -> std::io::Result<Result<Option<Head>, HttpResponse>> {
+    loop {
+        if let Some(end) = find_head_end(buffer) {
+            let head = parse_head(&buffer[..end]);
+            buffer.advance(end + 4);
+            return Ok(head.map(Some));
+        }
+        if buffer.len() > MAX_HEAD_LENGTH {
+            return Ok(Err(HttpResponse::new(
+                431,
+                "Request header fields too large\n",
+            )));
+        }
+        if stream.read_buf(buffer).await? == 0 {
+            return Ok(if buffer.is_empty() {
+                Ok(None)
+            } else {
+                Err(HttpResponse::new(400, "Incomplete request\n"))
+            });
+        }
+    }
+}
+
+fn find_head_end(buffer: &[u8]) -> Option<usize> {
+    buffer.windows(4).position(|window| window == b"\r\n\r\n")
+}
+
+fn parse_head(head: &[u8]) -> Result<Head, HttpResponse> {
+    let bad_request = || HttpResponse::new(400, "Malformed request\n");
+    let head = std::str::from_utf8(head).map_err(|_| bad_request())?;
+    let mut lines = head.split("\r\n");
+    let mut request_line = lines.next().unwrap_or_default().split(' ');
+    let (Some(method), Some(path), Some(version), None) = (
+        request_line.next(),
+        request_line.next(),
+        request_line.next(),
+        request_line.next(),
+    ) else {
+        return Err(bad_request());
+    };
+    let mut keep_alive = match version {
+        "HTTP/1.1" => true,
+        "HTTP/1.0" => false,
+        _ => return Err(HttpResponse::new(505, "Only HTTP/1.x is supported\n")),
+    };
+    let mut content_length = 0;
+    let mut ttl = None;
+    let mut expects_continue = false;
+    for line in lines {
+        let (name, value) = line.split_once(':').ok_or_else(bad_request)?;
+        let value = value.trim();
+        match name.to_ascii_lowercase().as_str() {
+            "content-length" => content_length = value.parse().map_err(|_| bad_request())?,
+            "transfer-encoding" => {
+                return Err(HttpResponse::new(
+                    501,
+                    "Transfer encodings are not supported\n",
+                ))
+            }
+            "connection" if value.eq_ignore_ascii_case("close") => keep_alive = false,
+            "connection" if value.eq_ignore_ascii_case("keep-alive") => keep_alive = true,
+            "expect" if value.eq_ignore_ascii_case("100-continue") => expects_continue = true,
+            TTL_HEADER => ttl = Some(value.to_string()),
+            _ => {}
+        }
+    }
+    Ok(Head {
+        method: method.to_string(),
+        path: path.to_string(),
+        content_length,
+        ttl,
+        expects_continue,
+        keep_alive,
+    })
+}
+
+/// Maps the HTTP request to the request the server carries out.
+fn into_request(head: &Head, body: &[u8]) -> Result<Request, HttpResponse> {
+    let path = head.path.split('?').next().unwrap_or_default();
+    let Some(key) = path.strip_prefix(KEYS_PATH) else {
+        return Err(HttpResponse::new(404, "Not found\n"));
+    };
+    let key = percent_decode(key)
+        .and_then(|key| Key::parse(key).ok())
+        .ok_or_else(|| HttpResponse::new(400, "Invalid key\n"))?;
+    match head.method.as_str() {
+        "GET" => Ok(Request::Get(key)),
+        "DELETE" => Ok(Request::Delete(key)),
+        "PUT" => {
+            let value = Value::parse(body.to_vec())
+                .map_err(|_| HttpResponse::new(413, "Value too long\n"))?;
+            let ttl_since_unix_epoch_in_millis = match &head.ttl {
+                None => None,
+                Some(ttl) => {
+                    let ttl: u64 = ttl
+                        .parse()
+                        .map_err(|_| HttpResponse::new(400, "Invalid TTL\n"))?;
+                    Some(now_in_millis() + ttl as u128)
+                }
+            };
+            Ok(Request::Set {
+                key,
+                value,
+                ttl_since_unix_epoch_in_millis,
+                flags: 0,
+            })
+        }
+        _ => Err(HttpResponse::new(405, "Method not allowed\n")
+            .with_header("Allow", "GET, PUT, DELETE".to_string())),
+    }
+}
+
+fn into_http_response(response: Response) -> HttpResponse {
+    match (response.status, response.body) {
+        (StatusCode::Ok, ResponseBody::Get(Some(get))) => {
+            let content_type = match std::str::from_utf8(get.value.as_bytes()) {
+                Ok(_) => "text/plain; charset=utf-8",
+                Err(_) => "application/octet-stream",
+            };
+            let response = HttpResponse::new(200, get.value.as_bytes())
+                .with_header("Content-Type", content_type.to_string());
+            match get.ttl_since_unix_epoch_in_millis {
+                None => response,
+                Some(ttl) => response.with_header(
+                    "X-Cached-Ttl",
+                    ttl.saturating_sub(now_in_millis()).to_string(),
+                ),
+            }
+        }
+        (StatusCode::Ok, ResponseBody::Set) => HttpResponse::new(201, Vec::new()),
+        (StatusCode::Ok, _) => HttpResponse::new(204, Vec::new()),
+        (status, _) => HttpResponse::from_status(http_code(status), status),
+    }
+}
+
+/// The HTTP status code closest to how the server answered.
+fn http_code(status: StatusCode) -> u16 {
+    match status {
+        StatusCode::Ok => 200,
+        StatusCode::KeyNotFound | StatusCode::NegativeCached => 404,
+        StatusCode::KeyExists
+        | StatusCode::WrongType
+        | StatusCode::InvalidValue
+        | StatusCode::OutOfRange => 409,
+        StatusCode::ValueTooLarge => 413,
+        StatusCode::OperationNotPermitted => 403,
+        StatusCode::InvalidKey => 400,
+        StatusCode::InternalError => 500,
+    }
+}
+
+fn reason(code: u16) -> &'static str {
+    match code {
+        100 => "Continue",
+        200 => "OK",
+        201 => "Created",
+        204 => "No Content",
+        400 => "Bad Request",
+        403 => "Forbidden",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        409 => "Conflict",
+        413 => "Payload Too Large",
+        431 => "Request Header Fields Too Large",
+        501 => "Not Implemented",
+        505 => "HTTP Version Not Supported",
+        _ => "Internal Server Error",
+    }
+}
+
+/// Decodes `%XX` escapes, `None` if an escape is malformed or the result is not UTF-8.
+fn percent_decode(encoded: &str) -> Option<String> {
+    let mut decoded = Vec::with_capacity(encoded.len());
+    let mut bytes = encoded.bytes();
+    while let Some(byte) = bytes.next() {
+        if byte == b'%' {
+            let hex = [bytes.next()?, bytes.next()?];
+            let hex = std::str::from_utf8(&hex).ok()?;
+            decoded.push(u8::from_str_radix(hex, 16).ok()?);
+        } else {
+            decoded.push(byte);
+        }
+    }
+    String::from_utf8(decoded).ok()
+}
+
+fn now_in_millis() -> u128 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("Time went backwards")
+        .as_millis()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rstest::rstest;
+
+    #[rstest]
+    #[case("foo", Some("foo"))]
+    #[case("foo%20bar", Some("foo bar"))]
+    #[case("a%2Fb%2fc", Some("a/b/c"))]
+    #[case("%E2%9C%93", Some("✓"))]
+    #[case("%", None)]
+    #[case("%2", None)]
+    #[case("%zz", None)]
+    #[case("%FF", None)]
+    fn test_percent_decoding(#[case] encoded: &str, #[case] expected: Option<&str>) {
+        assert_eq!(percent_decode(encoded).as_deref(), expected);
+    }
+
+    #[test]
+    fn test_parsing_a_head() {
+        let head = parse_head(
+            b"PUT /keys/foo HTTP/1.1\r\nHost: localhost\r\nContent-Length: 3\r\nX-Cached-Ttl: 500\r\nExpect: 100-continue",
+        )
+        .unwrap();
+        assert_eq!(
+            head,
+            Head {
+                method: "PUT".to_string(),
+                path: "/keys/foo".to_string(),
+                content_length: 3,
+                ttl: Some("500".to_string()),
+                expects_continue: true,
+                keep_alive: true,
+            }
+        );
+    }
+
+    #[rstest]
+    #[case(b"GET /keys/foo HTTP/1.1\r\nConnection: close".as_slice(), false)]
+    #[case(b"GET /keys/foo HTTP/1.0".as_slice(), false)]
+    #[case(b"GET /keys/foo HTTP/1.0\r\nConnection: keep-alive".as_slice(), true)]
+    fn test_parsing_whether_to_keep_the_connection_alive(
+        #[case] head: &[u8],
+        #[case] keep_alive: bool,
+    ) {
+        assert_eq!(parse_head(head).unwrap().keep_alive, keep_alive);
+    }
+
+    #[rstest]
+    #[case(b"GET /keys/foo".as_slice(), 400)]
+    #[case(b"GET /keys/foo HTTP/2".as_slice(), 505)]
+    #[case(b"PUT /keys/foo HTTP/1.1\r\nContent-Length: many".as_slice(), 400)]
+    #[case(b"PUT /keys/foo HTTP/1.1\r\nTransfer-Encoding: chunked".as_slice(), 501)]
+    #[case(b"GET /keys/foo HTTP/1.1\r\nno colon".as_slice(), 400)]
+    fn test_parsing_malformed_heads_fails(#[case] head: &[u8], #[case] code: u16) {
+        assert_eq!(parse_head(head).unwrap_err().code, code);
+    }
+
+    #[rstest]
+    #[case("GET", "/other/foo", 404)]
+    #[case("GET", "/keys/", 400)]
+    #[case("POST", "/keys/foo", 405)]
+    fn test_requests_outside_the_keys_are_refused(
+        #[case] method: &str,
+        #[case] path: &str,
+        #[case] code: u16,
+    ) {
+        let head = Head {
+            method: method.to_string(),
+            path: path.to_string(),
+            content_length: 0,
+            ttl: None,
+            expects_continue: false,
+            keep_alive: true,
+        };
+        assert_eq!(into_request(&head, b"").unwrap_err().code, code);
+    }
+}
@@ -0,0 +1,45 @@
+/// Everything the server knows about a key, obtained via [`Client::inspect`](crate::Client::inspect).
+///
+/// Meant for debugging, the values are reported as the shard holding the key stores them.
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub struct KeyInfo {
+    pub(crate) value_length: u32,
+    pub(crate) ttl_since_unix_epoch_in_millis: Option<u128>,
+    pub(crate) updated_at_in_millis: u128,
+    pub(crate) flags: u32,
+    pub(crate) negative: bool,
+    pub(crate) shard: u32,
+}
+
+impl KeyInfo {
+    /// The length of a string value in bytes, as stored, e.g. after encoding it with a
+    /// [`ValueCodec`](crate::ValueCodec). The amount of items for lists and sets, `0` for tombstones.
+    pub fn value_length(&self) -> usize {
+        self.value_length as usize
+    }
+
+    /// When the key expires in milliseconds since the unix epoch, `None` if it does not expire.
+    pub fn ttl_since_unix_epoch_in_millis(&self) -> Option<u128> {
+        self.ttl_since_unix_epoch_in_millis
+    }
+
+    /// When the value was last set in milliseconds since the unix epoch, by the clock of the server.
+    pub fn updated_at_in_millis(&self) -> u128 {
+        self.updated_at_in_millis
+    }
+
+    /// The flags stored with a string value, `0` for anything else.
+    pub fn flags(&self) -> u32 {
+        self.flags
+    }
+
+    /// Whether the key is remembered as missing, see [`Client::set_negative`](crate::Client::set_negative).
+    pub fn is_negative_cached(&self) -> bool {
+        self.negative
+    }
+
+    /// The index of the shard holding the key.
+    pub fn shard(&self) -> usize {
+        self.shard as usize
+    }
+}
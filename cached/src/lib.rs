@@ -5,22 +5,103 @@
 #![cfg_attr(all(test, feature = "full"), deny(warnings))]
 #![cfg_attr(all(test, feature = "nightly"), feature(test))]
 #![cfg_attr(docsrs, feature(doc_cfg))]
+// Most of the wire format is only put to use by the client and the server
+#![cfg_attr(not(feature = "runtime"), allow(dead_code))]
 
+#[cfg(feature = "runtime")]
+mod access_log;
+#[cfg(feature = "runtime")]
+mod balance;
+#[cfg(feature = "runtime")]
+mod cache;
+mod capabilities;
+#[cfg(feature = "runtime")]
 mod client;
+#[cfg(feature = "runtime")]
+mod codec;
+#[cfg(feature = "runtime")]
 mod connection;
+#[cfg(feature = "runtime")]
 mod db;
 mod domain;
 mod error;
+#[cfg(feature = "runtime")]
+mod eviction;
 mod frame;
+#[cfg(any(feature = "http", feature = "resp"))]
+mod gateway;
+mod glob;
+#[cfg(feature = "http")]
+mod http;
+mod key_info;
+#[cfg(feature = "runtime")]
+mod memoize;
+mod metrics;
 mod parsing;
 mod primitives;
+pub mod protocol;
+#[cfg(feature = "runtime")]
+mod replay;
 mod request;
+#[cfg(feature = "resp")]
+mod resp;
 mod response;
+#[cfg(feature = "runtime")]
+mod retry;
+#[cfg(feature = "runtime")]
 mod server;
+#[cfg(feature = "runtime")]
 mod shutdown;
+#[cfg(feature = "runtime")]
+mod tap;
+#[cfg(feature = "runtime")]
+mod upload;
 
+#[cfg(feature = "runtime")]
+pub use balance::{DnsResolver, Resolver};
+#[cfg(feature = "runtime")]
+pub use cache::Cache;
+pub use capabilities::{ServerCapabilities, ServerInfo};
+#[cfg(feature = "runtime")]
+pub use client::BatchOp;
+#[cfg(feature = "runtime")]
 pub use client::Client;
+#[cfg(feature = "runtime")]
 pub use client::ClientConnection;
+#[cfg(feature = "runtime")]
+pub use client::ClientWithDefaultTtl;
+#[cfg(feature = "runtime")]
+pub use codec::ValueCodec;
+pub use domain::TtlState;
 pub use error::Error;
+#[cfg(feature = "runtime")]
+pub use eviction::EvictionBatch;
+#[cfg(feature = "runtime")]
+pub use eviction::EvictionPolicy;
+#[cfg(feature = "runtime")]
+pub use eviction::EvictionReason;
+#[cfg(feature = "runtime")]
+pub use eviction::EvictionStream;
+#[cfg(feature = "runtime")]
+pub use eviction::EvictionSubscriber;
+pub use key_info::KeyInfo;
+#[cfg(feature = "runtime")]
+pub use memoize::Memoized;
+pub use metrics::ConnectionStats;
+pub use metrics::ServerMetrics;
+pub use primitives::OpCode;
 pub use primitives::StatusCode;
+#[cfg(feature = "runtime")]
+pub use replay::ReplayClient;
+#[cfg(feature = "runtime")]
+pub use retry::RetryPolicy;
+#[cfg(feature = "runtime")]
 pub use server::Server;
+#[cfg(feature = "runtime")]
+pub use server::ServerConfig;
+#[cfg(feature = "runtime")]
+pub use server::ServerHandle;
+#[cfg(feature = "runtime")]
+pub use server::ShutdownReason;
+#[cfg(feature = "runtime")]
+pub use tap::RequestLog;
@@ -0,0 +1,101 @@
+use crate::error::Result;
+use crate::{Client, StatusCode};
+use std::fmt;
+use std::fmt::Formatter;
+use std::future::Future;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Memoizes an async function in the cache, following the cache-aside pattern.
+///
+/// Calling [`Memoized::call`] derives the key from the argument and gets its value from the
+/// server. Only if there is none, the function is called and its result set under the key.
+/// Concurrent calls for the same missing key may each call the function,
+/// the first result to be set wins on the server while every caller gets its own result.
+///
+/// # Examples
+///
+/// ```
+/// use cached::{Client, Memoized};
+/// # use cached::Server;
+/// # use cached::Error;
+/// use std::time::Duration;
+///
+/// # #[tokio::main]
+/// # async fn main() -> Result<(), Error> {
+/// # let server = Server::new().bind("127.0.0.1:0").await.unwrap();
+/// # let port = server.port();
+/// # tokio::spawn(async { server.run().await;});
+/// let client = Client::new(format!("127.0.0.1:{port}")).await;
+/// let square = Memoized::new(
+///     client,
+///     Some(Duration::from_secs(60)),
+///     |n: &u64| format!("square:{n}"),
+///     |n: u64| async move { (n * n).to_string() },
+/// );
+///
+/// assert_eq!(square.call(12).await?, "144");
+/// // Answered by the cache this time
+/// assert_eq!(square.call(12).await?, "144");
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone)]
+pub struct Memoized<K, F> {
+    client: Client,
+    ttl: Option<Duration>,
+    key_fn: K,
+    function: F,
+}
+
+impl<K, F> Memoized<K, F> {
+    /// Memoizes `function` via `client`, caching each result under the key `key_fn` derives
+    /// from the argument.
+    ///
+    /// Results expire after `ttl`, counted from when they were computed, or never if it is `None`.
+    pub fn new(client: Client, ttl: Option<Duration>, key_fn: K, function: F) -> Self {
+        Self {
+            client,
+            ttl,
+            key_fn,
+            function,
+        }
+    }
+
+    /// Returns the cached result for `arg`, calling the function on a miss.
+    ///
+    /// Errors talking to the server are returned, the function is only called on a miss.
+    /// A cached value that is not valid UTF-8 counts as a miss.
+    pub async fn call<A, Fut>(&self, arg: A) -> Result<String>
+    where
+        K: Fn(&A) -> String,
+        F: Fn(A) -> Fut,
+        Fut: Future<Output = String>,
+    {
+        let key = (self.key_fn)(&arg);
+        let response = self.client.get(key.as_str()).await?;
+        if response.status() == StatusCode::Ok {
+            if let Some(Ok(value)) = response.into_value().map(String::from_utf8) {
+                return Ok(value);
+            }
+        }
+        let value = (self.function)(arg).await;
+        let ttl = self.ttl.map(|ttl| {
+            (SystemTime::now() + ttl)
+                .duration_since(UNIX_EPOCH)
+                .expect("Time went backwards")
+                .as_millis()
+        });
+        // Another caller may have set the key in the meantime, which is just as good
+        self.client.set(key.as_str(), value.as_str(), ttl).await?;
+        Ok(value)
+    }
+}
+
+impl<K, F> fmt::Debug for Memoized<K, F> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Memoized")
+            .field("client", &self.client)
+            .field("ttl", &self.ttl)
+            .finish_non_exhaustive()
+    }
+}
@@ -0,0 +1,473 @@
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Upper bounds of the request duration histogram buckets, in seconds.
+const REQUEST_DURATION_BUCKETS: [f64; 8] = [0.0001, 0.0005, 0.001, 0.005, 0.01, 0.05, 0.1, 0.5];
+/// Upper bounds of the expiry lag histogram buckets, in seconds.
+const EXPIRY_LAG_BUCKETS: [f64; 7] = [0.001, 0.01, 0.1, 0.5, 1.0, 5.0, 30.0];
+
+/// Counters shared between the server and its connection handlers.
+#[derive(Debug, Default)]
+pub(crate) struct Metrics {
+    accepted_connections: AtomicU64,
+    active_connections: AtomicUsize,
+    handled_requests: AtomicU64,
+    handler_panics: AtomicU64,
+    hits: AtomicU64,
+    misses: AtomicU64,
+    evictions: AtomicU64,
+    bytes_received: AtomicU64,
+    bytes_sent: AtomicU64,
+    inflight_bytes: AtomicU64,
+    // Not cumulative, each request is only counted in the first bucket it fits into
+    request_duration_buckets: [AtomicU64; REQUEST_DURATION_BUCKETS.len()],
+    request_duration_micros: AtomicU64,
+    expired_keys_reclaimed: AtomicU64,
+    // Not cumulative either
+    expiry_lag_buckets: [AtomicU64; EXPIRY_LAG_BUCKETS.len()],
+    expiry_lag_millis: AtomicU64,
+}
+
+impl Metrics {
+    pub(crate) fn connection_opened(&self) {
+        self.accepted_connections.fetch_add(1, Ordering::Relaxed);
+        self.active_connections.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn connection_closed(&self) {
+        self.active_connections.fetch_sub(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn request_handled(&self, duration: Duration) {
+        self.handled_requests.fetch_add(1, Ordering::Relaxed);
+        let seconds = duration.as_secs_f64();
+        if let Some(bucket) = REQUEST_DURATION_BUCKETS
+            .iter()
+            .position(|upper_bound| seconds <= *upper_bound)
+        {
+            self.request_duration_buckets[bucket].fetch_add(1, Ordering::Relaxed);
+        }
+        self.request_duration_micros.fetch_add(
+            u64::try_from(duration.as_micros()).unwrap_or(u64::MAX),
+            Ordering::Relaxed,
+        );
+    }
+
+    pub(crate) fn handler_panicked(&self) {
+        self.handler_panics.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn hit(&self) {
+        self.hits.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn miss(&self) {
+        self.misses.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn evicted(&self, keys: usize) {
+        self.evictions.fetch_add(keys as u64, Ordering::Relaxed);
+    }
+
+    /// Counts an expired key that was removed `lag` after it expired.
+    pub(crate) fn expired_key_reclaimed(&self, lag: Duration) {
+        self.expired_keys_reclaimed.fetch_add(1, Ordering::Relaxed);
+        let seconds = lag.as_secs_f64();
+        if let Some(bucket) = EXPIRY_LAG_BUCKETS
+            .iter()
+            .position(|upper_bound| seconds <= *upper_bound)
+        {
+            self.expiry_lag_buckets[bucket].fetch_add(1, Ordering::Relaxed);
+        }
+        self.expiry_lag_millis.fetch_add(
+            u64::try_from(lag.as_millis()).unwrap_or(u64::MAX),
+            Ordering::Relaxed,
+        );
+    }
+
+    pub(crate) fn transferred(&self, received: u64, sent: u64) {
+        self.bytes_received.fetch_add(received, Ordering::Relaxed);
+        self.bytes_sent.fetch_add(sent, Ordering::Relaxed);
+    }
+
+    /// Counts bytes a connection holds against [`Server::max_inflight_bytes`](crate::Server::max_inflight_bytes).
+    pub(crate) fn inflight_acquired(&self, bytes: u64) {
+        self.inflight_bytes.fetch_add(bytes, Ordering::Relaxed);
+    }
+
+    pub(crate) fn inflight_released(&self, bytes: u64) {
+        self.inflight_bytes.fetch_sub(bytes, Ordering::Relaxed);
+    }
+
+    pub(crate) fn active_connections(&self) -> usize {
+        self.active_connections.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn snapshot(&self) -> ServerMetrics {
+        ServerMetrics {
+            accepted_connections: self.accepted_connections.load(Ordering::Relaxed),
+            active_connections: self.active_connections(),
+            handled_requests: self.handled_requests.load(Ordering::Relaxed),
+            handler_panics: self.handler_panics.load(Ordering::Relaxed),
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            evictions: self.evictions.load(Ordering::Relaxed),
+            bytes_received: self.bytes_received.load(Ordering::Relaxed),
+            bytes_sent: self.bytes_sent.load(Ordering::Relaxed),
+            inflight_bytes: self.inflight_bytes.load(Ordering::Relaxed),
+            request_duration_buckets: self
+                .request_duration_buckets
+                .each_ref()
+                .map(|bucket| bucket.load(Ordering::Relaxed)),
+            request_duration_micros: self.request_duration_micros.load(Ordering::Relaxed),
+            expired_keys_reclaimed: self.expired_keys_reclaimed.load(Ordering::Relaxed),
+            expiry_lag_buckets: self
+                .expiry_lag_buckets
+                .each_ref()
+                .map(|bucket| bucket.load(Ordering::Relaxed)),
+            expiry_lag_millis: self.expiry_lag_millis.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// The stats of a single connection as seen by the server,
+/// obtained via [`Client::connection_stats`].
+///
+/// The request asking for the stats is not part of them yet.
+///
+/// [`Client::connection_stats`]: crate::Client::connection_stats
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub struct ConnectionStats {
+    pub(crate) handled_requests: u64,
+    pub(crate) bytes_received: u64,
+    pub(crate) bytes_sent: u64,
+    pub(crate) connected_since_unix_epoch_in_millis: u64,
+}
+
+impl ConnectionStats {
+    /// Starts counting for a connection accepted just now.
+    pub(crate) fn new() -> Self {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .expect("Time went backwards")
+            .as_millis();
+        Self {
+            handled_requests: 0,
+            bytes_received: 0,
+            bytes_sent: 0,
+            connected_since_unix_epoch_in_millis: u64::try_from(now).unwrap_or(u64::MAX),
+        }
+    }
+
+    pub(crate) fn request_handled(&mut self, received: u64, sent: u64) {
+        self.handled_requests += 1;
+        self.bytes_received += received;
+        self.bytes_sent += sent;
+    }
+
+    /// The number of requests answered on the connection.
+    pub fn handled_requests(&self) -> u64 {
+        self.handled_requests
+    }
+
+    /// The number of bytes the server read from the connection.
+    pub fn bytes_received(&self) -> u64 {
+        self.bytes_received
+    }
+
+    /// The number of bytes the server wrote to the connection.
+    pub fn bytes_sent(&self) -> u64 {
+        self.bytes_sent
+    }
+
+    /// When the server accepted the connection.
+    pub fn connected_since(&self) -> SystemTime {
+        UNIX_EPOCH + Duration::from_millis(self.connected_since_unix_epoch_in_millis)
+    }
+}
+
+/// A snapshot of the metrics of a running server, obtained via [`ServerHandle::metrics`].
+///
+/// [`ServerHandle::metrics`]: crate::ServerHandle::metrics
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub struct ServerMetrics {
+    accepted_connections: u64,
+    active_connections: usize,
+    handled_requests: u64,
+    handler_panics: u64,
+    hits: u64,
+    misses: u64,
+    evictions: u64,
+    bytes_received: u64,
+    bytes_sent: u64,
+    inflight_bytes: u64,
+    request_duration_buckets: [u64; REQUEST_DURATION_BUCKETS.len()],
+    request_duration_micros: u64,
+    expired_keys_reclaimed: u64,
+    expiry_lag_buckets: [u64; EXPIRY_LAG_BUCKETS.len()],
+    expiry_lag_millis: u64,
+}
+
+impl ServerMetrics {
+    /// The number of connections accepted since the server started.
+    pub fn accepted_connections(&self) -> u64 {
+        self.accepted_connections
+    }
+
+    /// The number of connections currently open.
+    pub fn active_connections(&self) -> usize {
+        self.active_connections
+    }
+
+    /// The number of requests answered since the server started.
+    pub fn handled_requests(&self) -> u64 {
+        self.handled_requests
+    }
+
+    /// The number of times a connection handler panicked.
+    pub fn handler_panics(&self) -> u64 {
+        self.handler_panics
+    }
+
+    /// The number of `get` requests that found a value.
+    pub fn hits(&self) -> u64 {
+        self.hits
+    }
+
+    /// The number of `get` requests that found no value.
+    pub fn misses(&self) -> u64 {
+        self.misses
+    }
+
+    /// The number of expired keys removed by the periodic sweep.
+    pub fn evictions(&self) -> u64 {
+        self.evictions
+    }
+
+    /// The number of bytes read from clients.
+    pub fn bytes_received(&self) -> u64 {
+        self.bytes_received
+    }
+
+    /// The number of bytes written to clients.
+    pub fn bytes_sent(&self) -> u64 {
+        self.bytes_sent
+    }
+
+    /// The bytes of the requests and responses the connections currently hold,
+    /// only counted with [`Server::max_inflight_bytes`](crate::Server::max_inflight_bytes) set.
+    pub fn inflight_bytes(&self) -> u64 {
+        self.inflight_bytes
+    }
+
+    /// The number of expired keys removed, by the periodic sweep or when they were accessed.
+    pub fn expired_keys_reclaimed(&self) -> u64 {
+        self.expired_keys_reclaimed
+    }
+
+    /// How long the expired keys were kept past their expiry before being removed, summed up.
+    ///
+    /// Divided by [`ServerMetrics::expired_keys_reclaimed`] this is the average expiry lag,
+    /// a high lag means the [`sweep interval`](crate::Server::sweep_interval) is too coarse.
+    pub fn expiry_lag_sum(&self) -> Duration {
+        Duration::from_millis(self.expiry_lag_millis)
+    }
+
+    /// Renders the metrics in the Prometheus text exposition format.
+    pub fn to_prometheus(&self) -> String {
+        let mut out = String::new();
+        let counters = [
+            (
+                "cached_connections_accepted_total",
+                "Connections accepted since the server started.",
+                self.accepted_connections,
+            ),
+            (
+                "cached_requests_total",
+                "Requests answered since the server started.",
+                self.handled_requests,
+            ),
+            (
+                "cached_handler_panics_total",
+                "Connection handlers that panicked.",
+                self.handler_panics,
+            ),
+            (
+                "cached_hits_total",
+                "Get requests that found a value.",
+                self.hits,
+            ),
+            (
+                "cached_misses_total",
+                "Get requests that found no value.",
+                self.misses,
+            ),
+            (
+                "cached_evictions_total",
+                "Expired keys removed by the periodic sweep.",
+                self.evictions,
+            ),
+            (
+                "cached_received_bytes_total",
+                "Bytes read from clients.",
+                self.bytes_received,
+            ),
+            (
+                "cached_sent_bytes_total",
+                "Bytes written to clients.",
+                self.bytes_sent,
+            ),
+        ];
+        // Writing to a String cannot fail
+        for (name, help, value) in counters {
+            let _ = writeln!(out, "# HELP {name} {help}");
+            let _ = writeln!(out, "# TYPE {name} counter");
+            let _ = writeln!(out, "{name} {value}");
+        }
+
+        let name = "cached_connections_active";
+        let _ = writeln!(out, "# HELP {name} Connections currently open.");
+        let _ = writeln!(out, "# TYPE {name} gauge");
+        let _ = writeln!(out, "{name} {}", self.active_connections);
+
+        let name = "cached_inflight_bytes";
+        let _ = writeln!(
+            out,
+            "# HELP {name} Bytes of requests and responses currently held."
+        );
+        let _ = writeln!(out, "# TYPE {name} gauge");
+        let _ = writeln!(out, "{name} {}", self.inflight_bytes);
+
+        write_histogram(
+            &mut out,
+            "cached_request_duration_seconds",
+            "Time taken to answer a request.",
+            &REQUEST_DURATION_BUCKETS,
+            &self.request_duration_buckets,
+            self.handled_requests,
+            self.request_duration_micros as f64 / 1_000_000.0,
+        );
+        write_histogram(
+            &mut out,
+            "cached_expiry_lag_seconds",
+            "Time expired keys were kept past their expiry before being removed.",
+            &EXPIRY_LAG_BUCKETS,
+            &self.expiry_lag_buckets,
+            self.expired_keys_reclaimed,
+            self.expiry_lag_millis as f64 / 1_000.0,
+        );
+        out
+    }
+}
+
+/// Writes a histogram from the counts of each bucket, `count` also covering what exceeds all buckets.
+fn write_histogram(
+    out: &mut String,
+    name: &str,
+    help: &str,
+    upper_bounds: &[f64],
+    buckets: &[u64],
+    count: u64,
+    sum: f64,
+) {
+    let _ = writeln!(out, "# HELP {name} {help}");
+    let _ = writeln!(out, "# TYPE {name} histogram");
+    let mut cumulative = 0;
+    for (upper_bound, bucket) in upper_bounds.iter().zip(buckets) {
+        cumulative += bucket;
+        let _ = writeln!(out, "{name}_bucket{{le=\"{upper_bound}\"}} {cumulative}");
+    }
+    let _ = writeln!(out, "{name}_bucket{{le=\"+Inf\"}} {count}");
+    let _ = writeln!(out, "{name}_sum {sum}");
+    let _ = writeln!(out, "{name}_count {count}");
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_snapshot_reflects_counters() {
+        let metrics = Metrics::default();
+        metrics.connection_opened();
+        metrics.connection_opened();
+        metrics.request_handled(Duration::from_micros(10));
+        metrics.connection_closed();
+        metrics.handler_panicked();
+        metrics.hit();
+        metrics.miss();
+        metrics.miss();
+        metrics.evicted(3);
+        metrics.transferred(20, 30);
+        metrics.inflight_acquired(100);
+        metrics.inflight_released(60);
+        metrics.expired_key_reclaimed(Duration::from_millis(5));
+        metrics.expired_key_reclaimed(Duration::from_millis(20));
+
+        let snapshot = metrics.snapshot();
+        assert_eq!(snapshot.accepted_connections(), 2);
+        assert_eq!(snapshot.active_connections(), 1);
+        assert_eq!(snapshot.handled_requests(), 1);
+        assert_eq!(snapshot.handler_panics(), 1);
+        assert_eq!(snapshot.hits(), 1);
+        assert_eq!(snapshot.misses(), 2);
+        assert_eq!(snapshot.evictions(), 3);
+        assert_eq!(snapshot.bytes_received(), 20);
+        assert_eq!(snapshot.bytes_sent(), 30);
+        assert_eq!(snapshot.inflight_bytes(), 40);
+        assert_eq!(snapshot.expired_keys_reclaimed(), 2);
+        assert_eq!(snapshot.expiry_lag_sum(), Duration::from_millis(25));
+    }
+
+    #[test]
+    fn test_prometheus_output_contains_metric_names_and_types() {
+        let metrics = Metrics::default();
+        metrics.connection_opened();
+        metrics.hit();
+        metrics.request_handled(Duration::from_micros(50));
+        metrics.request_handled(Duration::from_millis(3));
+        metrics.request_handled(Duration::from_secs(2));
+        metrics.expired_key_reclaimed(Duration::from_millis(200));
+        metrics.expired_key_reclaimed(Duration::from_secs(60));
+
+        let output = metrics.snapshot().to_prometheus();
+        for line in [
+            "# TYPE cached_connections_accepted_total counter",
+            "cached_connections_accepted_total 1",
+            "# TYPE cached_requests_total counter",
+            "cached_requests_total 3",
+            "# TYPE cached_handler_panics_total counter",
+            "# TYPE cached_hits_total counter",
+            "cached_hits_total 1",
+            "# TYPE cached_misses_total counter",
+            "cached_misses_total 0",
+            "# TYPE cached_evictions_total counter",
+            "# TYPE cached_received_bytes_total counter",
+            "# TYPE cached_sent_bytes_total counter",
+            "# TYPE cached_connections_active gauge",
+            "cached_connections_active 1",
+            "# TYPE cached_inflight_bytes gauge",
+            "cached_inflight_bytes 0",
+            "# TYPE cached_request_duration_seconds histogram",
+            "cached_request_duration_seconds_bucket{le=\"0.0001\"} 1",
+            "cached_request_duration_seconds_bucket{le=\"0.001\"} 1",
+            "cached_request_duration_seconds_bucket{le=\"0.005\"} 2",
+            "cached_request_duration_seconds_bucket{le=\"0.5\"} 2",
+            "cached_request_duration_seconds_bucket{le=\"+Inf\"} 3",
+            "cached_request_duration_seconds_count 3",
+            "# TYPE cached_expiry_lag_seconds histogram",
+            "cached_expiry_lag_seconds_bucket{le=\"0.1\"} 0",
+            "cached_expiry_lag_seconds_bucket{le=\"0.5\"} 1",
+            "cached_expiry_lag_seconds_bucket{le=\"30\"} 1",
+            "cached_expiry_lag_seconds_bucket{le=\"+Inf\"} 2",
+            "cached_expiry_lag_seconds_sum 60.2",
+            "cached_expiry_lag_seconds_count 2",
+        ] {
+            assert!(
+                output.lines().any(|l| l == line),
+                "missing `{line}` in:\n{output}"
+            );
+        }
+    }
+}
@@ -1,11 +1,24 @@
+use crate::capabilities::{ServerCapabilities, ServerInfo};
 use crate::domain::{Key, TTLSinceUnixEpochInMillis, Value};
 use crate::error::{FrameError, ParseError, Result};
 use crate::frame::{RequestFrame, RequestHeader, ResponseFrame, ResponseHeader};
+use crate::key_info::KeyInfo;
+use crate::metrics::ConnectionStats;
 use crate::primitives::OpCode;
+use crate::protocol::{
+    FLUSH_CONFIRMATION_FLAG, HEADER_SIZE, KEY_VALIDATOR_FLAG, MAX_VALUE_LENGTH, NO_LIMIT,
+};
 use crate::{Error, StatusCode};
+use bytes::Bytes;
 use nom::bytes::streaming::take;
-use nom::combinator::map_res;
-use nom::number::streaming::{be_u128, be_u32, u8};
+use nom::combinator::{all_consuming, map, map_res};
+use nom::error::ErrorKind;
+use nom::multi::{length_data, many0};
+use nom::number::{
+    complete,
+    streaming::{be_u32, be_u64, u8},
+};
+use nom::sequence::tuple;
 use nom::IResult;
 
 pub(crate) fn parse_request_frame(input: &[u8]) -> Result<RequestFrame> {
@@ -13,45 +26,36 @@ pub(crate) fn parse_request_frame(input: &[u8]) -> Result<RequestFrame> {
         _,
         RequestPrimitive {
             op_code,
+            request_id,
             ttl_since_unix_epoch_in_millis,
+            flags,
             key_bytes,
             value_bytes,
         },
-    ) = parse_request_primitives(input).map_err(|e| {
-        if e.is_incomplete() {
-            Error::new_frame(FrameError::Incomplete)
-        } else {
-            Error::new_parse(ParseError::Other)
-        }
-    })?;
+    ) = parse_request_primitives(input).map_err(into_error)?;
     let key = match key_bytes.len() {
         0 => None,
         // TODO use Cow instead?
         _ => {
             let key = String::from_utf8(key_bytes.to_vec())
-                .map_err(|e| Error::new_parse(ParseError::String(e)))?;
+                .map_err(|_| Error::new_parse(ParseError::KeyNotUtf8))?;
             let key = Key::parse(key)?;
             Some(key)
         }
     };
     let value = match value_bytes.len() {
         0 => None,
-        // TODO use Cow instead?
-        _ => {
-            let value = String::from_utf8(value_bytes.to_vec())
-                .map_err(|e| Error::new_parse(ParseError::String(e)))?;
-            let value = Value::parse(value)?;
-            Some(value)
-        }
+        _ => Some(Value::parse(Bytes::copy_from_slice(value_bytes))?),
     };
-    let ttl_since_unix_epoch_in_millis =
-        TTLSinceUnixEpochInMillis::parse(Some(ttl_since_unix_epoch_in_millis));
     RequestFrame::new(op_code, ttl_since_unix_epoch_in_millis, key, value)
+        .map(|frame| frame.with_request_id(request_id).with_flags(flags))
 }
 
 struct RequestPrimitive<'a> {
     op_code: OpCode,
-    ttl_since_unix_epoch_in_millis: u128,
+    request_id: u32,
+    ttl_since_unix_epoch_in_millis: TTLSinceUnixEpochInMillis,
+    flags: u32,
     key_bytes: &'a [u8],
     value_bytes: &'a [u8],
 }
@@ -60,17 +64,34 @@ fn parse_request_primitives(input: &[u8]) -> IResult<&[u8], RequestPrimitive<'_>
     let (remainder, op_code) = map_res(u8, OpCode::try_from)(input)?;
     let (remainder, _) = u8(remainder)?;
     let (remainder, key_length) = u8(remainder)?;
-    let (remainder, ttl_since_unix_epoch_in_millis) = be_u128(remainder)?;
+    let (remainder, request_id) = be_u32(remainder)?;
     let (remainder, total_frame_length) = be_u32(remainder)?;
-    let key_length = key_length as usize;
+    let (remainder, _) = check_total_frame_length(remainder, total_frame_length, key_length)?;
+    let (remainder, ttl_since_unix_epoch_in_millis) = if RequestHeader::has_ttl(op_code) {
+        map(be_u64, TTLSinceUnixEpochInMillis::from_wire)(remainder)?
+    } else {
+        (remainder, TTLSinceUnixEpochInMillis::parse(None))
+    };
+    let (remainder, flags) = if RequestHeader::has_flags(op_code) {
+        be_u32(remainder)?
+    } else {
+        (remainder, 0)
+    };
+    let (remainder, value_length) = value_length(
+        remainder,
+        total_frame_length,
+        RequestHeader::size(op_code),
+        key_length,
+    )?;
     let (remainder, key_bytes) = take(key_length)(remainder)?;
-    let value_length = total_frame_length as usize - RequestHeader::size() as usize - key_length;
     let (remainder, value_bytes) = take(value_length)(remainder)?;
     Ok((
         remainder,
         RequestPrimitive {
             op_code,
+            request_id,
             ttl_since_unix_epoch_in_millis,
+            flags,
             key_bytes,
             value_bytes,
         },
@@ -83,46 +104,43 @@ pub(crate) fn parse_response_frame(input: &[u8]) -> Result<ResponseFrame> {
         ResponsePrimitive {
             op_code,
             status,
+            request_id,
             ttl_since_unix_epoch_in_millis,
+            flags,
+            updated_at_in_millis,
             key_bytes,
             value_bytes,
         },
-    ) = parse_response_primitives(input).map_err(|e| {
-        if e.is_incomplete() {
-            Error::new_frame(FrameError::Incomplete)
-        } else {
-            Error::new_parse(ParseError::Other)
-        }
-    })?;
+    ) = parse_response_primitives(input).map_err(into_error)?;
     let key = match key_bytes.len() {
         0 => None,
         // TODO use Cow instead?
         _ => {
             let key = String::from_utf8(key_bytes.to_vec())
-                .map_err(|e| Error::new_parse(ParseError::String(e)))?;
+                .map_err(|_| Error::new_parse(ParseError::KeyNotUtf8))?;
             let key = Key::parse(key)?;
             Some(key)
         }
     };
     let value = match value_bytes.len() {
         0 => None,
-        // TODO use Cow instead?
-        _ => {
-            let value = String::from_utf8(value_bytes.to_vec())
-                .map_err(|e| Error::new_parse(ParseError::String(e)))?;
-            let value = Value::parse(value)?;
-            Some(value)
-        }
+        _ => Some(Value::parse(Bytes::copy_from_slice(value_bytes))?),
     };
-    let ttl_since_unix_epoch_in_millis =
-        TTLSinceUnixEpochInMillis::parse(Some(ttl_since_unix_epoch_in_millis));
-    ResponseFrame::new(op_code, status, ttl_since_unix_epoch_in_millis, key, value)
+    ResponseFrame::new(op_code, status, ttl_since_unix_epoch_in_millis, key, value).map(|frame| {
+        frame
+            .with_request_id(request_id)
+            .with_flags(flags)
+            .with_updated_at(updated_at_in_millis)
+    })
 }
 
 struct ResponsePrimitive<'a> {
     op_code: OpCode,
     status: StatusCode,
-    ttl_since_unix_epoch_in_millis: u128,
+    request_id: u32,
+    ttl_since_unix_epoch_in_millis: TTLSinceUnixEpochInMillis,
+    flags: u32,
+    updated_at_in_millis: u64,
     key_bytes: &'a [u8],
     value_bytes: &'a [u8],
 }
@@ -131,20 +149,411 @@ fn parse_response_primitives(input: &[u8]) -> IResult<&[u8], ResponsePrimitive<'
     let (remainder, op_code) = map_res(u8, OpCode::try_from)(input)?;
     let (remainder, status) = map_res(u8, StatusCode::try_from)(remainder)?;
     let (remainder, key_length) = u8(remainder)?;
-    let (remainder, ttl_since_unix_epoch_in_millis) = be_u128(remainder)?;
+    let (remainder, request_id) = be_u32(remainder)?;
     let (remainder, total_frame_length) = be_u32(remainder)?;
+    let (remainder, _) = check_total_frame_length(remainder, total_frame_length, key_length)?;
+    let (remainder, ttl_since_unix_epoch_in_millis) = if ResponseHeader::has_ttl(op_code) {
+        map(be_u64, TTLSinceUnixEpochInMillis::from_wire)(remainder)?
+    } else {
+        (remainder, TTLSinceUnixEpochInMillis::parse(None))
+    };
+    let (remainder, flags) = if ResponseHeader::has_flags(op_code) {
+        be_u32(remainder)?
+    } else {
+        (remainder, 0)
+    };
+    let (remainder, updated_at_in_millis) = if ResponseHeader::has_updated_at(op_code) {
+        be_u64(remainder)?
+    } else {
+        (remainder, 0)
+    };
+    let (remainder, value_length) = value_length(
+        remainder,
+        total_frame_length,
+        ResponseHeader::size(op_code),
+        key_length,
+    )?;
     let (remainder, key_bytes) = take(key_length)(remainder)?;
-    let value_length =
-        total_frame_length as usize - ResponseHeader::size() as usize - key_length as usize;
     let (_, value_bytes) = take(value_length)(remainder)?;
     Ok((
         remainder,
         ResponsePrimitive {
             op_code,
             status,
+            request_id,
             ttl_since_unix_epoch_in_millis,
+            flags,
+            updated_at_in_millis,
             key_bytes,
             value_bytes,
         },
     ))
 }
+
+/// Fails right away if the total frame length is shorter than the fixed part of the header and the key.
+///
+/// Checked before the optional header fields are parsed, so a frame announcing too few bytes
+/// is refused instead of waiting for fields that it claims not to contain.
+fn check_total_frame_length(
+    input: &[u8],
+    total_frame_length: u32,
+    key_length: u8,
+) -> IResult<&[u8], ()> {
+    if (total_frame_length as usize) < HEADER_SIZE as usize + key_length as usize {
+        return Err(nom::Err::Failure(nom::error::Error::new(
+            input,
+            ErrorKind::LengthValue,
+        )));
+    }
+    Ok((input, ()))
+}
+
+/// Returns the length of the value following the header and the key.
+///
+/// Fails right away, rather than waiting for more input, if the total frame length
+/// is shorter than header and key or announces a value longer than `MAX_VALUE_LENGTH`.
+fn value_length(
+    input: &[u8],
+    total_frame_length: u32,
+    header_size: u8,
+    key_length: u8,
+) -> IResult<&[u8], usize> {
+    let failure = |kind| nom::Err::Failure(nom::error::Error::new(input, kind));
+    let value_length = (total_frame_length as usize)
+        .checked_sub(header_size as usize + key_length as usize)
+        .ok_or_else(|| failure(ErrorKind::LengthValue))?;
+    if value_length > MAX_VALUE_LENGTH as usize {
+        return Err(failure(ErrorKind::TooLarge));
+    }
+    Ok((input, value_length))
+}
+
+fn into_error(e: nom::Err<nom::error::Error<&[u8]>>) -> Error {
+    match e {
+        nom::Err::Incomplete(_) => Error::new_frame(FrameError::Incomplete),
+        nom::Err::Failure(e) if e.code == ErrorKind::TooLarge => {
+            Error::new_parse(ParseError::ValueTooLong)
+        }
+        nom::Err::Failure(e) if e.code == ErrorKind::LengthValue => {
+            Error::new_frame(FrameError::InvalidLength)
+        }
+        _ => Error::new_parse(ParseError::Other),
+    }
+}
+
+/// Parses a list of keys, each prefixed with its length as a single byte.
+pub(crate) fn parse_keys(input: &[u8]) -> Result<Vec<Key>> {
+    let (_, keys_bytes) = all_consuming(many0(length_data(complete::u8)))(input)
+        .map_err(|_: nom::Err<nom::error::Error<&[u8]>>| Error::new_parse(ParseError::Other))?;
+    keys_bytes
+        .into_iter()
+        .map(|key_bytes| {
+            let key = String::from_utf8(key_bytes.to_vec())
+                .map_err(|_| Error::new_parse(ParseError::KeyNotUtf8))?;
+            Key::parse(key)
+        })
+        .collect()
+}
+
+/// Parses a list of entries, each key prefixed with its length as a single byte
+/// and each value prefixed with its length as a `u32`.
+pub(crate) fn parse_entries(input: &[u8]) -> Result<(Vec<Key>, Vec<Value>)> {
+    let (_, entries) = all_consuming(many0(tuple((
+        length_data(complete::u8),
+        length_data(complete::be_u32),
+    ))))(input)
+    .map_err(|_: nom::Err<nom::error::Error<&[u8]>>| Error::new_parse(ParseError::Other))?;
+    let mut keys = Vec::with_capacity(entries.len());
+    let mut values = Vec::with_capacity(entries.len());
+    for (key_bytes, value_bytes) in entries {
+        let key = String::from_utf8(key_bytes.to_vec())
+            .map_err(|_| Error::new_parse(ParseError::KeyNotUtf8))?;
+        keys.push(Key::parse(key)?);
+        values.push(Value::parse(Bytes::copy_from_slice(value_bytes))?);
+    }
+    Ok((keys, values))
+}
+
+/// Parses a list of status codes, one byte each.
+pub(crate) fn parse_statuses(input: &[u8]) -> Result<Vec<StatusCode>> {
+    input
+        .iter()
+        .map(|status| StatusCode::try_from(*status))
+        .collect()
+}
+
+/// Parses an amount, a single `u64`.
+pub(crate) fn parse_amount(input: &[u8]) -> Result<u64> {
+    let (_, amount) = all_consuming(complete::be_u64)(input)
+        .map_err(|_: nom::Err<nom::error::Error<&[u8]>>| Error::new_parse(ParseError::Other))?;
+    Ok(amount)
+}
+
+/// Parses the amount to change a counter by, or its new value, a single `i64`.
+pub(crate) fn parse_delta(input: &[u8]) -> Result<i64> {
+    let (_, delta) = all_consuming(complete::be_i64)(input)
+        .map_err(|_: nom::Err<nom::error::Error<&[u8]>>| Error::new_parse(ParseError::Other))?;
+    Ok(delta)
+}
+
+/// Parses the stats of a connection, four `u64`s in the order of the fields.
+pub(crate) fn parse_connection_stats(input: &[u8]) -> Result<ConnectionStats> {
+    let (_, (handled_requests, bytes_received, bytes_sent, connected_since_unix_epoch_in_millis)) =
+        all_consuming(tuple((
+            complete::be_u64,
+            complete::be_u64,
+            complete::be_u64,
+            complete::be_u64,
+        )))(input)
+        .map_err(|_: nom::Err<nom::error::Error<&[u8]>>| Error::new_parse(ParseError::Other))?;
+    Ok(ConnectionStats {
+        handled_requests,
+        bytes_received,
+        bytes_sent,
+        connected_since_unix_epoch_in_millis,
+    })
+}
+
+/// Parses the info about a key: the value length as a `u32`, a byte that is `1` for tombstones,
+/// the TTL and the update time as `u64`s, the TTL being `0` if there is none, then the flags and
+/// the shard as `u32`s.
+pub(crate) fn parse_key_info(input: &[u8]) -> Result<KeyInfo> {
+    let (_, (value_length, negative, ttl, updated_at, flags, shard)) = all_consuming(tuple((
+        complete::be_u32,
+        complete::u8,
+        complete::be_u64,
+        complete::be_u64,
+        complete::be_u32,
+        complete::be_u32,
+    )))(input)
+    .map_err(|_: nom::Err<nom::error::Error<&[u8]>>| Error::new_parse(ParseError::Other))?;
+    Ok(KeyInfo {
+        value_length,
+        ttl_since_unix_epoch_in_millis: (ttl != 0).then_some(u128::from(ttl)),
+        updated_at_in_millis: u128::from(updated_at),
+        flags,
+        negative: negative == 1,
+        shard,
+    })
+}
+
+/// Parses the capabilities of a server: the key and value limits as `u32`s, the entry limit as a `u64`,
+/// a byte of feature flags and then the allowed op codes, one byte each.
+///
+/// Op codes unknown to this version are skipped, so newer servers can add operations.
+pub(crate) fn parse_capabilities(input: &[u8]) -> Result<ServerCapabilities> {
+    let (op_codes, (max_key_length, max_value_length, max_entries, flags)) = tuple((
+        complete::be_u32,
+        complete::be_u32,
+        complete::be_u64,
+        complete::u8,
+    ))(input)
+    .map_err(|_: nom::Err<nom::error::Error<&[u8]>>| Error::new_parse(ParseError::Other))?;
+    Ok(ServerCapabilities {
+        max_key_length,
+        max_value_length,
+        max_entries: (max_entries != NO_LIMIT).then_some(max_entries),
+        requires_flush_confirmation: flags & FLUSH_CONFIRMATION_FLAG != 0,
+        validates_keys: flags & KEY_VALIDATOR_FLAG != 0,
+        allowed_opcodes: op_codes
+            .iter()
+            .filter_map(|op_code| OpCode::try_from(*op_code).ok())
+            .collect(),
+    })
+}
+
+/// Parses the info of a server, a byte of feature flags followed by the version as UTF-8.
+pub(crate) fn parse_server_info(input: &[u8]) -> Result<ServerInfo> {
+    let (&features, version) = input
+        .split_first()
+        .ok_or_else(|| Error::new_parse(ParseError::Other))?;
+    let version =
+        String::from_utf8(version.to_vec()).map_err(|e| Error::new_parse(ParseError::String(e)))?;
+    Ok(ServerInfo { version, features })
+}
+
+/// Parses a list of flags, prefixed with their amount and packed into bits, lowest bit first.
+pub(crate) fn parse_bits(input: &[u8]) -> Result<Vec<bool>> {
+    let (packed, amount) = complete::be_u32::<_, nom::error::Error<&[u8]>>(input)
+        .map_err(|_| Error::new_parse(ParseError::Other))?;
+    let amount = amount as usize;
+    if packed.len() != amount.div_ceil(8) {
+        return Err(Error::new_parse(ParseError::Other));
+    }
+    Ok((0..amount)
+        .map(|i| packed[i / 8] & (1 << (i % 8)) != 0)
+        .collect())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::error::ErrorInner;
+    use rstest::rstest;
+
+    #[test]
+    fn test_parsing_keys_works() {
+        let keys = parse_keys(b"\x03ABC\x01D").unwrap();
+        assert_eq!(
+            keys,
+            vec![
+                Key::parse("ABC".to_string()).unwrap(),
+                Key::parse("D".to_string()).unwrap()
+            ]
+        );
+        assert!(parse_keys(b"").unwrap().is_empty());
+    }
+
+    #[rstest]
+    #[case(b"\x03AB".as_slice())]
+    #[case(b"\x01\xff".as_slice())]
+    fn test_parsing_invalid_keys_fails(#[case] input: &[u8]) {
+        assert!(parse_keys(input).is_err());
+    }
+
+    #[test]
+    fn test_parsing_entries_works() {
+        let (keys, values) = parse_entries(b"\x03ABC\0\0\0\x02de\x01F\0\0\0\0").unwrap();
+        assert_eq!(
+            keys,
+            vec![
+                Key::parse("ABC".to_string()).unwrap(),
+                Key::parse("F".to_string()).unwrap()
+            ]
+        );
+        assert_eq!(
+            values,
+            vec![Value::parse("de").unwrap(), Value::parse("").unwrap()]
+        );
+        let (keys, values) = parse_entries(b"").unwrap();
+        assert!(keys.is_empty() && values.is_empty());
+    }
+
+    #[rstest]
+    #[case(b"\x03ABC".as_slice())]
+    #[case(b"\x03ABC\0\0\0\x02d".as_slice())]
+    #[case(b"\x01\xff\0\0\0\0".as_slice())]
+    #[case(b"\0\0\0\0\0".as_slice())]
+    fn test_parsing_invalid_entries_fails(#[case] input: &[u8]) {
+        assert!(parse_entries(input).is_err());
+    }
+
+    #[rstest]
+    #[case(&[0, 0, 0, 0, 0, 0, 1, 2], Some(258))]
+    #[case(&[0, 0, 0, 0, 0, 0, 1], None)]
+    #[case(&[0, 0, 0, 0, 0, 0, 0, 1, 0], None)]
+    fn test_parsing_amounts_works(#[case] input: &[u8], #[case] expected: Option<u64>) {
+        assert_eq!(parse_amount(input).ok(), expected);
+    }
+
+    #[test]
+    fn test_parsing_bits_works() {
+        assert_eq!(
+            parse_bits(&[0, 0, 0, 10, 0b0000_0101, 0b0000_0010]).unwrap(),
+            vec![true, false, true, false, false, false, false, false, false, true]
+        );
+        assert!(parse_bits(&[0, 0, 0, 0]).unwrap().is_empty());
+    }
+
+    #[rstest]
+    #[case(&[0, 0, 0])]
+    #[case(&[0, 0, 0, 9, 0])]
+    #[case(&[0, 0, 0, 1, 0, 0])]
+    fn test_parsing_invalid_bits_fails(#[case] input: &[u8]) {
+        assert!(parse_bits(input).is_err());
+    }
+
+    /// Feeds `input` through the parsers and the conversions the connection runs on a parsed frame.
+    ///
+    /// Parsing must return an error rather than panic, and a parsed frame must lie within `input`
+    /// as the connection skips that many bytes.
+    fn parse_arbitrary(input: &[u8]) {
+        if let Ok(frame) = parse_request_frame(input) {
+            assert!(frame.header.total_frame_length as usize <= input.len());
+            let _ = crate::request::Request::try_from(frame);
+        }
+        if let Ok(frame) = parse_response_frame(input) {
+            assert!(frame.header.total_frame_length as usize <= input.len());
+            let _ = crate::response::Response::try_from(frame);
+        }
+    }
+
+    /// Valid frames for every op code, to mutate into frames that are almost but not quite valid.
+    fn valid_frames() -> Vec<Vec<u8>> {
+        let mut frames = vec![];
+        for op_code in OpCode::ALL {
+            let key = Key::parse("key".to_string()).unwrap();
+            let value = Value::parse(&b"\x03key\x01k"[..]).unwrap();
+            let ttl = TTLSinceUnixEpochInMillis::parse(Some(1234));
+            let mut buf = bytes::BytesMut::new();
+            RequestFrame::new(op_code, ttl, Some(key), Some(value))
+                .unwrap()
+                .encode(&mut buf);
+            frames.push(buf.to_vec());
+            buf.clear();
+            ResponseFrame::new(op_code, StatusCode::Ok, ttl, None, None)
+                .unwrap()
+                .encode(&mut buf);
+            frames.push(buf.to_vec());
+        }
+        frames
+    }
+
+    #[test]
+    fn test_parsing_arbitrary_bytes_never_panics() {
+        use rand::rngs::StdRng;
+        use rand::{Rng, SeedableRng};
+
+        // Seeded, so a failing input can be reproduced
+        let mut rng = StdRng::seed_from_u64(0x00ca_c4ed);
+        for _ in 0..20_000 {
+            let length = rng.gen_range(0..64);
+            let input: Vec<u8> = (0..length).map(|_| rng.gen()).collect();
+            parse_arbitrary(&input);
+        }
+
+        let frames = valid_frames();
+        for _ in 0..50_000 {
+            let mut input = frames[rng.gen_range(0..frames.len())].clone();
+            for _ in 0..rng.gen_range(1..4) {
+                let idx = rng.gen_range(0..input.len());
+                input[idx] = rng.gen();
+            }
+            input.truncate(rng.gen_range(0..=input.len()));
+            parse_arbitrary(&input);
+        }
+    }
+
+    #[rstest]
+    // Total frame length shorter than the header
+    #[case(b"\x02\0\0\0\0\0\0\0\0\0\0".as_slice())]
+    // Total frame length shorter than header and key, before a TTL that did not arrive
+    #[case(b"\x01\0\x03\0\0\0\0\0\0\0\x0d".as_slice())]
+    fn test_parsing_frames_shorter_than_header_and_key_fails_as_invalid_length(
+        #[case] input: &[u8],
+    ) {
+        for e in [
+            parse_request_frame(input).unwrap_err(),
+            parse_response_frame(input).unwrap_err(),
+        ] {
+            assert!(
+                matches!(e, Error(ErrorInner::Frame(FrameError::InvalidLength))),
+                "{e:?}"
+            );
+        }
+    }
+
+    #[rstest]
+    // Total frame length shorter than the header
+    #[case(b"\x03\0\0\0\0\0\0\0\0\0\x01".as_slice())]
+    // Total frame length shorter than header and key
+    #[case(b"\x03\0\x03\0\0\0\0\0\0\0\x0bkey".as_slice())]
+    // Total frame length announcing a value longer than the limit
+    #[case(b"\x03\0\0\0\0\0\0\xff\xff\xff\xff".as_slice())]
+    fn test_parsing_frames_with_invalid_lengths_fails(#[case] input: &[u8]) {
+        let e = parse_request_frame(input).unwrap_err();
+        assert!(!e.is_incomplete_frame(), "{e:?}");
+        let e = parse_response_frame(input).unwrap_err();
+        assert!(!e.is_incomplete_frame(), "{e:?}");
+    }
+}
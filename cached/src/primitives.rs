@@ -11,6 +11,17 @@ pub enum StatusCode {
     KeyNotFound = 1,
     KeyExists = 2,
     InternalError = 3,
+    WrongType = 4,
+    ValueTooLarge = 5,
+    OperationNotPermitted = 6,
+    /// The key is remembered as missing, so there is no point in looking it up elsewhere.
+    NegativeCached = 7,
+    /// The key was rejected by the server's key validator.
+    InvalidKey = 8,
+    /// The stored value is not what the operation works on, e.g. not an integer to increment.
+    InvalidValue = 9,
+    /// The result of the operation does not fit, e.g. incrementing a counter beyond `i64::MAX`.
+    OutOfRange = 10,
 }
 
 impl fmt::Display for StatusCode {
@@ -20,6 +31,13 @@ impl fmt::Display for StatusCode {
             Self::KeyNotFound => write!(f, "Key not found"),
             Self::KeyExists => write!(f, "Key exists"),
             Self::InternalError => write!(f, "INTERNAL ERROR"),
+            Self::WrongType => write!(f, "Wrong type"),
+            Self::ValueTooLarge => write!(f, "Value too large"),
+            Self::OperationNotPermitted => write!(f, "Operation not permitted"),
+            Self::NegativeCached => write!(f, "Negative cached"),
+            Self::InvalidKey => write!(f, "Invalid key"),
+            Self::InvalidValue => write!(f, "Invalid value"),
+            Self::OutOfRange => write!(f, "Out of range"),
         }
     }
 }
@@ -33,19 +51,77 @@ impl TryFrom<u8> for StatusCode {
             1 => Ok(StatusCode::KeyNotFound),
             2 => Ok(StatusCode::KeyExists),
             3 => Ok(StatusCode::InternalError),
+            4 => Ok(StatusCode::WrongType),
+            5 => Ok(StatusCode::ValueTooLarge),
+            6 => Ok(StatusCode::OperationNotPermitted),
+            7 => Ok(StatusCode::NegativeCached),
+            8 => Ok(StatusCode::InvalidKey),
+            9 => Ok(StatusCode::InvalidValue),
+            10 => Ok(StatusCode::OutOfRange),
             _ => Err(Error::new_frame(FrameError::InvalidStatusCode)),
         }
     }
 }
 
-#[derive(Debug, Copy, Clone)]
-#[cfg_attr(test, derive(PartialEq, Eq))]
+/// The operations a client can request from the server.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[repr(u8)]
-pub(crate) enum OpCode {
+pub enum OpCode {
     Set = 1,
     Get = 2,
     Delete = 3,
     Flush = 4,
+    ExistsMany = 5,
+    LPush = 6,
+    RPop = 7,
+    SAdd = 8,
+    SIsMember = 9,
+    SRem = 10,
+    KeysGlob = 11,
+    SetNegative = 12,
+    ConnStats = 13,
+    Capabilities = 14,
+    DeleteReturning = 15,
+    SetMany = 16,
+    FlushExpired = 17,
+    Version = 18,
+    RandomKey = 19,
+    Inspect = 20,
+    SubscribeEvictions = 21,
+    Increment = 22,
+    Decrement = 23,
+    SetChunk = 24,
+}
+
+impl OpCode {
+    /// All operations, in the order of their op codes.
+    pub(crate) const ALL: [OpCode; 24] = [
+        OpCode::Set,
+        OpCode::Get,
+        OpCode::Delete,
+        OpCode::Flush,
+        OpCode::ExistsMany,
+        OpCode::LPush,
+        OpCode::RPop,
+        OpCode::SAdd,
+        OpCode::SIsMember,
+        OpCode::SRem,
+        OpCode::KeysGlob,
+        OpCode::SetNegative,
+        OpCode::ConnStats,
+        OpCode::Capabilities,
+        OpCode::DeleteReturning,
+        OpCode::SetMany,
+        OpCode::FlushExpired,
+        OpCode::Version,
+        OpCode::RandomKey,
+        OpCode::Inspect,
+        OpCode::SubscribeEvictions,
+        OpCode::Increment,
+        OpCode::Decrement,
+        OpCode::SetChunk,
+    ];
 }
 
 impl TryFrom<u8> for OpCode {
@@ -57,6 +133,26 @@ impl TryFrom<u8> for OpCode {
             2 => Ok(OpCode::Get),
             3 => Ok(OpCode::Delete),
             4 => Ok(OpCode::Flush),
+            5 => Ok(OpCode::ExistsMany),
+            6 => Ok(OpCode::LPush),
+            7 => Ok(OpCode::RPop),
+            8 => Ok(OpCode::SAdd),
+            9 => Ok(OpCode::SIsMember),
+            10 => Ok(OpCode::SRem),
+            11 => Ok(OpCode::KeysGlob),
+            12 => Ok(OpCode::SetNegative),
+            13 => Ok(OpCode::ConnStats),
+            14 => Ok(OpCode::Capabilities),
+            15 => Ok(OpCode::DeleteReturning),
+            16 => Ok(OpCode::SetMany),
+            17 => Ok(OpCode::FlushExpired),
+            18 => Ok(OpCode::Version),
+            19 => Ok(OpCode::RandomKey),
+            20 => Ok(OpCode::Inspect),
+            21 => Ok(OpCode::SubscribeEvictions),
+            22 => Ok(OpCode::Increment),
+            23 => Ok(OpCode::Decrement),
+            24 => Ok(OpCode::SetChunk),
             _ => Err(Error::new_frame(FrameError::InvalidOpCode)),
         }
     }
@@ -73,6 +169,26 @@ mod test {
         assert_eq!(OpCode::Get as u8, 2);
         assert_eq!(OpCode::Delete as u8, 3);
         assert_eq!(OpCode::Flush as u8, 4);
+        assert_eq!(OpCode::ExistsMany as u8, 5);
+        assert_eq!(OpCode::LPush as u8, 6);
+        assert_eq!(OpCode::RPop as u8, 7);
+        assert_eq!(OpCode::SAdd as u8, 8);
+        assert_eq!(OpCode::SIsMember as u8, 9);
+        assert_eq!(OpCode::SRem as u8, 10);
+        assert_eq!(OpCode::KeysGlob as u8, 11);
+        assert_eq!(OpCode::SetNegative as u8, 12);
+        assert_eq!(OpCode::ConnStats as u8, 13);
+        assert_eq!(OpCode::Capabilities as u8, 14);
+        assert_eq!(OpCode::DeleteReturning as u8, 15);
+        assert_eq!(OpCode::SetMany as u8, 16);
+        assert_eq!(OpCode::FlushExpired as u8, 17);
+        assert_eq!(OpCode::Version as u8, 18);
+        assert_eq!(OpCode::RandomKey as u8, 19);
+        assert_eq!(OpCode::Inspect as u8, 20);
+        assert_eq!(OpCode::SubscribeEvictions as u8, 21);
+        assert_eq!(OpCode::Increment as u8, 22);
+        assert_eq!(OpCode::Decrement as u8, 23);
+        assert_eq!(OpCode::SetChunk as u8, 24);
     }
 
     #[test]
@@ -81,16 +197,39 @@ mod test {
         assert_eq!(OpCode::try_from(2).unwrap(), OpCode::Get);
         assert_eq!(OpCode::try_from(3).unwrap(), OpCode::Delete);
         assert_eq!(OpCode::try_from(4).unwrap(), OpCode::Flush);
+        assert_eq!(OpCode::try_from(5).unwrap(), OpCode::ExistsMany);
+        assert_eq!(OpCode::try_from(6).unwrap(), OpCode::LPush);
+        assert_eq!(OpCode::try_from(7).unwrap(), OpCode::RPop);
+        assert_eq!(OpCode::try_from(8).unwrap(), OpCode::SAdd);
+        assert_eq!(OpCode::try_from(9).unwrap(), OpCode::SIsMember);
+        assert_eq!(OpCode::try_from(10).unwrap(), OpCode::SRem);
+        assert_eq!(OpCode::try_from(11).unwrap(), OpCode::KeysGlob);
+        assert_eq!(OpCode::try_from(12).unwrap(), OpCode::SetNegative);
+        assert_eq!(OpCode::try_from(13).unwrap(), OpCode::ConnStats);
+        assert_eq!(OpCode::try_from(14).unwrap(), OpCode::Capabilities);
+        assert_eq!(OpCode::try_from(15).unwrap(), OpCode::DeleteReturning);
+        assert_eq!(OpCode::try_from(16).unwrap(), OpCode::SetMany);
+        assert_eq!(OpCode::try_from(17).unwrap(), OpCode::FlushExpired);
+        assert_eq!(OpCode::try_from(18).unwrap(), OpCode::Version);
+        assert_eq!(OpCode::try_from(19).unwrap(), OpCode::RandomKey);
+        assert_eq!(OpCode::try_from(20).unwrap(), OpCode::Inspect);
+        assert_eq!(OpCode::try_from(21).unwrap(), OpCode::SubscribeEvictions);
+        assert_eq!(OpCode::try_from(22).unwrap(), OpCode::Increment);
+        assert_eq!(OpCode::try_from(23).unwrap(), OpCode::Decrement);
+        assert_eq!(OpCode::try_from(24).unwrap(), OpCode::SetChunk);
+    }
+
+    #[test]
+    fn test_all_op_codes_are_listed_in_order() {
+        let op_codes: Vec<u8> = OpCode::ALL.iter().map(|op_code| *op_code as u8).collect();
+        assert_eq!(op_codes, (1..=24).collect::<Vec<u8>>());
     }
 
     #[rstest]
     #[case(0)]
-    #[case(5)]
-    #[case(6)]
-    #[case(7)]
-    #[case(8)]
-    #[case(9)]
-    #[case(10)]
+    #[case(25)]
+    #[case(26)]
+    #[case(27)]
     fn test_op_code_deserialization_fails_for_wrong_codes(#[case] input: u8) {
         assert!(OpCode::try_from(input).is_err());
     }
@@ -101,6 +240,13 @@ mod test {
         assert_eq!(StatusCode::KeyNotFound as u8, 1);
         assert_eq!(StatusCode::KeyExists as u8, 2);
         assert_eq!(StatusCode::InternalError as u8, 3);
+        assert_eq!(StatusCode::WrongType as u8, 4);
+        assert_eq!(StatusCode::ValueTooLarge as u8, 5);
+        assert_eq!(StatusCode::OperationNotPermitted as u8, 6);
+        assert_eq!(StatusCode::NegativeCached as u8, 7);
+        assert_eq!(StatusCode::InvalidKey as u8, 8);
+        assert_eq!(StatusCode::InvalidValue as u8, 9);
+        assert_eq!(StatusCode::OutOfRange as u8, 10);
     }
 
     #[test]
@@ -109,16 +255,23 @@ mod test {
         assert_eq!(StatusCode::try_from(1).unwrap(), StatusCode::KeyNotFound);
         assert_eq!(StatusCode::try_from(2).unwrap(), StatusCode::KeyExists);
         assert_eq!(StatusCode::try_from(3).unwrap(), StatusCode::InternalError);
+        assert_eq!(StatusCode::try_from(4).unwrap(), StatusCode::WrongType);
+        assert_eq!(StatusCode::try_from(5).unwrap(), StatusCode::ValueTooLarge);
+        assert_eq!(
+            StatusCode::try_from(6).unwrap(),
+            StatusCode::OperationNotPermitted
+        );
+        assert_eq!(StatusCode::try_from(7).unwrap(), StatusCode::NegativeCached);
+        assert_eq!(StatusCode::try_from(8).unwrap(), StatusCode::InvalidKey);
+        assert_eq!(StatusCode::try_from(9).unwrap(), StatusCode::InvalidValue);
+        assert_eq!(StatusCode::try_from(10).unwrap(), StatusCode::OutOfRange);
     }
 
     #[rstest]
-    #[case(4)]
-    #[case(5)]
-    #[case(6)]
-    #[case(7)]
-    #[case(8)]
-    #[case(9)]
-    #[case(10)]
+    #[case(11)]
+    #[case(12)]
+    #[case(13)]
+    #[case(14)]
     fn test_status_code_deserialization_fails_for_wrong_codes(#[case] input: u8) {
         assert!(StatusCode::try_from(input).is_err());
     }
@@ -0,0 +1,365 @@
+//! The wire format spoken between clients and the server, for writing clients in other languages.
+//!
+//! Requests and responses are sent as frames over a single TCP connection.
+//! All integers are big endian.
+//!
+//! # Frame layout
+//!
+//! | Offset | Size | Field                                                                            |
+//! |--------|------|----------------------------------------------------------------------------------|
+//! | 0      | 1    | [`OpCode`]                                                                       |
+//! | 1      | 1    | [`StatusCode`] in responses, always `0` in requests                              |
+//! | 2      | 1    | Key length in bytes                                                              |
+//! | 3      | 4    | Request id, echoed back by the server in the response                            |
+//! | 7      | 4    | Total frame length in bytes, including the header                                |
+//! | 11     | 8    | TTL in milliseconds since the unix epoch, see [`request_has_ttl`] and [`response_has_ttl`] |
+//! | 19     | 4    | Flags stored with the value, see [`request_has_flags`] and [`response_has_flags`] |
+//! | 23     | 8    | When the value was last set in milliseconds since the unix epoch, see [`response_has_updated_at`] |
+//! | ...    | ...  | Key, then the value, taking up the rest of the frame                             |
+//!
+//! A TTL of [`NO_TTL`] stands for no TTL at all.
+//! Frames lacking any of these fields skip it, the key directly follows the fields the frame has.
+//! The flags are stored with the value as they are, for clients to tag e.g. how the value is encoded.
+//! Keys must be valid UTF-8 and must not be empty, a key length of `0` stands for no key.
+//! Values are arbitrary bytes.
+//!
+//! # Values of specific operations
+//!
+//! - A [`OpCode::Flush`] request is only carried out by servers requiring confirmation
+//!   if its value is [`FLUSH_CONFIRMATION`].
+//! - [`OpCode::ExistsMany`] requests and [`OpCode::KeysGlob`] responses carry a list of keys
+//!   as the value, each key prefixed with its length as a single byte.
+//! - [`OpCode::SetMany`] requests carry a list of entries as the value, each key prefixed with its
+//!   length as a single byte and each value prefixed with its length as a `u32`.
+//!   The TTL in the header applies to all of them.
+//! - [`OpCode::SetMany`] responses carry the status of each entry, one byte each, in the order of the request.
+//! - [`OpCode::ExistsMany`] responses carry the amount of flags as a `u32`, followed by the flags
+//!   packed into bytes, lowest bit first.
+//! - The set operations [`OpCode::SAdd`], [`OpCode::SIsMember`] and [`OpCode::SRem`]
+//!   carry the member as the value.
+//! - The [`OpCode::KeysGlob`] request carries the pattern as the key.
+//! - The [`OpCode::SetNegative`] request carries the key but no value.
+//! - [`OpCode::DeleteReturning`] responses carry the deleted value, if there was one.
+//! - [`OpCode::ConnStats`] responses carry four `u64`s: the requests handled on the connection,
+//!   the bytes received and sent on it, and when it was accepted in milliseconds since the unix epoch.
+//! - [`OpCode::FlushExpired`] responses carry the amount of expired keys removed as a `u64`.
+//! - [`OpCode::Capabilities`] responses carry the longest key and value accepted as `u32`s,
+//!   the limit on entries as a `u64`, [`NO_LIMIT`] if there is none, a byte of feature flags,
+//!   see [`FLUSH_CONFIRMATION_FLAG`] and [`KEY_VALIDATOR_FLAG`], and then the op codes the server
+//!   carries out, one byte each.
+//! - [`OpCode::Version`] responses carry a byte of feature flags, see [`HTTP_FEATURE`] and [`RESP_FEATURE`],
+//!   followed by the version of the server as UTF-8.
+//! - [`OpCode::RandomKey`] responses carry a random live key as the key, if there was one.
+//! - [`OpCode::Inspect`] responses carry the length of the value as a `u32`, a byte that is `1` for
+//!   negative cached keys, when the key expires and when it was last set in milliseconds since the
+//!   unix epoch as `u64`s, the former `0` if it does not expire, then the flags and the shard as `u32`s.
+//! - The [`OpCode::SubscribeEvictions`] request is answered by a response without keys, followed by
+//!   a response with the same request id for every batch of expired keys the server removes.
+//!   Those carry the keys like [`OpCode::ExistsMany`] requests do. Nothing else is answered on the
+//!   connection from then on.
+//! - [`OpCode::Increment`] and [`OpCode::Decrement`] requests carry the amount to add or subtract
+//!   as an `i64`. Their responses are laid out like [`OpCode::Get`] responses, carrying the counter
+//!   as it is stored afterwards, its value a decimal string.
+//! - [`OpCode::SetChunk`] requests stream a value too long to hold at once in chunks, each carrying
+//!   the key and the next chunk as the value. Their flags tell whether more chunks follow (`0`),
+//!   the chunk is the last one ([`CHUNK_LAST_FLAG`]), or the chunks so far are to be discarded
+//!   ([`CHUNK_ABORT_FLAG`], without a value). The server collects the chunks per connection and key
+//!   and sets the value with the TTL of the last chunk, without flags. Chunks of a value that
+//!   grows longer than [`MAX_VALUE_LENGTH`] are discarded and answered with
+//!   [`StatusCode::ValueTooLarge`], as are chunks left over when the connection closes.
+//!
+//! # Without the runtime
+//!
+//! Turning off the default `runtime` feature leaves only this module, [`Frame`] encoding and decoding
+//! frames, without depending on tokio. This suits tooling and clients that bring their own IO.
+//!
+//! ```
+//! use bytes::BytesMut;
+//! use cached::protocol::{Frame, OpCode};
+//!
+//! let mut frame = Frame::new(OpCode::Set, 1);
+//! frame.key = Some("foo".to_string());
+//! frame.value = Some("bar".into());
+//! let mut buf = BytesMut::new();
+//! frame.encode_request(&mut buf).unwrap();
+//!
+//! let (decoded, length) = Frame::decode_request(&buf).unwrap().unwrap();
+//! assert_eq!(decoded, frame);
+//! assert_eq!(length, buf.len());
+//! ```
+
+use crate::domain::{Key, TTLSinceUnixEpochInMillis, Value};
+use crate::error::Result;
+use crate::frame::{RequestFrame, ResponseFrame};
+use crate::parsing::{parse_request_frame, parse_response_frame};
+pub use crate::primitives::{OpCode, StatusCode};
+use bytes::{Bytes, BytesMut};
+
+/// The size of the fixed part of the header every frame starts with.
+pub const HEADER_SIZE: u8 = 11;
+/// The size of the TTL field following the fixed part of the header.
+pub const TTL_SIZE: u8 = 8;
+/// The size of the flags field following the TTL field.
+pub const FLAGS_SIZE: u8 = 4;
+/// The size of the field following the flags field, telling when the value was last set.
+pub const UPDATED_AT_SIZE: u8 = 8;
+
+/// Where the op code is in the header.
+pub const OP_CODE_OFFSET: usize = 0;
+/// Where the status code is in the header of responses, requests have a padding byte there.
+pub const STATUS_OFFSET: usize = 1;
+/// Where the key length is in the header.
+pub const KEY_LENGTH_OFFSET: usize = 2;
+/// Where the request id is in the header.
+pub const REQUEST_ID_OFFSET: usize = 3;
+/// Where the total frame length is in the header.
+pub const TOTAL_FRAME_LENGTH_OFFSET: usize = 7;
+/// Where the TTL is, for the frames that carry one.
+pub const TTL_OFFSET: usize = HEADER_SIZE as usize;
+/// Where the flags are, for the frames that carry them.
+pub const FLAGS_OFFSET: usize = TTL_OFFSET + TTL_SIZE as usize;
+/// Where the time the value was last set is, for the frames that carry it.
+pub const UPDATED_AT_OFFSET: usize = FLAGS_OFFSET + FLAGS_SIZE as usize;
+
+/// Stands for no TTL on the wire, so `0`, the unix epoch itself, remains an ordinary TTL.
+pub const NO_TTL: u64 = u64::MAX;
+/// Keys must not be longer than this many bytes.
+pub const MAX_KEY_LENGTH: usize = u8::MAX as usize;
+/// Values must not be longer than this many bytes, 1MB.
+pub const MAX_VALUE_LENGTH: u32 = 1024 * 1024;
+/// The value confirming that a flush is meant to wipe the whole cache.
+pub const FLUSH_CONFIRMATION: &[u8] = b"FLUSH ALL";
+/// Stands for no limit on the entries in [`OpCode::Capabilities`] responses.
+pub const NO_LIMIT: u64 = u64::MAX;
+/// Set in [`OpCode::Capabilities`] responses if flushing must be confirmed.
+pub const FLUSH_CONFIRMATION_FLAG: u8 = 0b01;
+/// Set in [`OpCode::Capabilities`] responses if keys are checked against a validator.
+pub const KEY_VALIDATOR_FLAG: u8 = 0b10;
+/// Set in [`OpCode::Version`] responses if the server was built with the `http` feature.
+pub const HTTP_FEATURE: u8 = 0b01;
+/// Set in [`OpCode::Version`] responses if the server was built with the `resp` feature.
+pub const RESP_FEATURE: u8 = 0b10;
+/// Set in [`OpCode::SetChunk`] requests carrying the last chunk of the value.
+pub const CHUNK_LAST_FLAG: u32 = 0b01;
+/// Set in [`OpCode::SetChunk`] requests discarding the chunks sent so far.
+pub const CHUNK_ABORT_FLAG: u32 = 0b10;
+
+/// A request or a response as it is sent over the wire.
+///
+/// The value is carried as is, the values of specific operations are not encoded or decoded.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Frame {
+    pub op_code: OpCode,
+    /// The status of a response, requests don't carry one and decode as [`StatusCode::Ok`].
+    pub status: StatusCode,
+    pub request_id: u32,
+    /// Only carried by the frames that have a TTL field, see [`request_has_ttl`] and [`response_has_ttl`].
+    pub ttl_since_unix_epoch_in_millis: Option<u128>,
+    /// Only carried by the frames that have a flags field, see [`request_has_flags`] and [`response_has_flags`].
+    pub flags: u32,
+    /// When the value was last set, only carried by the frames that have the field, see [`response_has_updated_at`].
+    pub updated_at_in_millis: u64,
+    pub key: Option<String>,
+    pub value: Option<Bytes>,
+}
+
+impl Frame {
+    /// Creates a frame without TTL, flags, key or value, with the status [`StatusCode::Ok`].
+    pub fn new(op_code: OpCode, request_id: u32) -> Self {
+        Self {
+            op_code,
+            status: StatusCode::Ok,
+            request_id,
+            ttl_since_unix_epoch_in_millis: None,
+            flags: 0,
+            updated_at_in_millis: 0,
+            key: None,
+            value: None,
+        }
+    }
+
+    /// Appends the frame as a request to `buf`.
+    ///
+    /// Fails if the key is empty or longer than [`MAX_KEY_LENGTH`],
+    /// or if the value is longer than [`MAX_VALUE_LENGTH`].
+    pub fn encode_request(&self, buf: &mut BytesMut) -> Result<()> {
+        let (key, value) = self.key_and_value()?;
+        let ttl = TTLSinceUnixEpochInMillis::parse(self.ttl_since_unix_epoch_in_millis);
+        RequestFrame::new(self.op_code, ttl, key, value)?
+            .with_request_id(self.request_id)
+            .with_flags(self.flags)
+            .encode(buf);
+        Ok(())
+    }
+
+    /// Appends the frame as a response to `buf`, failing like [`Frame::encode_request`].
+    pub fn encode_response(&self, buf: &mut BytesMut) -> Result<()> {
+        let (key, value) = self.key_and_value()?;
+        let ttl = TTLSinceUnixEpochInMillis::parse(self.ttl_since_unix_epoch_in_millis);
+        ResponseFrame::new(self.op_code, self.status, ttl, key, value)?
+            .with_request_id(self.request_id)
+            .with_flags(self.flags)
+            .with_updated_at(self.updated_at_in_millis)
+            .encode(buf);
+        Ok(())
+    }
+
+    /// Decodes the request at the start of `buf` and returns it together with its length in bytes.
+    ///
+    /// Returns `None` if `buf` does not hold the whole frame yet.
+    pub fn decode_request(buf: &[u8]) -> Result<Option<(Self, usize)>> {
+        let frame = match parse_request_frame(buf) {
+            Err(e) if e.is_incomplete_frame() => return Ok(None),
+            frame => frame?,
+        };
+        let decoded = Self {
+            op_code: frame.header.op_code,
+            status: StatusCode::Ok,
+            request_id: frame.header.request_id,
+            ttl_since_unix_epoch_in_millis: frame.header.ttl_since_unix_epoch_in_millis.into_ttl(),
+            flags: frame.header.flags,
+            updated_at_in_millis: 0,
+            key: frame.key.map(Key::into_inner),
+            value: frame.value.map(Value::into_bytes),
+        };
+        Ok(Some((decoded, frame.header.total_frame_length as usize)))
+    }
+
+    /// Decodes the response at the start of `buf` like [`Frame::decode_request`].
+    pub fn decode_response(buf: &[u8]) -> Result<Option<(Self, usize)>> {
+        let frame = match parse_response_frame(buf) {
+            Err(e) if e.is_incomplete_frame() => return Ok(None),
+            frame => frame?,
+        };
+        let decoded = Self {
+            op_code: frame.header.op_code,
+            status: frame.header.status,
+            request_id: frame.header.request_id,
+            ttl_since_unix_epoch_in_millis: frame.header.ttl_since_unix_epoch_in_millis.into_ttl(),
+            flags: frame.header.flags,
+            updated_at_in_millis: frame.header.updated_at_in_millis,
+            key: frame.key.map(Key::into_inner),
+            value: frame.value.map(Value::into_bytes),
+        };
+        Ok(Some((decoded, frame.header.total_frame_length as usize)))
+    }
+
+    fn key_and_value(&self) -> Result<(Option<Key>, Option<Value>)> {
+        let key = self.key.clone().map(Key::parse).transpose()?;
+        let value = self.value.clone().map(Value::parse).transpose()?;
+        Ok((key, value))
+    }
+}
+
+/// Returns whether a request for `op_code` carries a TTL field,
+/// only Set, SetNegative, SetMany and SetChunk requests do.
+pub fn request_has_ttl(op_code: OpCode) -> bool {
+    matches!(
+        op_code,
+        OpCode::Set | OpCode::SetNegative | OpCode::SetMany | OpCode::SetChunk
+    )
+}
+
+/// Returns whether a response for `op_code` carries a TTL field,
+/// only Get responses and the ones laid out like them do.
+pub fn response_has_ttl(op_code: OpCode) -> bool {
+    matches!(op_code, OpCode::Get | OpCode::Increment | OpCode::Decrement)
+}
+
+/// Returns whether a request for `op_code` carries a flags field, only Set and SetChunk requests do.
+pub fn request_has_flags(op_code: OpCode) -> bool {
+    matches!(op_code, OpCode::Set | OpCode::SetChunk)
+}
+
+/// Returns whether a response for `op_code` carries a flags field, like [`response_has_ttl`].
+pub fn response_has_flags(op_code: OpCode) -> bool {
+    response_has_ttl(op_code)
+}
+
+/// Returns whether a response for `op_code` carries when the value was last set, like [`response_has_ttl`].
+pub fn response_has_updated_at(op_code: OpCode) -> bool {
+    response_has_ttl(op_code)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_protocol_constants_do_not_change() {
+        assert_eq!(HEADER_SIZE, 11);
+        assert_eq!(TTL_SIZE, 8);
+        assert_eq!(OP_CODE_OFFSET, 0);
+        assert_eq!(STATUS_OFFSET, 1);
+        assert_eq!(KEY_LENGTH_OFFSET, 2);
+        assert_eq!(REQUEST_ID_OFFSET, 3);
+        assert_eq!(TOTAL_FRAME_LENGTH_OFFSET, 7);
+        assert_eq!(TTL_OFFSET, 11);
+        assert_eq!(FLAGS_SIZE, 4);
+        assert_eq!(FLAGS_OFFSET, 19);
+        assert_eq!(UPDATED_AT_SIZE, 8);
+        assert_eq!(UPDATED_AT_OFFSET, 23);
+        assert_eq!(NO_TTL, u64::MAX);
+        assert_eq!(MAX_KEY_LENGTH, 255);
+        assert_eq!(MAX_VALUE_LENGTH, 1_048_576);
+        assert_eq!(FLUSH_CONFIRMATION, b"FLUSH ALL");
+        assert_eq!(NO_LIMIT, u64::MAX);
+        assert_eq!(FLUSH_CONFIRMATION_FLAG, 1);
+        assert_eq!(KEY_VALIDATOR_FLAG, 2);
+        assert_eq!(HTTP_FEATURE, 1);
+        assert_eq!(RESP_FEATURE, 2);
+        assert_eq!(CHUNK_LAST_FLAG, 1);
+        assert_eq!(CHUNK_ABORT_FLAG, 2);
+    }
+
+    #[test]
+    fn test_encoded_frames_follow_the_documented_layout() {
+        use crate::domain::{Key, TTLSinceUnixEpochInMillis, Value};
+        use crate::frame::RequestFrame;
+        use bytes::BytesMut;
+
+        let frame = RequestFrame::new(
+            OpCode::Set,
+            TTLSinceUnixEpochInMillis::parse(Some(1234)),
+            Some(Key::parse("key".to_string()).unwrap()),
+            Some(Value::parse("value").unwrap()),
+        )
+        .unwrap()
+        .with_request_id(42)
+        .with_flags(7);
+        let mut buf = BytesMut::new();
+        frame.encode(&mut buf);
+
+        assert_eq!(buf[OP_CODE_OFFSET], OpCode::Set as u8);
+        assert_eq!(buf[STATUS_OFFSET], 0);
+        assert_eq!(buf[KEY_LENGTH_OFFSET], 3);
+        assert_eq!(
+            buf[REQUEST_ID_OFFSET..REQUEST_ID_OFFSET + 4],
+            42u32.to_be_bytes()
+        );
+        assert_eq!(
+            buf[TOTAL_FRAME_LENGTH_OFFSET..TOTAL_FRAME_LENGTH_OFFSET + 4],
+            (buf.len() as u32).to_be_bytes()
+        );
+        assert_eq!(buf[TTL_OFFSET..FLAGS_OFFSET], 1234u64.to_be_bytes());
+        let key_offset = FLAGS_OFFSET + FLAGS_SIZE as usize;
+        assert_eq!(buf[FLAGS_OFFSET..key_offset], 7u32.to_be_bytes());
+        assert_eq!(&buf[key_offset..key_offset + 3], b"key");
+        assert_eq!(&buf[key_offset + 3..], b"value");
+    }
+
+    #[test]
+    fn test_only_setting_requests_and_responses_laid_out_like_get_have_a_ttl() {
+        assert!(request_has_ttl(OpCode::Set));
+        assert!(request_has_ttl(OpCode::SetNegative));
+        assert!(request_has_ttl(OpCode::SetMany));
+        assert!(request_has_ttl(OpCode::SetChunk));
+        assert!(!request_has_ttl(OpCode::Get));
+        assert!(!request_has_ttl(OpCode::Delete));
+        assert!(response_has_ttl(OpCode::Get));
+        assert!(response_has_ttl(OpCode::Increment));
+        assert!(response_has_ttl(OpCode::Decrement));
+        assert!(!response_has_ttl(OpCode::Set));
+        assert!(!response_has_ttl(OpCode::Delete));
+    }
+}
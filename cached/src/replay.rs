@@ -0,0 +1,112 @@
+use crate::error::Result;
+use crate::tap::RequestLog;
+use crate::Client;
+use std::time::SystemTime;
+use tokio::time::Instant;
+
+/// Replays requests recorded via [`Server::request_tap`](crate::Server::request_tap)
+/// against a server, e.g. to load test it or to reproduce an issue.
+///
+/// The requests are sent one after the other over the client's connection,
+/// keeping the pauses between them as recorded, scaled by the [`speed`](ReplayClient::speed).
+/// TTLs are moved forward by the time passed since recording,
+/// so each value lives as long as it did originally, no matter the speed.
+///
+/// # Examples
+///
+/// ```
+/// use cached::{Client, ReplayClient, Server};
+/// # use cached::Error;
+///
+/// # #[tokio::main]
+/// # async fn main() -> Result<(), Error> {
+/// let (tap, mut logs) = tokio::sync::mpsc::channel(16);
+/// let recorded = Server::new().request_tap(tap).bind("127.0.0.1:0").await?.spawn();
+/// let client = Client::new(recorded.local_addr()).await;
+/// client.set("foo", "bar", None).await?;
+///
+/// let replayed = Server::new().bind("127.0.0.1:0").await?.spawn();
+/// let client = Client::new(replayed.local_addr()).await;
+/// let log = vec![logs.recv().await.unwrap()];
+/// let mismatches = ReplayClient::new(client.clone(), log).replay().await?;
+/// assert_eq!(mismatches, 0);
+/// assert_eq!(client.get("foo").await?.value(), Some(b"bar".as_slice()));
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct ReplayClient {
+    client: Client,
+    logs: Vec<RequestLog>,
+    speed: f64,
+    loops: usize,
+}
+
+impl ReplayClient {
+    /// Creates a client replaying `logs` via `client`, ordered by when they were received.
+    pub fn new(client: Client, mut logs: Vec<RequestLog>) -> Self {
+        logs.sort_by_key(|log| log.received_at);
+        Self {
+            client,
+            logs,
+            speed: 1.0,
+            loops: 1,
+        }
+    }
+
+    /// Replays the requests `speed` times as fast as they were recorded.
+    ///
+    /// Pass `f64::INFINITY` to send them without any pauses. Defaults to `1.0`.
+    ///
+    /// Panics unless `speed` is greater than zero.
+    pub fn speed(mut self, speed: f64) -> Self {
+        assert!(speed > 0.0, "The replay speed must be greater than zero");
+        self.speed = speed;
+        self
+    }
+
+    /// Replays the requests `loops` times in a row. Defaults to `1`.
+    pub fn loops(mut self, loops: usize) -> Self {
+        self.loops = loops;
+        self
+    }
+
+    /// Replays the requests and returns how many of them were answered
+    /// with a different status than when they were recorded.
+    pub async fn replay(&self) -> Result<usize> {
+        let mut mismatches = 0;
+        let Some(first_received_at) = self.logs.first().map(|log| log.received_at) else {
+            return Ok(mismatches);
+        };
+        for _ in 0..self.loops {
+            let started = Instant::now();
+            for log in &self.logs {
+                let offset = log
+                    .received_at
+                    .duration_since(first_received_at)
+                    .unwrap_or_default();
+                tokio::time::sleep_until(started + offset.div_f64(self.speed)).await;
+                let request = self.shifted(log).to_request()?;
+                let response = self.client.handle_request(request).await?;
+                if response.status != log.status {
+                    mismatches += 1;
+                }
+            }
+        }
+        Ok(mismatches)
+    }
+
+    /// Moves the TTL of the log forward by the time passed since it was recorded.
+    fn shifted(&self, log: &RequestLog) -> RequestLog {
+        let passed = SystemTime::now()
+            .duration_since(log.received_at)
+            .unwrap_or_default()
+            .as_millis();
+        RequestLog {
+            ttl_since_unix_epoch_in_millis: log
+                .ttl_since_unix_epoch_in_millis
+                .map(|ttl| ttl.saturating_add(passed)),
+            ..log.clone()
+        }
+    }
+}
@@ -1,7 +1,10 @@
 use crate::domain::{Key, TTLSinceUnixEpochInMillis, Value};
 use crate::error::{Error, ParseError};
 use crate::frame::RequestFrame;
+use crate::parsing::{parse_delta, parse_entries, parse_keys};
 use crate::primitives::OpCode;
+use crate::protocol::{CHUNK_ABORT_FLAG, CHUNK_LAST_FLAG, FLUSH_CONFIRMATION};
+use bytes::{BufMut, BytesMut};
 
 #[derive(Debug)]
 #[cfg_attr(test, derive(PartialEq, Eq))]
@@ -11,21 +14,185 @@ pub(crate) enum Request {
         key: Key,
         value: Value,
         ttl_since_unix_epoch_in_millis: Option<u128>,
+        /// Stored with the value as they are.
+        flags: u32,
     },
     Delete(Key),
-    Flush,
+    /// Servers may refuse to flush unless `confirmed`.
+    Flush {
+        confirmed: bool,
+    },
+    ExistsMany(Vec<Key>),
+    LPush {
+        key: Key,
+        item: Value,
+    },
+    RPop(Key),
+    SAdd {
+        key: Key,
+        member: Value,
+    },
+    SIsMember {
+        key: Key,
+        member: Value,
+    },
+    SRem {
+        key: Key,
+        member: Value,
+    },
+    /// The glob pattern is carried as the key.
+    KeysGlob(Key),
+    /// Remembers the key as missing.
+    SetNegative {
+        key: Key,
+        ttl_since_unix_epoch_in_millis: Option<u128>,
+    },
+    ConnStats,
+    Capabilities,
+    Version,
+    /// Answers with a random live key.
+    RandomKey,
+    /// Removes the key and answers with the value it held.
+    DeleteReturning(Key),
+    /// Answers with everything known about the key.
+    Inspect(Key),
+    /// Turns the connection into a stream of the keys the server evicts.
+    SubscribeEvictions,
+    /// Adds `delta` to the integer stored under the key and answers with the result.
+    Increment {
+        key: Key,
+        delta: i64,
+    },
+    /// Subtracts `delta` from the integer stored under the key and answers with the result.
+    Decrement {
+        key: Key,
+        delta: i64,
+    },
+    /// Removes the expired keys, live ones are left alone.
+    FlushExpired,
+    /// Sets each key to the value at the same position, all with the same TTL.
+    SetMany {
+        keys: Vec<Key>,
+        values: Vec<Value>,
+        ttl_since_unix_epoch_in_millis: Option<u128>,
+    },
+    /// Appends a chunk to the value streamed for the key on this connection,
+    /// setting it with the TTL of the last chunk, see [`ChunkStep`].
+    SetChunk {
+        key: Key,
+        chunk: Option<Value>,
+        ttl_since_unix_epoch_in_millis: Option<u128>,
+        step: ChunkStep,
+    },
+}
+
+/// Where a chunk of a streamed value stands, carried as the flags of the frame.
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(test, derive(PartialEq, Eq))]
+pub(crate) enum ChunkStep {
+    /// More chunks follow.
+    More,
+    /// The chunk completes the value, which is set now.
+    Last,
+    /// The chunks so far are discarded, the request carries none.
+    Abort,
+}
+
+impl ChunkStep {
+    pub(crate) fn flags(self) -> u32 {
+        match self {
+            ChunkStep::More => 0,
+            ChunkStep::Last => CHUNK_LAST_FLAG,
+            ChunkStep::Abort => CHUNK_ABORT_FLAG,
+        }
+    }
+
+    pub(crate) fn from_flags(flags: u32) -> Result<Self, Error> {
+        match flags {
+            0 => Ok(ChunkStep::More),
+            CHUNK_LAST_FLAG => Ok(ChunkStep::Last),
+            CHUNK_ABORT_FLAG => Ok(ChunkStep::Abort),
+            _ => Err(Error::new_parse(ParseError::UnknownChunkStep)),
+        }
+    }
+}
+
+impl Request {
+    pub(crate) fn op_code(&self) -> OpCode {
+        match self {
+            Request::Get(_) => OpCode::Get,
+            Request::Set { .. } => OpCode::Set,
+            Request::Delete(_) => OpCode::Delete,
+            Request::Flush { .. } => OpCode::Flush,
+            Request::ExistsMany(_) => OpCode::ExistsMany,
+            Request::LPush { .. } => OpCode::LPush,
+            Request::RPop(_) => OpCode::RPop,
+            Request::SAdd { .. } => OpCode::SAdd,
+            Request::SIsMember { .. } => OpCode::SIsMember,
+            Request::SRem { .. } => OpCode::SRem,
+            Request::KeysGlob(_) => OpCode::KeysGlob,
+            Request::SetNegative { .. } => OpCode::SetNegative,
+            Request::ConnStats => OpCode::ConnStats,
+            Request::Capabilities => OpCode::Capabilities,
+            Request::Version => OpCode::Version,
+            Request::RandomKey => OpCode::RandomKey,
+            Request::DeleteReturning(_) => OpCode::DeleteReturning,
+            Request::Inspect(_) => OpCode::Inspect,
+            Request::SubscribeEvictions => OpCode::SubscribeEvictions,
+            Request::Increment { .. } => OpCode::Increment,
+            Request::Decrement { .. } => OpCode::Decrement,
+            Request::SetMany { .. } => OpCode::SetMany,
+            Request::FlushExpired => OpCode::FlushExpired,
+            Request::SetChunk { .. } => OpCode::SetChunk,
+        }
+    }
+
+    /// Returns the keys the request operates on, glob patterns do not count as keys.
+    pub(crate) fn keys(&self) -> &[Key] {
+        match self {
+            Request::Get(key)
+            | Request::Delete(key)
+            | Request::RPop(key)
+            | Request::DeleteReturning(key)
+            | Request::Inspect(key)
+            | Request::Increment { key, .. }
+            | Request::Decrement { key, .. }
+            | Request::Set { key, .. }
+            | Request::LPush { key, .. }
+            | Request::SAdd { key, .. }
+            | Request::SIsMember { key, .. }
+            | Request::SRem { key, .. }
+            | Request::SetNegative { key, .. }
+            | Request::SetChunk { key, .. } => std::slice::from_ref(key),
+            Request::ExistsMany(keys) | Request::SetMany { keys, .. } => keys,
+            Request::Flush { .. }
+            | Request::FlushExpired
+            | Request::KeysGlob(_)
+            | Request::ConnStats
+            | Request::Capabilities
+            | Request::Version
+            | Request::RandomKey
+            | Request::SubscribeEvictions => &[],
+        }
+    }
 }
 
 impl TryFrom<Request> for RequestFrame {
     type Error = Error;
 
     fn try_from(req: Request) -> Result<Self, Self::Error> {
+        let flags = match req {
+            Request::Set { flags, .. } => flags,
+            Request::SetChunk { step, .. } => step.flags(),
+            _ => 0,
+        };
         let (op_code, ttl, key, value) = match req {
             Request::Get(key) => (OpCode::Get, None, Some(key), None),
             Request::Set {
                 key,
                 value,
                 ttl_since_unix_epoch_in_millis,
+                ..
             } => (
                 OpCode::Set,
                 ttl_since_unix_epoch_in_millis,
@@ -33,11 +200,78 @@ impl TryFrom<Request> for RequestFrame {
                 Some(value),
             ),
             Request::Delete(key) => (OpCode::Delete, None, Some(key), None),
-            Request::Flush => (OpCode::Flush, None, None, None),
+            Request::Flush { confirmed } => {
+                let confirmation = if confirmed {
+                    Some(Value::parse(FLUSH_CONFIRMATION)?)
+                } else {
+                    None
+                };
+                (OpCode::Flush, None, None, confirmation)
+            }
+            Request::ExistsMany(keys) => (OpCode::ExistsMany, None, None, encode_keys(&keys)?),
+            Request::LPush { key, item } => (OpCode::LPush, None, Some(key), Some(item)),
+            Request::RPop(key) => (OpCode::RPop, None, Some(key), None),
+            // The member is carried as the value of the frame
+            Request::SAdd { key, member } => (OpCode::SAdd, None, Some(key), Some(member)),
+            Request::SIsMember { key, member } => {
+                (OpCode::SIsMember, None, Some(key), Some(member))
+            }
+            Request::SRem { key, member } => (OpCode::SRem, None, Some(key), Some(member)),
+            Request::KeysGlob(pattern) => (OpCode::KeysGlob, None, Some(pattern), None),
+            Request::SetNegative {
+                key,
+                ttl_since_unix_epoch_in_millis,
+            } => (
+                OpCode::SetNegative,
+                ttl_since_unix_epoch_in_millis,
+                Some(key),
+                None,
+            ),
+            Request::ConnStats => (OpCode::ConnStats, None, None, None),
+            Request::Capabilities => (OpCode::Capabilities, None, None, None),
+            Request::Version => (OpCode::Version, None, None, None),
+            Request::RandomKey => (OpCode::RandomKey, None, None, None),
+            Request::SubscribeEvictions => (OpCode::SubscribeEvictions, None, None, None),
+            Request::Increment { key, delta } => (
+                OpCode::Increment,
+                None,
+                Some(key),
+                Some(encode_delta(delta)?),
+            ),
+            Request::Decrement { key, delta } => (
+                OpCode::Decrement,
+                None,
+                Some(key),
+                Some(encode_delta(delta)?),
+            ),
+            Request::FlushExpired => (OpCode::FlushExpired, None, None, None),
+            Request::DeleteReturning(key) => (OpCode::DeleteReturning, None, Some(key), None),
+            Request::Inspect(key) => (OpCode::Inspect, None, Some(key), None),
+            Request::SetMany {
+                keys,
+                values,
+                ttl_since_unix_epoch_in_millis,
+            } => (
+                OpCode::SetMany,
+                ttl_since_unix_epoch_in_millis,
+                None,
+                encode_entries(&keys, &values)?,
+            ),
+            Request::SetChunk {
+                key,
+                chunk,
+                ttl_since_unix_epoch_in_millis,
+                ..
+            } => (
+                OpCode::SetChunk,
+                ttl_since_unix_epoch_in_millis,
+                Some(key),
+                chunk,
+            ),
         };
 
         let ttl = TTLSinceUnixEpochInMillis::parse(ttl);
-        RequestFrame::new(op_code, ttl, key, value)
+        RequestFrame::new(op_code, ttl, key, value).map(|frame| frame.with_flags(flags))
     }
 }
 
@@ -57,7 +291,31 @@ impl TryFrom<RequestFrame> for Request {
                     .header
                     .ttl_since_unix_epoch_in_millis
                     .into_ttl(),
+                flags: frame.header.flags,
             }),
+            OpCode::SetChunk => {
+                let step = ChunkStep::from_flags(frame.header.flags)?;
+                match (step, &frame.value) {
+                    (ChunkStep::More, None) => {
+                        return Err(Error::new_parse(ParseError::ValueMissing))
+                    }
+                    (ChunkStep::Abort, Some(_)) => {
+                        return Err(Error::new_parse(ParseError::UnexpectedValue))
+                    }
+                    _ => {}
+                }
+                Ok(Request::SetChunk {
+                    key: frame
+                        .key
+                        .ok_or_else(|| Error::new_parse(ParseError::KeyMissing))?,
+                    chunk: frame.value,
+                    ttl_since_unix_epoch_in_millis: frame
+                        .header
+                        .ttl_since_unix_epoch_in_millis
+                        .into_ttl(),
+                    step,
+                })
+            }
             OpCode::Get => {
                 if frame.value.is_some() {
                     return Err(Error::new_parse(ParseError::UnexpectedValue));
@@ -68,29 +326,200 @@ impl TryFrom<RequestFrame> for Request {
                         .ok_or_else(|| Error::new_parse(ParseError::KeyMissing))?,
                 ))
             }
-            OpCode::Delete => {
+            OpCode::Delete | OpCode::DeleteReturning | OpCode::Inspect => {
+                if frame.value.is_some() {
+                    return Err(Error::new_parse(ParseError::UnexpectedValue));
+                }
+                let key = frame
+                    .key
+                    .ok_or_else(|| Error::new_parse(ParseError::KeyMissing))?;
+                Ok(match frame.header.op_code {
+                    OpCode::Delete => Request::Delete(key),
+                    OpCode::Inspect => Request::Inspect(key),
+                    _ => Request::DeleteReturning(key),
+                })
+            }
+            OpCode::Flush => {
+                if frame.key.is_some() {
+                    return Err(Error::new_parse(ParseError::UnexpectedKey));
+                }
+                match frame.value {
+                    None => Ok(Request::Flush { confirmed: false }),
+                    Some(value) if value.as_bytes() == FLUSH_CONFIRMATION => {
+                        Ok(Request::Flush { confirmed: true })
+                    }
+                    Some(_) => Err(Error::new_parse(ParseError::UnexpectedValue)),
+                }
+            }
+            OpCode::ExistsMany => {
+                if frame.key.is_some() {
+                    return Err(Error::new_parse(ParseError::UnexpectedKey));
+                }
+                let keys = frame
+                    .value
+                    .map_or(Ok(vec![]), |value| parse_keys(value.as_bytes()))?;
+                Ok(Request::ExistsMany(keys))
+            }
+            OpCode::SetMany => {
+                if frame.key.is_some() {
+                    return Err(Error::new_parse(ParseError::UnexpectedKey));
+                }
+                let (keys, values) = frame.value.map_or(Ok((vec![], vec![])), |value| {
+                    parse_entries(value.as_bytes())
+                })?;
+                Ok(Request::SetMany {
+                    keys,
+                    values,
+                    ttl_since_unix_epoch_in_millis: frame
+                        .header
+                        .ttl_since_unix_epoch_in_millis
+                        .into_ttl(),
+                })
+            }
+            OpCode::LPush => Ok(Request::LPush {
+                key: frame
+                    .key
+                    .ok_or_else(|| Error::new_parse(ParseError::KeyMissing))?,
+                item: frame
+                    .value
+                    .ok_or_else(|| Error::new_parse(ParseError::ValueMissing))?,
+            }),
+            OpCode::Increment | OpCode::Decrement => {
+                let key = frame
+                    .key
+                    .ok_or_else(|| Error::new_parse(ParseError::KeyMissing))?;
+                let delta = frame
+                    .value
+                    .ok_or_else(|| Error::new_parse(ParseError::ValueMissing))
+                    .and_then(|value| parse_delta(value.as_bytes()))?;
+                Ok(match frame.header.op_code {
+                    OpCode::Increment => Request::Increment { key, delta },
+                    _ => Request::Decrement { key, delta },
+                })
+            }
+            OpCode::RPop => {
                 if frame.value.is_some() {
                     return Err(Error::new_parse(ParseError::UnexpectedValue));
                 }
-                Ok(Request::Delete(
+                Ok(Request::RPop(
                     frame
                         .key
                         .ok_or_else(|| Error::new_parse(ParseError::KeyMissing))?,
                 ))
             }
-            OpCode::Flush => {
+            OpCode::SAdd | OpCode::SIsMember | OpCode::SRem => {
+                let key = frame
+                    .key
+                    .ok_or_else(|| Error::new_parse(ParseError::KeyMissing))?;
+                let member = frame
+                    .value
+                    .ok_or_else(|| Error::new_parse(ParseError::ValueMissing))?;
+                Ok(match frame.header.op_code {
+                    OpCode::SAdd => Request::SAdd { key, member },
+                    OpCode::SIsMember => Request::SIsMember { key, member },
+                    _ => Request::SRem { key, member },
+                })
+            }
+            OpCode::KeysGlob => {
+                if frame.value.is_some() {
+                    return Err(Error::new_parse(ParseError::UnexpectedValue));
+                }
+                Ok(Request::KeysGlob(
+                    frame
+                        .key
+                        .ok_or_else(|| Error::new_parse(ParseError::KeyMissing))?,
+                ))
+            }
+            OpCode::SetNegative => {
+                if frame.value.is_some() {
+                    return Err(Error::new_parse(ParseError::UnexpectedValue));
+                }
+                Ok(Request::SetNegative {
+                    key: frame
+                        .key
+                        .ok_or_else(|| Error::new_parse(ParseError::KeyMissing))?,
+                    ttl_since_unix_epoch_in_millis: frame
+                        .header
+                        .ttl_since_unix_epoch_in_millis
+                        .into_ttl(),
+                })
+            }
+            OpCode::ConnStats => {
+                if frame.key.is_some() {
+                    return Err(Error::new_parse(ParseError::UnexpectedKey));
+                }
+                if frame.value.is_some() {
+                    return Err(Error::new_parse(ParseError::UnexpectedValue));
+                }
+                Ok(Request::ConnStats)
+            }
+            OpCode::Capabilities
+            | OpCode::FlushExpired
+            | OpCode::Version
+            | OpCode::RandomKey
+            | OpCode::SubscribeEvictions => {
                 if frame.key.is_some() {
                     return Err(Error::new_parse(ParseError::UnexpectedKey));
                 }
                 if frame.value.is_some() {
                     return Err(Error::new_parse(ParseError::UnexpectedValue));
                 }
-                Ok(Request::Flush)
+                Ok(match frame.header.op_code {
+                    OpCode::Capabilities => Request::Capabilities,
+                    OpCode::Version => Request::Version,
+                    OpCode::RandomKey => Request::RandomKey,
+                    OpCode::SubscribeEvictions => Request::SubscribeEvictions,
+                    _ => Request::FlushExpired,
+                })
             }
         }
     }
 }
 
+/// Encodes the amount to change a counter by, or its new value, into a value of a single `i64`.
+pub(crate) fn encode_delta(delta: i64) -> Result<Value, Error> {
+    Value::parse(delta.to_be_bytes().to_vec())
+}
+
+/// Encodes the keys into the value of the frame, each prefixed with its length as a single byte.
+pub(crate) fn encode_keys(keys: &[Key]) -> Result<Option<Value>, Error> {
+    if keys.is_empty() {
+        return Ok(None);
+    }
+    let mut buf = BytesMut::with_capacity(keys.iter().map(|key| key.len() as usize + 1).sum());
+    for key in keys {
+        buf.put_u8(key.len());
+        buf.put_slice(key.as_bytes());
+    }
+    Value::parse(buf.freeze()).map(Some)
+}
+
+/// Encodes the entries into the value of the frame, each key prefixed with its length as a single byte
+/// and each value prefixed with its length as a `u32`.
+///
+/// Fails if the entries don't fit into a single value.
+pub(crate) fn encode_entries(keys: &[Key], values: &[Value]) -> Result<Option<Value>, Error> {
+    if keys.is_empty() {
+        return Ok(None);
+    }
+    let mut buf = BytesMut::with_capacity(encoded_entries_length(keys, values));
+    for (key, value) in keys.iter().zip(values) {
+        buf.put_u8(key.len());
+        buf.put_slice(key.as_bytes());
+        buf.put_u32(value.len());
+        buf.put_slice(value.as_bytes());
+    }
+    Value::parse(buf.freeze()).map(Some)
+}
+
+/// The length of the entries once encoded by [`encode_entries`].
+pub(crate) fn encoded_entries_length(keys: &[Key], values: &[Value]) -> usize {
+    keys.iter()
+        .zip(values)
+        .map(|(key, value)| key.len() as usize + 1 + value.len() as usize + 4)
+        .sum()
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -109,7 +538,7 @@ mod test {
         OpCode::Set,
         Some("ABC".to_string()),
         Some("Some value".to_string()),
-        Request::Set {key: Key::parse("ABC".to_string()).unwrap(), value: Value::parse("Some value".to_string()).unwrap(), ttl_since_unix_epoch_in_millis: None }
+        Request::Set {key: Key::parse("ABC".to_string()).unwrap(), value: Value::parse("Some value".to_string()).unwrap(), ttl_since_unix_epoch_in_millis: None, flags: 0 }
     )]
     #[case(
         OpCode::Delete,
@@ -117,7 +546,93 @@ mod test {
         None,
         Request::Delete(Key::parse("ABC".to_string()).unwrap())
     )]
-    #[case(OpCode::Flush, None, None, Request::Flush)]
+    #[case(
+        OpCode::DeleteReturning,
+        Some("ABC".to_string()),
+        None,
+        Request::DeleteReturning(Key::parse("ABC".to_string()).unwrap())
+    )]
+    #[case(
+        OpCode::Inspect,
+        Some("ABC".to_string()),
+        None,
+        Request::Inspect(Key::parse("ABC".to_string()).unwrap())
+    )]
+    #[case(OpCode::Flush, None, None, Request::Flush { confirmed: false })]
+    #[case(
+        OpCode::Flush,
+        None,
+        Some("FLUSH ALL".to_string()),
+        Request::Flush { confirmed: true }
+    )]
+    #[case(
+        OpCode::LPush,
+        Some("ABC".to_string()),
+        Some("Some item".to_string()),
+        Request::LPush {key: Key::parse("ABC".to_string()).unwrap(), item: Value::parse("Some item".to_string()).unwrap() }
+    )]
+    #[case(
+        OpCode::RPop,
+        Some("ABC".to_string()),
+        None,
+        Request::RPop(Key::parse("ABC".to_string()).unwrap())
+    )]
+    #[case(
+        OpCode::SAdd,
+        Some("ABC".to_string()),
+        Some("member".to_string()),
+        Request::SAdd {key: Key::parse("ABC".to_string()).unwrap(), member: Value::parse("member".to_string()).unwrap() }
+    )]
+    #[case(
+        OpCode::SIsMember,
+        Some("ABC".to_string()),
+        Some("member".to_string()),
+        Request::SIsMember {key: Key::parse("ABC".to_string()).unwrap(), member: Value::parse("member".to_string()).unwrap() }
+    )]
+    #[case(
+        OpCode::SRem,
+        Some("ABC".to_string()),
+        Some("member".to_string()),
+        Request::SRem {key: Key::parse("ABC".to_string()).unwrap(), member: Value::parse("member".to_string()).unwrap() }
+    )]
+    #[case(OpCode::ExistsMany, None, None, Request::ExistsMany(vec![]))]
+    #[case(
+        OpCode::ExistsMany,
+        None,
+        Some("\u{3}ABC\u{1}D".to_string()),
+        Request::ExistsMany(vec![Key::parse("ABC".to_string()).unwrap(), Key::parse("D".to_string()).unwrap()])
+    )]
+    #[case(OpCode::KeysGlob, Some("user:*".to_string()), None, Request::KeysGlob(Key::parse("user:*".to_string()).unwrap()))]
+    #[case(OpCode::ConnStats, None, None, Request::ConnStats)]
+    #[case(OpCode::Capabilities, None, None, Request::Capabilities)]
+    #[case(OpCode::Version, None, None, Request::Version)]
+    #[case(OpCode::RandomKey, None, None, Request::RandomKey)]
+    #[case(OpCode::SubscribeEvictions, None, None, Request::SubscribeEvictions)]
+    #[case(
+        OpCode::Increment,
+        Some("ABC".to_string()),
+        Some("\0\0\0\0\0\0\0\x05".to_string()),
+        Request::Increment {key: Key::parse("ABC".to_string()).unwrap(), delta: 5 }
+    )]
+    #[case(
+        OpCode::Decrement,
+        Some("ABC".to_string()),
+        Some("\0\0\0\0\0\0\x01\0".to_string()),
+        Request::Decrement {key: Key::parse("ABC".to_string()).unwrap(), delta: 256 }
+    )]
+    #[case(OpCode::FlushExpired, None, None, Request::FlushExpired)]
+    #[case(
+        OpCode::SetNegative,
+        Some("ABC".to_string()),
+        None,
+        Request::SetNegative {key: Key::parse("ABC".to_string()).unwrap(), ttl_since_unix_epoch_in_millis: None }
+    )]
+    #[case(
+        OpCode::SetChunk,
+        Some("ABC".to_string()),
+        Some("chunk".to_string()),
+        Request::SetChunk {key: Key::parse("ABC".to_string()).unwrap(), chunk: Some(Value::parse("chunk").unwrap()), ttl_since_unix_epoch_in_millis: None, step: ChunkStep::More }
+    )]
     fn test_conversion_from_valid_request_frame_to_request_works(
         #[case] op_code: OpCode,
         #[case] key: Option<String>,
@@ -150,6 +665,10 @@ mod test {
     #[case(OpCode::Set, None, None)]
     #[case(OpCode::Delete, None, None)]
     #[case(OpCode::Delete, None, Some("Some value".to_string()))]
+    #[case(OpCode::DeleteReturning, None, None)]
+    #[case(OpCode::DeleteReturning, Some("ABC".to_string()), Some("Some value".to_string()))]
+    #[case(OpCode::Inspect, None, None)]
+    #[case(OpCode::Inspect, Some("ABC".to_string()), Some("Some value".to_string()))]
     #[case(OpCode::Flush,
         Some("ABC".to_string()),
         Some("Some value".to_string()))]
@@ -163,6 +682,37 @@ mod test {
         None,
         Some("Some value".to_string()),
     )]
+    #[case(OpCode::LPush, Some("ABC".to_string()), None)]
+    #[case(OpCode::LPush, None, Some("Some item".to_string()))]
+    #[case(OpCode::RPop, None, None)]
+    #[case(OpCode::RPop, Some("ABC".to_string()), Some("Some item".to_string()))]
+    #[case(OpCode::SAdd, Some("ABC".to_string()), None)]
+    #[case(OpCode::SIsMember, None, Some("member".to_string()))]
+    #[case(OpCode::SRem, None, None)]
+    #[case(OpCode::ExistsMany, Some("ABC".to_string()), None)]
+    #[case(OpCode::ExistsMany, None, Some("\u{4}ABC".to_string()))]
+    #[case(OpCode::SetMany, Some("ABC".to_string()), None)]
+    #[case(OpCode::SetMany, None, Some("\u{3}ABC".to_string()))]
+    #[case(OpCode::KeysGlob, None, None)]
+    #[case(OpCode::SetNegative, None, None)]
+    #[case(OpCode::ConnStats, Some("ABC".to_string()), None)]
+    #[case(OpCode::ConnStats, None, Some("Some value".to_string()))]
+    #[case(OpCode::Capabilities, Some("ABC".to_string()), None)]
+    #[case(OpCode::Capabilities, None, Some("Some value".to_string()))]
+    #[case(OpCode::Version, Some("ABC".to_string()), None)]
+    #[case(OpCode::Version, None, Some("Some value".to_string()))]
+    #[case(OpCode::RandomKey, Some("ABC".to_string()), None)]
+    #[case(OpCode::RandomKey, None, Some("Some value".to_string()))]
+    #[case(OpCode::SubscribeEvictions, Some("ABC".to_string()), None)]
+    #[case(OpCode::SubscribeEvictions, None, Some("Some value".to_string()))]
+    #[case(OpCode::Increment, None, Some("\0\0\0\0\0\0\0\x05".to_string()))]
+    #[case(OpCode::Increment, Some("ABC".to_string()), None)]
+    #[case(OpCode::Decrement, Some("ABC".to_string()), Some("five".to_string()))]
+    #[case(OpCode::FlushExpired, Some("ABC".to_string()), None)]
+    #[case(OpCode::FlushExpired, None, Some("FLUSH ALL".to_string()))]
+    #[case(OpCode::SetNegative, Some("ABC".to_string()), Some("Some value".to_string()))]
+    #[case(OpCode::SetChunk, Some("ABC".to_string()), None)]
+    #[case(OpCode::SetChunk, None, Some("chunk".to_string()))]
     fn test_conversion_from_invalid_request_frame_to_request_fails(
         #[case] op_code: OpCode,
         #[case] key: Option<String>,
@@ -174,4 +724,91 @@ mod test {
         let req_frame = RequestFrame::new(op_code, ttl, key, value).unwrap();
         assert!(Request::try_from(req_frame).is_err())
     }
+
+    #[test]
+    fn test_exists_many_request_round_trips_through_frame() {
+        let keys: Vec<Key> = ["ABC", "D", "a key with spaces"]
+            .into_iter()
+            .map(|key| Key::parse(key.to_string()).unwrap())
+            .collect();
+        let expected_keys: Vec<Key> = keys
+            .iter()
+            .map(|key| Key::parse(key.to_string()).unwrap())
+            .collect();
+        let frame = RequestFrame::try_from(Request::ExistsMany(keys)).unwrap();
+        assert!(frame.key.is_none());
+        assert_eq!(
+            Request::try_from(frame).unwrap(),
+            Request::ExistsMany(expected_keys)
+        );
+    }
+
+    #[test]
+    fn test_set_many_request_round_trips_through_frame() {
+        let entries = || {
+            [("ABC", "1"), ("D", ""), ("a key with spaces", "a value")]
+                .into_iter()
+                .map(|(key, value)| {
+                    (
+                        Key::parse(key.to_string()).unwrap(),
+                        Value::parse(value).unwrap(),
+                    )
+                })
+                .unzip()
+        };
+        let (keys, values) = entries();
+        let frame = RequestFrame::try_from(Request::SetMany {
+            keys,
+            values,
+            ttl_since_unix_epoch_in_millis: Some(1234),
+        })
+        .unwrap();
+        assert!(frame.key.is_none());
+        let (keys, values) = entries();
+        assert_eq!(
+            Request::try_from(frame).unwrap(),
+            Request::SetMany {
+                keys,
+                values,
+                ttl_since_unix_epoch_in_millis: Some(1234)
+            }
+        );
+    }
+
+    #[rstest]
+    #[case(Some("chunk"), ChunkStep::More)]
+    #[case(Some("chunk"), ChunkStep::Last)]
+    #[case(None, ChunkStep::Last)]
+    #[case(None, ChunkStep::Abort)]
+    fn test_set_chunk_request_round_trips_through_frame(
+        #[case] chunk: Option<&str>,
+        #[case] step: ChunkStep,
+    ) {
+        let request = || Request::SetChunk {
+            key: Key::parse("ABC".to_string()).unwrap(),
+            chunk: chunk.map(|chunk| Value::parse(chunk.to_string()).unwrap()),
+            ttl_since_unix_epoch_in_millis: Some(1234),
+            step,
+        };
+        let frame = RequestFrame::try_from(request()).unwrap();
+        assert_eq!(frame.header.flags, step.flags());
+        assert_eq!(Request::try_from(frame).unwrap(), request());
+    }
+
+    #[rstest]
+    #[case(Some("chunk"), CHUNK_ABORT_FLAG)]
+    #[case(Some("chunk"), CHUNK_LAST_FLAG | CHUNK_ABORT_FLAG)]
+    #[case(Some("chunk"), 4)]
+    fn test_set_chunk_request_with_invalid_step_fails(
+        #[case] chunk: Option<&str>,
+        #[case] flags: u32,
+    ) {
+        let key = Key::parse("ABC".to_string()).unwrap();
+        let chunk = chunk.map(|chunk| Value::parse(chunk.to_string()).unwrap());
+        let ttl = TTLSinceUnixEpochInMillis::parse(None);
+        let frame = RequestFrame::new(OpCode::SetChunk, ttl, Some(key), chunk)
+            .unwrap()
+            .with_flags(flags);
+        assert!(Request::try_from(frame).is_err())
+    }
 }
@@ -0,0 +1,432 @@
+//! A subset of the Redis protocol (RESP), see [`Server::bind_resp`](crate::Server::bind_resp).
+//!
+//! Commands are translated into the requests of the wire protocol and carried out like them.
+use bytes::{BufMut, Bytes, BytesMut};
+use std::io;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::time::Instant;
+
+use crate::domain::{Key, Value, MAX_VALUE_LENGTH};
+use crate::gateway::GatewayConnection;
+use crate::metrics::ConnectionStats;
+use crate::primitives::StatusCode;
+use crate::request::Request;
+use crate::response::{Response, ResponseBody};
+use crate::server::Service;
+
+/// How many arguments a single command may have.
+const MAX_ARGUMENTS: usize = 4 * 1024;
+/// How long the line announcing the length of an array or a bulk string may be.
+const MAX_LENGTH_LINE: usize = 32;
+
+/// Serves RESP commands on the connection until it is closed or the server shuts down.
+pub(crate) async fn serve_connection(mut conn: GatewayConnection) -> io::Result<()> {
+    let mut buffer = BytesMut::with_capacity(4 * 1024);
+    let mut reply_buffer = BytesMut::new();
+    while !conn.shutdown.is_shutdown() {
+        let command = match parse_command(&mut buffer) {
+            Ok(Some(command)) => command,
+            Ok(None) => {
+                let read = tokio::select! {
+                    read = conn.stream.read_buf(&mut buffer) => read?,
+                    _ = conn.shutdown.recv() => return Ok(()),
+                };
+                if read == 0 {
+                    return Ok(());
+                }
+                continue;
+            }
+            Err(e) => {
+                Reply::Error(format!("ERR Protocol error: {e}")).encode(&mut reply_buffer);
+                return conn.stream.write_all(&reply_buffer).await;
+            }
+        };
+        let Some(name) = command.first() else {
+            continue;
+        };
+        let quit = name.eq_ignore_ascii_case(b"QUIT");
+        let started = Instant::now();
+        let reply = if quit {
+            Reply::Simple("OK")
+        } else {
+            execute(&conn.service, &command).await
+        };
+        reply.encode(&mut reply_buffer);
+        conn.stream.write_all(&reply_buffer).await?;
+        reply_buffer.clear();
+        conn.service.metrics().request_handled(started.elapsed());
+        if quit {
+            return Ok(());
+        }
+    }
+    Ok(())
+}
+
+/// Why a command could not be parsed.
+#[derive(Debug, PartialEq, Eq)]
+enum ParseError {
+    UnexpectedByte(u8),
+    InvalidLength,
+}
+
+impl std::fmt::Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::UnexpectedByte(byte) => write!(f, "expected '*' or '$', got '{}'", *byte as char),
+            Self::InvalidLength => write!(f, "invalid length"),
+        }
+    }
+}
+
+/// Takes the next command, an array of bulk strings, off the buffer.
+///
+/// Returns `None` and leaves the buffer as it is if the command did not fully arrive yet.
+fn parse_command(buffer: &mut BytesMut) -> Result<Option<Vec<Bytes>>, ParseError> {
+    let mut cursor = 0;
+    let Some(arguments) = parse_length(buffer, &mut cursor, b'*', MAX_ARGUMENTS)? else {
+        return Ok(None);
+    };
+    let mut lengths = Vec::with_capacity(arguments);
+    for _ in 0..arguments {
+        let Some(length) = parse_length(buffer, &mut cursor, b'$', MAX_VALUE_LENGTH as usize)?
+        else {
+            return Ok(None);
+        };
+        if buffer.len() < cursor + length + 2 {
+            return Ok(None);
+        }
+        if &buffer[cursor + length..cursor + length + 2] != b"\r\n" {
+            return Err(ParseError::InvalidLength);
+        }
+        lengths.push((cursor, length));
+        cursor += length + 2;
+    }
+    let command = buffer.split_to(cursor).freeze();
+    Ok(Some(
+        lengths
+            .into_iter()
+            .map(|(start, length)| command.slice(start..start + length))
+            .collect(),
+    ))
+}
+
+/// Parses a line like `*3\r\n`, with `prefix` instead of `*`, at the cursor and moves past it.
+///
+/// Negative lengths, as used for null arrays, count as zero.
+fn parse_length(
+    buffer: &[u8],
+    cursor: &mut usize,
+    prefix: u8,
+    max: usize,
+) -> Result<Option<usize>, ParseError> {
+    let rest = &buffer[*cursor..];
+    let Some(&first) = rest.first() else {
+        return Ok(None);
+    };
+    if first != prefix {
+        return Err(ParseError::UnexpectedByte(first));
+    }
+    let Some(end) = rest.windows(2).position(|window| window == b"\r\n") else {
+        return if rest.len() > MAX_LENGTH_LINE {
+            Err(ParseError::InvalidLength)
+        } else {
+            Ok(None)
+        };
+    };
+    let length: i64 = std::str::from_utf8(&rest[1..end])
+        .ok()
+        .and_then(|length| length.parse().ok())
+        .ok_or(ParseError::InvalidLength)?;
+    if length > max as i64 {
+        return Err(ParseError::InvalidLength);
+    }
+    *cursor += end + 2;
+    Ok(Some(length.max(0) as usize))
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum Reply {
+    Simple(&'static str),
+    Error(String),
+    Integer(i64),
+    Bulk(Option<Bytes>),
+    Array(Vec<Reply>),
+}
+
+impl Reply {
+    fn encode(&self, buf: &mut BytesMut) {
+        match self {
+            Reply::Simple(simple) => {
+                buf.put_u8(b'+');
+                buf.put_slice(simple.as_bytes());
+            }
+            Reply::Error(error) => {
+                buf.put_u8(b'-');
+                // Errors must fit on a single line
+                buf.put_slice(error.replace(['\r', '\n'], " ").as_bytes());
+            }
+            Reply::Integer(integer) => buf.put_slice(format!(":{integer}").as_bytes()),
+            Reply::Bulk(None) => buf.put_slice(b"$-1"),
+            Reply::Bulk(Some(bulk)) => {
+                buf.put_slice(format!("${}\r\n", bulk.len()).as_bytes());
+                buf.put_slice(bulk);
+            }
+            Reply::Array(replies) => {
+                buf.put_slice(format!("*{}\r\n", replies.len()).as_bytes());
+                for reply in replies {
+                    reply.encode(buf);
+                }
+                // Each reply ended its own line
+                return;
+            }
+        }
+        buf.put_slice(b"\r\n");
+    }
+
+    fn wrong_arguments(command: &str) -> Self {
+        Reply::Error(format!(
+            "ERR wrong number of arguments for '{command}' command"
+        ))
+    }
+
+    fn from_status(status: StatusCode) -> Self {
+        match status {
+            StatusCode::WrongType => Reply::Error(
+                "WRONGTYPE Operation against a key holding the wrong kind of value".to_string(),
+            ),
+            status => Reply::Error(format!("ERR {status}")),
+        }
+    }
+}
+
+/// Carries out the command, its name being the first argument.
+async fn execute(service: &Service, command: &[Bytes]) -> Reply {
+    let name = String::from_utf8_lossy(&command[0]).to_ascii_lowercase();
+    let args = &command[1..];
+    let executed = match (name.as_str(), args) {
+        ("ping", []) => Ok(Reply::Simple("PONG")),
+        ("ping", [message]) => Ok(Reply::Bulk(Some(message.clone()))),
+        ("command", _) => Ok(Reply::Array(vec![])),
+        ("get", [key]) => get(service, key).await,
+        ("set", [key, value, options @ ..]) => set(service, key, value, options).await,
+        ("del", keys) if !keys.is_empty() => del(service, keys).await,
+        ("expire", [key, seconds]) => expire(service, key, seconds).await,
+        ("flushall", _) => {
+            let response = handle(service, Request::Flush { confirmed: true }).await;
+            match response.status {
+                StatusCode::Ok => Ok(Reply::Simple("OK")),
+                status => Ok(Reply::from_status(status)),
+            }
+        }
+        ("ping" | "get" | "set" | "del" | "expire", _) => Ok(Reply::wrong_arguments(&name)),
+        _ => Ok(Reply::Error(format!("ERR unknown command '{name}'"))),
+    };
+    executed.unwrap_or_else(|e| e)
+}
+
+async fn handle(service: &Service, request: Request) -> Response {
+    service
+        .handle_request(request, ConnectionStats::new())
+        .await
+}
+
+async fn get(service: &Service, key: &Bytes) -> Result<Reply, Reply> {
+    let response = handle(service, Request::Get(parse_key(key)?)).await;
+    match (response.status, response.body) {
+        (StatusCode::Ok, ResponseBody::Get(Some(get))) => {
+            Ok(Reply::Bulk(Some(get.value.into_bytes())))
+        }
+        (StatusCode::KeyNotFound | StatusCode::NegativeCached, _) => Ok(Reply::Bulk(None)),
+        (status, _) => Ok(Reply::from_status(status)),
+    }
+}
+
+/// Sets the value, replacing an existing one unless `NX` is given, like Redis does.
+async fn set(
+    service: &Service,
+    key: &Bytes,
+    value: &Bytes,
+    options: &[Bytes],
+) -> Result<Reply, Reply> {
+    let key = parse_key(key)?;
+    let value = parse_value(value)?;
+    let mut ttl_since_unix_epoch_in_millis = None;
+    let mut only_if_missing = false;
+    let mut options = options.iter();
+    while let Some(option) = options.next() {
+        let option = String::from_utf8_lossy(option).to_ascii_lowercase();
+        match option.as_str() {
+            "nx" => only_if_missing = true,
+            "ex" | "px" => {
+                let amount = options
+                    .next()
+                    .and_then(parse_integer)
+                    .filter(|amount| *amount > 0)
+                    .ok_or_else(|| {
+                        Reply::Error("ERR invalid expire time in 'set' command".to_string())
+                    })?;
+                let millis = if option == "ex" {
+                    amount * 1000
+                } else {
+                    amount
+                };
+                ttl_since_unix_epoch_in_millis = Some(now_in_millis() + millis as u128);
+            }
+            _ => return Err(Reply::Error("ERR syntax error".to_string())),
+        }
+    }
+    if !only_if_missing {
+        // Not atomic, a concurrent SET of the same key may win instead
+        handle(service, Request::Delete(key.clone())).await;
+    }
+    let response = handle(
+        service,
+        Request::Set {
+            key,
+            value,
+            ttl_since_unix_epoch_in_millis,
+            flags: 0,
+        },
+    )
+    .await;
+    match response.status {
+        StatusCode::Ok => Ok(Reply::Simple("OK")),
+        StatusCode::KeyExists if only_if_missing => Ok(Reply::Bulk(None)),
+        StatusCode::KeyExists => Ok(Reply::Simple("OK")),
+        status => Ok(Reply::from_status(status)),
+    }
+}
+
+/// Deletes the keys and returns how many of them existed.
+async fn del(service: &Service, keys: &[Bytes]) -> Result<Reply, Reply> {
+    let mut deleted = 0;
+    for key in keys {
+        let response = handle(service, Request::Delete(parse_key(key)?)).await;
+        if response.status == StatusCode::Ok {
+            deleted += 1;
+        }
+    }
+    Ok(Reply::Integer(deleted))
+}
+
+/// Sets the TTL of an existing value, by setting the value again with the new TTL.
+///
+/// This is not atomic, a value set in between may be replaced.
+async fn expire(service: &Service, key: &Bytes, seconds: &Bytes) -> Result<Reply, Reply> {
+    let key = parse_key(key)?;
+    let seconds = parse_integer(seconds)
+        .ok_or_else(|| Reply::Error("ERR value is not an integer or out of range".to_string()))?;
+    let response = handle(service, Request::Get(key.clone())).await;
+    let get = match (response.status, response.body) {
+        (StatusCode::Ok, ResponseBody::Get(Some(get))) => get,
+        (StatusCode::KeyNotFound | StatusCode::NegativeCached, _) => return Ok(Reply::Integer(0)),
+        (status, _) => return Ok(Reply::from_status(status)),
+    };
+    handle(service, Request::Delete(key.clone())).await;
+    // Like Redis, a TTL that is not in the future deletes the key
+    if seconds > 0 {
+        let request = Request::Set {
+            key,
+            value: get.value,
+            ttl_since_unix_epoch_in_millis: Some(now_in_millis() + seconds as u128 * 1000),
+            flags: get.flags,
+        };
+        handle(service, request).await;
+    }
+    Ok(Reply::Integer(1))
+}
+
+fn parse_key(key: &Bytes) -> Result<Key, Reply> {
+    String::from_utf8(key.to_vec())
+        .ok()
+        .and_then(|key| Key::parse(key).ok())
+        .ok_or_else(|| Reply::Error("ERR invalid key".to_string()))
+}
+
+fn parse_value(value: &Bytes) -> Result<Value, Reply> {
+    Value::parse(value.clone()).map_err(|_| Reply::Error("ERR value too long".to_string()))
+}
+
+fn parse_integer(integer: &Bytes) -> Option<i64> {
+    std::str::from_utf8(integer).ok()?.parse().ok()
+}
+
+fn now_in_millis() -> u128 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("Time went backwards")
+        .as_millis()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rstest::rstest;
+
+    fn args(args: &[&str]) -> Vec<Bytes> {
+        args.iter()
+            .map(|arg| Bytes::copy_from_slice(arg.as_bytes()))
+            .collect()
+    }
+
+    #[test]
+    fn test_parsing_pipelined_commands() {
+        let mut buffer =
+            BytesMut::from("*2\r\n$3\r\nGET\r\n$3\r\nfoo\r\n*1\r\n$4\r\nPING\r\n*1\r\n");
+        assert_eq!(parse_command(&mut buffer), Ok(Some(args(&["GET", "foo"]))));
+        assert_eq!(parse_command(&mut buffer), Ok(Some(args(&["PING"]))));
+        assert_eq!(parse_command(&mut buffer), Ok(None));
+        assert_eq!(&buffer[..], b"*1\r\n");
+    }
+
+    #[rstest]
+    #[case(b"".as_slice())]
+    #[case(b"*".as_slice())]
+    #[case(b"*2\r\n$3\r\nGET\r\n".as_slice())]
+    #[case(b"*2\r\n$3\r\nGET\r\n$3\r\nfo".as_slice())]
+    #[case(b"*2\r\n$3\r\nGET\r\n$3\r\nfoo\r".as_slice())]
+    fn test_parsing_incomplete_commands_waits_for_more(#[case] incomplete: &[u8]) {
+        let mut buffer = BytesMut::from(incomplete);
+        assert_eq!(parse_command(&mut buffer), Ok(None));
+        assert_eq!(&buffer[..], incomplete);
+    }
+
+    #[test]
+    fn test_values_may_contain_line_breaks() {
+        let mut buffer = BytesMut::from("*3\r\n$3\r\nSET\r\n$1\r\nk\r\n$4\r\na\r\nb\r\n");
+        assert_eq!(
+            parse_command(&mut buffer),
+            Ok(Some(args(&["SET", "k", "a\r\nb"])))
+        );
+    }
+
+    #[rstest]
+    #[case(b"PING\r\n".as_slice(), ParseError::UnexpectedByte(b'P'))]
+    #[case(b"*1\r\n:1\r\n".as_slice(), ParseError::UnexpectedByte(b':'))]
+    #[case(b"*x\r\n".as_slice(), ParseError::InvalidLength)]
+    #[case(b"*1\r\n$3\r\nfoobar\r\n".as_slice(), ParseError::InvalidLength)]
+    #[case(b"*1\r\n$99999999999\r\n".as_slice(), ParseError::InvalidLength)]
+    #[case(b"*1111111111111111111111111111111111".as_slice(), ParseError::InvalidLength)]
+    fn test_parsing_malformed_commands_fails(
+        #[case] malformed: &[u8],
+        #[case] expected: ParseError,
+    ) {
+        let mut buffer = BytesMut::from(malformed);
+        assert_eq!(parse_command(&mut buffer), Err(expected));
+    }
+
+    #[rstest]
+    #[case(Reply::Simple("OK"), "+OK\r\n")]
+    #[case(Reply::Error("ERR oh\nno".to_string()), "-ERR oh no\r\n")]
+    #[case(Reply::Integer(-2), ":-2\r\n")]
+    #[case(Reply::Bulk(None), "$-1\r\n")]
+    #[case(Reply::Bulk(Some(Bytes::from("a\r\nb"))), "$4\r\na\r\nb\r\n")]
+    #[case(Reply::Array(vec![]), "*0\r\n")]
+    #[case(Reply::Array(vec![Reply::Integer(1), Reply::Bulk(None)]), "*2\r\n:1\r\n$-1\r\n")]
+    fn test_encoding_replies(#[case] reply: Reply, #[case] expected: &str) {
+        let mut buf = BytesMut::new();
+        reply.encode(&mut buf);
+        assert_eq!(&buf[..], expected.as_bytes());
+    }
+}
@@ -1,9 +1,22 @@
-use crate::domain::{Key, TTLSinceUnixEpochInMillis, Value};
-use crate::error::{Error, ParseError, Result};
+use crate::capabilities::{ServerCapabilities, ServerInfo};
+use crate::domain::{Key, TTLSinceUnixEpochInMillis, TtlState, Value};
+use crate::error::{ClientError, Error, ParseError, Result};
 use crate::frame::ResponseFrame;
+use crate::key_info::KeyInfo;
+use crate::metrics::ConnectionStats;
+use crate::parsing::{
+    parse_amount, parse_bits, parse_capabilities, parse_connection_stats, parse_key_info,
+    parse_keys, parse_server_info, parse_statuses,
+};
 use crate::primitives::{OpCode, StatusCode};
+use crate::protocol::{FLUSH_CONFIRMATION_FLAG, KEY_VALIDATOR_FLAG, NO_LIMIT};
+use crate::request::encode_keys;
+use bytes::{BufMut, Bytes, BytesMut};
 use std::fmt;
 use std::fmt::Formatter;
+use std::str::Utf8Error;
+use std::string::FromUtf8Error;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 #[derive(Debug, Eq, PartialEq)]
 pub(crate) struct Response {
@@ -14,40 +27,168 @@ pub(crate) struct Response {
 /// The response struct for a GET request.
 ///
 /// The `value` is `None` if the key does not exist in the cache.
+/// Values are kept as the raw bytes the server sent, they are only checked
+/// for valid UTF-8 when viewed as a string.
 #[derive(Debug, Eq, PartialEq, Clone, Hash)]
 pub struct ResponseGet {
     status: StatusCode,
-    value: Option<String>,
-    ttl_since_unix_epoch_in_millis: Option<u128>,
+    value: Option<Bytes>,
+    ttl: TtlState,
+    flags: u32,
+    updated_at_in_millis: Option<u128>,
 }
 
 impl ResponseGet {
-    pub(crate) fn new(
-        status: StatusCode,
-        value: Option<String>,
-        ttl_since_unix_epoch_in_millis: Option<u128>,
-    ) -> Self {
+    pub(crate) fn new(status: StatusCode, value: Option<Bytes>, ttl: TtlState) -> Self {
         Self {
             status,
             value,
-            ttl_since_unix_epoch_in_millis,
+            ttl,
+            flags: 0,
+            updated_at_in_millis: None,
         }
     }
 
+    pub(crate) fn with_flags(mut self, flags: u32) -> Self {
+        self.flags = flags;
+        self
+    }
+
+    pub(crate) fn with_updated_at(mut self, updated_at_in_millis: Option<u128>) -> Self {
+        self.updated_at_in_millis = updated_at_in_millis;
+        self
+    }
+
     pub fn status(&self) -> StatusCode {
         self.status
     }
 
+    /// Returns the flags the value was set with, see [`Client::set_with_flags`](crate::Client::set_with_flags).
+    ///
+    /// Values set without flags and missing values have the flags `0`.
+    pub fn flags(&self) -> u32 {
+        self.flags
+    }
+
+    /// Returns when the value was last set in milliseconds since the unix epoch, by the clock of the server.
+    ///
+    /// This is `None` if there was no value.
+    pub fn updated_at_in_millis(&self) -> Option<u128> {
+        self.updated_at_in_millis
+    }
+
+    /// Returns whether the value expires, never expires, or if the TTL is unknown as there was no value.
+    pub fn ttl(&self) -> TtlState {
+        self.ttl
+    }
+
+    /// Returns when the value expires, `None` if it never expires or there was no value,
+    /// see [`ResponseGet::ttl`] to tell these apart.
     pub fn ttl_since_unix_epoch_in_millis(&self) -> Option<u128> {
-        self.ttl_since_unix_epoch_in_millis
+        self.ttl.expires_at()
+    }
+
+    /// Returns how long the value is still valid for, based on the local clock.
+    ///
+    /// This is `None` if the value has no TTL and saturates at zero once the TTL passed.
+    pub fn remaining_ttl(&self) -> Option<Duration> {
+        let ttl = self.ttl.expires_at()?;
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .expect("Time went backwards")
+            .as_millis();
+        let remaining_millis = u64::try_from(ttl.saturating_sub(now)).unwrap_or(u64::MAX);
+        Some(Duration::from_millis(remaining_millis))
+    }
+
+    /// Returns the value as the raw bytes it was set with.
+    ///
+    /// Use [`ResponseGet::value_str`] for values that are strings.
+    pub fn value(&self) -> Option<&[u8]> {
+        self.value.as_deref()
+    }
+
+    /// Returns a string view of the value, failing if it is not valid UTF-8.
+    pub fn value_str(&self) -> Option<std::result::Result<&str, Utf8Error>> {
+        self.value.as_deref().map(std::str::from_utf8)
+    }
+
+    /// Takes the value as the raw bytes it was set with, see [`ResponseGet::value`].
+    pub fn into_value(self) -> Option<Vec<u8>> {
+        self.value.map(Vec::from)
+    }
+
+    /// Returns the value, failing with the status of the response if there is none,
+    /// e.g. with [`StatusCode::KeyNotFound`] for missing keys, see [`Error::status`].
+    ///
+    /// Also fails if the value is not valid UTF-8.
+    pub fn ok_or_not_found(self) -> Result<String> {
+        if self.status != StatusCode::Ok {
+            return Err(Error::new_client(ClientError::Status(self.status)));
+        }
+        let value = self
+            .value
+            .ok_or_else(|| Error::new_client(ClientError::ExpectedValue))?;
+        String::from_utf8(value.into()).map_err(|e| Error::new_parse(ParseError::String(e)))
+    }
+
+    /// Returns the value for [`StatusCode::Ok`], like [`ResponseGet::into_value`],
+    /// and any other status as the error, [`StatusCode::KeyNotFound`] included.
+    pub fn into_result(self) -> std::result::Result<Option<Vec<u8>>, StatusCode> {
+        match self.status {
+            StatusCode::Ok => Ok(self.into_value()),
+            status => Err(status),
+        }
+    }
+}
+
+impl TryFrom<ResponseGet> for Option<String> {
+    type Error = FromUtf8Error;
+
+    /// Takes the value as a string, failing if it is not valid UTF-8, see [`ResponseGet::value_str`].
+    ///
+    /// The error still holds the bytes of the value.
+    fn try_from(response: ResponseGet) -> std::result::Result<Self, Self::Error> {
+        response.into_value().map(String::from_utf8).transpose()
+    }
+}
+
+impl From<ResponseGet> for Option<Vec<u8>> {
+    /// Takes the raw bytes of the value, see [`ResponseGet::into_value`].
+    fn from(response: ResponseGet) -> Self {
+        response.into_value()
+    }
+}
+
+/// The response to a request answered by its status alone, e.g. a SET or a DELETE.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub struct ResponseStatus {
+    status: StatusCode,
+    op_code: OpCode,
+}
+
+impl ResponseStatus {
+    pub fn status(&self) -> StatusCode {
+        self.status
+    }
+
+    /// Returns the operation the server answered.
+    pub fn op_code(&self) -> OpCode {
+        self.op_code
     }
 
-    pub fn value(&self) -> Option<&String> {
-        self.value.as_ref()
+    /// Returns whether the server answered [`StatusCode::Ok`].
+    pub fn is_ok(&self) -> bool {
+        self.status == StatusCode::Ok
     }
+}
 
-    pub fn into_value(self) -> Option<String> {
-        self.value
+impl From<&Response> for ResponseStatus {
+    fn from(response: &Response) -> Self {
+        Self {
+            status: response.status,
+            op_code: response.body.op_code(),
+        }
     }
 }
 
@@ -73,6 +214,43 @@ pub(crate) enum ResponseBody {
     Set,
     Delete,
     Flush,
+    /// Whether each of the requested keys exists, in the order they were requested in.
+    ExistsMany(Vec<bool>),
+    LPush,
+    /// The popped item, `None` if the list was empty.
+    RPop(Option<Value>),
+    // The outcome of set operations is conveyed by the status alone
+    SAdd,
+    SIsMember,
+    SRem,
+    /// The keys matching the requested glob pattern, sorted.
+    KeysGlob(Vec<Key>),
+    SetNegative,
+    /// `None` if the stats could not be provided, the status tells why.
+    ConnStats(Option<ConnectionStats>),
+    /// `None` if the capabilities could not be provided, the status tells why.
+    Capabilities(Option<ServerCapabilities>),
+    /// `None` if the info could not be provided, the status tells why.
+    Version(Option<ServerInfo>),
+    /// The random key, `None` if there were no live keys.
+    RandomKey(Option<Key>),
+    /// `None` if the key was not found, the status tells why.
+    Inspect(Option<KeyInfo>),
+    /// The keys of a batch of expired keys, none in the response confirming the subscription.
+    Evictions(Vec<Key>),
+    /// The counter after incrementing it, its value a decimal string,
+    /// `None` if it could not be incremented, the status tells why.
+    Increment(Option<ResponseBodyGet>),
+    /// The counter after decrementing it like [`ResponseBody::Increment`].
+    Decrement(Option<ResponseBodyGet>),
+    /// The deleted value, `None` if there was nothing to delete.
+    DeleteReturning(Option<Value>),
+    /// The status of setting each of the entries, in the order they were requested in.
+    SetMany(Vec<StatusCode>),
+    /// How many expired keys were removed, `None` if they could not be, the status tells why.
+    FlushExpired(Option<u64>),
+    // Whether the chunk was taken, or the value was set after the last one, is conveyed by the status alone
+    SetChunk,
 }
 
 impl fmt::Display for ResponseBody {
@@ -80,7 +258,62 @@ impl fmt::Display for ResponseBody {
         match self {
             Self::Delete => write!(f, "DELETE"),
             Self::Set => write!(f, "SET"),
+            Self::SetChunk => write!(f, "SET_CHUNK"),
             Self::Flush => write!(f, "FLUSH"),
+            Self::ExistsMany(exists) => write!(f, "EXISTS_MANY {exists:?}"),
+            Self::SetMany(statuses) => write!(f, "SET_MANY {statuses:?}"),
+            Self::FlushExpired(None) => write!(f, "FLUSH_EXPIRED None"),
+            Self::FlushExpired(Some(removed)) => write!(f, "FLUSH_EXPIRED {removed}"),
+            Self::LPush => write!(f, "LPUSH"),
+            Self::SAdd => write!(f, "SADD"),
+            Self::SIsMember => write!(f, "SISMEMBER"),
+            Self::SRem => write!(f, "SREM"),
+            Self::SetNegative => write!(f, "SET_NEGATIVE"),
+            Self::ConnStats(None) => write!(f, "CONN_STATS None"),
+            Self::Capabilities(None) => write!(f, "CAPABILITIES None"),
+            Self::Capabilities(Some(capabilities)) => {
+                write!(f, "{capabilities:?}")
+            }
+            Self::Version(None) => write!(f, "VERSION None"),
+            Self::Version(Some(info)) => {
+                write!(f, "{} features {:#04b}", info.version, info.features)
+            }
+            Self::ConnStats(Some(stats)) => write!(
+                f,
+                "requests {} received {} sent {} since {}",
+                stats.handled_requests,
+                stats.bytes_received,
+                stats.bytes_sent,
+                stats.connected_since_unix_epoch_in_millis
+            ),
+            Self::Increment(None) => write!(f, "INCREMENT None"),
+            Self::Decrement(None) => write!(f, "DECREMENT None"),
+            Self::Increment(Some(counter)) | Self::Decrement(Some(counter)) => {
+                write!(f, "{}", counter.value)
+            }
+            Self::RandomKey(None) => write!(f, "RANDOM_KEY None"),
+            Self::RandomKey(Some(key)) => write!(f, "\"{key}\""),
+            Self::Inspect(None) => write!(f, "INSPECT None"),
+            Self::Inspect(Some(info)) => write!(
+                f,
+                "length {} negative {} flags {} shard {}",
+                info.value_length(),
+                info.is_negative_cached(),
+                info.flags(),
+                info.shard()
+            ),
+            Self::KeysGlob(keys) | Self::Evictions(keys) => {
+                let keys: Vec<String> = keys.iter().map(|key| format!("\"{key}\"")).collect();
+                write!(f, "[{}]", keys.join(", "))
+            }
+            Self::RPop(maybe_item) => match maybe_item {
+                None => write!(f, "RPOP None"),
+                Some(item) => write!(f, "\"{item}\""),
+            },
+            Self::DeleteReturning(maybe_value) => match maybe_value {
+                None => write!(f, "DELETE_RETURNING None"),
+                Some(value) => write!(f, "\"{value}\""),
+            },
             Self::Get(maybe_get) => match maybe_get {
                 None => write!(f, "GET None"),
                 Some(get_resp) => write!(f, "{get_resp}"),
@@ -94,6 +327,70 @@ pub(crate) struct ResponseBodyGet {
     pub key: Key,
     pub value: Value,
     pub ttl_since_unix_epoch_in_millis: Option<u128>,
+    pub flags: u32,
+    pub updated_at_in_millis: u128,
+}
+
+impl ResponseBody {
+    /// Returns the operation this is the body of a response to.
+    pub(crate) fn op_code(&self) -> OpCode {
+        match self {
+            ResponseBody::Get(_) => OpCode::Get,
+            ResponseBody::Set => OpCode::Set,
+            ResponseBody::Delete => OpCode::Delete,
+            ResponseBody::Flush => OpCode::Flush,
+            ResponseBody::ExistsMany(_) => OpCode::ExistsMany,
+            ResponseBody::LPush => OpCode::LPush,
+            ResponseBody::RPop(_) => OpCode::RPop,
+            ResponseBody::DeleteReturning(_) => OpCode::DeleteReturning,
+            ResponseBody::SAdd => OpCode::SAdd,
+            ResponseBody::SIsMember => OpCode::SIsMember,
+            ResponseBody::SRem => OpCode::SRem,
+            ResponseBody::KeysGlob(_) => OpCode::KeysGlob,
+            ResponseBody::SetNegative => OpCode::SetNegative,
+            ResponseBody::ConnStats(_) => OpCode::ConnStats,
+            ResponseBody::Capabilities(_) => OpCode::Capabilities,
+            ResponseBody::Version(_) => OpCode::Version,
+            ResponseBody::RandomKey(_) => OpCode::RandomKey,
+            ResponseBody::Inspect(_) => OpCode::Inspect,
+            ResponseBody::Evictions(_) => OpCode::SubscribeEvictions,
+            ResponseBody::Increment(_) => OpCode::Increment,
+            ResponseBody::Decrement(_) => OpCode::Decrement,
+            ResponseBody::SetMany(_) => OpCode::SetMany,
+            ResponseBody::FlushExpired(_) => OpCode::FlushExpired,
+            ResponseBody::SetChunk => OpCode::SetChunk,
+        }
+    }
+
+    /// The body of a response to `op_code` that carries nothing but its status.
+    pub(crate) fn empty(op_code: OpCode) -> Self {
+        match op_code {
+            OpCode::Get => ResponseBody::Get(None),
+            OpCode::Set => ResponseBody::Set,
+            OpCode::Delete => ResponseBody::Delete,
+            OpCode::Flush => ResponseBody::Flush,
+            OpCode::ExistsMany => ResponseBody::ExistsMany(vec![]),
+            OpCode::LPush => ResponseBody::LPush,
+            OpCode::RPop => ResponseBody::RPop(None),
+            OpCode::DeleteReturning => ResponseBody::DeleteReturning(None),
+            OpCode::SAdd => ResponseBody::SAdd,
+            OpCode::SIsMember => ResponseBody::SIsMember,
+            OpCode::SRem => ResponseBody::SRem,
+            OpCode::KeysGlob => ResponseBody::KeysGlob(vec![]),
+            OpCode::SetNegative => ResponseBody::SetNegative,
+            OpCode::ConnStats => ResponseBody::ConnStats(None),
+            OpCode::Capabilities => ResponseBody::Capabilities(None),
+            OpCode::Version => ResponseBody::Version(None),
+            OpCode::RandomKey => ResponseBody::RandomKey(None),
+            OpCode::Inspect => ResponseBody::Inspect(None),
+            OpCode::SubscribeEvictions => ResponseBody::Evictions(vec![]),
+            OpCode::Increment => ResponseBody::Increment(None),
+            OpCode::Decrement => ResponseBody::Decrement(None),
+            OpCode::SetMany => ResponseBody::SetMany(vec![]),
+            OpCode::FlushExpired => ResponseBody::FlushExpired(None),
+            OpCode::SetChunk => ResponseBody::SetChunk,
+        }
+    }
 }
 
 impl fmt::Display for ResponseBodyGet {
@@ -108,19 +405,86 @@ impl fmt::Display for ResponseBodyGet {
 impl TryFrom<Response> for ResponseFrame {
     type Error = Error;
     fn try_from(resp: Response) -> Result<Self> {
+        let (flags, updated_at_in_millis) = match &resp.body {
+            ResponseBody::Get(Some(get_body))
+            | ResponseBody::Increment(Some(get_body))
+            | ResponseBody::Decrement(Some(get_body)) => {
+                (get_body.flags, get_body.updated_at_in_millis)
+            }
+            _ => (0, 0),
+        };
         let (op_code, key, value, ttl) = match resp.body {
             ResponseBody::Get(get_body) => {
-                let (k, v, ttl) = get_body.map_or((None, None, None), |b| {
-                    (Some(b.key), Some(b.value), b.ttl_since_unix_epoch_in_millis)
-                });
+                let (k, v, ttl) = split_get_body(get_body);
                 (OpCode::Get, k, v, ttl)
             }
+            ResponseBody::Increment(counter) => {
+                let (k, v, ttl) = split_get_body(counter);
+                (OpCode::Increment, k, v, ttl)
+            }
+            ResponseBody::Decrement(counter) => {
+                let (k, v, ttl) = split_get_body(counter);
+                (OpCode::Decrement, k, v, ttl)
+            }
             ResponseBody::Set => (OpCode::Set, None, None, None),
+            ResponseBody::SetChunk => (OpCode::SetChunk, None, None, None),
             ResponseBody::Delete => (OpCode::Delete, None, None, None),
             ResponseBody::Flush => (OpCode::Flush, None, None, None),
+            ResponseBody::ExistsMany(exists) => {
+                (OpCode::ExistsMany, None, Some(encode_bits(&exists)?), None)
+            }
+            ResponseBody::LPush => (OpCode::LPush, None, None, None),
+            ResponseBody::RPop(item) => (OpCode::RPop, None, item, None),
+            ResponseBody::DeleteReturning(value) => (OpCode::DeleteReturning, None, value, None),
+            ResponseBody::SAdd => (OpCode::SAdd, None, None, None),
+            ResponseBody::SIsMember => (OpCode::SIsMember, None, None, None),
+            ResponseBody::SRem => (OpCode::SRem, None, None, None),
+            ResponseBody::KeysGlob(keys) => (OpCode::KeysGlob, None, encode_keys(&keys)?, None),
+            ResponseBody::Evictions(keys) => {
+                (OpCode::SubscribeEvictions, None, encode_keys(&keys)?, None)
+            }
+            ResponseBody::SetMany(statuses) => {
+                (OpCode::SetMany, None, encode_statuses(&statuses)?, None)
+            }
+            ResponseBody::FlushExpired(removed) => (
+                OpCode::FlushExpired,
+                None,
+                removed.map(encode_amount).transpose()?,
+                None,
+            ),
+            ResponseBody::SetNegative => (OpCode::SetNegative, None, None, None),
+            ResponseBody::ConnStats(stats) => (
+                OpCode::ConnStats,
+                None,
+                stats.as_ref().map(encode_connection_stats).transpose()?,
+                None,
+            ),
+            ResponseBody::Capabilities(capabilities) => (
+                OpCode::Capabilities,
+                None,
+                capabilities.as_ref().map(encode_capabilities).transpose()?,
+                None,
+            ),
+            ResponseBody::Version(info) => (
+                OpCode::Version,
+                None,
+                info.as_ref().map(encode_server_info).transpose()?,
+                None,
+            ),
+            ResponseBody::RandomKey(key) => (OpCode::RandomKey, key, None, None),
+            ResponseBody::Inspect(info) => (
+                OpCode::Inspect,
+                None,
+                info.as_ref().map(encode_key_info).transpose()?,
+                None,
+            ),
         };
         let ttl = TTLSinceUnixEpochInMillis::parse(ttl);
-        ResponseFrame::new(op_code, resp.status, ttl, key, value)
+        ResponseFrame::new(op_code, resp.status, ttl, key, value).map(|frame| {
+            frame
+                .with_flags(flags)
+                .with_updated_at(u64::try_from(updated_at_in_millis).unwrap_or(u64::MAX))
+        })
     }
 }
 
@@ -128,39 +492,17 @@ impl TryFrom<ResponseFrame> for Response {
     type Error = Error;
 
     fn try_from(frame: ResponseFrame) -> Result<Response> {
+        let status = frame.header.status;
         let body = match frame.header.op_code {
-            OpCode::Get => {
-                // TODO beautify
-                let body_result = match (frame.key, frame.value) {
-                    (Some(key), Some(value)) => {
-                        let ttl_since_unix_epoch_in_millis =
-                            frame.header.ttl_since_unix_epoch_in_millis.into_ttl();
-                        Ok(Some(ResponseBodyGet {
-                            key,
-                            value,
-                            ttl_since_unix_epoch_in_millis,
-                        }))
-                    }
-                    (Some(_), None) => Err(Error::new_parse(ParseError::ValueMissing)),
-                    (None, Some(_)) => Err(Error::new_parse(ParseError::KeyMissing)),
-                    (None, None) => Err(Error::new_parse(ParseError::KeyAndValueMissing)),
-                };
-                let body = match body_result {
-                    Ok(Some(response_body)) => Some(response_body),
-                    Ok(None) => None,
-                    Err(e) => {
-                        if frame.header.status == StatusCode::Ok {
-                            return Err(e);
-                        }
-                        None
-                    }
-                };
-                ResponseBody::Get(body)
-            }
+            OpCode::Get => ResponseBody::Get(parse_get_body(frame)?),
             OpCode::Set => {
                 ensure_key_and_value_are_none(frame.key, frame.value)?;
                 ResponseBody::Set
             }
+            OpCode::SetChunk => {
+                ensure_key_and_value_are_none(frame.key, frame.value)?;
+                ResponseBody::SetChunk
+            }
             OpCode::Delete => {
                 ensure_key_and_value_are_none(frame.key, frame.value)?;
                 ResponseBody::Delete
@@ -169,12 +511,248 @@ impl TryFrom<ResponseFrame> for Response {
                 ensure_key_and_value_are_none(frame.key, frame.value)?;
                 ResponseBody::Flush
             }
+            OpCode::ExistsMany => {
+                if frame.key.is_some() {
+                    return Err(Error::new_parse(ParseError::UnexpectedKey));
+                }
+                match frame.value {
+                    Some(value) => ResponseBody::ExistsMany(parse_bits(value.as_bytes())?),
+                    // Whatever went wrong is in the status
+                    None if frame.header.status != StatusCode::Ok => {
+                        ResponseBody::ExistsMany(vec![])
+                    }
+                    None => return Err(Error::new_parse(ParseError::ValueMissing)),
+                }
+            }
+            OpCode::LPush => {
+                ensure_key_and_value_are_none(frame.key, frame.value)?;
+                ResponseBody::LPush
+            }
+            OpCode::RPop => {
+                if frame.key.is_some() {
+                    return Err(Error::new_parse(ParseError::UnexpectedKey));
+                }
+                ResponseBody::RPop(frame.value)
+            }
+            OpCode::DeleteReturning => {
+                if frame.key.is_some() {
+                    return Err(Error::new_parse(ParseError::UnexpectedKey));
+                }
+                ResponseBody::DeleteReturning(frame.value)
+            }
+            OpCode::SAdd => {
+                ensure_key_and_value_are_none(frame.key, frame.value)?;
+                ResponseBody::SAdd
+            }
+            OpCode::SIsMember => {
+                ensure_key_and_value_are_none(frame.key, frame.value)?;
+                ResponseBody::SIsMember
+            }
+            OpCode::SRem => {
+                ensure_key_and_value_are_none(frame.key, frame.value)?;
+                ResponseBody::SRem
+            }
+            OpCode::KeysGlob => {
+                if frame.key.is_some() {
+                    return Err(Error::new_parse(ParseError::UnexpectedKey));
+                }
+                // No value at all means no key matched
+                let keys = frame
+                    .value
+                    .map_or(Ok(vec![]), |value| parse_keys(value.as_bytes()))?;
+                ResponseBody::KeysGlob(keys)
+            }
+            OpCode::SubscribeEvictions => {
+                if frame.key.is_some() {
+                    return Err(Error::new_parse(ParseError::UnexpectedKey));
+                }
+                let keys = frame
+                    .value
+                    .map_or(Ok(vec![]), |value| parse_keys(value.as_bytes()))?;
+                ResponseBody::Evictions(keys)
+            }
+            OpCode::SetMany => {
+                if frame.key.is_some() {
+                    return Err(Error::new_parse(ParseError::UnexpectedKey));
+                }
+                // No value at all means no entries were requested or whatever went wrong is in the status
+                let statuses = frame
+                    .value
+                    .map_or(Ok(vec![]), |value| parse_statuses(value.as_bytes()))?;
+                ResponseBody::SetMany(statuses)
+            }
+            OpCode::SetNegative => {
+                ensure_key_and_value_are_none(frame.key, frame.value)?;
+                ResponseBody::SetNegative
+            }
+            OpCode::ConnStats => {
+                if frame.key.is_some() {
+                    return Err(Error::new_parse(ParseError::UnexpectedKey));
+                }
+                let stats = frame
+                    .value
+                    .map(|value| parse_connection_stats(value.as_bytes()))
+                    .transpose()?;
+                ResponseBody::ConnStats(stats)
+            }
+            OpCode::FlushExpired => {
+                if frame.key.is_some() {
+                    return Err(Error::new_parse(ParseError::UnexpectedKey));
+                }
+                let removed = frame
+                    .value
+                    .map(|value| parse_amount(value.as_bytes()))
+                    .transpose()?;
+                ResponseBody::FlushExpired(removed)
+            }
+            OpCode::Capabilities => {
+                if frame.key.is_some() {
+                    return Err(Error::new_parse(ParseError::UnexpectedKey));
+                }
+                let capabilities = frame
+                    .value
+                    .map(|value| parse_capabilities(value.as_bytes()))
+                    .transpose()?;
+                ResponseBody::Capabilities(capabilities)
+            }
+            OpCode::Version => {
+                if frame.key.is_some() {
+                    return Err(Error::new_parse(ParseError::UnexpectedKey));
+                }
+                let info = frame
+                    .value
+                    .map(|value| parse_server_info(value.as_bytes()))
+                    .transpose()?;
+                ResponseBody::Version(info)
+            }
+            OpCode::RandomKey => {
+                if frame.value.is_some() {
+                    return Err(Error::new_parse(ParseError::UnexpectedValue));
+                }
+                ResponseBody::RandomKey(frame.key)
+            }
+            OpCode::Inspect => {
+                if frame.key.is_some() {
+                    return Err(Error::new_parse(ParseError::UnexpectedKey));
+                }
+                let info = frame
+                    .value
+                    .map(|value| parse_key_info(value.as_bytes()))
+                    .transpose()?;
+                ResponseBody::Inspect(info)
+            }
+            OpCode::Increment => ResponseBody::Increment(parse_get_body(frame)?),
+            OpCode::Decrement => ResponseBody::Decrement(parse_get_body(frame)?),
         };
-        Ok(Self {
-            status: frame.header.status,
-            body,
-        })
+        Ok(Self { status, body })
+    }
+}
+
+/// Splits the body of a response laid out like a GET response into its key, value and TTL.
+fn split_get_body(body: Option<ResponseBodyGet>) -> (Option<Key>, Option<Value>, Option<u128>) {
+    body.map_or((None, None, None), |b| {
+        (Some(b.key), Some(b.value), b.ttl_since_unix_epoch_in_millis)
+    })
+}
+
+/// Parses the body of a response laid out like a GET response,
+/// which carries both key and value unless the status tells why not.
+fn parse_get_body(frame: ResponseFrame) -> Result<Option<ResponseBodyGet>> {
+    match (frame.key, frame.value) {
+        (Some(key), Some(value)) => Ok(Some(ResponseBodyGet {
+            key,
+            value,
+            ttl_since_unix_epoch_in_millis: frame.header.ttl_since_unix_epoch_in_millis.into_ttl(),
+            flags: frame.header.flags,
+            updated_at_in_millis: frame.header.updated_at_in_millis.into(),
+        })),
+        _ if frame.header.status != StatusCode::Ok => Ok(None),
+        (Some(_), None) => Err(Error::new_parse(ParseError::ValueMissing)),
+        (None, Some(_)) => Err(Error::new_parse(ParseError::KeyMissing)),
+        (None, None) => Err(Error::new_parse(ParseError::KeyAndValueMissing)),
+    }
+}
+
+/// Encodes the stats into a value of four `u64`s, in the order of the fields.
+fn encode_connection_stats(stats: &ConnectionStats) -> Result<Value> {
+    let mut buf = BytesMut::with_capacity(32);
+    buf.put_u64(stats.handled_requests);
+    buf.put_u64(stats.bytes_received);
+    buf.put_u64(stats.bytes_sent);
+    buf.put_u64(stats.connected_since_unix_epoch_in_millis);
+    Value::parse(buf.freeze())
+}
+
+/// Encodes the key info into a value, see [`parse_key_info`] for the layout.
+fn encode_key_info(info: &KeyInfo) -> Result<Value> {
+    let mut buf = BytesMut::with_capacity(29);
+    buf.put_u32(info.value_length);
+    buf.put_u8(u8::from(info.negative));
+    buf.put_u64(
+        info.ttl_since_unix_epoch_in_millis
+            .map_or(0, |ttl| u64::try_from(ttl).unwrap_or(u64::MAX)),
+    );
+    buf.put_u64(u64::try_from(info.updated_at_in_millis).unwrap_or(u64::MAX));
+    buf.put_u32(info.flags);
+    buf.put_u32(info.shard);
+    Value::parse(buf.freeze())
+}
+
+/// Encodes the capabilities into a value, see [`parse_capabilities`] for the layout.
+fn encode_capabilities(capabilities: &ServerCapabilities) -> Result<Value> {
+    let mut buf = BytesMut::with_capacity(17 + capabilities.allowed_opcodes.len());
+    buf.put_u32(capabilities.max_key_length);
+    buf.put_u32(capabilities.max_value_length);
+    buf.put_u64(capabilities.max_entries.unwrap_or(NO_LIMIT));
+    let mut flags = 0;
+    if capabilities.requires_flush_confirmation {
+        flags |= FLUSH_CONFIRMATION_FLAG;
+    }
+    if capabilities.validates_keys {
+        flags |= KEY_VALIDATOR_FLAG;
     }
+    buf.put_u8(flags);
+    for op_code in &capabilities.allowed_opcodes {
+        buf.put_u8(*op_code as u8);
+    }
+    Value::parse(buf.freeze())
+}
+
+/// Encodes the info into a value of the feature flags followed by the version.
+fn encode_server_info(info: &ServerInfo) -> Result<Value> {
+    let mut buf = BytesMut::with_capacity(1 + info.version.len());
+    buf.put_u8(info.features);
+    buf.put_slice(info.version.as_bytes());
+    Value::parse(buf.freeze())
+}
+
+/// Encodes the amount into a value of a single `u64`.
+fn encode_amount(amount: u64) -> Result<Value> {
+    Value::parse(amount.to_be_bytes().to_vec())
+}
+
+/// Encodes the statuses into a value, one byte each.
+fn encode_statuses(statuses: &[StatusCode]) -> Result<Option<Value>> {
+    if statuses.is_empty() {
+        return Ok(None);
+    }
+    let statuses: Vec<u8> = statuses.iter().map(|status| *status as u8).collect();
+    Value::parse(statuses).map(Some)
+}
+
+/// Encodes the flags into a value, prefixed with their amount and packed into bits, lowest bit first.
+fn encode_bits(bits: &[bool]) -> Result<Value> {
+    let mut buf = BytesMut::with_capacity(4 + bits.len().div_ceil(8));
+    // The flags are bounded by the amount of keys fitting into a single request
+    buf.put_u32(bits.len() as u32);
+    for chunk in bits.chunks(8) {
+        let byte = chunk
+            .iter()
+            .enumerate()
+            .fold(0, |byte, (i, bit)| byte | (u8::from(*bit) << i));
+        buf.put_u8(byte);
+    }
+    Value::parse(buf.freeze())
 }
 
 fn ensure_key_and_value_are_none(key: Option<Key>, value: Option<Value>) -> Result<()> {
@@ -190,6 +768,7 @@ fn ensure_key_and_value_are_none(key: Option<Key>, value: Option<Value>) -> Resu
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::protocol::RESP_FEATURE;
     use crate::response::{Response, ResponseBody, ResponseBodyGet};
     use rstest::rstest;
 
@@ -200,7 +779,7 @@ mod test {
         Some("ABC".to_string()),
         Some("Some value".to_string()),
         None,
-        ResponseBody::Get(Some( ResponseBodyGet {key: Key::parse("ABC".to_string()).unwrap(), value: Value::parse("Some value".to_string()).unwrap(), ttl_since_unix_epoch_in_millis: None}))
+        ResponseBody::Get(Some( ResponseBodyGet {key: Key::parse("ABC".to_string()).unwrap(), value: Value::parse("Some value".to_string()).unwrap(), ttl_since_unix_epoch_in_millis: None, flags: 0, updated_at_in_millis: 0}))
     )]
     #[case(
         OpCode::Get,
@@ -208,11 +787,52 @@ mod test {
         Some("ABC".to_string()),
         Some("Some value".to_string()),
         Some(123456678901),
-        ResponseBody::Get(Some( ResponseBodyGet {key: Key::parse("ABC".to_string()).unwrap(), value: Value::parse("Some value".to_string()).unwrap(), ttl_since_unix_epoch_in_millis: Some(123456678901)}))
+        ResponseBody::Get(Some( ResponseBodyGet {key: Key::parse("ABC".to_string()).unwrap(), value: Value::parse("Some value".to_string()).unwrap(), ttl_since_unix_epoch_in_millis: Some(123456678901), flags: 0, updated_at_in_millis: 0}))
     )]
     #[case(OpCode::Set, StatusCode::Ok, None, None, None, ResponseBody::Set)]
+    #[case(
+        OpCode::SetChunk,
+        StatusCode::Ok,
+        None,
+        None,
+        None,
+        ResponseBody::SetChunk
+    )]
     #[case(OpCode::Delete, StatusCode::Ok, None, None, None, ResponseBody::Delete)]
     #[case(OpCode::Flush, StatusCode::Ok, None, None, None, ResponseBody::Flush)]
+    #[case(OpCode::LPush, StatusCode::Ok, None, None, None, ResponseBody::LPush)]
+    #[case(
+        OpCode::RPop,
+        StatusCode::Ok,
+        None,
+        Some("Some item".to_string()),
+        None,
+        ResponseBody::RPop(Some(Value::parse("Some item".to_string()).unwrap()))
+    )]
+    #[case(
+        OpCode::RPop,
+        StatusCode::Ok,
+        None,
+        None,
+        None,
+        ResponseBody::RPop(None)
+    )]
+    #[case(
+        OpCode::DeleteReturning,
+        StatusCode::Ok,
+        None,
+        Some("Some value".to_string()),
+        None,
+        ResponseBody::DeleteReturning(Some(Value::parse("Some value".to_string()).unwrap()))
+    )]
+    #[case(
+        OpCode::DeleteReturning,
+        StatusCode::KeyNotFound,
+        None,
+        None,
+        None,
+        ResponseBody::DeleteReturning(None)
+    )]
     fn test_conversion_from_valid_response_frame_to_response_works(
         #[case] op_code: OpCode,
         #[case] status: StatusCode,
@@ -251,6 +871,13 @@ mod test {
     #[case(OpCode::Flush, StatusCode::Ok, Some("ABC".to_string()), None)]
     #[case(OpCode::Flush, StatusCode::Ok, None, Some("ABC".to_string()))]
     #[case(OpCode::Flush, StatusCode::Ok, Some("ABC".to_string()), Some("ABC".to_string()))]
+    #[case(OpCode::LPush, StatusCode::Ok, Some("ABC".to_string()), None)]
+    #[case(OpCode::LPush, StatusCode::Ok, None, Some("ABC".to_string()))]
+    #[case(OpCode::RPop, StatusCode::Ok, Some("ABC".to_string()), Some("ABC".to_string()))]
+    #[case(OpCode::DeleteReturning, StatusCode::Ok, Some("ABC".to_string()), None)]
+    #[case(OpCode::SAdd, StatusCode::Ok, None, Some("ABC".to_string()))]
+    #[case(OpCode::SIsMember, StatusCode::Ok, Some("ABC".to_string()), None)]
+    #[case(OpCode::SRem, StatusCode::Ok, None, Some("ABC".to_string()))]
     fn test_conversion_from_invalid_response_frame_to_response_fails(
         #[case] op_code: OpCode,
         #[case] status: StatusCode,
@@ -263,4 +890,359 @@ mod test {
         let resp_frame = ResponseFrame::new(op_code, status, ttl, key, value).unwrap();
         assert!(Response::try_from(resp_frame).is_err())
     }
+
+    fn now_in_millis() -> u128 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_millis()
+    }
+
+    #[test]
+    fn test_remaining_ttl_for_a_future_ttl_is_positive() {
+        let response = ResponseGet::new(
+            StatusCode::Ok,
+            Some(Bytes::from("1234")),
+            TtlState::ExpiresAt(now_in_millis() + 60_000),
+        );
+        let remaining_ttl = response.remaining_ttl().unwrap();
+        assert!(remaining_ttl > Duration::from_secs(59));
+        assert!(remaining_ttl <= Duration::from_secs(60));
+    }
+
+    #[test]
+    fn test_remaining_ttl_for_an_expired_ttl_is_zero() {
+        let response = ResponseGet::new(
+            StatusCode::Ok,
+            Some(Bytes::from("1234")),
+            TtlState::ExpiresAt(now_in_millis() - 1),
+        );
+        assert_eq!(response.remaining_ttl(), Some(Duration::ZERO));
+    }
+
+    #[test]
+    fn test_remaining_ttl_without_ttl_is_none() {
+        let response = ResponseGet::new(StatusCode::Ok, Some(Bytes::from("1234")), TtlState::NoTtl);
+        assert!(response.remaining_ttl().is_none());
+    }
+
+    #[rstest]
+    #[case(TtlState::NoTtl, None)]
+    #[case(TtlState::ExpiresAt(1234), Some(1234))]
+    #[case(TtlState::Unknown, None)]
+    fn test_ttl_states_are_kept_apart(#[case] ttl: TtlState, #[case] expires_at: Option<u128>) {
+        let response = ResponseGet::new(StatusCode::Ok, Some(Bytes::from("1234")), ttl);
+        assert_eq!(response.ttl(), ttl);
+        assert_eq!(response.ttl_since_unix_epoch_in_millis(), expires_at);
+    }
+
+    #[rstest]
+    #[case(None, TtlState::NoTtl)]
+    #[case(Some(1234), TtlState::ExpiresAt(1234))]
+    fn test_ttls_of_values_convert_to_their_state(
+        #[case] ttl: Option<u128>,
+        #[case] expected: TtlState,
+    ) {
+        assert_eq!(TtlState::from(ttl), expected);
+    }
+
+    #[test]
+    fn test_value_str_of_a_utf8_value_is_ok() {
+        let response = ResponseGet::new(StatusCode::Ok, Some(Bytes::from("1234")), TtlState::NoTtl);
+        assert_eq!(response.value_str(), Some(Ok("1234")));
+        assert_eq!(response.value(), Some(b"1234".as_slice()));
+    }
+
+    #[test]
+    fn test_empty_bodies_belong_to_their_op_code() {
+        for op_code in OpCode::ALL {
+            assert_eq!(ResponseBody::empty(op_code).op_code(), op_code);
+        }
+    }
+
+    #[rstest]
+    #[case(StatusCode::Ok, ResponseBody::Set, OpCode::Set, true)]
+    #[case(StatusCode::KeyExists, ResponseBody::Set, OpCode::Set, false)]
+    #[case(StatusCode::KeyNotFound, ResponseBody::Delete, OpCode::Delete, false)]
+    fn test_response_status_carries_status_and_op_code(
+        #[case] status: StatusCode,
+        #[case] body: ResponseBody,
+        #[case] op_code: OpCode,
+        #[case] is_ok: bool,
+    ) {
+        let response = ResponseStatus::from(&Response::new(status, body));
+        assert_eq!(response.status(), status);
+        assert_eq!(response.op_code(), op_code);
+        assert_eq!(response.is_ok(), is_ok);
+    }
+
+    #[test]
+    fn test_get_responses_convert_into_their_value() {
+        let response = ResponseGet::new(StatusCode::Ok, Some(Bytes::from("1234")), TtlState::NoTtl);
+        assert_eq!(
+            Option::<String>::try_from(response.clone()).unwrap(),
+            Some("1234".to_string())
+        );
+        assert_eq!(
+            Option::<Vec<u8>>::from(response.clone()),
+            Some(b"1234".to_vec())
+        );
+        assert_eq!(response.ok_or_not_found().unwrap(), "1234");
+
+        let invalid_utf8 = ResponseGet::new(
+            StatusCode::Ok,
+            Some(Bytes::from_static(&[0xff])),
+            TtlState::NoTtl,
+        );
+        let e = Option::<String>::try_from(invalid_utf8.clone()).unwrap_err();
+        assert_eq!(e.into_bytes(), vec![0xff]);
+        assert_eq!(
+            Option::<Vec<u8>>::from(invalid_utf8.clone()),
+            Some(vec![0xff])
+        );
+        let e = invalid_utf8.ok_or_not_found().unwrap_err();
+        assert_eq!(e.status(), None);
+    }
+
+    #[rstest]
+    #[case(StatusCode::KeyNotFound)]
+    #[case(StatusCode::NegativeCached)]
+    #[case(StatusCode::WrongType)]
+    fn test_get_responses_without_value_convert_into_nothing_or_errors(#[case] status: StatusCode) {
+        let response = ResponseGet::new(status, None, TtlState::Unknown);
+        assert_eq!(Option::<String>::try_from(response.clone()).unwrap(), None);
+        assert_eq!(Option::<Vec<u8>>::from(response.clone()), None);
+        let e = response.ok_or_not_found().unwrap_err();
+        assert_eq!(e.status(), Some(status));
+    }
+
+    #[rstest]
+    #[case(StatusCode::Ok, Some(Bytes::from_static(b"bar")), Ok(Some(b"bar".to_vec())))]
+    #[case(StatusCode::Ok, None, Ok(None))]
+    #[case(StatusCode::Ok, Some(Bytes::from_static(&[0xff])), Ok(Some(vec![0xff])))]
+    #[case(StatusCode::KeyNotFound, None, Err(StatusCode::KeyNotFound))]
+    #[case(StatusCode::WrongType, None, Err(StatusCode::WrongType))]
+    fn test_get_responses_convert_into_results_by_status(
+        #[case] status: StatusCode,
+        #[case] value: Option<Bytes>,
+        #[case] expected: std::result::Result<Option<Vec<u8>>, StatusCode>,
+    ) {
+        let response = ResponseGet::new(status, value, TtlState::NoTtl);
+        assert_eq!(response.into_result(), expected);
+    }
+
+    #[test]
+    fn test_value_str_of_invalid_utf8_is_err_but_bytes_are_kept() {
+        let response = ResponseGet::new(
+            StatusCode::Ok,
+            Some(Bytes::from_static(&[0xff, 0xfe])),
+            TtlState::NoTtl,
+        );
+        assert!(matches!(response.value_str(), Some(Err(_))));
+        assert_eq!(response.value(), Some(&[0xff, 0xfe][..]));
+        assert_eq!(response.into_value(), Some(vec![0xff, 0xfe]));
+    }
+
+    #[rstest]
+    #[case(vec![])]
+    #[case(vec![true])]
+    #[case(vec![false, true, true, false, false, false, false, false, true, false])]
+    fn test_exists_many_response_round_trips_through_frame(#[case] exists: Vec<bool>) {
+        let response = Response::new(StatusCode::Ok, ResponseBody::ExistsMany(exists.clone()));
+        let frame = ResponseFrame::try_from(response).unwrap();
+        assert_eq!(
+            Response::try_from(frame).unwrap(),
+            Response::new(StatusCode::Ok, ResponseBody::ExistsMany(exists))
+        );
+    }
+
+    #[rstest]
+    #[case(vec![])]
+    #[case(vec![StatusCode::Ok, StatusCode::KeyExists, StatusCode::Ok])]
+    fn test_set_many_response_round_trips_through_frame(#[case] statuses: Vec<StatusCode>) {
+        let response = Response::new(StatusCode::Ok, ResponseBody::SetMany(statuses.clone()));
+        let frame = ResponseFrame::try_from(response).unwrap();
+        assert_eq!(
+            Response::try_from(frame).unwrap(),
+            Response::new(StatusCode::Ok, ResponseBody::SetMany(statuses))
+        );
+    }
+
+    #[test]
+    fn test_flags_and_updated_at_of_get_responses_round_trip_through_the_wire() {
+        let body = || ResponseBodyGet {
+            key: Key::parse("ABC".to_string()).unwrap(),
+            value: Value::parse("{}").unwrap(),
+            ttl_since_unix_epoch_in_millis: Some(1_700_000_000_000),
+            flags: 0xC0FFEE,
+            updated_at_in_millis: 1_690_000_000_000,
+        };
+        let response = Response::new(StatusCode::Ok, ResponseBody::Get(Some(body())));
+        let mut buf = bytes::BytesMut::new();
+        ResponseFrame::try_from(response).unwrap().encode(&mut buf);
+
+        let frame = crate::parsing::parse_response_frame(&buf).unwrap();
+        assert_eq!(
+            Response::try_from(frame).unwrap(),
+            Response::new(StatusCode::Ok, ResponseBody::Get(Some(body())))
+        );
+    }
+
+    #[test]
+    fn test_conn_stats_response_round_trips_through_frame() {
+        let stats = ConnectionStats {
+            handled_requests: 3,
+            bytes_received: 120,
+            bytes_sent: u64::MAX,
+            connected_since_unix_epoch_in_millis: 1_700_000_000_000,
+        };
+        let response = Response::new(StatusCode::Ok, ResponseBody::ConnStats(Some(stats)));
+        let frame = ResponseFrame::try_from(response).unwrap();
+        assert_eq!(
+            Response::try_from(frame).unwrap(),
+            Response::new(StatusCode::Ok, ResponseBody::ConnStats(Some(stats)))
+        );
+    }
+
+    #[rstest]
+    #[case(StatusCode::Ok, Some(Some(1_700_000_000_000)), false)]
+    #[case(StatusCode::Ok, Some(None), true)]
+    #[case(StatusCode::KeyNotFound, None, false)]
+    fn test_inspect_response_round_trips_through_frame(
+        #[case] status: StatusCode,
+        #[case] ttl: Option<Option<u128>>,
+        #[case] negative: bool,
+    ) {
+        let info = ttl.map(|ttl| KeyInfo {
+            value_length: 4,
+            ttl_since_unix_epoch_in_millis: ttl,
+            updated_at_in_millis: 1_690_000_000_000,
+            flags: 7,
+            negative,
+            shard: 3,
+        });
+        let response = Response::new(status, ResponseBody::Inspect(info.clone()));
+        let frame = ResponseFrame::try_from(response).unwrap();
+        assert_eq!(
+            Response::try_from(frame).unwrap(),
+            Response::new(status, ResponseBody::Inspect(info))
+        );
+    }
+
+    #[rstest]
+    #[case(StatusCode::Ok, Some(42))]
+    #[case(StatusCode::Ok, Some(i64::MIN))]
+    #[case(StatusCode::InvalidValue, None)]
+    #[case(StatusCode::OutOfRange, None)]
+    #[case(StatusCode::WrongType, None)]
+    fn test_counter_responses_round_trip_through_frame(
+        #[case] status: StatusCode,
+        #[case] value: Option<i64>,
+    ) {
+        let counter = || {
+            value.map(|value| ResponseBodyGet {
+                key: Key::parse("ABC".to_string()).unwrap(),
+                value: Value::parse(value.to_string()).unwrap(),
+                ttl_since_unix_epoch_in_millis: Some(1_000),
+                flags: 7,
+                updated_at_in_millis: 500,
+            })
+        };
+        for body in [ResponseBody::Increment, ResponseBody::Decrement] {
+            let frame = ResponseFrame::try_from(Response::new(status, body(counter()))).unwrap();
+            assert_eq!(
+                Response::try_from(frame).unwrap(),
+                Response::new(status, body(counter()))
+            );
+        }
+    }
+
+    #[rstest]
+    #[case(StatusCode::Ok, Some(0))]
+    #[case(StatusCode::Ok, Some(u64::MAX))]
+    #[case(StatusCode::OperationNotPermitted, None)]
+    fn test_flush_expired_response_round_trips_through_frame(
+        #[case] status: StatusCode,
+        #[case] removed: Option<u64>,
+    ) {
+        let response = Response::new(status, ResponseBody::FlushExpired(removed));
+        let frame = ResponseFrame::try_from(response).unwrap();
+        assert_eq!(
+            Response::try_from(frame).unwrap(),
+            Response::new(status, ResponseBody::FlushExpired(removed))
+        );
+    }
+
+    #[rstest]
+    #[case(None, false, false, OpCode::ALL.to_vec())]
+    #[case(Some(0), true, false, vec![OpCode::Get])]
+    #[case(Some(10_000), false, true, vec![])]
+    fn test_capabilities_response_round_trips_through_frame(
+        #[case] max_entries: Option<u64>,
+        #[case] requires_flush_confirmation: bool,
+        #[case] validates_keys: bool,
+        #[case] allowed_opcodes: Vec<OpCode>,
+    ) {
+        let capabilities = ServerCapabilities {
+            max_key_length: 255,
+            max_value_length: 1024 * 1024,
+            max_entries,
+            requires_flush_confirmation,
+            validates_keys,
+            allowed_opcodes,
+        };
+        let response = Response::new(
+            StatusCode::Ok,
+            ResponseBody::Capabilities(Some(capabilities.clone())),
+        );
+        let frame = ResponseFrame::try_from(response).unwrap();
+        assert_eq!(
+            Response::try_from(frame).unwrap(),
+            Response::new(
+                StatusCode::Ok,
+                ResponseBody::Capabilities(Some(capabilities))
+            )
+        );
+    }
+
+    #[test]
+    fn test_version_response_round_trips_through_frame() {
+        let info = ServerInfo {
+            version: "1.2.3".to_string(),
+            features: RESP_FEATURE,
+        };
+        let response = Response::new(StatusCode::Ok, ResponseBody::Version(Some(info.clone())));
+        let frame = ResponseFrame::try_from(response).unwrap();
+        assert_eq!(
+            Response::try_from(frame).unwrap(),
+            Response::new(StatusCode::Ok, ResponseBody::Version(Some(info)))
+        );
+    }
+
+    #[test]
+    fn test_unknown_op_codes_in_capabilities_are_skipped() {
+        let mut value = vec![0, 0, 0, 255, 0, 16, 0, 0];
+        value.extend(NO_LIMIT.to_be_bytes());
+        value.extend([0, OpCode::Get as u8, 200, OpCode::Set as u8]);
+        let capabilities = parse_capabilities(&value).unwrap();
+        assert_eq!(capabilities.allowed_opcodes(), [OpCode::Get, OpCode::Set]);
+        assert_eq!(capabilities.max_entries(), None);
+    }
+
+    #[rstest]
+    #[case(vec![])]
+    #[case(vec!["user:1:session", "user:2:session"])]
+    fn test_keys_glob_response_round_trips_through_frame(#[case] keys: Vec<&str>) {
+        let parse_keys = || {
+            keys.iter()
+                .map(|key| Key::parse(key.to_string()).unwrap())
+                .collect::<Vec<_>>()
+        };
+        let response = Response::new(StatusCode::Ok, ResponseBody::KeysGlob(parse_keys()));
+        let frame = ResponseFrame::try_from(response).unwrap();
+        assert_eq!(
+            Response::try_from(frame).unwrap(),
+            Response::new(StatusCode::Ok, ResponseBody::KeysGlob(parse_keys()))
+        );
+    }
 }
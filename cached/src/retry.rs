@@ -0,0 +1,94 @@
+use std::time::Duration;
+
+/// How often and how soon a request is retried after failing due to its connection,
+/// see [`Client::get_retry`](crate::Client::get_retry).
+///
+/// Every retry waits for the backoff first, which starts at 10ms and doubles with every retry up to 1s.
+///
+/// # Examples
+///
+/// ```
+/// use cached::RetryPolicy;
+/// use std::time::Duration;
+///
+/// let policy = RetryPolicy::new(5).backoff(Duration::from_millis(50), Duration::from_secs(2));
+/// assert_eq!(policy.max_retries(), 5);
+/// assert!(!policy.retries_sets());
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+    max_retries: u32,
+    initial_backoff: Duration,
+    max_backoff: Duration,
+    retry_sets: bool,
+}
+
+impl RetryPolicy {
+    /// Retries a request up to `max_retries` times.
+    pub fn new(max_retries: u32) -> Self {
+        Self {
+            max_retries,
+            initial_backoff: Duration::from_millis(10),
+            max_backoff: Duration::from_secs(1),
+            retry_sets: false,
+        }
+    }
+
+    /// Waits `initial_backoff` before the first retry, doubling it for every further one up to `max_backoff`.
+    pub fn backoff(self, initial_backoff: Duration, max_backoff: Duration) -> Self {
+        Self {
+            initial_backoff,
+            max_backoff,
+            ..self
+        }
+    }
+
+    /// Controls whether sets are retried, see [`Client::set_retry`](crate::Client::set_retry).
+    ///
+    /// A set whose response was lost may have been applied, retrying it is then refused
+    /// with [`StatusCode::KeyExists`](crate::StatusCode::KeyExists). Defaults to `false`.
+    pub fn retry_sets(self, retry_sets: bool) -> Self {
+        Self { retry_sets, ..self }
+    }
+
+    /// How often a request is retried at most.
+    pub fn max_retries(&self) -> u32 {
+        self.max_retries
+    }
+
+    /// Whether sets are retried, see [`Self::retry_sets`].
+    pub fn retries_sets(&self) -> bool {
+        self.retry_sets
+    }
+
+    /// Returns how long to wait before retrying for the `retry`th time, counting from zero.
+    pub(crate) fn backoff_before(&self, retry: u32) -> Duration {
+        self.initial_backoff
+            .saturating_mul(2u32.saturating_pow(retry))
+            .min(self.max_backoff)
+    }
+}
+
+impl Default for RetryPolicy {
+    /// Retries a request up to three times.
+    fn default() -> Self {
+        Self::new(3)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_backoff_doubles_up_to_the_limit() {
+        let policy =
+            RetryPolicy::new(10).backoff(Duration::from_millis(10), Duration::from_millis(50));
+        let backoffs: Vec<Duration> = (0..5).map(|retry| policy.backoff_before(retry)).collect();
+        assert_eq!(
+            backoffs,
+            [10, 20, 40, 50, 50].map(Duration::from_millis).to_vec()
+        );
+        assert_eq!(policy.backoff_before(u32::MAX), Duration::from_millis(50));
+    }
+}
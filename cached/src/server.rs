@@ -1,75 +1,789 @@
-use crate::primitives::StatusCode;
+use crate::primitives::{OpCode, StatusCode};
 use crate::request::Request;
 use crate::response::{Response, ResponseBody, ResponseBodyGet};
-use std::sync::Arc;
-use tokio::net::{TcpListener, ToSocketAddrs};
-use tokio::sync::{broadcast, mpsc, Semaphore};
+use socket2::{Domain, Protocol, Socket, Type};
+#[cfg(feature = "tracing")]
+use std::any::Any;
+use std::collections::HashSet;
+use std::convert::Infallible;
+use std::fmt;
+use std::future::{poll_fn, Future};
+use std::io::{self, Write};
+use std::net::SocketAddr;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::PathBuf;
+use std::pin::pin;
+use std::sync::{Arc, OnceLock};
+use std::task::Poll;
+use std::thread;
+use std::time::Duration;
+use tokio::net::{lookup_host, TcpListener, ToSocketAddrs};
+use tokio::runtime::Handle;
+use tokio::sync::{broadcast, mpsc, watch, Semaphore};
+use tokio::time::Instant;
 
-use crate::connection::Connection;
-use crate::db::{Database, Db};
-use crate::domain::Value;
+use crate::access_log::{AccessLog, AccessLogEntry};
+use crate::capabilities::{ServerCapabilities, ServerInfo};
+use crate::codec::ValueCodec;
+use crate::connection::{Connection, InflightLimit};
+use crate::db::{
+    default_shard_amount, report_expired, run_sweeper, warm, Clock, Database, Db, DbError, DbTasks,
+};
+use crate::domain::{Key, Value, MAX_VALUE_LENGTH};
 use crate::error::ConnectionError;
+use crate::eviction::{
+    EvictionBatch, EvictionPolicy, EvictionSubscriber, EVICTION_CHANNEL_CAPACITY,
+};
+#[cfg(any(feature = "http", feature = "resp"))]
+use crate::gateway::{self, Gateway};
+use crate::metrics::{ConnectionStats, Metrics, ServerMetrics};
+use crate::protocol::MAX_KEY_LENGTH;
 use crate::shutdown::Shutdown;
+use crate::tap::RequestLog;
+use crate::upload::Uploads;
 use crate::{error, Error};
 #[cfg(feature = "tracing")]
-use tracing::{debug, error, info, instrument};
+use tracing::{debug, error, info, instrument, warn};
+
+static DEFAULT_MAX_CONNECTIONS: usize = 250;
+static DEFAULT_SWEEP_INTERVAL: Duration = Duration::from_secs(1);
+#[cfg(feature = "tracing")]
+static DRAIN_PROGRESS_INTERVAL: Duration = Duration::from_secs(1);
+/// The same backlog tokio uses for `TcpListener::bind`.
+static LISTEN_BACKLOG: i32 = 1024;
+/// How long [`Server::serve_forever`] waits before accepting again after the first transient error,
+/// doubled for every further error in a row.
+static MIN_ACCEPT_BACKOFF: Duration = Duration::from_millis(10);
+static MAX_ACCEPT_BACKOFF: Duration = Duration::from_secs(1);
 
 #[derive(Debug)]
 struct ServerInner {
     listener: TcpListener,
-    db: Db,
+    service: Service,
     notify_shutdown: broadcast::Sender<()>,
     shutdown_complete_tx: mpsc::Sender<()>,
     shutdown_complete_rx: mpsc::Receiver<()>,
     connection_limit: Arc<Semaphore>,
+    max_handler_restarts: usize,
+    request_tap: Option<mpsc::Sender<RequestLog>>,
+    access_log: Option<AccessLog>,
+    write_timeout: Option<Duration>,
+    frame_timeout: Option<Duration>,
+    accept_timeout: Option<Duration>,
+    inflight_limit: Option<InflightLimit>,
+    retry_accept_errors: bool,
+    runtime: Handle,
 }
 
-#[derive(Debug, Default)]
+/// Carries out requests against the database, shared by all connections whatever their transport.
+#[derive(Debug, Clone)]
+pub(crate) struct Service {
+    db: Db,
+    metrics: Arc<Metrics>,
+    evictions: broadcast::Sender<EvictionBatch>,
+    require_flush_confirmation: bool,
+    allowed_opcodes: Option<Arc<HashSet<OpCode>>>,
+    key_validator: Option<KeyValidator>,
+    printable_ascii_keys: bool,
+    capabilities: Arc<ServerCapabilities>,
+}
+
+/// Decides whether the server accepts a key, see [`Server::key_validator`].
+#[derive(Clone)]
+struct KeyValidator(Arc<dyn Fn(&str) -> bool + Send + Sync>);
+
+impl fmt::Debug for KeyValidator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("KeyValidator").finish_non_exhaustive()
+    }
+}
+
+#[derive(Debug)]
 pub struct Server {
     builder: ServerBuilder,
     listener: Option<TcpListener>,
     port: Option<u16>,
+    #[cfg(feature = "http")]
+    http_listener: Option<TcpListener>,
+    #[cfg(feature = "resp")]
+    resp_listener: Option<TcpListener>,
+    shared: Arc<Shared>,
+}
+
+/// State shared between the server and its handles.
+#[derive(Debug)]
+struct Shared {
+    state: watch::Sender<RunState>,
+    metrics: Arc<Metrics>,
+    local_addr: OnceLock<SocketAddr>,
+    evictions: broadcast::Sender<EvictionBatch>,
+}
+
+/// Why [`Server::run`] returned.
+#[derive(Debug)]
+pub enum ShutdownReason {
+    /// The process received Ctrl-C.
+    Signal,
+    /// Accepting connections failed.
+    Error(Error),
+    /// A [`ServerHandle`] shut the server down, or quiesced it and all connections were closed.
+    Requested,
+    /// The server was quiesced and the deadline passed before all connections were closed.
+    Timeout,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum RunState {
+    Running,
+    Quiescing { deadline: Option<Instant> },
+    ShuttingDown,
+    Stopped,
+}
+
+/// A handle to control a [`Server`] after it was started,
+/// obtained via [`Server::handle`] or [`Server::spawn`].
+#[derive(Debug, Clone)]
+pub struct ServerHandle {
+    shared: Arc<Shared>,
+}
+
+impl ServerHandle {
+    /// Stops the server from accepting new connections while still serving the existing ones.
+    ///
+    /// The listening socket is closed, so new connection attempts fail.
+    /// [`Server::run`] returns once all existing connections were closed by their clients,
+    /// or, if a `deadline` is given, once the deadline passed and the remaining connections
+    /// were shut down.
+    pub fn quiesce(&self, deadline: Option<Duration>) {
+        let deadline = deadline.map(|deadline| Instant::now() + deadline);
+        self.shared.state.send_if_modified(|state| {
+            if *state == RunState::Running {
+                *state = RunState::Quiescing { deadline };
+                true
+            } else {
+                false
+            }
+        });
+    }
+
+    /// Shuts the server down, closing all connections, and waits until it stopped.
+    ///
+    /// This also cuts short a drain started by [`ServerHandle::quiesce`].
+    pub async fn shutdown(&self) {
+        let mut state = self.shared.state.subscribe();
+        self.shared.state.send_if_modified(|state| {
+            if matches!(state, RunState::Running | RunState::Quiescing { .. }) {
+                *state = RunState::ShuttingDown;
+                true
+            } else {
+                false
+            }
+        });
+        state_reached(&mut state, |state| *state == RunState::Stopped).await;
+    }
+
+    /// Returns a snapshot of the server's metrics.
+    pub fn metrics(&self) -> ServerMetrics {
+        self.shared.metrics.snapshot()
+    }
+
+    /// Returns the server's metrics in the Prometheus text exposition format.
+    pub fn metrics_prometheus(&self) -> String {
+        self.metrics().to_prometheus()
+    }
+
+    /// Subscribes to the keys the server evicts.
+    ///
+    /// Keys removed by the periodic sweep of expired keys are delivered as one batch per sweep.
+    /// Expired keys that are removed when they are accessed are not reported.
+    pub fn subscribe_evictions(&self) -> EvictionSubscriber {
+        EvictionSubscriber::new(self.shared.evictions.subscribe())
+    }
+
+    /// Returns the number of connections currently open.
+    pub fn active_connections(&self) -> usize {
+        self.shared.metrics.active_connections()
+    }
+
+    /// Returns the number of connection handlers that still have to finish
+    /// while the server is quiescing or shutting down, `None` otherwise.
+    pub fn draining_handlers(&self) -> Option<usize> {
+        match *self.shared.state.borrow() {
+            RunState::Quiescing { .. } | RunState::ShuttingDown => {
+                Some(self.shared.metrics.active_connections())
+            }
+            RunState::Running | RunState::Stopped => None,
+        }
+    }
+
+    /// Returns the address the server is listening on.
+    pub fn local_addr(&self) -> SocketAddr {
+        *self
+            .shared
+            .local_addr
+            .get()
+            .expect("No address available, did you bind the server?")
+    }
 }
 
+/// The options of a [`Server`] that are plain data, e.g. to be read from a config file
+/// and passed to [`Server::with_config`] in one go.
+///
+/// Options left at `None` fall back to the default documented on the [`Server`] method of the same
+/// name, setting an option through that method overrides it. Options holding runtime objects, like
+/// [`Server::key_validator`], [`Server::request_tap`], [`Server::access_log`] and [`Server::runtime`],
+/// can only be set through their methods.
+/// With the `serde` feature the config can be (de)serialized, missing fields are `None`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct ServerConfig {
+    /// See [`Server::max_connections`].
+    pub max_connections: Option<usize>,
+    /// See [`Server::shard_amount`].
+    pub shard_amount: Option<usize>,
+    /// See [`Server::sweep_interval`].
+    pub sweep_interval: Option<Duration>,
+    /// See [`Server::expire_by_tokio_time`].
+    pub expire_by_tokio_time: Option<bool>,
+    /// See [`Server::reuse_address`].
+    pub reuse_address: Option<bool>,
+    /// See [`Server::reuse_port`].
+    pub reuse_port: Option<bool>,
+    /// See [`Server::max_handler_restarts`].
+    pub max_handler_restarts: Option<usize>,
+    /// See [`Server::require_flush_confirmation`].
+    pub require_flush_confirmation: Option<bool>,
+    /// See [`Server::allowed_opcodes`].
+    pub allowed_opcodes: Option<HashSet<OpCode>>,
+    /// See [`Server::printable_ascii_keys`].
+    pub printable_ascii_keys: Option<bool>,
+    /// See [`Server::warm_from`].
+    pub warm_from: Option<PathBuf>,
+    /// See [`Server::max_entries`].
+    pub max_entries: Option<usize>,
+    /// See [`Server::eviction_policy`].
+    pub eviction_policy: Option<EvictionPolicy>,
+    /// See [`Server::max_ttl_keys`].
+    pub max_ttl_keys: Option<usize>,
+    /// See [`Server::large_value_threshold`].
+    pub large_value_threshold: Option<usize>,
+    /// See [`Server::write_timeout`].
+    pub write_timeout: Option<Duration>,
+    /// See [`Server::frame_timeout`].
+    pub frame_timeout: Option<Duration>,
+    /// See [`Server::accept_timeout`].
+    pub accept_timeout: Option<Duration>,
+    /// See [`Server::max_inflight_bytes`].
+    pub max_inflight_bytes: Option<u64>,
+    /// See [`Server::access_log_plain_keys`].
+    pub access_log_plain_keys: Option<bool>,
+}
+
+/// Everything a [`Server`] is configured with, its [`ServerConfig`] plus the runtime objects.
 #[derive(Debug, Default)]
 pub struct ServerBuilder {
-    max_connections: Option<usize>,
+    config: ServerConfig,
+    key_validator: Option<KeyValidator>,
+    value_codec: Option<Arc<dyn ValueCodec>>,
+    request_tap: Option<mpsc::Sender<RequestLog>>,
+    access_log: Option<AccessLog>,
+    retry_accept_errors: Option<bool>,
+    runtime: Option<Handle>,
 }
 
 impl ServerBuilder {
-    pub fn new() -> Self {
+    fn with_config(config: ServerConfig) -> Self {
         Self {
-            max_connections: None,
+            config,
+            key_validator: None,
+            value_codec: None,
+            request_tap: None,
+            access_log: None,
+            retry_accept_errors: None,
+            runtime: None,
+        }
+    }
+
+    fn bind_listener(&self, addr: SocketAddr) -> io::Result<TcpListener> {
+        let socket = Socket::new(Domain::for_address(addr), Type::STREAM, Some(Protocol::TCP))?;
+        socket.set_nonblocking(true)?;
+        // Same default as tokio's `TcpListener::bind`
+        socket.set_reuse_address(self.config.reuse_address.unwrap_or(cfg!(unix)))?;
+        #[cfg(not(any(windows, target_os = "solaris", target_os = "illumos")))]
+        if let Some(reuse_port) = self.config.reuse_port {
+            socket.set_reuse_port(reuse_port)?;
         }
+        socket.bind(&addr.into())?;
+        socket.listen(LISTEN_BACKLOG)?;
+        // Registered with the runtime the server runs on, so it keeps working once the current one is gone
+        let _runtime = self.runtime.as_ref().map(Handle::enter);
+        TcpListener::from_std(socket.into())
+    }
+
+    fn shard_amount(&self) -> usize {
+        self.config
+            .shard_amount
+            .unwrap_or_else(default_shard_amount)
+    }
+
+    /// The runtime to spawn the server's tasks on, the current one unless another was set.
+    fn runtime(&self) -> Handle {
+        self.runtime.clone().unwrap_or_else(Handle::current)
+    }
+
+    fn db(&self, runtime: &Handle, metrics: Arc<Metrics>) -> (Db, DbTasks) {
+        let (db, tasks) = Db::with_limits(
+            runtime,
+            self.shard_amount(),
+            self.config
+                .max_entries
+                .map(|max_entries| (max_entries, self.config.eviction_policy.unwrap_or_default())),
+            self.config.max_ttl_keys,
+            self.config.large_value_threshold,
+            Some(metrics),
+            if self.config.expire_by_tokio_time.unwrap_or_default() {
+                Clock::tokio()
+            } else {
+                Clock::System
+            },
+        );
+        (db.with_value_codec(self.value_codec.clone()), tasks)
+    }
+
+    fn capabilities(&self) -> ServerCapabilities {
+        ServerCapabilities {
+            max_key_length: MAX_KEY_LENGTH as u32,
+            max_value_length: MAX_VALUE_LENGTH,
+            max_entries: self
+                .config
+                .max_entries
+                .map(|max_entries| max_entries as u64),
+            requires_flush_confirmation: self.config.require_flush_confirmation.unwrap_or(true),
+            validates_keys: self.key_validator.is_some()
+                || self.config.printable_ascii_keys.unwrap_or_default(),
+            allowed_opcodes: OpCode::ALL
+                .into_iter()
+                .filter(|op_code| {
+                    self.config
+                        .allowed_opcodes
+                        .as_ref()
+                        .is_none_or(|allowed| allowed.contains(op_code))
+                })
+                .collect(),
+        }
+    }
+
+    fn connection_permits(&self) -> usize {
+        match self.config.max_connections {
+            None => DEFAULT_MAX_CONNECTIONS,
+            Some(0) => Semaphore::MAX_PERMITS,
+            Some(max_connections) => max_connections,
+        }
+    }
+}
+
+impl Default for Server {
+    fn default() -> Self {
+        Self::new()
     }
 }
 
 impl Server {
     pub fn new() -> Self {
+        Self::with_config(ServerConfig::default())
+    }
+
+    /// Creates a server with all options of `config` set at once.
+    ///
+    /// Further options can still be set through the methods of the server,
+    /// overriding those of `config`.
+    pub fn with_config(config: ServerConfig) -> Self {
+        let (state, _) = watch::channel(RunState::Running);
+        let (evictions, _) = broadcast::channel(EVICTION_CHANNEL_CAPACITY);
         Self {
-            builder: ServerBuilder::new(),
+            builder: ServerBuilder::with_config(config),
             listener: None,
             port: None,
+            #[cfg(feature = "http")]
+            http_listener: None,
+            #[cfg(feature = "resp")]
+            resp_listener: None,
+            shared: Arc::new(Shared {
+                state,
+                metrics: Default::default(),
+                local_addr: OnceLock::new(),
+                evictions,
+            }),
+        }
+    }
+
+    /// Returns the server's metrics in the Prometheus text exposition format,
+    /// use [`Server::handle`] to keep access to them once the server is running.
+    pub fn metrics_prometheus(&self) -> String {
+        self.shared.metrics.snapshot().to_prometheus()
+    }
+
+    /// Returns a handle to control the server once it is running.
+    pub fn handle(&self) -> ServerHandle {
+        ServerHandle {
+            shared: self.shared.clone(),
         }
     }
 
     /// Binds to the address.
+    ///
+    /// If the address resolves to several socket addresses, the first one that can be bound to is used.
     pub async fn bind<A: ToSocketAddrs>(mut self, addr: A) -> error::Result<Self> {
-        let listener = TcpListener::bind(addr)
-            .await
-            .map_err(|e| Error::new_connection(ConnectionError::Io(e)))?;
-        let port = listener
+        let listener = self.bind_first(addr).await?;
+        let local_addr = listener
             .local_addr()
-            .map_err(|e| Error::new_connection(ConnectionError::Io(e)))?
-            .port();
+            .map_err(|e| Error::new_connection(ConnectionError::Io(e)))?;
+        let _ = self.shared.local_addr.set(local_addr);
         self.listener = Some(listener);
-        self.port = Some(port);
+        self.port = Some(local_addr.port());
         Ok(self)
     }
 
+    /// Additionally serves the cache over HTTP on the address, next to the wire protocol.
+    ///
+    /// Values are available under `/keys/{key}`, with the key percent-encoded.
+    /// `GET` returns the value as the body, `PUT` stores the body as the value,
+    /// unless the key exists already, and `DELETE` removes it. Values are sent as they are,
+    /// any bytes. TTLs are set and returned in the `X-Cached-Ttl` header,
+    /// in milliseconds from now. HTTP connections count towards [`Server::max_connections`]
+    /// and are subject to the same restrictions as any other connection.
+    ///
+    /// The socket options set for `bind` apply here as well.
+    #[cfg(feature = "http")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "http")))]
+    pub async fn bind_http<A: ToSocketAddrs>(mut self, addr: A) -> error::Result<Self> {
+        self.http_listener = Some(self.bind_first(addr).await?);
+        Ok(self)
+    }
+
+    /// Returns the port the HTTP gateway is listening on, see [`Server::bind_http`].
+    #[cfg(feature = "http")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "http")))]
+    pub fn http_port(&self) -> u16 {
+        self.http_listener
+            .as_ref()
+            .and_then(|listener| listener.local_addr().ok())
+            .expect("No HTTP port available, did you bind the HTTP gateway?")
+            .port()
+    }
+
+    /// Additionally serves the cache over a subset of the Redis protocol (RESP) on the address,
+    /// so existing Redis clients can be used.
+    ///
+    /// `GET`, `SET` with its `EX`, `PX` and `NX` options, `DEL`, `EXPIRE`, `FLUSHALL` and `PING`
+    /// are supported. Unlike the wire protocol, `SET` replaces existing values, as Redis does.
+    /// `SET` without `NX` and `EXPIRE` take several steps, so they are not atomic.
+    /// Keys must be valid UTF-8, values may be any bytes. RESP connections count towards
+    /// [`Server::max_connections`] and are subject to the same restrictions as any other connection.
+    ///
+    /// The socket options set for `bind` apply here as well.
+    #[cfg(feature = "resp")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "resp")))]
+    pub async fn bind_resp<A: ToSocketAddrs>(mut self, addr: A) -> error::Result<Self> {
+        self.resp_listener = Some(self.bind_first(addr).await?);
+        Ok(self)
+    }
+
+    /// Returns the port the RESP gateway is listening on, see [`Server::bind_resp`].
+    #[cfg(feature = "resp")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "resp")))]
+    pub fn resp_port(&self) -> u16 {
+        self.resp_listener
+            .as_ref()
+            .and_then(|listener| listener.local_addr().ok())
+            .expect("No RESP port available, did you bind the RESP gateway?")
+            .port()
+    }
+
+    /// Binds to the first socket address the address resolves to that can be bound to.
+    async fn bind_first<A: ToSocketAddrs>(&self, addr: A) -> error::Result<TcpListener> {
+        let mut last_error = None;
+        let mut bound_listener = None;
+        for addr in lookup_host(addr)
+            .await
+            .map_err(|e| Error::new_connection(ConnectionError::Io(e)))?
+        {
+            match self.builder.bind_listener(addr) {
+                Ok(listener) => {
+                    bound_listener = Some(listener);
+                    break;
+                }
+                Err(e) => last_error = Some(e),
+            }
+        }
+        bound_listener.ok_or_else(|| {
+            Error::new_connection(ConnectionError::Io(last_error.unwrap_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "could not resolve to any address",
+                )
+            })))
+        })
+    }
+
     /// Controls the maximum number of connections the server have open at any one point.
+    ///
+    /// Defaults to 250. Passing `0` removes the limit altogether.
     pub fn max_connections(mut self, max_connections: usize) -> Self {
-        self.builder.max_connections = Some(max_connections);
+        self.builder.config.max_connections = Some(max_connections);
+        self
+    }
+
+    /// Controls into how many shards the database is split.
+    ///
+    /// Each shard is served by its own task, so unrelated keys can be
+    /// read and written in parallel. Defaults to the available parallelism.
+    pub fn shard_amount(mut self, shard_amount: usize) -> Self {
+        self.builder.config.shard_amount = Some(shard_amount);
+        self
+    }
+
+    /// Controls how often expired keys are removed in the background.
+    ///
+    /// Expired keys are never returned, but they only free their memory once swept.
+    /// Defaults to one second.
+    pub fn sweep_interval(mut self, sweep_interval: Duration) -> Self {
+        self.builder.config.sweep_interval = Some(sweep_interval);
+        self
+    }
+
+    /// Controls whether keys expire by tokio's clock rather than by the system clock.
+    ///
+    /// The clock starts out at the system time when the server starts running, and from then on
+    /// advances with tokio's clock. Tests pausing and advancing tokio's clock, e.g. with
+    /// `tokio::time::pause`, thereby control when keys expire, along with the
+    /// [`sweeps`](Server::sweep_interval) that run on tokio's clock anyway. Once tokio's clock was
+    /// advanced, the server is ahead of the system clock, so TTLs that clients compute from the system
+    /// clock afterwards may already have passed for the server. As tokio's clock doesn't follow
+    /// adjustments of the system clock either, this is meant for tests only. Defaults to `false`.
+    pub fn expire_by_tokio_time(mut self, expire_by_tokio_time: bool) -> Self {
+        self.builder.config.expire_by_tokio_time = Some(expire_by_tokio_time);
+        self
+    }
+
+    /// Limits how many entries the cache holds, evicting entries per the
+    /// [`eviction policy`](Server::eviction_policy) to make room for new keys.
+    ///
+    /// The limit is split evenly across the shards, each shard evicting on its own once it is full,
+    /// so it is rounded up to a multiple of the [`shard amount`](Server::shard_amount).
+    /// Lists, sets and tombstones count as one entry each. Unlimited by default.
+    pub fn max_entries(mut self, max_entries: usize) -> Self {
+        self.builder.config.max_entries = Some(max_entries);
+        self
+    }
+
+    /// Controls which entry is evicted once [`Server::max_entries`] is hit.
+    ///
+    /// Defaults to [`EvictionPolicy::Lru`].
+    pub fn eviction_policy(mut self, eviction_policy: EvictionPolicy) -> Self {
+        self.builder.config.eviction_policy = Some(eviction_policy);
+        self
+    }
+
+    /// Sweeps the expired keys of a shard right away once more than about `max_ttl_keys` of its keys
+    /// have a TTL, instead of waiting for the next [`sweep`](Server::sweep_interval).
+    ///
+    /// This bounds the memory held by expired keys under TTL-heavy workloads. Like
+    /// [`Server::max_entries`] the threshold is split evenly across the shards. While most keys are
+    /// live, the threshold grows with them so that inserts don't sweep over and over again.
+    /// Keys removed this way are not reported to eviction subscribers. Unlimited by default.
+    pub fn max_ttl_keys(mut self, max_ttl_keys: usize) -> Self {
+        self.builder.config.max_ttl_keys = Some(max_ttl_keys);
+        self
+    }
+
+    /// Shares values longer than `large_value_threshold` bytes between the database and the requests
+    /// reading them, instead of copying them for every read.
+    ///
+    /// Shorter values are copied, which costs less than sharing them. Defaults to 1024 bytes.
+    pub fn large_value_threshold(mut self, large_value_threshold: usize) -> Self {
+        self.builder.config.large_value_threshold = Some(large_value_threshold);
+        self
+    }
+
+    /// Controls whether `SO_REUSEADDR` is set on the listening socket.
+    ///
+    /// This allows binding to a port right away again after a restart,
+    /// even while connections of the previous process are lingering.
+    /// Must be set before calling `bind`. Defaults to `true` on unix, `false` elsewhere.
+    pub fn reuse_address(mut self, reuse_address: bool) -> Self {
+        self.builder.config.reuse_address = Some(reuse_address);
+        self
+    }
+
+    /// Controls how often the loop serving a connection is restarted after it panicked.
+    ///
+    /// A panic is always caught and logged, it never affects other connections.
+    /// The request being handled when panicking is not answered.
+    /// Once the restarts are used up, the connection is closed. Defaults to `0`.
+    pub fn max_handler_restarts(mut self, max_handler_restarts: usize) -> Self {
+        self.builder.config.max_handler_restarts = Some(max_handler_restarts);
+        self
+    }
+
+    /// Controls whether flushing the cache must be confirmed by the client,
+    /// see [`Client::flush_confirmed`](crate::Client::flush_confirmed).
+    ///
+    /// Unconfirmed flushes are refused with [`StatusCode::OperationNotPermitted`].
+    /// Defaults to `true`.
+    pub fn require_flush_confirmation(mut self, require_flush_confirmation: bool) -> Self {
+        self.builder.config.require_flush_confirmation = Some(require_flush_confirmation);
+        self
+    }
+
+    /// Seeds the cache from the file at `path` before serving the first connection.
+    ///
+    /// Each line holds a key and its value separated by a tab, the values are set without TTL.
+    /// Malformed lines are skipped. If the file can't be read, the server starts with an empty cache.
+    /// This only seeds the cache, nothing is ever written back to the file.
+    pub fn warm_from(mut self, path: impl Into<PathBuf>) -> Self {
+        self.builder.config.warm_from = Some(path.into());
+        self
+    }
+
+    /// Restricts the operations the server carries out to `allowed_opcodes`.
+    ///
+    /// Requests for any other operation are refused with [`StatusCode::OperationNotPermitted`].
+    /// All operations are allowed by default.
+    pub fn allowed_opcodes(mut self, allowed_opcodes: HashSet<OpCode>) -> Self {
+        self.builder.config.allowed_opcodes = Some(allowed_opcodes);
+        self
+    }
+
+    /// Rejects requests for keys that `key_validator` returns `false` for,
+    /// e.g. to enforce a naming convention like a tenant prefix.
+    ///
+    /// Every key of a request is checked, a single rejected key fails the whole request
+    /// with [`StatusCode::InvalidKey`]. Glob patterns are not keys and are not checked.
+    /// All keys are accepted by default.
+    pub fn key_validator(mut self, key_validator: Arc<dyn Fn(&str) -> bool + Send + Sync>) -> Self {
+        self.builder.key_validator = Some(KeyValidator(key_validator));
+        self
+    }
+
+    /// Rejects requests for keys with characters other than printable ASCII, that is letters, digits,
+    /// punctuation and spaces, for interoperability with tools that choke on control characters.
+    ///
+    /// Like [`Server::key_validator`], a single rejected key fails the whole request
+    /// with [`StatusCode::InvalidKey`]. Keys that are not valid UTF-8 are always rejected this way.
+    /// Defaults to `false`.
+    pub fn printable_ascii_keys(mut self, printable_ascii_keys: bool) -> Self {
+        self.builder.config.printable_ascii_keys = Some(printable_ascii_keys);
+        self
+    }
+
+    /// Stores values as encoded by `value_codec` and decodes them when they are read,
+    /// e.g. to keep them encrypted in memory, see [`ValueCodec`].
+    ///
+    /// Encoding and decoding run on the connection handlers, not on the database shards.
+    /// Decoding copies values on every read, whatever the [`Server::large_value_threshold`].
+    /// Values that can't be decoded are answered with [`StatusCode::InternalError`].
+    /// Values are stored as they are by default.
+    pub fn value_codec(mut self, value_codec: Arc<dyn ValueCodec>) -> Self {
+        self.builder.value_codec = Some(value_codec);
+        self
+    }
+
+    /// Sends a [`RequestLog`] to `request_tap` for every request the server answered,
+    /// e.g. to record traffic for debugging or replaying it.
+    ///
+    /// The server never waits for the tap, logs are dropped while its channel is full.
+    /// Besides sending, the keys of each request are copied for its log, values are shared.
+    pub fn request_tap(mut self, request_tap: mpsc::Sender<RequestLog>) -> Self {
+        self.builder.request_tap = Some(request_tap);
+        self
+    }
+
+    /// Writes a line to `access_log` for every request the server answered, e.g. for an audit trail,
+    /// independent of tracing.
+    ///
+    /// Each line holds `key=value` pairs: when the request was answered in milliseconds since the
+    /// unix epoch, the address of the client, the operation, its first key, the status,
+    /// the bytes received and sent for it and how long it took in microseconds.
+    /// Keys are hashed unless [`Server::access_log_plain_keys`] is set.
+    ///
+    /// Connections take turns writing, so a slow `access_log` holds up the server,
+    /// consider buffering it. Lines that fail to be written are lost.
+    pub fn access_log(mut self, access_log: Box<dyn Write + Send>) -> Self {
+        self.builder.access_log = Some(AccessLog::new(access_log));
+        self
+    }
+
+    /// Writes keys to the [`Server::access_log`] as they are, quoted, rather than hashed.
+    ///
+    /// Defaults to `false`, so the log does not reveal keys.
+    pub fn access_log_plain_keys(mut self, access_log_plain_keys: bool) -> Self {
+        self.builder.config.access_log_plain_keys = Some(access_log_plain_keys);
+        self
+    }
+
+    /// Closes connections whose responses could not be written within `write_timeout`,
+    /// so a client that stopped reading does not hold up its connection handler forever.
+    ///
+    /// Writing a response normally completes right away, it only blocks while the client's receive
+    /// buffer is full. Responses are written without a timeout by default.
+    pub fn write_timeout(mut self, write_timeout: Duration) -> Self {
+        self.builder.config.write_timeout = Some(write_timeout);
+        self
+    }
+
+    /// Closes connections that stall in the middle of a request, once the rest of it
+    /// did not arrive within `frame_timeout` of its header.
+    ///
+    /// This keeps a client announcing a large request and never sending it from holding up
+    /// its connection handler, and the inflight bytes of [`Server::max_inflight_bytes`].
+    /// Connections may still idle between requests. There is no timeout by default.
+    pub fn frame_timeout(mut self, frame_timeout: Duration) -> Self {
+        self.builder.config.frame_timeout = Some(frame_timeout);
+        self
+    }
+
+    /// Closes connections that did not send their first request in full within `accept_timeout`
+    /// of being accepted, so clients that connect but never get going don't hold up a connection slot.
+    ///
+    /// Once the first request arrived, the connection may stay idle for as long as it likes.
+    /// Only applies to connections of the wire protocol. There is no timeout by default.
+    pub fn accept_timeout(mut self, accept_timeout: Duration) -> Self {
+        self.builder.config.accept_timeout = Some(accept_timeout);
+        self
+    }
+
+    /// Limits the bytes of requests and responses all connections hold at once to `max_inflight_bytes`,
+    /// so a few large requests arriving at the same time can't exhaust the memory.
+    ///
+    /// A connection waits for others to finish before reading on a request or writing a response
+    /// that would exceed the limit, holding up its client. A single frame larger than the limit
+    /// is let through once no other connection holds anything. There is no limit by default.
+    pub fn max_inflight_bytes(mut self, max_inflight_bytes: u64) -> Self {
+        self.builder.config.max_inflight_bytes = Some(max_inflight_bytes);
+        self
+    }
+
+    /// Spawns the tasks of the server on `runtime` rather than on the runtime the server is run on.
+    ///
+    /// This covers the database shards, the sweeper of expired keys and the connection handlers,
+    /// as well as the server itself when using [`Server::spawn`].
+    /// Must be set before calling `bind`, the listening socket is registered with `runtime` as well.
+    /// Useful when embedding the server in an application with a runtime dedicated to the cache.
+    pub fn runtime(mut self, runtime: Handle) -> Self {
+        self.builder.runtime = Some(runtime);
+        self
+    }
+
+    /// Controls whether `SO_REUSEPORT` is set on the listening socket.
+    ///
+    /// This allows several servers, e.g. in different processes, to listen on the same port,
+    /// with the operating system distributing connections between them.
+    /// Must be set before calling `bind`. Not supported on Windows, Solaris and illumos,
+    /// where it is ignored.
+    pub fn reuse_port(mut self, reuse_port: bool) -> Self {
+        self.builder.config.reuse_port = Some(reuse_port);
         self
     }
 
@@ -80,50 +794,291 @@ impl Server {
             .expect("No port available, did you bind the server?")
     }
 
+    /// Runs the server on a new task and returns a handle to control it.
+    ///
+    /// Panics if no socket address was provided (via `bind`).
+    pub fn spawn(self) -> ServerHandle {
+        assert!(
+            self.listener.is_some(),
+            "No listener available. Did you call `bind`?"
+        );
+        let handle = self.handle();
+        self.builder.runtime().spawn(self.run());
+        handle
+    }
+
+    /// Runs the server like [`Server::run`], but keeps accepting connections after transient errors.
+    ///
+    /// [`Server::run`] stops with [`ShutdownReason::Error`] as soon as accepting a connection fails,
+    /// e.g. as the process ran out of file descriptors. Here errors caused by a single connection
+    /// are skipped, and on other errors the server waits, from 10ms doubling up to 1s, before
+    /// accepting again. Only errors of the listener itself, e.g. as it was shut down, still stop the server.
+    ///
+    /// Panics if no socket address was provided (via `bind`).
+    pub async fn serve_forever(mut self) -> ShutdownReason {
+        self.builder.retry_accept_errors = Some(true);
+        self.run().await
+    }
+
+    /// Runs the server until it is shut down and returns why it was.
+    ///
     /// Panics if no socket address was provided (via `bind`).
-    pub async fn run(self) {
+    pub async fn run(self) -> ShutdownReason {
         let (notify_shutdown, _) = broadcast::channel(1);
         let (shutdown_complete_tx, shutdown_complete_rx) = mpsc::channel(1);
+        let runtime = self.builder.runtime();
+        let (db, db_tasks) = self.builder.db(&runtime, self.shared.metrics.clone());
         let mut server = ServerInner {
             listener: self
                 .listener
                 .expect("No listener available. Did you call `bind`?"),
-            db: Db::new(),
+            service: Service {
+                db,
+                metrics: self.shared.metrics.clone(),
+                evictions: self.shared.evictions.clone(),
+                require_flush_confirmation: self
+                    .builder
+                    .config
+                    .require_flush_confirmation
+                    .unwrap_or(true),
+                allowed_opcodes: self.builder.config.allowed_opcodes.clone().map(Arc::new),
+                key_validator: self.builder.key_validator.clone(),
+                printable_ascii_keys: self.builder.config.printable_ascii_keys.unwrap_or_default(),
+                capabilities: Arc::new(self.builder.capabilities()),
+            },
             notify_shutdown,
             shutdown_complete_tx,
             shutdown_complete_rx,
-            connection_limit: Arc::new(Semaphore::new(self.builder.max_connections.unwrap_or(250))),
+            connection_limit: Arc::new(Semaphore::new(self.builder.connection_permits())),
+            max_handler_restarts: self.builder.config.max_handler_restarts.unwrap_or_default(),
+            request_tap: self.builder.request_tap.clone(),
+            access_log: self.builder.access_log.clone().map(|access_log| {
+                access_log.with_plain_keys(
+                    self.builder
+                        .config
+                        .access_log_plain_keys
+                        .unwrap_or_default(),
+                )
+            }),
+            write_timeout: self.builder.config.write_timeout,
+            frame_timeout: self.builder.config.frame_timeout,
+            accept_timeout: self.builder.config.accept_timeout,
+            inflight_limit: self
+                .builder
+                .config
+                .max_inflight_bytes
+                .map(|max_bytes| InflightLimit::new(max_bytes, self.shared.metrics.clone())),
+            retry_accept_errors: self.builder.retry_accept_errors.unwrap_or_default(),
+            runtime,
         };
 
-        tokio::select! {
-            _res = server.serve() => {
-                #[cfg(feature = "tracing")]
-                if let Err(e) = _res {
-                    error!("Error: {:?}", e);
+        if let Some(path) = &self.builder.config.warm_from {
+            match tokio::fs::read_to_string(path).await {
+                Ok(contents) => {
+                    let (_seeded, _skipped) = warm(&server.service.db, &contents).await;
+                    #[cfg(feature = "tracing")]
+                    info!(
+                        "Warmed the cache with {_seeded} keys from {}",
+                        path.display()
+                    );
+                    #[cfg(feature = "tracing")]
+                    if _skipped > 0 {
+                        warn!("Skipped {_skipped} malformed lines while warming the cache");
+                    }
+                }
+                Err(_e) => {
+                    #[cfg(feature = "tracing")]
+                    error!("Could not warm the cache from {}: {_e}", path.display());
                 }
             }
+        }
+
+        server.runtime.spawn(run_sweeper(
+            server.service.db.clone(),
+            self.builder
+                .config
+                .sweep_interval
+                .unwrap_or(DEFAULT_SWEEP_INTERVAL),
+            self.shared.metrics.clone(),
+            self.shared.evictions.clone(),
+            Shutdown::new(server.notify_shutdown.subscribe()),
+        ));
+
+        #[cfg(feature = "http")]
+        if let Some(listener) = self.http_listener {
+            server.runtime.spawn(gateway::serve(
+                listener,
+                server.gateway(),
+                stopped_running(self.shared.state.subscribe()),
+                crate::http::serve_connection,
+            ));
+        }
+        #[cfg(feature = "resp")]
+        if let Some(listener) = self.resp_listener {
+            server.runtime.spawn(gateway::serve(
+                listener,
+                server.gateway(),
+                stopped_running(self.shared.state.subscribe()),
+                crate::resp::serve_connection,
+            ));
+        }
+
+        let mut state = self.shared.state.subscribe();
+        let mut drain_deadline = None;
+        let mut reason = tokio::select! {
+            res = server.serve() => match res {
+                Ok(never) => match never {},
+                Err(e) => {
+                    #[cfg(feature = "tracing")]
+                    error!("Error: {:?}", e);
+                    ShutdownReason::Error(e)
+                }
+            },
             _ = tokio::signal::ctrl_c() => {
                 #[cfg(feature = "tracing")]
                 info!("Shutting down");
+                ShutdownReason::Signal
             }
-        }
+            stop = state_reached(&mut state, |state| *state != RunState::Running) => {
+                if let RunState::Quiescing { deadline } = stop {
+                    #[cfg(feature = "tracing")]
+                    info!("Quiescing");
+                    drain_deadline = Some(deadline);
+                } else {
+                    #[cfg(feature = "tracing")]
+                    info!("Shutting down");
+                }
+                ShutdownReason::Requested
+            }
+        };
+
+        // Stopping for any other reason than a handle also counts as shutting down
+        self.shared.state.send_if_modified(|state| {
+            if *state == RunState::Running {
+                *state = RunState::ShuttingDown;
+                true
+            } else {
+                false
+            }
+        });
 
         let ServerInner {
+            listener,
+            service,
             notify_shutdown,
             shutdown_complete_tx,
             mut shutdown_complete_rx,
             ..
         } = server;
 
-        drop(notify_shutdown);
+        // No new connections are accepted from here on
+        drop(listener);
         drop(shutdown_complete_tx);
 
-        let _ = shutdown_complete_rx.recv().await;
+        if let Some(deadline) = drain_deadline {
+            let deadline_passed = async {
+                match deadline {
+                    Some(deadline) => tokio::time::sleep_until(deadline).await,
+                    None => std::future::pending().await,
+                }
+            };
+            tokio::select! {
+                _ = drain(&mut shutdown_complete_rx, &self.shared.metrics) => {}
+                _ = deadline_passed => {
+                    #[cfg(feature = "tracing")]
+                    info!("Drain deadline passed, shutting down remaining connections");
+                    reason = ShutdownReason::Timeout;
+                }
+                _ = state_reached(&mut state, |state| *state == RunState::ShuttingDown) => {}
+            }
+        }
+
+        drop(notify_shutdown);
+
+        drain(&mut shutdown_complete_rx, &self.shared.metrics).await;
+        // The sweeper stops along with the connections, leaving this as the last handle to the database
+        drop(service);
+        db_tasks.join().await;
+        self.shared.state.send_replace(RunState::Stopped);
+        reason
+    }
+}
+
+/// Resolves with the output of `future`, or with the panic payload if polling it panicked.
+async fn catch_unwind<F: Future>(future: F) -> thread::Result<F::Output> {
+    let mut future = pin!(future);
+    poll_fn(
+        move |cx| match panic::catch_unwind(AssertUnwindSafe(|| future.as_mut().poll(cx))) {
+            Ok(poll) => poll.map(Ok),
+            Err(panic) => Poll::Ready(Err(panic)),
+        },
+    )
+    .await
+}
+
+#[cfg(feature = "tracing")]
+fn panic_message(panic: &(dyn Any + Send)) -> Option<&str> {
+    panic
+        .downcast_ref::<&str>()
+        .copied()
+        .or_else(|| panic.downcast_ref::<String>().map(String::as_str))
+}
+
+/// Waits until all connection handlers finished, logging how many are left while waiting.
+async fn drain(shutdown_complete_rx: &mut mpsc::Receiver<()>, _metrics: &Metrics) {
+    #[cfg(feature = "tracing")]
+    {
+        let mut progress = tokio::time::interval(DRAIN_PROGRESS_INTERVAL);
+        loop {
+            tokio::select! {
+                _ = shutdown_complete_rx.recv() => break,
+                _ = progress.tick() => {
+                    info!("Waiting for {} connection handlers to finish", _metrics.active_connections());
+                }
+            }
+        }
+        info!("All connection handlers finished");
+    }
+    #[cfg(not(feature = "tracing"))]
+    let _ = shutdown_complete_rx.recv().await;
+}
+
+/// Resolves once the server stopped running, so gateways stop accepting along with its listener.
+#[cfg(any(feature = "http", feature = "resp"))]
+async fn stopped_running(mut state: watch::Receiver<RunState>) {
+    state_reached(&mut state, |state| *state != RunState::Running).await;
+}
+
+/// Resolves with the state once it matches `predicate`.
+async fn state_reached(
+    state: &mut watch::Receiver<RunState>,
+    predicate: impl Fn(&RunState) -> bool,
+) -> RunState {
+    loop {
+        let current = *state.borrow_and_update();
+        if predicate(&current) {
+            return current;
+        }
+        if state.changed().await.is_err() {
+            // The server and all handles are gone, the state can't change anymore
+            std::future::pending::<()>().await;
+        }
     }
 }
 
 impl ServerInner {
-    async fn serve(&mut self) -> error::Result<()> {
+    #[cfg(any(feature = "http", feature = "resp"))]
+    fn gateway(&self) -> Gateway {
+        Gateway {
+            service: self.service.clone(),
+            connection_limit: self.connection_limit.clone(),
+            notify_shutdown: self.notify_shutdown.subscribe(),
+            shutdown_complete_tx: self.shutdown_complete_tx.clone(),
+            runtime: self.runtime.clone(),
+        }
+    }
+
+    async fn serve(&mut self) -> error::Result<Infallible> {
         loop {
             self.connection_limit
                 .acquire()
@@ -131,66 +1086,318 @@ impl ServerInner {
                 .map_err(|e| Error::new_connection(ConnectionError::Acquire(e)))?
                 .forget();
 
-            let (stream, _) = self
-                .listener
-                .accept()
-                .await
-                .map_err(|e| Error::new_connection(ConnectionError::Io(e)))?;
+            let (stream, peer_addr) =
+                accept_retrying(|| self.listener.accept(), self.retry_accept_errors)
+                    .await
+                    .map_err(|e| Error::new_connection(ConnectionError::Io(e)))?;
+            self.service.metrics.connection_opened();
             let mut handler = Handler {
-                conn: Connection::new(stream),
-                db: self.db.clone(),
+                conn: Connection::new(stream)
+                    .with_write_timeout(self.write_timeout)
+                    .with_frame_timeout(self.frame_timeout)
+                    .with_inflight_limit(self.inflight_limit.clone()),
+                service: self.service.clone(),
                 shutdown: Shutdown::new(self.notify_shutdown.subscribe()),
                 _shutdown_complete: self.shutdown_complete_tx.clone(),
                 connection_limit: self.connection_limit.clone(),
+                request_tap: self.request_tap.clone(),
+                access_log: self.access_log.clone(),
+                peer_addr,
+                stats: ConnectionStats::new(),
+                first_request_deadline: self
+                    .accept_timeout
+                    .map(|accept_timeout| Instant::now() + accept_timeout),
+                uploads: Uploads::default(),
             };
-            tokio::spawn(async move {
-                handler.run().await;
+            let max_restarts = self.max_handler_restarts;
+            self.runtime.spawn(async move {
+                let mut restarts = 0;
+                while let Err(_panic) = catch_unwind(handler.run()).await {
+                    handler.service.metrics.handler_panicked();
+                    #[cfg(feature = "tracing")]
+                    error!(
+                        "Connection handler panicked: {}",
+                        panic_message(&_panic).unwrap_or("unknown panic")
+                    );
+                    if restarts == max_restarts {
+                        break;
+                    }
+                    restarts += 1;
+                }
             });
         }
     }
 }
 
+/// Sleeps until `deadline`, or forever if there is none.
+async fn sleep_until_some(deadline: Option<Instant>) {
+    match deadline {
+        Some(deadline) => tokio::time::sleep_until(deadline).await,
+        None => std::future::pending().await,
+    }
+}
+
+/// Accepts the next connection via `accept`, retrying on transient errors if `retry` is set.
+async fn accept_retrying<T, F, Fut>(mut accept: F, retry: bool) -> io::Result<T>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = io::Result<T>>,
+{
+    let mut backoff = MIN_ACCEPT_BACKOFF;
+    loop {
+        let e = match accept().await {
+            Ok(accepted) => return Ok(accepted),
+            Err(e) if !retry || is_fatal_accept_error(&e) => return Err(e),
+            Err(e) => e,
+        };
+        if is_connection_error(&e) {
+            #[cfg(feature = "tracing")]
+            debug!("Skipping a connection that failed while being accepted: {e}");
+            continue;
+        }
+        #[cfg(feature = "tracing")]
+        warn!("Could not accept a connection, retrying in {backoff:?}: {e}");
+        tokio::time::sleep(backoff).await;
+        backoff = (backoff * 2).min(MAX_ACCEPT_BACKOFF);
+    }
+}
+
+/// Whether accepting failed because of the listener itself, e.g. as it was shut down,
+/// so accepting again won't ever succeed.
+fn is_fatal_accept_error(e: &io::Error) -> bool {
+    matches!(
+        e.kind(),
+        io::ErrorKind::InvalidInput | io::ErrorKind::NotConnected | io::ErrorKind::Unsupported
+    )
+}
+
+/// Whether accepting failed because of the connection being accepted, the next one may well succeed.
+fn is_connection_error(e: &io::Error) -> bool {
+    matches!(
+        e.kind(),
+        io::ErrorKind::ConnectionAborted
+            | io::ErrorKind::ConnectionRefused
+            | io::ErrorKind::ConnectionReset
+            | io::ErrorKind::Interrupted
+    )
+}
+
 struct Handler {
     conn: Connection,
-    db: Db,
+    service: Service,
     shutdown: Shutdown,
     _shutdown_complete: mpsc::Sender<()>,
     connection_limit: Arc<Semaphore>,
+    request_tap: Option<mpsc::Sender<RequestLog>>,
+    access_log: Option<AccessLog>,
+    peer_addr: SocketAddr,
+    stats: ConnectionStats,
+    // Until the first request arrived in full, see `Server::accept_timeout`
+    first_request_deadline: Option<Instant>,
+    uploads: Uploads,
 }
 
 impl Handler {
     async fn run(&mut self) {
         while !self.shutdown.is_shutdown() {
             let request = tokio::select! {
-                // TODO is unwrap OK here?
-                res = self.conn.read_request() => res.unwrap(),
+                res = self.conn.read_request() => res,
+                _ = sleep_until_some(self.first_request_deadline) => {
+                    #[cfg(feature = "tracing")]
+                    debug!("Closing the connection, the first request did not arrive in time.");
+                    return
+                }
                 _ = self.shutdown.recv() => {
                     #[cfg(feature = "tracing")]
                     debug!("Received shutdown signal.");
                     return
                 }
             };
-            if let Some(r) = request {
+            self.first_request_deadline = None;
+            let request = match request {
+                Ok(request) => request,
+                Err(e) => {
+                    // Requests with invalid keys are answered, the peer sending garbage,
+                    // stalling or going away mid-frame closes the connection
+                    let (request_id, op_code) = match e.invalid_key_request() {
+                        Some(request) => request,
+                        None if e.is_caused_by_peer() => {
+                            #[cfg(feature = "tracing")]
+                            debug!("Closing the connection, could not read the request: {e}");
+                            break;
+                        }
+                        None => panic!("Reading a request failed unexpectedly: {e}"),
+                    };
+                    let started = Instant::now();
+                    let response =
+                        Response::new(StatusCode::InvalidKey, ResponseBody::empty(op_code));
+                    if let Err(_e) = self.conn.write_response(request_id, response).await {
+                        #[cfg(feature = "tracing")]
+                        debug!("Closing the connection, could not write the response: {_e}");
+                        break;
+                    }
+                    let (received, sent) = self.conn.take_transferred();
+                    self.service.metrics.transferred(received, sent);
+                    self.stats.request_handled(received, sent);
+                    self.log_access(AccessLogEntry {
+                        peer_addr: self.peer_addr,
+                        op_code,
+                        key: None,
+                        status: StatusCode::InvalidKey,
+                        bytes_received: received,
+                        bytes_sent: sent,
+                        latency: started.elapsed(),
+                    });
+                    continue;
+                }
+            };
+            if let Some((request_id, r)) = request {
+                let started = Instant::now();
+                let log = self.request_tap.as_ref().map(|_| RequestLog::new(&r));
+                let op_code = r.op_code();
+                let key = self
+                    .access_log
+                    .as_ref()
+                    .and_then(|_| r.keys().first().map(|key| key.to_string()));
+                // Subscribing before answering, so no batch removed afterwards is missed
+                let subscriber = matches!(r, Request::SubscribeEvictions)
+                    .then(|| EvictionSubscriber::new(self.service.evictions.subscribe()));
                 let response = self.handle_request(r).await;
-                self.conn.write_response(response).await.unwrap();
+                let status = response.status;
+                if let Err(_e) = self.conn.write_response(request_id, response).await {
+                    #[cfg(feature = "tracing")]
+                    debug!("Closing the connection, could not write the response: {_e}");
+                    break;
+                }
+                let elapsed = started.elapsed();
+                self.service.metrics.request_handled(elapsed);
+                if let (Some(tap), Some(mut log)) = (&self.request_tap, log) {
+                    log.status = status;
+                    log.duration = elapsed;
+                    // Dropping the log rather than holding up the connection
+                    let _ = tap.try_send(log);
+                }
+                let (received, sent) = self.conn.take_transferred();
+                self.service.metrics.transferred(received, sent);
+                self.stats.request_handled(received, sent);
+                self.log_access(AccessLogEntry {
+                    peer_addr: self.peer_addr,
+                    op_code,
+                    key: key.as_deref(),
+                    status,
+                    bytes_received: received,
+                    bytes_sent: sent,
+                    latency: elapsed,
+                });
+                if let (StatusCode::Ok, Some(subscriber)) = (status, subscriber) {
+                    self.stream_evictions(request_id, subscriber).await;
+                    break;
+                }
             } else {
                 break;
             }
         }
     }
 
+    /// Carries out `request`, collecting the chunks of values streamed over the connection
+    /// until they are complete.
+    async fn handle_request(&mut self, request: Request) -> Response {
+        let Request::SetChunk {
+            key,
+            chunk,
+            ttl_since_unix_epoch_in_millis,
+            step,
+        } = request
+        else {
+            return self.service.handle_request(request, self.stats).await;
+        };
+        if let Some(refusal) = self
+            .service
+            .refuse(OpCode::SetChunk, std::slice::from_ref(&key))
+        {
+            return refusal;
+        }
+        let status = match self.uploads.take(&key, chunk, step) {
+            Ok(Some(value)) => {
+                self.service
+                    .set(key, value, ttl_since_unix_epoch_in_millis, 0)
+                    .await
+            }
+            Ok(None) => StatusCode::Ok,
+            Err(status) => status,
+        };
+        Response::new(status, ResponseBody::SetChunk)
+    }
+
+    fn log_access(&self, entry: AccessLogEntry<'_>) {
+        if let Some(access_log) = &self.access_log {
+            access_log.record(&entry);
+        }
+    }
+
+    /// Sends every batch of evicted keys as a response to the subscription with `request_id`,
+    /// until the client goes away or the server shuts down.
+    ///
+    /// Requests arriving in the meantime are not answered.
+    async fn stream_evictions(&mut self, request_id: u32, mut subscriber: EvictionSubscriber) {
+        loop {
+            let batch = tokio::select! {
+                batch = subscriber.recv() => batch,
+                read = self.conn.read_request() => match read {
+                    Ok(Some(_)) => continue,
+                    _ => return,
+                },
+                _ = self.shutdown.recv() => return,
+            };
+            let Some(batch) = batch else {
+                return;
+            };
+            let keys = batch
+                .keys
+                .into_iter()
+                .filter_map(|key| Key::parse(key).ok())
+                .collect();
+            let response = Response::new(StatusCode::Ok, ResponseBody::Evictions(keys));
+            if let Err(_e) = self.conn.write_response(request_id, response).await {
+                #[cfg(feature = "tracing")]
+                debug!("Closing the subscription, could not write the evictions: {_e}");
+                return;
+            }
+            let (received, sent) = self.conn.take_transferred();
+            self.service.metrics.transferred(received, sent);
+        }
+    }
+}
+
+impl Service {
+    #[cfg(any(feature = "http", feature = "resp"))]
+    pub(crate) fn metrics(&self) -> &Metrics {
+        &self.metrics
+    }
+
+    /// Carries out `req`, `stats` are the stats of the connection it came in on.
     #[cfg_attr(feature = "tracing", instrument(skip(self)))]
-    async fn handle_request(&self, req: Request) -> Response {
+    pub(crate) async fn handle_request(&self, req: Request, stats: ConnectionStats) -> Response {
+        if let Some(refusal) = self.refuse(req.op_code(), req.keys()) {
+            return refusal;
+        }
         match req {
             Request::Get(key) => match self.db.get(&key).await {
-                Some(val) => {
-                    match Value::parse(val.value.to_string()) {
+                Some(Ok(val)) if val.negative => {
+                    self.metrics.hit();
+                    Response::new(StatusCode::NegativeCached, ResponseBody::Get(None))
+                }
+                Some(Ok(val)) => {
+                    self.metrics.hit();
+                    match Value::parse(val.value) {
                         Ok(value) => Response::new(
                             StatusCode::Ok,
                             ResponseBody::Get(Some(ResponseBodyGet {
                                 key,
                                 value,
                                 ttl_since_unix_epoch_in_millis: val.ttl_since_unix_epoch_in_millis,
+                                flags: val.flags,
+                                updated_at_in_millis: val.updated_at_in_millis,
                             })),
                         ),
                         Err(_) => Response::new(
@@ -200,47 +1407,378 @@ impl Handler {
                         ),
                     }
                 }
+                Some(Err(e)) => Response::new(e.into(), ResponseBody::Get(None)),
                 // TODO pass error as value too
-                None => Response::new(StatusCode::KeyNotFound, ResponseBody::Get(None)),
+                None => {
+                    self.metrics.miss();
+                    Response::new(StatusCode::KeyNotFound, ResponseBody::Get(None))
+                }
             },
             Request::Set {
                 key,
                 value,
                 ttl_since_unix_epoch_in_millis,
+                flags,
+            } => {
+                let status = self
+                    .set(key, value, ttl_since_unix_epoch_in_millis, flags)
+                    .await;
+                Response::new(status, ResponseBody::Set)
+            }
+            Request::SetMany {
+                keys,
+                values,
+                ttl_since_unix_epoch_in_millis,
+            } => {
+                let mut statuses = Vec::with_capacity(keys.len());
+                for (key, value) in keys.into_iter().zip(values) {
+                    statuses.push(
+                        self.set(key, value, ttl_since_unix_epoch_in_millis, 0)
+                            .await,
+                    );
+                }
+                Response::new(StatusCode::Ok, ResponseBody::SetMany(statuses))
+            }
+            // Tombstones are deleted like any other key
+            Request::Delete(key) => {
+                if self.db.remove(&key).await {
+                    Response::new(StatusCode::Ok, ResponseBody::Delete)
+                } else {
+                    Response::new(StatusCode::KeyNotFound, ResponseBody::Delete)
+                }
+            }
+            Request::DeleteReturning(key) => match self.db.take(&key).await {
+                Ok(Some(value)) => match Value::parse(value) {
+                    Ok(value) => {
+                        Response::new(StatusCode::Ok, ResponseBody::DeleteReturning(Some(value)))
+                    }
+                    Err(_) => Response::new(
+                        StatusCode::InternalError,
+                        ResponseBody::DeleteReturning(None),
+                    ),
+                },
+                Ok(None) => {
+                    Response::new(StatusCode::KeyNotFound, ResponseBody::DeleteReturning(None))
+                }
+                Err(e) => Response::new(e.into(), ResponseBody::DeleteReturning(None)),
+            },
+            Request::Capabilities => Response::new(
+                StatusCode::Ok,
+                ResponseBody::Capabilities(Some(ServerCapabilities::clone(&self.capabilities))),
+            ),
+            Request::Version => Response::new(
+                StatusCode::Ok,
+                ResponseBody::Version(Some(ServerInfo::current())),
+            ),
+            Request::RandomKey => match self.db.random_key().await.map(Key::parse) {
+                Some(Ok(key)) => Response::new(StatusCode::Ok, ResponseBody::RandomKey(Some(key))),
+                Some(Err(_)) => {
+                    Response::new(StatusCode::InternalError, ResponseBody::RandomKey(None))
+                }
+                None => Response::new(StatusCode::KeyNotFound, ResponseBody::RandomKey(None)),
+            },
+            // The handler of the connection streams the evictions once this is answered
+            Request::SubscribeEvictions => {
+                Response::new(StatusCode::Ok, ResponseBody::Evictions(vec![]))
+            }
+            Request::Inspect(key) => match self.db.inspect(&key).await {
+                Some(info) => Response::new(StatusCode::Ok, ResponseBody::Inspect(Some(info))),
+                None => Response::new(StatusCode::KeyNotFound, ResponseBody::Inspect(None)),
+            },
+            Request::Increment { key, delta } => {
+                let (status, counter) = self.increment(key, i128::from(delta)).await;
+                Response::new(status, ResponseBody::Increment(counter))
+            }
+            Request::Decrement { key, delta } => {
+                let (status, counter) = self.increment(key, -i128::from(delta)).await;
+                Response::new(status, ResponseBody::Decrement(counter))
+            }
+            Request::ConnStats => {
+                Response::new(StatusCode::Ok, ResponseBody::ConnStats(Some(stats)))
+            }
+            Request::SetNegative {
+                key,
+                ttl_since_unix_epoch_in_millis,
             } => {
+                // Like values, tombstones do not replace existing values
                 if self.db.contains_key(&key).await {
-                    Response::new(StatusCode::KeyExists, ResponseBody::Set)
+                    Response::new(StatusCode::KeyExists, ResponseBody::SetNegative)
                 } else {
                     self.db
-                        .insert(
-                            key.into_inner(),
-                            value.into_inner(),
-                            ttl_since_unix_epoch_in_millis,
-                        )
+                        .insert_negative(key.into_inner(), ttl_since_unix_epoch_in_millis)
                         .await;
-                    Response::new(StatusCode::Ok, ResponseBody::Set)
+                    Response::new(StatusCode::Ok, ResponseBody::SetNegative)
                 }
             }
-            Request::Delete(key) => {
-                if !self.db.contains_key(&key).await {
-                    Response::new(StatusCode::KeyNotFound, ResponseBody::Delete)
+            Request::Flush { confirmed } => {
+                if self.require_flush_confirmation && !confirmed {
+                    Response::new(StatusCode::OperationNotPermitted, ResponseBody::Flush)
                 } else {
-                    self.db.remove(&key).await;
-                    Response::new(StatusCode::Ok, ResponseBody::Delete)
+                    self.db.clear().await;
+                    Response::new(StatusCode::Ok, ResponseBody::Flush)
                 }
             }
-            Request::Flush => {
-                self.db.clear().await;
-                Response::new(StatusCode::Ok, ResponseBody::Flush)
+            // Like the sweeper, only with the caller waiting for it
+            Request::FlushExpired => {
+                let removed = self.db.sweep_expired().await;
+                let amount = removed.len() as u64;
+                report_expired(removed, &self.metrics, &self.evictions);
+                Response::new(StatusCode::Ok, ResponseBody::FlushExpired(Some(amount)))
+            }
+            // Only the connections of the wire protocol collect chunks, see `Handler::handle_request`
+            Request::SetChunk { .. } => {
+                Response::new(StatusCode::OperationNotPermitted, ResponseBody::SetChunk)
+            }
+            Request::ExistsMany(keys) => {
+                let mut exists = Vec::with_capacity(keys.len());
+                for key in keys {
+                    exists.push(self.db.contains_key(&key).await);
+                }
+                Response::new(StatusCode::Ok, ResponseBody::ExistsMany(exists))
+            }
+            Request::LPush { key, item } => match item.into_string() {
+                Ok(item) => match self.db.push_front(key.into_inner(), item).await {
+                    Ok(_) => Response::new(StatusCode::Ok, ResponseBody::LPush),
+                    Err(e) => Response::new(e.into(), ResponseBody::LPush),
+                },
+                // Items are kept as strings
+                Err(_) => Response::new(StatusCode::InvalidValue, ResponseBody::LPush),
+            },
+            Request::RPop(key) => match self.db.pop_back(&key).await {
+                Ok(Some(item)) => match Value::parse(item) {
+                    Ok(item) => Response::new(StatusCode::Ok, ResponseBody::RPop(Some(item))),
+                    Err(_) => Response::new(StatusCode::InternalError, ResponseBody::RPop(None)),
+                },
+                Ok(None) => Response::new(StatusCode::KeyNotFound, ResponseBody::RPop(None)),
+                Err(e) => Response::new(e.into(), ResponseBody::RPop(None)),
+            },
+            Request::SAdd { key, member } => match member.into_string() {
+                Ok(member) => match self.db.set_add(key.into_inner(), member).await {
+                    Ok(true) => Response::new(StatusCode::Ok, ResponseBody::SAdd),
+                    Ok(false) => Response::new(StatusCode::KeyExists, ResponseBody::SAdd),
+                    Err(e) => Response::new(e.into(), ResponseBody::SAdd),
+                },
+                Err(_) => Response::new(StatusCode::InvalidValue, ResponseBody::SAdd),
+            },
+            Request::SIsMember { key, member } => match member.into_string() {
+                Ok(member) => match self.db.set_contains(&key, member).await {
+                    Ok(true) => Response::new(StatusCode::Ok, ResponseBody::SIsMember),
+                    Ok(false) => Response::new(StatusCode::KeyNotFound, ResponseBody::SIsMember),
+                    Err(e) => Response::new(e.into(), ResponseBody::SIsMember),
+                },
+                Err(_) => Response::new(StatusCode::InvalidValue, ResponseBody::SIsMember),
+            },
+            Request::SRem { key, member } => match member.into_string() {
+                Ok(member) => match self.db.set_remove(&key, member).await {
+                    Ok(true) => Response::new(StatusCode::Ok, ResponseBody::SRem),
+                    Ok(false) => Response::new(StatusCode::KeyNotFound, ResponseBody::SRem),
+                    Err(e) => Response::new(e.into(), ResponseBody::SRem),
+                },
+                Err(_) => Response::new(StatusCode::InvalidValue, ResponseBody::SRem),
+            },
+            Request::KeysGlob(pattern) => {
+                let keys = self.db.keys_matching(&pattern).await;
+                // Each key is prefixed with its length on the wire
+                let encoded_length: usize = keys.iter().map(|key| key.len() + 1).sum();
+                if encoded_length > MAX_VALUE_LENGTH as usize {
+                    return Response::new(
+                        StatusCode::ValueTooLarge,
+                        ResponseBody::KeysGlob(vec![]),
+                    );
+                }
+                match keys.into_iter().map(Key::parse).collect() {
+                    Ok(keys) => Response::new(StatusCode::Ok, ResponseBody::KeysGlob(keys)),
+                    Err(_) => {
+                        Response::new(StatusCode::InternalError, ResponseBody::KeysGlob(vec![]))
+                    }
+                }
             }
         }
     }
+
+    /// Answers a request for `op_code` right away if the server does not carry it out,
+    /// because of the op code or the `keys` of the request.
+    fn refuse(&self, op_code: OpCode, keys: &[Key]) -> Option<Response> {
+        if self
+            .allowed_opcodes
+            .as_ref()
+            .is_some_and(|allowed| !allowed.contains(&op_code))
+        {
+            return Some(Response::new(
+                StatusCode::OperationNotPermitted,
+                ResponseBody::empty(op_code),
+            ));
+        }
+        if self.printable_ascii_keys
+            && !keys.iter().all(|key| {
+                key.bytes()
+                    .all(|byte| byte.is_ascii_graphic() || byte == b' ')
+            })
+        {
+            return Some(Response::new(
+                StatusCode::InvalidKey,
+                ResponseBody::empty(op_code),
+            ));
+        }
+        if let Some(KeyValidator(is_valid)) = &self.key_validator {
+            if !keys.iter().all(|key| is_valid(key)) {
+                return Some(Response::new(
+                    StatusCode::InvalidKey,
+                    ResponseBody::empty(op_code),
+                ));
+            }
+        }
+        None
+    }
+
+    /// Sets the key unless it exists already, returning how that went.
+    async fn set(
+        &self,
+        key: Key,
+        value: Value,
+        ttl_since_unix_epoch_in_millis: Option<u128>,
+        flags: u32,
+    ) -> StatusCode {
+        if self.db.contains_key(&key).await {
+            return StatusCode::KeyExists;
+        }
+        self.db
+            .insert_with_flags(
+                key.into_inner(),
+                value.into_bytes().into(),
+                ttl_since_unix_epoch_in_millis,
+                flags,
+            )
+            .await;
+        StatusCode::Ok
+    }
+
+    /// Adds `delta` to the counter under `key`, returning how that went and the counter afterwards.
+    async fn increment(&self, key: Key, delta: i128) -> (StatusCode, Option<ResponseBodyGet>) {
+        let counter = match self.db.increment(key.to_string(), delta).await {
+            Some(Ok(counter)) => counter,
+            Some(Err(e)) => return (e.into(), None),
+            None => return (StatusCode::InternalError, None),
+        };
+        match Value::parse(counter.value) {
+            Ok(value) => (
+                StatusCode::Ok,
+                Some(ResponseBodyGet {
+                    key,
+                    value,
+                    ttl_since_unix_epoch_in_millis: counter.ttl_since_unix_epoch_in_millis,
+                    flags: counter.flags,
+                    updated_at_in_millis: counter.updated_at_in_millis,
+                }),
+            ),
+            Err(_) => (StatusCode::InternalError, None),
+        }
+    }
+}
+
+impl From<DbError> for StatusCode {
+    fn from(e: DbError) -> Self {
+        match e {
+            DbError::WrongType => StatusCode::WrongType,
+            DbError::ValueTooLarge => StatusCode::ValueTooLarge,
+            DbError::Undecodable => StatusCode::InternalError,
+            DbError::NotAnInteger => StatusCode::InvalidValue,
+            DbError::OutOfRange => StatusCode::OutOfRange,
+        }
+    }
 }
 
 impl Drop for Handler {
     fn drop(&mut self) {
         self.connection_limit.add_permits(1);
+        self.service.metrics.connection_closed();
         #[cfg(feature = "tracing")]
         debug!("Added permit back to connection semaphore.");
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rstest::rstest;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[cfg(target_os = "linux")]
+    #[tokio::test]
+    async fn test_run_reports_the_error_that_stopped_serving() {
+        let server = Server::new().bind("127.0.0.1:0").await.unwrap();
+        // Accepting on a listening socket that was shut down fails right away
+        let listener = server.listener.as_ref().unwrap();
+        socket2::SockRef::from(listener)
+            .shutdown(std::net::Shutdown::Both)
+            .unwrap();
+
+        let reason = tokio::time::timeout(Duration::from_secs(5), server.run())
+            .await
+            .unwrap();
+        assert!(matches!(reason, ShutdownReason::Error(_)), "{reason:?}");
+    }
+
+    #[cfg(target_os = "linux")]
+    #[tokio::test]
+    async fn test_serving_forever_still_stops_once_the_listener_is_shut_down() {
+        let server = Server::new().bind("127.0.0.1:0").await.unwrap();
+        let listener = server.listener.as_ref().unwrap();
+        socket2::SockRef::from(listener)
+            .shutdown(std::net::Shutdown::Both)
+            .unwrap();
+
+        let reason = tokio::time::timeout(Duration::from_secs(5), server.serve_forever())
+            .await
+            .unwrap();
+        assert!(matches!(reason, ShutdownReason::Error(_)), "{reason:?}");
+    }
+
+    /// Accepts `errors` in turn before accepting the connection `1`.
+    fn failing_accept(
+        errors: Vec<io::ErrorKind>,
+    ) -> (
+        impl FnMut() -> std::future::Ready<io::Result<u8>>,
+        Arc<AtomicUsize>,
+    ) {
+        let attempts = Arc::new(AtomicUsize::new(0));
+        let counted = attempts.clone();
+        let accept = move || {
+            let attempt = counted.fetch_add(1, Ordering::SeqCst);
+            std::future::ready(match errors.get(attempt) {
+                Some(kind) => Err(io::Error::from(*kind)),
+                None => Ok(1),
+            })
+        };
+        (accept, attempts)
+    }
+
+    #[rstest]
+    #[case(io::ErrorKind::OutOfMemory)]
+    #[case(io::ErrorKind::ConnectionAborted)]
+    #[case(io::ErrorKind::Other)]
+    #[tokio::test]
+    async fn test_accepting_continues_after_transient_errors(#[case] kind: io::ErrorKind) {
+        let (accept_fn, attempts) = failing_accept(vec![kind; 3]);
+
+        let accepted =
+            tokio::time::timeout(Duration::from_secs(5), accept_retrying(accept_fn, true))
+                .await
+                .unwrap();
+        assert_eq!(accepted.unwrap(), 1);
+        assert_eq!(attempts.load(Ordering::SeqCst), 4);
+    }
+
+    #[tokio::test]
+    async fn test_accepting_stops_on_fatal_errors_or_without_retrying() {
+        let (accept_fn, attempts) = failing_accept(vec![io::ErrorKind::InvalidInput]);
+        let e = accept_retrying(accept_fn, true).await.unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::InvalidInput);
+        assert_eq!(attempts.load(Ordering::SeqCst), 1);
+
+        let (accept_fn, attempts) = failing_accept(vec![io::ErrorKind::OutOfMemory]);
+        let e = accept_retrying(accept_fn, false).await.unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::OutOfMemory);
+        assert_eq!(attempts.load(Ordering::SeqCst), 1);
+    }
+}
@@ -0,0 +1,235 @@
+use crate::domain::{Key, Value};
+use crate::error::{ParseError, Result};
+use crate::parsing::{parse_delta, parse_entries};
+use crate::primitives::{OpCode, StatusCode};
+use crate::protocol::FLUSH_CONFIRMATION;
+use crate::request::{encode_delta, encode_entries, ChunkStep, Request};
+use crate::Error;
+use bytes::Bytes;
+use std::time::{Duration, SystemTime};
+
+/// A request handled by the server, as reported to the tap registered via
+/// [`Server::request_tap`](crate::Server::request_tap).
+///
+/// It holds everything needed to send the request again, e.g. to replay recorded traffic
+/// with a [`ReplayClient`](crate::ReplayClient).
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct RequestLog {
+    pub(crate) received_at: SystemTime,
+    pub(crate) op_code: OpCode,
+    pub(crate) keys: Vec<String>,
+    pub(crate) value: Option<Bytes>,
+    pub(crate) ttl_since_unix_epoch_in_millis: Option<u128>,
+    pub(crate) flags: u32,
+    pub(crate) status: StatusCode,
+    pub(crate) duration: Duration,
+}
+
+impl RequestLog {
+    /// Copies what is needed from `request` before it is handled,
+    /// the status and duration are filled in once it was answered.
+    pub(crate) fn new(request: &Request) -> Self {
+        let mut keys: Vec<String> = request.keys().iter().map(|key| key.to_string()).collect();
+        let (value, ttl_since_unix_epoch_in_millis) = match request {
+            Request::Set {
+                value,
+                ttl_since_unix_epoch_in_millis,
+                ..
+            } => (Some(value.to_bytes()), *ttl_since_unix_epoch_in_millis),
+            Request::SetChunk {
+                chunk,
+                ttl_since_unix_epoch_in_millis,
+                ..
+            } => (
+                chunk.as_ref().map(Value::to_bytes),
+                *ttl_since_unix_epoch_in_millis,
+            ),
+            Request::SetNegative {
+                ttl_since_unix_epoch_in_millis,
+                ..
+            } => (None, *ttl_since_unix_epoch_in_millis),
+            Request::LPush { item: value, .. }
+            | Request::SAdd { member: value, .. }
+            | Request::SIsMember { member: value, .. }
+            | Request::SRem { member: value, .. } => (Some(value.to_bytes()), None),
+            Request::Increment { delta, .. } | Request::Decrement { delta, .. } => (
+                // An `i64` always fits into a value
+                encode_delta(*delta).ok().map(Value::into_bytes),
+                None,
+            ),
+            Request::KeysGlob(pattern) => {
+                keys.push(pattern.to_string());
+                (None, None)
+            }
+            // As on the wire
+            Request::Flush { confirmed: true } => {
+                (Some(Bytes::from_static(FLUSH_CONFIRMATION)), None)
+            }
+            Request::SetMany {
+                keys,
+                values,
+                ttl_since_unix_epoch_in_millis,
+            } => (
+                // Both fit into a frame, so they fit into its value again
+                encode_entries(keys, values)
+                    .ok()
+                    .flatten()
+                    .map(Value::into_bytes),
+                *ttl_since_unix_epoch_in_millis,
+            ),
+            _ => (None, None),
+        };
+        let flags = match request {
+            Request::Set { flags, .. } => *flags,
+            // As on the wire
+            Request::SetChunk { step, .. } => step.flags(),
+            _ => 0,
+        };
+        Self {
+            received_at: SystemTime::now(),
+            op_code: request.op_code(),
+            keys,
+            value,
+            ttl_since_unix_epoch_in_millis,
+            flags,
+            status: StatusCode::Ok,
+            duration: Duration::ZERO,
+        }
+    }
+
+    /// Turns the log back into the request it was made for.
+    pub(crate) fn to_request(&self) -> Result<Request> {
+        let key = || {
+            let key = self
+                .keys
+                .first()
+                .ok_or_else(|| Error::new_parse(ParseError::KeyMissing))?;
+            Key::parse(key.clone())
+        };
+        let value = || {
+            let value = self
+                .value
+                .clone()
+                .ok_or_else(|| Error::new_parse(ParseError::ValueMissing))?;
+            Value::parse(value)
+        };
+        let request = match self.op_code {
+            OpCode::Get => Request::Get(key()?),
+            OpCode::Set => Request::Set {
+                key: key()?,
+                value: value()?,
+                ttl_since_unix_epoch_in_millis: self.ttl_since_unix_epoch_in_millis,
+                flags: self.flags,
+            },
+            OpCode::Delete => Request::Delete(key()?),
+            OpCode::Flush => Request::Flush {
+                confirmed: self.value.as_deref() == Some(FLUSH_CONFIRMATION),
+            },
+            OpCode::ExistsMany => Request::ExistsMany(
+                self.keys
+                    .iter()
+                    .map(|key| Key::parse(key.clone()))
+                    .collect::<Result<_>>()?,
+            ),
+            OpCode::LPush => Request::LPush {
+                key: key()?,
+                item: value()?,
+            },
+            OpCode::RPop => Request::RPop(key()?),
+            OpCode::DeleteReturning => Request::DeleteReturning(key()?),
+            OpCode::Inspect => Request::Inspect(key()?),
+            OpCode::SAdd => Request::SAdd {
+                key: key()?,
+                member: value()?,
+            },
+            OpCode::SIsMember => Request::SIsMember {
+                key: key()?,
+                member: value()?,
+            },
+            OpCode::SRem => Request::SRem {
+                key: key()?,
+                member: value()?,
+            },
+            OpCode::KeysGlob => Request::KeysGlob(key()?),
+            OpCode::SetNegative => Request::SetNegative {
+                key: key()?,
+                ttl_since_unix_epoch_in_millis: self.ttl_since_unix_epoch_in_millis,
+            },
+            OpCode::ConnStats => Request::ConnStats,
+            OpCode::Capabilities => Request::Capabilities,
+            OpCode::Version => Request::Version,
+            OpCode::RandomKey => Request::RandomKey,
+            OpCode::SubscribeEvictions => Request::SubscribeEvictions,
+            OpCode::Increment => Request::Increment {
+                key: key()?,
+                delta: parse_delta(&value()?.into_bytes())?,
+            },
+            OpCode::Decrement => Request::Decrement {
+                key: key()?,
+                delta: parse_delta(&value()?.into_bytes())?,
+            },
+            OpCode::FlushExpired => Request::FlushExpired,
+            OpCode::SetChunk => Request::SetChunk {
+                key: key()?,
+                chunk: self.value.clone().map(Value::parse).transpose()?,
+                ttl_since_unix_epoch_in_millis: self.ttl_since_unix_epoch_in_millis,
+                step: ChunkStep::from_flags(self.flags)?,
+            },
+            OpCode::SetMany => {
+                let (keys, values) = self
+                    .value
+                    .as_deref()
+                    .map_or(Ok((vec![], vec![])), parse_entries)?;
+                Request::SetMany {
+                    keys,
+                    values,
+                    ttl_since_unix_epoch_in_millis: self.ttl_since_unix_epoch_in_millis,
+                }
+            }
+        };
+        Ok(request)
+    }
+
+    /// When the server read the request.
+    pub fn received_at(&self) -> SystemTime {
+        self.received_at
+    }
+
+    /// The operation that was requested.
+    pub fn op_code(&self) -> OpCode {
+        self.op_code
+    }
+
+    /// The keys of the request, or the pattern for [`OpCode::KeysGlob`].
+    pub fn keys(&self) -> &[String] {
+        &self.keys
+    }
+
+    /// The value, list item or set member of the request, if it carried one.
+    ///
+    /// For [`OpCode::SetMany`] it holds the entries as they are sent on the wire.
+    pub fn value(&self) -> Option<&[u8]> {
+        self.value.as_deref()
+    }
+
+    /// The TTL of the request in milliseconds since the unix epoch, if it carried one.
+    pub fn ttl_since_unix_epoch_in_millis(&self) -> Option<u128> {
+        self.ttl_since_unix_epoch_in_millis
+    }
+
+    /// The flags stored with the value of a set request, the step of a chunk of a streamed value
+    /// as on the wire, 0 for any other request.
+    pub fn flags(&self) -> u32 {
+        self.flags
+    }
+
+    /// The status the server answered with.
+    pub fn status(&self) -> StatusCode {
+        self.status
+    }
+
+    /// How long handling and answering the request took.
+    pub fn duration(&self) -> Duration {
+        self.duration
+    }
+}
@@ -0,0 +1,121 @@
+use crate::domain::{Key, Value};
+use crate::primitives::StatusCode;
+use crate::protocol::MAX_VALUE_LENGTH;
+use crate::request::ChunkStep;
+use bytes::BytesMut;
+use std::collections::HashMap;
+
+/// The values streamed in chunks over a connection, see [`OpCode::SetChunk`](crate::OpCode::SetChunk).
+///
+/// Dropping them discards the values not completed yet.
+#[derive(Debug, Default)]
+pub(crate) struct Uploads(HashMap<Key, BytesMut>);
+
+impl Uploads {
+    /// Takes the next chunk of the value streamed for `key`.
+    ///
+    /// Returns the whole value once `step` completes it, `None` while more chunks are to follow
+    /// and after discarding the chunks so far. Fails with [`StatusCode::ValueTooLarge`]
+    /// if the value grows too long, discarding it.
+    pub(crate) fn take(
+        &mut self,
+        key: &Key,
+        chunk: Option<Value>,
+        step: ChunkStep,
+    ) -> Result<Option<Value>, StatusCode> {
+        if let ChunkStep::Abort = step {
+            self.0.remove(key);
+            return Ok(None);
+        }
+        let upload = self.0.entry(key.clone()).or_default();
+        if let Some(chunk) = chunk {
+            if upload.len() + chunk.len() as usize > MAX_VALUE_LENGTH as usize {
+                self.0.remove(key);
+                return Err(StatusCode::ValueTooLarge);
+            }
+            upload.extend_from_slice(chunk.as_bytes());
+        }
+        match step {
+            ChunkStep::Last => {
+                let value = self.0.remove(key).unwrap_or_default().freeze();
+                // Never longer than `MAX_VALUE_LENGTH`, see above
+                Value::parse(value)
+                    .map(Some)
+                    .map_err(|_| StatusCode::ValueTooLarge)
+            }
+            _ => Ok(None),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn key(key: &str) -> Key {
+        Key::parse(key.to_string()).unwrap()
+    }
+
+    fn chunk(chunk: &[u8]) -> Option<Value> {
+        Some(Value::parse(chunk.to_vec()).unwrap())
+    }
+
+    #[test]
+    fn test_chunks_are_joined_once_the_last_one_arrives() {
+        let mut uploads = Uploads::default();
+        assert_eq!(
+            uploads.take(&key("A"), chunk(b"ab"), ChunkStep::More),
+            Ok(None)
+        );
+        assert_eq!(
+            uploads.take(&key("B"), chunk(b"xy"), ChunkStep::More),
+            Ok(None)
+        );
+        assert_eq!(
+            uploads.take(&key("A"), chunk(b"cd"), ChunkStep::More),
+            Ok(None)
+        );
+        let value = uploads
+            .take(&key("A"), None, ChunkStep::Last)
+            .unwrap()
+            .unwrap();
+        assert_eq!(value.as_bytes(), b"abcd");
+        let value = uploads
+            .take(&key("B"), chunk(b"z"), ChunkStep::Last)
+            .unwrap()
+            .unwrap();
+        assert_eq!(value.as_bytes(), b"xyz");
+        assert!(uploads.0.is_empty());
+    }
+
+    #[test]
+    fn test_aborting_discards_the_chunks_so_far() {
+        let mut uploads = Uploads::default();
+        uploads
+            .take(&key("A"), chunk(b"ab"), ChunkStep::More)
+            .unwrap();
+        assert_eq!(uploads.take(&key("A"), None, ChunkStep::Abort), Ok(None));
+        let value = uploads
+            .take(&key("A"), chunk(b"cd"), ChunkStep::Last)
+            .unwrap()
+            .unwrap();
+        assert_eq!(value.as_bytes(), b"cd");
+    }
+
+    #[test]
+    fn test_a_value_growing_too_long_is_discarded() {
+        let mut uploads = Uploads::default();
+        let half = vec![b'a'; MAX_VALUE_LENGTH as usize / 2];
+        uploads
+            .take(&key("A"), chunk(&half), ChunkStep::More)
+            .unwrap();
+        uploads
+            .take(&key("A"), chunk(&half), ChunkStep::More)
+            .unwrap();
+        assert_eq!(
+            uploads.take(&key("A"), chunk(b"a"), ChunkStep::More),
+            Err(StatusCode::ValueTooLarge)
+        );
+        assert!(uploads.0.is_empty());
+    }
+}
@@ -0,0 +1,55 @@
+use cached::{Client, EvictionPolicy, OpCode, Server, ServerConfig, StatusCode};
+use std::collections::HashSet;
+
+#[tokio::test]
+async fn test_a_server_runs_with_a_deserialized_config() {
+    let config: ServerConfig = serde_json::from_str(
+        r#"{
+            "shard_amount": 1,
+            "max_entries": 1,
+            "eviction_policy": "Lru",
+            "require_flush_confirmation": false,
+            "allowed_opcodes": ["Get", "Set", "Flush", "Capabilities"]
+        }"#,
+    )
+    .unwrap();
+    assert_eq!(
+        config,
+        ServerConfig {
+            shard_amount: Some(1),
+            max_entries: Some(1),
+            eviction_policy: Some(EvictionPolicy::Lru),
+            require_flush_confirmation: Some(false),
+            allowed_opcodes: Some(HashSet::from([
+                OpCode::Get,
+                OpCode::Set,
+                OpCode::Flush,
+                OpCode::Capabilities
+            ])),
+            ..Default::default()
+        }
+    );
+
+    let handle = Server::with_config(config)
+        .bind("127.0.0.1:0")
+        .await
+        .unwrap()
+        .spawn();
+    let client = Client::new(handle.local_addr()).await;
+    let capabilities = client.capabilities().await.unwrap();
+    assert_eq!(capabilities.max_entries(), Some(1));
+    assert!(!capabilities.requires_flush_confirmation());
+    assert!(!capabilities.allows(OpCode::Delete));
+
+    client.set("ABC", "1234", None).await.unwrap();
+    client.set("DEF", "5678", None).await.unwrap();
+    assert_eq!(
+        client.get("ABC").await.unwrap().status(),
+        StatusCode::KeyNotFound
+    );
+    assert_eq!(
+        client.get("DEF").await.unwrap().value(),
+        Some(b"5678".as_slice())
+    );
+    assert_eq!(client.flush_confirmed().await.unwrap(), StatusCode::Ok);
+}
@@ -0,0 +1,238 @@
+use cached::{Client, Server, ServerHandle, StatusCode};
+use std::net::SocketAddr;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio::time::timeout;
+
+/// Returns the addresses of the wire protocol and of the HTTP gateway.
+async fn run_test_server() -> (SocketAddr, SocketAddr, ServerHandle) {
+    let server = Server::new()
+        .bind("127.0.0.1:0")
+        .await
+        .unwrap()
+        .bind_http("127.0.0.1:0")
+        .await
+        .unwrap();
+    let address: SocketAddr = format!("127.0.0.1:{}", server.port()).parse().unwrap();
+    let http_address: SocketAddr = format!("127.0.0.1:{}", server.http_port()).parse().unwrap();
+    let handle = server.spawn();
+    (address, http_address, handle)
+}
+
+#[derive(Debug)]
+struct HttpResponse {
+    code: u16,
+    headers: Vec<(String, String)>,
+    body: String,
+}
+
+impl HttpResponse {
+    fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(header, _)| header.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+fn parse_response(response: &str) -> HttpResponse {
+    let (head, body) = response.split_once("\r\n\r\n").unwrap();
+    let mut lines = head.split("\r\n");
+    let code = lines.next().unwrap().split(' ').nth(1).unwrap();
+    let headers = lines
+        .map(|line| {
+            let (name, value) = line.split_once(": ").unwrap();
+            (name.to_string(), value.to_string())
+        })
+        .collect();
+    HttpResponse {
+        code: code.parse().unwrap(),
+        headers,
+        body: body.to_string(),
+    }
+}
+
+/// Sends a single request on its own connection.
+async fn request(
+    address: SocketAddr,
+    method: &str,
+    path: &str,
+    headers: &[(&str, &str)],
+    body: &str,
+) -> HttpResponse {
+    let mut stream = TcpStream::connect(address).await.unwrap();
+    let mut request = format!(
+        "{method} {path} HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\nContent-Length: {}\r\n",
+        body.len()
+    );
+    for (name, value) in headers {
+        request.push_str(&format!("{name}: {value}\r\n"));
+    }
+    request.push_str("\r\n");
+    request.push_str(body);
+    stream.write_all(request.as_bytes()).await.unwrap();
+    let mut response = String::new();
+    timeout(Duration::from_secs(5), stream.read_to_string(&mut response))
+        .await
+        .unwrap()
+        .unwrap();
+    parse_response(&response)
+}
+
+#[tokio::test]
+async fn test_values_can_be_put_got_and_deleted_over_http() {
+    let (_, http_address, _handle) = run_test_server().await;
+
+    let resp = request(http_address, "GET", "/keys/foo", &[], "").await;
+    assert_eq!(resp.code, 404);
+
+    let resp = request(http_address, "PUT", "/keys/foo", &[], "bar").await;
+    assert_eq!(resp.code, 201);
+    let resp = request(http_address, "PUT", "/keys/foo", &[], "baz").await;
+    assert_eq!(resp.code, 409);
+
+    let resp = request(http_address, "GET", "/keys/foo", &[], "").await;
+    assert_eq!(resp.code, 200);
+    assert_eq!(resp.body, "bar");
+    assert_eq!(resp.header("X-Cached-Ttl"), None);
+
+    let resp = request(http_address, "DELETE", "/keys/foo", &[], "").await;
+    assert_eq!(resp.code, 204);
+    let resp = request(http_address, "DELETE", "/keys/foo", &[], "").await;
+    assert_eq!(resp.code, 404);
+    let resp = request(http_address, "GET", "/keys/foo", &[], "").await;
+    assert_eq!(resp.code, 404);
+}
+
+#[tokio::test]
+async fn test_http_and_the_wire_protocol_share_the_cache() {
+    let (address, http_address, _handle) = run_test_server().await;
+    let client = Client::new(address).await;
+
+    client.set("from client", "1", None).await.unwrap();
+    let resp = request(http_address, "GET", "/keys/from%20client", &[], "").await;
+    assert_eq!(resp.code, 200);
+    assert_eq!(resp.body, "1");
+
+    let resp = request(http_address, "PUT", "/keys/from%2Fhttp", &[], "2").await;
+    assert_eq!(resp.code, 201);
+    let resp = client.get("from/http").await.unwrap();
+    assert_eq!(resp.value(), Some(b"2".as_slice()));
+}
+
+#[tokio::test]
+async fn test_values_put_over_http_expire_after_their_ttl() {
+    let (address, http_address, _handle) = run_test_server().await;
+    let client = Client::new(address).await;
+
+    let resp = request(
+        http_address,
+        "PUT",
+        "/keys/long",
+        &[("X-Cached-Ttl", "60000")],
+        "1",
+    )
+    .await;
+    assert_eq!(resp.code, 201);
+    let resp = request(http_address, "GET", "/keys/long", &[], "").await;
+    let ttl: u64 = resp.header("X-Cached-Ttl").unwrap().parse().unwrap();
+    assert!(ttl > 50_000 && ttl <= 60_000, "{ttl}");
+    let remaining = client.get("long").await.unwrap().remaining_ttl().unwrap();
+    assert!(remaining <= Duration::from_secs(60));
+
+    let resp = request(
+        http_address,
+        "PUT",
+        "/keys/short",
+        &[("X-Cached-Ttl", "10")],
+        "1",
+    )
+    .await;
+    assert_eq!(resp.code, 201);
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    let resp = request(http_address, "GET", "/keys/short", &[], "").await;
+    assert_eq!(resp.code, 404);
+
+    let resp = request(
+        http_address,
+        "PUT",
+        "/keys/invalid",
+        &[("X-Cached-Ttl", "soon")],
+        "1",
+    )
+    .await;
+    assert_eq!(resp.code, 400);
+    assert_eq!(
+        client.get("invalid").await.unwrap().status(),
+        StatusCode::KeyNotFound
+    );
+}
+
+#[tokio::test]
+async fn test_invalid_http_requests_are_refused() {
+    let (_, http_address, _handle) = run_test_server().await;
+
+    let resp = request(http_address, "POST", "/keys/foo", &[], "bar").await;
+    assert_eq!(resp.code, 405);
+    assert_eq!(resp.header("Allow"), Some("GET, PUT, DELETE"));
+    let resp = request(http_address, "GET", "/values/foo", &[], "").await;
+    assert_eq!(resp.code, 404);
+    let resp = request(http_address, "GET", "/keys/", &[], "").await;
+    assert_eq!(resp.code, 400);
+    let long_key = "k".repeat(u8::MAX as usize + 1);
+    let resp = request(http_address, "GET", &format!("/keys/{long_key}"), &[], "").await;
+    assert_eq!(resp.code, 400);
+
+    let mut stream = TcpStream::connect(http_address).await.unwrap();
+    stream
+        .write_all(b"PUT /keys/foo HTTP/1.1\r\nContent-Length: 99999999999\r\n\r\n")
+        .await
+        .unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).await.unwrap();
+    assert_eq!(parse_response(&response).code, 413);
+}
+
+#[tokio::test]
+async fn test_http_connections_are_kept_alive_between_requests() {
+    let (_, http_address, _handle) = run_test_server().await;
+    let mut stream = TcpStream::connect(http_address).await.unwrap();
+
+    // Both requests are sent at once, the answers come back in order
+    stream
+        .write_all(
+            b"PUT /keys/foo HTTP/1.1\r\nContent-Length: 3\r\n\r\nbar\
+              GET /keys/foo HTTP/1.1\r\nConnection: close\r\n\r\n",
+        )
+        .await
+        .unwrap();
+    let mut response = String::new();
+    timeout(Duration::from_secs(5), stream.read_to_string(&mut response))
+        .await
+        .unwrap()
+        .unwrap();
+    let (put, get) = response.split_at(response.find("HTTP/1.1 200").unwrap());
+    assert_eq!(parse_response(put).code, 201);
+    let get = parse_response(get);
+    assert_eq!(get.code, 200);
+    assert_eq!(get.body, "bar");
+}
+
+#[tokio::test]
+async fn test_shutting_down_closes_idle_http_connections() {
+    let (_, http_address, handle) = run_test_server().await;
+    let mut stream = TcpStream::connect(http_address).await.unwrap();
+    stream
+        .write_all(b"PUT /keys/foo HTTP/1.1\r\nContent-Length: 3\r\n\r\nbar")
+        .await
+        .unwrap();
+    let mut buf = [0; 1024];
+    assert!(stream.read(&mut buf).await.unwrap() > 0);
+
+    timeout(Duration::from_secs(5), handle.shutdown())
+        .await
+        .unwrap();
+    assert_eq!(stream.read(&mut buf).await.unwrap(), 0);
+    assert!(TcpStream::connect(http_address).await.is_err());
+}
@@ -1,6 +1,14 @@
-use cached::{Client, Server, StatusCode};
+use cached::protocol::{Frame, CHUNK_LAST_FLAG, MAX_VALUE_LENGTH};
+use cached::{
+    BatchOp, Client, ClientConnection, EvictionPolicy, EvictionReason, Memoized, OpCode,
+    ReplayClient, RequestLog, Resolver, RetryPolicy, Server, ShutdownReason, StatusCode, TtlState,
+};
+use std::collections::{HashMap, HashSet};
 use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
 use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
 use tokio::time::timeout;
 
 async fn run_test_server() -> SocketAddr {
@@ -17,6 +25,18 @@ async fn run_test_server() -> SocketAddr {
         .expect("Could not parse address as SocketAddr")
 }
 
+/// Runs a server whose keys expire by tokio's clock,
+/// so tests running with paused time control when they expire.
+async fn run_test_server_on_tokio_time() -> SocketAddr {
+    Server::new()
+        .expire_by_tokio_time(true)
+        .bind("127.0.0.1:0")
+        .await
+        .unwrap()
+        .spawn()
+        .local_addr()
+}
+
 #[tokio::test]
 async fn test_getting_a_non_existing_key_fails() {
     let address = run_test_server().await;
@@ -44,7 +64,7 @@ async fn test_setting_a_key_works() {
 
     let resp = client.get(key.clone()).await.unwrap();
     assert_eq!(resp.status(), StatusCode::Ok);
-    assert_eq!(resp.value(), Some(&value));
+    assert_eq!(resp.value(), Some(value.as_bytes()));
     assert_eq!(resp.ttl_since_unix_epoch_in_millis(), None);
 }
 
@@ -72,10 +92,34 @@ async fn test_setting_a_key_with_ttl_in_the_future_works() {
 
     let resp = client.get(key.clone()).await.unwrap();
     assert_eq!(resp.status(), StatusCode::Ok);
-    assert_eq!(resp.value(), Some(&value));
+    assert_eq!(resp.value(), Some(value.as_bytes()));
     assert_eq!(resp.ttl_since_unix_epoch_in_millis(), Some(ttl));
 }
 
+#[tokio::test]
+async fn test_get_responses_tell_apart_values_without_ttl_and_missing_values() {
+    let address = run_test_server().await;
+    let client = Client::new(address).await;
+    let ttl = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_millis()
+        + 60_000;
+
+    client.set("forever", "1234", None).await.unwrap();
+    client.set("expiring", "1234", Some(ttl)).await.unwrap();
+
+    assert_eq!(client.get("forever").await.unwrap().ttl(), TtlState::NoTtl);
+    assert_eq!(
+        client.get("expiring").await.unwrap().ttl(),
+        TtlState::ExpiresAt(ttl)
+    );
+    assert_eq!(
+        client.get("missing").await.unwrap().ttl(),
+        TtlState::Unknown
+    );
+}
+
 #[tokio::test]
 async fn test_setting_a_key_with_ttl_in_the_past_works() {
     let address = run_test_server().await;
@@ -104,9 +148,219 @@ async fn test_setting_a_key_with_ttl_in_the_past_works() {
 }
 
 #[tokio::test]
-async fn test_setting_a_key_with_ttl_in_the_future_works_and_then_expires() {
+async fn test_setting_a_key_until_a_time_works() {
+    let address = run_test_server().await;
+    let client = Client::new(address).await;
+
+    let expires_at = SystemTime::now() + Duration::from_secs(60);
+    let resp = client.set_until("ABC", "1234", expires_at).await.unwrap();
+    assert_eq!(resp, StatusCode::Ok);
+
+    let resp = client.get("ABC").await.unwrap();
+    assert_eq!(resp.value(), Some(b"1234".as_slice()));
+    assert_eq!(
+        resp.ttl_since_unix_epoch_in_millis(),
+        Some(expires_at.duration_since(UNIX_EPOCH).unwrap().as_millis())
+    );
+}
+
+#[tokio::test]
+async fn test_full_responses_to_set_delete_and_flush_match_their_status() {
+    let address = run_test_server().await;
+    let client = Client::new(address).await;
+
+    let resp = client.set_response("ABC", "1234", None).await.unwrap();
+    assert_eq!(resp.status(), StatusCode::Ok);
+    assert_eq!(resp.op_code(), OpCode::Set);
+    assert!(resp.is_ok());
+    let resp = client.set_response("ABC", "5678", None).await.unwrap();
+    assert_eq!(resp.status(), StatusCode::KeyExists);
+    assert!(!resp.is_ok());
+
+    let resp = client.delete_response("ABC").await.unwrap();
+    assert_eq!(resp.status(), StatusCode::Ok);
+    assert_eq!(resp.op_code(), OpCode::Delete);
+    let resp = client.delete_response("ABC").await.unwrap();
+    assert_eq!(resp.status(), StatusCode::KeyNotFound);
+    assert_eq!(resp.op_code(), OpCode::Delete);
+
+    let resp = client.flush_response(false).await.unwrap();
+    assert_eq!(resp.status(), StatusCode::OperationNotPermitted);
+    assert_eq!(resp.op_code(), OpCode::Flush);
+    let resp = client.flush_response(true).await.unwrap();
+    assert_eq!(resp.status(), StatusCode::Ok);
+    assert_eq!(resp.op_code(), OpCode::Flush);
+}
+
+#[tokio::test]
+async fn test_values_round_trip_arbitrary_bytes() {
+    let address = run_test_server().await;
+    let client = Client::new(address).await;
+    // The start of a gzip stream, not valid UTF-8
+    let blob = vec![0x1f, 0x8b, 0x08, 0x00, 0xff, 0xfe, 0x00, 0x80];
+
+    assert_eq!(
+        client.set("ABC", blob.clone(), None).await.unwrap(),
+        StatusCode::Ok
+    );
+    let resp = client.get("ABC").await.unwrap();
+    assert_eq!(resp.status(), StatusCode::Ok);
+    assert_eq!(resp.value(), Some(blob.as_slice()));
+    assert!(resp.value_str().unwrap().is_err());
+    assert_eq!(resp.into_value(), Some(blob));
+}
+
+#[tokio::test]
+async fn test_setting_a_key_until_a_time_in_the_past_stores_nothing() {
+    let address = run_test_server().await;
+    let client = Client::new(address).await;
+
+    let expired_at = SystemTime::now() - Duration::from_millis(1);
+    let resp = client.set_until("ABC", "1234", expired_at).await.unwrap();
+    assert_eq!(resp, StatusCode::Ok);
+    // Even before the unix epoch
+    let resp = client
+        .set_until("DEF", "1234", UNIX_EPOCH - Duration::from_secs(1))
+        .await
+        .unwrap();
+    assert_eq!(resp, StatusCode::Ok);
+
+    let resp = client.get("ABC").await.unwrap();
+    assert_eq!(resp.status(), StatusCode::KeyNotFound);
+    let resp = client.get("DEF").await.unwrap();
+    assert_eq!(resp.status(), StatusCode::KeyNotFound);
+}
+
+#[tokio::test]
+async fn test_a_ttl_at_the_unix_epoch_is_distinct_from_no_ttl() {
+    let address = run_test_server().await;
+    let client = Client::new(address).await;
+
+    // The unix epoch itself has long passed, so the value expires right away
+    let resp = client.set("epoch", "1234", Some(0)).await.unwrap();
+    assert_eq!(resp, StatusCode::Ok);
+    let resp = client.get("epoch").await.unwrap();
+    assert_eq!(resp.status(), StatusCode::KeyNotFound);
+
+    let resp = client.set("never", "1234", None).await.unwrap();
+    assert_eq!(resp, StatusCode::Ok);
+    let resp = client.get("never").await.unwrap();
+    assert_eq!(resp.status(), StatusCode::Ok);
+    assert_eq!(resp.ttl_since_unix_epoch_in_millis(), None);
+}
+
+#[tokio::test]
+async fn test_setting_through_a_client_with_default_ttl_applies_the_ttl() {
     let address = run_test_server().await;
     let client = Client::new(address).await;
+    let default_ttl = Duration::from_secs(5 * 60);
+    let expiring = client.with_default_ttl(default_ttl);
+    let now = || {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_millis()
+    };
+
+    let before = now();
+    let resp = expiring.set("expiring", "1234", None).await.unwrap();
+    assert_eq!(resp, StatusCode::Ok);
+    let after = now();
+    let ttl = expiring
+        .client()
+        .get("expiring")
+        .await
+        .unwrap()
+        .ttl_since_unix_epoch_in_millis()
+        .unwrap();
+    assert!(ttl >= before + default_ttl.as_millis());
+    assert!(ttl <= after + default_ttl.as_millis());
+
+    // Every way of setting values applies the default
+    expiring
+        .set_with_flags("flagged", "1234", None, 7)
+        .await
+        .unwrap();
+    expiring
+        .set_response("responded", "1234", None)
+        .await
+        .unwrap();
+    expiring
+        .set_from_reader("read", "1234".as_bytes(), None)
+        .await
+        .unwrap();
+    expiring
+        .set_many_pipelined([("pipelined", "1234")], None)
+        .await
+        .pop()
+        .unwrap()
+        .1
+        .unwrap();
+    expiring
+        .set_many_ttl([("many", "1234")], None)
+        .await
+        .unwrap();
+    expiring
+        .batch_transaction([BatchOp::set("batched", "1234", None)])
+        .await
+        .unwrap();
+    expiring
+        .set_retry("retried", "1234", None, RetryPolicy::default())
+        .await
+        .unwrap();
+    for key in [
+        "flagged",
+        "responded",
+        "read",
+        "pipelined",
+        "many",
+        "batched",
+        "retried",
+    ] {
+        let ttl = client
+            .get(key)
+            .await
+            .unwrap()
+            .ttl_since_unix_epoch_in_millis();
+        assert!(
+            ttl.is_some_and(|ttl| ttl >= before + default_ttl.as_millis()),
+            "{key}"
+        );
+    }
+
+    // An explicit TTL takes precedence over the default
+    let explicit_ttl = now() + 1000;
+    expiring
+        .set("explicit", "1234", Some(explicit_ttl))
+        .await
+        .unwrap();
+    let resp = client.get("explicit").await.unwrap();
+    assert_eq!(resp.ttl_since_unix_epoch_in_millis(), Some(explicit_ttl));
+
+    // The underlying client is unaffected
+    client.set("plain", "1234", None).await.unwrap();
+    let resp = client.get("plain").await.unwrap();
+    assert_eq!(resp.ttl_since_unix_epoch_in_millis(), None);
+
+    // A default TTL beyond what the system clock can represent is refused
+    let forever = client.with_default_ttl(Duration::MAX);
+    let err = forever.set("forever", "1234", None).await.unwrap_err();
+    assert_eq!(err.to_string(), "ttl out of range");
+    let err = forever
+        .set_many_ttl([("forever", "1234")], None)
+        .await
+        .unwrap_err();
+    assert_eq!(err.to_string(), "ttl out of range");
+    assert_eq!(
+        client.get("forever").await.unwrap().status(),
+        StatusCode::KeyNotFound
+    );
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_setting_a_key_with_ttl_in_the_future_works_and_then_expires() {
+    let address = run_test_server_on_tokio_time().await;
+    let client = Client::new(address).await;
 
     let key = "ABC".to_string();
     let value = "1234".to_string();
@@ -118,7 +372,7 @@ async fn test_setting_a_key_with_ttl_in_the_future_works_and_then_expires() {
         .duration_since(UNIX_EPOCH)
         .unwrap()
         .as_millis()
-        + 100;
+        + 60_000;
     let resp = client
         .set(key.clone(), value.clone(), Some(ttl))
         .await
@@ -127,10 +381,11 @@ async fn test_setting_a_key_with_ttl_in_the_future_works_and_then_expires() {
 
     let resp = client.get(key.clone()).await.unwrap();
     assert_eq!(resp.status(), StatusCode::Ok);
-    assert_eq!(resp.value(), Some(&value));
+    assert_eq!(resp.value(), Some(value.as_bytes()));
     assert_eq!(resp.ttl_since_unix_epoch_in_millis(), Some(ttl));
 
-    tokio::time::sleep(Duration::from_millis(110)).await;
+    // Time is paused, so this returns right away
+    tokio::time::sleep(Duration::from_secs(120)).await;
 
     let resp = client.get(key).await.unwrap();
     assert_eq!(resp.status(), StatusCode::KeyNotFound);
@@ -153,7 +408,7 @@ async fn test_setting_the_same_key_twice_fails() {
 
     let resp = client.get(key.clone()).await.unwrap();
     assert_eq!(resp.status(), StatusCode::Ok);
-    assert_eq!(resp.value(), Some(&value));
+    assert_eq!(resp.value(), Some(value.as_bytes()));
     assert_eq!(resp.ttl_since_unix_epoch_in_millis(), None);
 
     let resp = client.set(key, value, None).await.unwrap();
@@ -176,7 +431,7 @@ async fn test_deleting_a_key_works() {
 
     let resp = client.get(key.clone()).await.unwrap();
     assert_eq!(resp.status(), StatusCode::Ok);
-    assert_eq!(resp.value(), Some(&value));
+    assert_eq!(resp.value(), Some(value.as_bytes()));
     assert_eq!(resp.ttl_since_unix_epoch_in_millis(), None);
 
     let resp = client.delete(key.clone()).await.unwrap();
@@ -187,6 +442,75 @@ async fn test_deleting_a_key_works() {
     assert!(resp.value().is_none());
 }
 
+#[tokio::test]
+async fn test_deleting_a_key_can_return_its_value() {
+    let address = run_test_server().await;
+    let client = Client::new(address).await;
+
+    client.set("ABC", "1234", None).await.unwrap();
+
+    assert_eq!(
+        client.delete_returning("ABC").await.unwrap(),
+        Some("1234".to_string())
+    );
+    let resp = client.get("ABC").await.unwrap();
+    assert_eq!(resp.status(), StatusCode::KeyNotFound);
+    // Nothing is left to delete
+    assert_eq!(client.delete_returning("ABC").await.unwrap(), None);
+
+    client.push("list", "item").await.unwrap();
+    let err = client.delete_returning("list").await.unwrap_err();
+    assert_eq!(err.status(), Some(StatusCode::WrongType));
+    assert_eq!(client.pop("list").await.unwrap(), Some("item".to_string()));
+}
+
+#[tokio::test]
+async fn test_values_keep_the_flags_they_were_set_with() {
+    let address = run_test_server().await;
+    let client = Client::new(address).await;
+
+    client
+        .set_with_flags("ABC", "1234", None, u32::MAX)
+        .await
+        .unwrap();
+    let resp = client.get("ABC").await.unwrap();
+    assert_eq!(resp.value(), Some(b"1234".as_slice()));
+    assert_eq!(resp.flags(), u32::MAX);
+
+    // Setting the value again without flags clears them
+    client.delete("ABC").await.unwrap();
+    client.set("ABC", "5678", None).await.unwrap();
+    assert_eq!(client.get("ABC").await.unwrap().flags(), 0);
+}
+
+#[tokio::test]
+async fn test_get_returns_when_the_value_was_last_set() {
+    let address = run_test_server().await;
+    let client = Client::new(address).await;
+    let now = || {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_millis()
+    };
+
+    let before = now();
+    client.set("ABC", "1234", None).await.unwrap();
+    let set_at = client.get("ABC").await.unwrap().updated_at_in_millis();
+    assert!(set_at.is_some_and(|set_at| (before..=now()).contains(&set_at)));
+
+    tokio::time::sleep(Duration::from_millis(10)).await;
+    client.delete("ABC").await.unwrap();
+    client.set("ABC", "5678", None).await.unwrap();
+    let set_again_at = client.get("ABC").await.unwrap().updated_at_in_millis();
+    assert!(set_again_at > set_at);
+
+    assert_eq!(
+        client.get("missing").await.unwrap().updated_at_in_millis(),
+        None
+    );
+}
+
 #[tokio::test]
 async fn test_deleting_a_non_existing_key_fails() {
     let address = run_test_server().await;
@@ -214,10 +538,10 @@ async fn test_flushing_works() {
 
     let resp = client.get(key.clone()).await.unwrap();
     assert_eq!(resp.status(), StatusCode::Ok);
-    assert_eq!(resp.value(), Some(&value));
+    assert_eq!(resp.value(), Some(value.as_bytes()));
     assert_eq!(resp.ttl_since_unix_epoch_in_millis(), None);
 
-    let resp = client.flush().await.unwrap();
+    let resp = client.flush_confirmed().await.unwrap();
     assert_eq!(resp, StatusCode::Ok);
 
     let resp = client.get(key.clone()).await.unwrap();
@@ -226,55 +550,2355 @@ async fn test_flushing_works() {
 }
 
 #[tokio::test]
-async fn test_setting_and_getting_keys_concurrently_works() {
+async fn test_set_many_pipelined_reports_failures_per_entry() {
     let address = run_test_server().await;
     let client = Client::new(address).await;
+    let oversized_value = "x".repeat(1024 * 1024 + 1);
 
-    let key_1 = "ABC".to_string();
-    let key_2 = "DEF".to_string();
-    let value_1 = "1234".to_string();
-    let value_2 = "5678".to_string();
-    let (resp_1, resp_2) = tokio::join!(
-        client.set(key_1.clone(), value_1.clone(), None),
-        client.set(key_2.clone(), value_2.clone(), None)
+    let results = client
+        .set_many_pipelined(
+            [
+                ("A", "1"),
+                ("B", oversized_value.as_str()),
+                ("C", "3"),
+                ("A", "4"),
+            ],
+            None,
+        )
+        .await;
+
+    let keys: Vec<_> = results.iter().map(|(key, _)| key.as_str()).collect();
+    assert_eq!(keys, vec!["A", "B", "C", "A"]);
+    assert_eq!(results[0].1.as_ref().unwrap(), &StatusCode::Ok);
+    assert!(results[1].1.is_err());
+    assert_eq!(results[2].1.as_ref().unwrap(), &StatusCode::Ok);
+    assert_eq!(results[3].1.as_ref().unwrap(), &StatusCode::KeyExists);
+
+    assert_eq!(
+        client.get("A").await.unwrap().value(),
+        Some(b"1".as_slice())
     );
-    assert_eq!(resp_1.unwrap(), StatusCode::Ok);
-    assert_eq!(resp_2.unwrap(), StatusCode::Ok);
+    assert_eq!(
+        client.get("B").await.unwrap().status(),
+        StatusCode::KeyNotFound
+    );
+    assert_eq!(
+        client.get("C").await.unwrap().value(),
+        Some(b"3".as_slice())
+    );
+}
 
-    let (resp_1, resp_2) = tokio::join!(client.get(key_1.clone()), client.get(key_2.clone()));
-    let resp_1 = resp_1.unwrap();
-    let resp_2 = resp_2.unwrap();
-    assert_eq!(resp_1.status(), StatusCode::Ok);
-    assert_eq!(resp_1.value(), Some(&value_1));
-    assert_eq!(resp_1.ttl_since_unix_epoch_in_millis(), None);
+#[tokio::test(start_paused = true)]
+async fn test_entries_set_with_a_shared_ttl_expire_together() {
+    let address = run_test_server_on_tokio_time().await;
+    let client = Client::new(address).await;
+    client.set("B", "existing", None).await.unwrap();
+    let ttl = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_millis()
+        + 60_000;
 
-    assert_eq!(resp_2.status(), StatusCode::Ok);
-    assert_eq!(resp_2.value(), Some(&value_2));
-    assert_eq!(resp_2.ttl_since_unix_epoch_in_millis(), None);
+    let statuses = client
+        .set_many_ttl([("A", "1"), ("B", "2"), ("C", "3")], Some(ttl))
+        .await
+        .unwrap();
+    assert_eq!(
+        statuses,
+        vec![StatusCode::Ok, StatusCode::KeyExists, StatusCode::Ok]
+    );
+    for key in ["A", "C"] {
+        let resp = client.get(key).await.unwrap();
+        assert_eq!(resp.ttl_since_unix_epoch_in_millis(), Some(ttl));
+    }
+    let resp = client.get("B").await.unwrap();
+    assert_eq!(resp.value(), Some(b"existing".as_slice()));
+    assert_eq!(resp.ttl_since_unix_epoch_in_millis(), None);
+
+    tokio::time::sleep(Duration::from_secs(120)).await;
+    assert_eq!(
+        client.exists_many(["A", "B", "C"]).await.unwrap(),
+        vec![false, true, false]
+    );
+
+    // The entries must fit into a single frame
+    let value = "x".repeat(1024 * 1024 / 2);
+    let error = client
+        .set_many_ttl([("D", value.as_str()), ("E", value.as_str())], None)
+        .await
+        .unwrap_err();
+    assert!(error.status().is_none(), "{error:?}");
+    assert!(client
+        .set_many_ttl(Vec::<(String, String)>::new(), None)
+        .await
+        .unwrap()
+        .is_empty());
 }
 
 #[tokio::test]
-async fn test_max_connections_limit() {
+async fn test_a_failing_batch_transaction_leaves_the_cache_unchanged() {
     let address = run_test_server().await;
-    let client_1 = Client::new(address).await;
-    let client_2 = Client::new(address).await;
+    let client = Client::new(address).await;
+    let ttl = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_millis()
+        + 60_000;
+    client
+        .set_with_flags("existing", "1234", Some(ttl), 7)
+        .await
+        .unwrap();
+    client.set("taken", "5678", None).await.unwrap();
 
-    let key = "ABC".to_string();
-    let resp = client_1.get(key.clone()).await.unwrap();
-    assert_eq!(resp.status(), StatusCode::KeyNotFound);
-    assert!(resp.value().is_none());
+    let error = client
+        .batch_transaction([
+            BatchOp::set("new", "1", None),
+            BatchOp::delete("existing"),
+            BatchOp::set("taken", "2", None),
+            BatchOp::set("never applied", "3", None),
+        ])
+        .await
+        .unwrap_err();
+    assert_eq!(error.status(), Some(StatusCode::KeyExists));
+    assert_eq!(
+        client.exists_many(["new", "never applied"]).await.unwrap(),
+        vec![false, false]
+    );
+    let existing = client.get("existing").await.unwrap();
+    assert_eq!(existing.value(), Some(b"1234".as_slice()));
+    assert_eq!(existing.ttl_since_unix_epoch_in_millis(), Some(ttl));
+    assert_eq!(existing.flags(), 7);
+    assert_eq!(
+        client.get("taken").await.unwrap().value(),
+        Some(b"5678".as_slice())
+    );
 
-    // We're expecting a timeout here as only one connection is allowed
-    assert!(
-        timeout(Duration::from_millis(100), client_2.get(key.clone()))
+    client
+        .batch_transaction([BatchOp::set("new", "1", None), BatchOp::delete("existing")])
+        .await
+        .unwrap();
+    assert_eq!(
+        client.exists_many(["new", "existing"]).await.unwrap(),
+        vec![true, false]
+    );
+}
+
+#[tokio::test]
+async fn test_flushing_expired_keys_leaves_live_keys_alone() {
+    let handle = Server::new()
+        .sweep_interval(Duration::from_secs(3600))
+        .bind("127.0.0.1:0")
+        .await
+        .unwrap()
+        .spawn();
+    let mut evictions = handle.subscribe_evictions();
+    let client = Client::new(handle.local_addr()).await;
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_millis();
+    for i in 0..5 {
+        client
+            .set(format!("expired-{i}"), "1".to_string(), Some(now + 50))
             .await
-            .is_err()
+            .unwrap();
+    }
+    client.set("live", "1", None).await.unwrap();
+    client
+        .set("live with ttl", "1", Some(now + 60_000))
+        .await
+        .unwrap();
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    assert_eq!(client.flush_expired().await.unwrap(), 5);
+    assert_eq!(client.flush_expired().await.unwrap(), 0);
+    assert_eq!(
+        client
+            .exists_many(["live", "live with ttl", "expired-0"])
+            .await
+            .unwrap(),
+        vec![true, true, false]
     );
+    let batch = timeout(Duration::from_secs(2), evictions.recv())
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(batch.reason, EvictionReason::Expired);
+    assert_eq!(batch.keys.len(), 5);
+    assert_eq!(handle.metrics().evictions(), 5);
+}
 
-    // Once client 1 frees up the connection, client 2 should be able to connect
-    drop(client_1);
+#[tokio::test]
+async fn test_values_larger_than_the_threshold_are_read_back_unchanged() {
+    let handle = Server::new()
+        .large_value_threshold(16)
+        .bind("127.0.0.1:0")
+        .await
+        .unwrap()
+        .spawn();
+    let client = Client::new(handle.local_addr()).await;
+    let large_value = "äöü".repeat(100);
+    client
+        .set("large", large_value.as_str(), None)
+        .await
+        .unwrap();
+    client.set("small", "small", None).await.unwrap();
 
-    let resp = client_2.get(key.clone()).await.unwrap();
+    for _ in 0..2 {
+        let resp = client.get("large").await.unwrap();
+        assert_eq!(resp.value(), Some(large_value.as_bytes()));
+    }
+    assert_eq!(
+        client.get("small").await.unwrap().value(),
+        Some(b"small".as_slice())
+    );
+    assert_eq!(
+        client.delete_returning("large").await.unwrap(),
+        Some(large_value)
+    );
+}
+
+#[tokio::test]
+#[allow(deprecated)]
+async fn test_unconfirmed_flushing_is_rejected() {
+    let address = run_test_server().await;
+    let client = Client::new(address).await;
+    client.set("ABC", "1234", None).await.unwrap();
+
+    let resp = client.flush().await.unwrap();
+    assert_eq!(resp, StatusCode::OperationNotPermitted);
+
+    let resp = client.get("ABC").await.unwrap();
+    assert_eq!(resp.status(), StatusCode::Ok);
+}
+
+#[tokio::test]
+async fn test_checked_flushing_is_confirmed_and_clears_the_cache() {
+    let address = run_test_server().await;
+    let client = Client::new(address).await;
+    client.set("ABC", "1234", None).await.unwrap();
+
+    client.flush_checked().await.unwrap();
+    let resp = client.get("ABC").await.unwrap();
     assert_eq!(resp.status(), StatusCode::KeyNotFound);
-    assert!(resp.value().is_none());
+}
+
+#[tokio::test]
+#[allow(deprecated)]
+async fn test_unconfirmed_flushing_works_when_the_server_permits_it() {
+    let handle = Server::new()
+        .require_flush_confirmation(false)
+        .bind("127.0.0.1:0")
+        .await
+        .unwrap()
+        .spawn();
+    let client = Client::new(handle.local_addr()).await;
+    client.set("ABC", "1234", None).await.unwrap();
+
+    let resp = client.flush().await.unwrap();
+    assert_eq!(resp, StatusCode::Ok);
+    let resp = client.get("ABC").await.unwrap();
+    assert_eq!(resp.status(), StatusCode::KeyNotFound);
+}
+
+#[tokio::test]
+async fn test_only_allowed_opcodes_are_carried_out() {
+    let handle = Server::new()
+        .allowed_opcodes(HashSet::from([OpCode::Get, OpCode::Set]))
+        .bind("127.0.0.1:0")
+        .await
+        .unwrap()
+        .spawn();
+    let client = Client::new(handle.local_addr()).await;
+
+    let resp = client.set("ABC", "1234", None).await.unwrap();
+    assert_eq!(resp, StatusCode::Ok);
+    let resp = client.get("ABC").await.unwrap();
+    assert_eq!(resp.status(), StatusCode::Ok);
+
+    let resp = client.delete("ABC").await.unwrap();
+    assert_eq!(resp, StatusCode::OperationNotPermitted);
+    let resp = client.flush_confirmed().await.unwrap();
+    assert_eq!(resp, StatusCode::OperationNotPermitted);
+    let error = client.exists_many(["ABC"]).await.unwrap_err();
+    assert_eq!(error.status(), Some(StatusCode::OperationNotPermitted));
+    let resp = client.push("list", "1234").await.unwrap();
+    assert_eq!(resp, StatusCode::OperationNotPermitted);
+
+    // Nothing was deleted
+    let resp = client.get("ABC").await.unwrap();
+    assert_eq!(resp.status(), StatusCode::Ok);
+}
+
+#[tokio::test]
+async fn test_warming_seeds_the_cache_from_a_file() {
+    let path = std::env::temp_dir().join(format!("cached-warm-{}.tsv", std::process::id()));
+    std::fs::write(&path, "foo\tbar\nmalformed\nbaz\tqux\n").unwrap();
+    let handle = Server::new()
+        .warm_from(&path)
+        .bind("127.0.0.1:0")
+        .await
+        .unwrap()
+        .spawn();
+    let client = Client::new(handle.local_addr()).await;
+
+    let resp = client.get("foo").await.unwrap();
+    assert_eq!(resp.value(), Some(b"bar".as_slice()));
+    assert_eq!(resp.ttl_since_unix_epoch_in_millis(), None);
+    let resp = client.get("baz").await.unwrap();
+    assert_eq!(resp.value(), Some(b"qux".as_slice()));
+    assert_eq!(
+        client.exists_many(["malformed"]).await.unwrap(),
+        vec![false]
+    );
+    std::fs::remove_file(path).unwrap();
+}
+
+#[tokio::test]
+async fn test_a_value_streamed_from_a_file_reads_back_in_full() {
+    let address = run_test_server().await;
+    let client = Client::new(address).await;
+    let path = std::env::temp_dir().join(format!("cached-reader-{}.txt", std::process::id()));
+    // Several chunks, the last one coming up short
+    let value = "0123456789abcdef".repeat(60 * 1024 + 3);
+    std::fs::write(&path, &value).unwrap();
+
+    let file = tokio::fs::File::open(&path).await.unwrap();
+    let resp = client
+        .set_from_reader(String::from("ABC"), file, None)
+        .await
+        .unwrap();
+    assert_eq!(resp, StatusCode::Ok);
+    assert_eq!(
+        client.get("ABC").await.unwrap().value(),
+        Some(value.as_bytes())
+    );
+    std::fs::remove_file(path).unwrap();
+}
+
+struct FailingReader;
+
+impl tokio::io::AsyncRead for FailingReader {
+    fn poll_read(
+        self: std::pin::Pin<&mut Self>,
+        _cx: &mut std::task::Context<'_>,
+        _buf: &mut tokio::io::ReadBuf<'_>,
+    ) -> std::task::Poll<std::io::Result<()>> {
+        std::task::Poll::Ready(Err(std::io::ErrorKind::BrokenPipe.into()))
+    }
+}
+
+#[tokio::test]
+async fn test_a_reader_failing_midway_leaves_no_value_behind() {
+    let address = run_test_server().await;
+    let client = Client::new(address).await;
+
+    // Failing after some chunks were sent already
+    let partial = vec![b'a'; 200 * 1024];
+    let reader = AsyncReadExt::chain(partial.as_slice(), FailingReader);
+    assert!(client.set_from_reader("ABC", reader, None).await.is_err());
+    assert_eq!(
+        client.get("ABC").await.unwrap().status(),
+        StatusCode::KeyNotFound
+    );
+
+    // Nothing of the aborted value is left to prefix the next one
+    let status = client
+        .set_from_reader("ABC", "1234".as_bytes(), None)
+        .await
+        .unwrap();
+    assert_eq!(status, StatusCode::Ok);
+    assert_eq!(
+        client.get("ABC").await.unwrap().value(),
+        Some(b"1234".as_slice())
+    );
+}
+
+#[tokio::test]
+async fn test_a_streamed_value_growing_too_long_is_refused_and_discarded() {
+    let address = run_test_server().await;
+    let client = Client::new(address).await;
+
+    let value = vec![b'a'; MAX_VALUE_LENGTH as usize + 1];
+    let status = client
+        .set_from_reader("ABC", value.as_slice(), None)
+        .await
+        .unwrap();
+    assert_eq!(status, StatusCode::ValueTooLarge);
+    assert_eq!(
+        client.get("ABC").await.unwrap().status(),
+        StatusCode::KeyNotFound
+    );
+
+    let value = vec![b'a'; MAX_VALUE_LENGTH as usize];
+    let status = client
+        .set_from_reader("ABC", value.as_slice(), None)
+        .await
+        .unwrap();
+    assert_eq!(status, StatusCode::Ok);
+    assert_eq!(
+        client.get("ABC").await.unwrap().value(),
+        Some(value.as_slice())
+    );
+}
+
+#[tokio::test]
+async fn test_chunks_are_only_set_once_the_last_one_arrived_on_the_same_connection() {
+    let handle = Server::new().bind("127.0.0.1:0").await.unwrap().spawn();
+    let address = handle.local_addr();
+    let conn = ClientConnection::new(address).await;
+    let client = Client::with_connection(&conn);
+    let send_chunk = |chunk: &'static [u8], flags: u32| {
+        let mut request = Frame::new(OpCode::SetChunk, 1);
+        request.key = Some("ABC".to_string());
+        request.value = Some(chunk.into());
+        request.flags = flags;
+        let mut buf = bytes::BytesMut::new();
+        request.encode_request(&mut buf).unwrap();
+        let conn = conn.clone();
+        async move {
+            let raw = conn.send_raw(buf.freeze()).await.unwrap();
+            Frame::decode_response(&raw).unwrap().unwrap().0.status
+        }
+    };
+
+    assert_eq!(send_chunk(b"12", 0).await, StatusCode::Ok);
+    assert_eq!(
+        client.get("ABC").await.unwrap().status(),
+        StatusCode::KeyNotFound
+    );
+    // Chunks sent over another connection belong to another value
+    let other = Client::new(address).await;
+    assert_eq!(
+        other
+            .set_from_reader("ABC", "other".as_bytes(), None)
+            .await
+            .unwrap(),
+        StatusCode::Ok
+    );
+    other.delete("ABC").await.unwrap();
+
+    assert_eq!(send_chunk(b"34", CHUNK_LAST_FLAG).await, StatusCode::Ok);
+    assert_eq!(
+        client.get("ABC").await.unwrap().value(),
+        Some(b"1234".as_slice())
+    );
+}
+
+#[tokio::test]
+async fn test_get_map_contains_only_the_keys_that_were_found() {
+    let address = run_test_server().await;
+    let client = Client::new(address).await;
+    client.set("A", "1", None).await.unwrap();
+    client.set("C", "3", None).await.unwrap();
+
+    let values = client.get_map(["A", "B", "C", "D"]).await.unwrap();
+    assert_eq!(
+        values,
+        HashMap::from([
+            ("A".to_string(), "1".to_string()),
+            ("C".to_string(), "3".to_string())
+        ])
+    );
+}
+
+#[tokio::test]
+async fn test_setting_and_getting_keys_concurrently_works() {
+    let address = run_test_server().await;
+    let client = Client::new(address).await;
+
+    let key_1 = "ABC".to_string();
+    let key_2 = "DEF".to_string();
+    let value_1 = "1234".to_string();
+    let value_2 = "5678".to_string();
+    let (resp_1, resp_2) = tokio::join!(
+        client.set(key_1.clone(), value_1.clone(), None),
+        client.set(key_2.clone(), value_2.clone(), None)
+    );
+    assert_eq!(resp_1.unwrap(), StatusCode::Ok);
+    assert_eq!(resp_2.unwrap(), StatusCode::Ok);
+
+    let (resp_1, resp_2) = tokio::join!(client.get(key_1.clone()), client.get(key_2.clone()));
+    let resp_1 = resp_1.unwrap();
+    let resp_2 = resp_2.unwrap();
+    assert_eq!(resp_1.status(), StatusCode::Ok);
+    assert_eq!(resp_1.value(), Some(value_1.as_bytes()));
+    assert_eq!(resp_1.ttl_since_unix_epoch_in_millis(), None);
+
+    assert_eq!(resp_2.status(), StatusCode::Ok);
+    assert_eq!(resp_2.value(), Some(value_2.as_bytes()));
+    assert_eq!(resp_2.ttl_since_unix_epoch_in_millis(), None);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_concurrent_calls_on_one_client_are_applied_in_the_order_they_are_polled() {
+    let address = run_test_server().await;
+    let client = Client::new(address).await;
+
+    for i in 0..100 {
+        let key = format!("key-{i}");
+        let (set, get) = tokio::join!(
+            client.set(key.as_str(), "1234", None),
+            client.get(key.as_str())
+        );
+        assert_eq!(set.unwrap(), StatusCode::Ok);
+        assert_eq!(get.unwrap().value(), Some(b"1234".as_slice()));
+
+        let (get, delete) = tokio::join!(client.get(key.as_str()), client.delete(key.as_str()));
+        assert_eq!(get.unwrap().status(), StatusCode::Ok);
+        assert_eq!(delete.unwrap(), StatusCode::Ok);
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_completed_writes_are_visible_on_other_connections() {
+    let handle = Server::new().bind("127.0.0.1:0").await.unwrap().spawn();
+    let writer = Client::new(handle.local_addr()).await;
+    let reader = Client::new(handle.local_addr()).await;
+
+    for i in 0..100 {
+        let key = format!("key-{i}");
+        assert_eq!(
+            writer.set(key.as_str(), "1234", None).await.unwrap(),
+            StatusCode::Ok
+        );
+        assert_eq!(
+            reader.get(key.as_str()).await.unwrap().status(),
+            StatusCode::Ok
+        );
+        assert_eq!(writer.delete(key.as_str()).await.unwrap(), StatusCode::Ok);
+        assert_eq!(
+            reader.get(key.as_str()).await.unwrap().status(),
+            StatusCode::KeyNotFound
+        );
+    }
+    writer.set("ABC", "1234", None).await.unwrap();
+    assert_eq!(writer.flush_confirmed().await.unwrap(), StatusCode::Ok);
+    assert_eq!(
+        reader.get("ABC").await.unwrap().status(),
+        StatusCode::KeyNotFound
+    );
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_exists_many_reports_present_absent_and_expired_keys() {
+    let address = run_test_server_on_tokio_time().await;
+    let client = Client::new(address).await;
+
+    let ttl_soon = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_millis()
+        + 60_000;
+    let ttl_later = ttl_soon + 600_000;
+    let resp = client.set("present", "1234", None).await.unwrap();
+    assert_eq!(resp, StatusCode::Ok);
+    let resp = client
+        .set("present with ttl", "1234", Some(ttl_later))
+        .await
+        .unwrap();
+    assert_eq!(resp, StatusCode::Ok);
+    let resp = client.set("expired", "1234", Some(ttl_soon)).await.unwrap();
+    assert_eq!(resp, StatusCode::Ok);
+
+    tokio::time::sleep(Duration::from_secs(120)).await;
+
+    let exists = client
+        .exists_many(["present", "absent", "expired", "present with ttl"])
+        .await
+        .unwrap();
+    assert_eq!(exists, vec![true, false, false, true]);
+
+    let exists = client.exists_many(Vec::<String>::new()).await.unwrap();
+    assert!(exists.is_empty());
+}
+
+#[tokio::test]
+async fn test_pushing_and_popping_concurrently_neither_loses_nor_duplicates_items() {
+    let address = run_test_server().await;
+    let conn = ClientConnection::new(address).await;
+    let pushers = 4;
+    let items_per_pusher = 100;
+    let poppers = 4;
+
+    let pusher_tasks: Vec<_> = (0..pushers)
+        .map(|pusher| {
+            let client = Client::with_connection(&conn);
+            tokio::spawn(async move {
+                for i in 0..items_per_pusher {
+                    let resp = client
+                        .push("queue".to_string(), format!("{pusher}-{i}"))
+                        .await
+                        .unwrap();
+                    assert_eq!(resp, StatusCode::Ok);
+                }
+            })
+        })
+        .collect();
+    let popped_items = Arc::new(Mutex::new(vec![]));
+    let popper_tasks: Vec<_> = (0..poppers)
+        .map(|_| {
+            let client = Client::with_connection(&conn);
+            let popped_items = popped_items.clone();
+            tokio::spawn(async move {
+                while popped_items.lock().unwrap().len() < pushers * items_per_pusher {
+                    match client.pop("queue").await.unwrap() {
+                        Some(item) => popped_items.lock().unwrap().push(item),
+                        None => tokio::task::yield_now().await,
+                    }
+                }
+            })
+        })
+        .collect();
+
+    for task in pusher_tasks {
+        task.await.unwrap();
+    }
+    timeout(Duration::from_secs(5), async {
+        for task in popper_tasks {
+            task.await.unwrap();
+        }
+    })
+    .await
+    .expect("Not all items were popped");
+
+    let popped_items = popped_items.lock().unwrap().clone();
+    let unique_items: HashSet<&String> = popped_items.iter().collect();
+    assert_eq!(popped_items.len(), pushers * items_per_pusher);
+    assert_eq!(unique_items.len(), pushers * items_per_pusher);
+    assert!(Client::with_connection(&conn)
+        .pop("queue")
+        .await
+        .unwrap()
+        .is_none());
+}
+
+#[tokio::test]
+async fn test_list_operations_on_a_plain_value_fail_with_wrong_type() {
+    let address = run_test_server().await;
+    let client = Client::new(address).await;
+    client.set("ABC", "1234", None).await.unwrap();
+    client.push("list", "1234").await.unwrap();
+
+    assert_eq!(
+        client.push("ABC", "1").await.unwrap(),
+        StatusCode::WrongType
+    );
+    assert!(client.pop("ABC").await.is_err());
+    let resp = client.get("list").await.unwrap();
+    assert_eq!(resp.status(), StatusCode::WrongType);
+}
+
+#[tokio::test]
+async fn test_set_membership_operations_work() {
+    let address = run_test_server().await;
+    let client = Client::new(address).await;
+
+    assert!(client.set_add("set", "a").await.unwrap());
+    assert!(client.set_add("set", "b").await.unwrap());
+    // Adding an existing member again does not change the set
+    assert!(!client.set_add("set", "a").await.unwrap());
+
+    assert!(client.set_contains("set", "a").await.unwrap());
+    assert!(client.set_contains("set", "b").await.unwrap());
+    assert!(!client.set_contains("set", "c").await.unwrap());
+    assert!(!client.set_contains("other", "a").await.unwrap());
+
+    assert!(client.set_remove("set", "a").await.unwrap());
+    assert!(!client.set_remove("set", "a").await.unwrap());
+    assert!(!client.set_contains("set", "a").await.unwrap());
+    assert!(client.set_contains("set", "b").await.unwrap());
+
+    // The set is gone once its last member is removed
+    assert!(client.set_remove("set", "b").await.unwrap());
+    client.set("set", "1234", None).await.unwrap();
+    assert!(client.set_add("set", "a").await.is_err());
+}
+
+#[tokio::test]
+async fn test_keys_matching_glob_patterns() {
+    let address = run_test_server().await;
+    let client = Client::new(address).await;
+    for key in [
+        "user:1:session",
+        "user:2:session",
+        "user:2:profile",
+        "user:10:session",
+        "admin:1:session",
+    ] {
+        client.set(key, "value", None).await.unwrap();
+    }
+
+    assert_eq!(
+        client.keys_matching("user:*:session").await.unwrap(),
+        vec!["user:10:session", "user:1:session", "user:2:session"]
+    );
+    assert_eq!(
+        client.keys_matching("user:?:*").await.unwrap(),
+        vec!["user:1:session", "user:2:profile", "user:2:session"]
+    );
+    assert_eq!(
+        client.keys_matching("*:1:session").await.unwrap(),
+        vec!["admin:1:session", "user:1:session"]
+    );
+    assert_eq!(client.keys_matching("*").await.unwrap().len(), 5);
+    assert!(client.keys_matching("guest:*").await.unwrap().is_empty());
+    assert_eq!(
+        client.keys_matching("user:2:profile").await.unwrap(),
+        vec!["user:2:profile"]
+    );
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_negative_entries_are_stored_hit_and_expire() {
+    let address = run_test_server_on_tokio_time().await;
+    let client = Client::new(address).await;
+
+    let status = client
+        .set_negative("missing", Duration::from_secs(60))
+        .await
+        .unwrap();
+    assert_eq!(status, StatusCode::Ok);
+    let resp = client.get("missing").await.unwrap();
+    assert_eq!(resp.status(), StatusCode::NegativeCached);
+    assert!(resp.value().is_none());
+    assert_eq!(client.exists_many(["missing"]).await.unwrap(), vec![false]);
+
+    // Tombstones neither replace values nor keep values from being set
+    client.set("present", "1234", None).await.unwrap();
+    let status = client
+        .set_negative("present", Duration::from_secs(10))
+        .await
+        .unwrap();
+    assert_eq!(status, StatusCode::KeyExists);
+    client
+        .set_negative("filled", Duration::from_secs(10))
+        .await
+        .unwrap();
+    assert_eq!(
+        client.set("filled", "5678", None).await.unwrap(),
+        StatusCode::Ok
+    );
+    assert_eq!(
+        client.get("filled").await.unwrap().value(),
+        Some(b"5678".as_slice())
+    );
+
+    client
+        .set_negative("deleted", Duration::from_secs(10))
+        .await
+        .unwrap();
+    assert_eq!(client.delete("deleted").await.unwrap(), StatusCode::Ok);
+    let resp = client.get("deleted").await.unwrap();
+    assert_eq!(resp.status(), StatusCode::KeyNotFound);
+
+    // TTLs beyond what the system clock can represent are refused before anything is sent
+    let err = client
+        .set_negative("forever", Duration::MAX)
+        .await
+        .unwrap_err();
+    assert_eq!(err.to_string(), "ttl out of range");
+    let resp = client.get("forever").await.unwrap();
+    assert_eq!(resp.status(), StatusCode::KeyNotFound);
+
+    // Last, as TTLs computed from the system clock are in the past of the server from then on
+    tokio::time::sleep(Duration::from_secs(120)).await;
+    let resp = client.get("missing").await.unwrap();
+    assert_eq!(resp.status(), StatusCode::KeyNotFound);
+}
+
+#[tokio::test]
+async fn test_max_connections_limit() {
+    let address = run_test_server().await;
+    let client_1 = Client::new(address).await;
+    let client_2 = Client::new(address).await;
+
+    let key = "ABC".to_string();
+    let resp = client_1.get(key.clone()).await.unwrap();
+    assert_eq!(resp.status(), StatusCode::KeyNotFound);
+    assert!(resp.value().is_none());
+
+    // We're expecting a timeout here as only one connection is allowed
+    assert!(
+        timeout(Duration::from_millis(100), client_2.get(key.clone()))
+            .await
+            .is_err()
+    );
+
+    // Once client 1 frees up the connection, client 2 should be able to connect
+    drop(client_1);
+
+    let resp = client_2.get(key.clone()).await.unwrap();
+    assert_eq!(resp.status(), StatusCode::KeyNotFound);
+    assert!(resp.value().is_none());
+}
+
+#[tokio::test]
+async fn test_connections_not_sending_a_request_within_the_accept_timeout_are_dropped() {
+    let handle = Server::new()
+        .max_connections(1)
+        .accept_timeout(Duration::from_millis(100))
+        .bind("127.0.0.1:0")
+        .await
+        .unwrap()
+        .spawn();
+    let mut silent = TcpStream::connect(handle.local_addr()).await.unwrap();
+    let read = timeout(Duration::from_secs(2), silent.read(&mut [0; 1]))
+        .await
+        .expect("The connection was not dropped within the accept timeout");
+    assert!(matches!(read, Ok(0) | Err(_)), "{read:?}");
+
+    // The dropped connection freed its slot, and after the first request idling is fine
+    let client = Client::new(handle.local_addr()).await;
+    client.set("ABC", "1234", None).await.unwrap();
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    assert_eq!(
+        client.get("ABC").await.unwrap().value(),
+        Some(b"1234".as_slice())
+    );
+}
+
+#[tokio::test]
+async fn test_connections_stalling_in_the_middle_of_a_request_are_dropped() {
+    let handle = Server::new()
+        .frame_timeout(Duration::from_millis(100))
+        .bind("127.0.0.1:0")
+        .await
+        .unwrap()
+        .spawn();
+    let mut stalling = TcpStream::connect(handle.local_addr()).await.unwrap();
+    // A header announcing a frame of 100 bytes, without the rest of it
+    let mut header = vec![OpCode::Get as u8, 0, 3];
+    header.extend_from_slice(&0u32.to_be_bytes());
+    header.extend_from_slice(&100u32.to_be_bytes());
+    stalling.write_all(&header).await.unwrap();
+    let read = timeout(Duration::from_secs(2), stalling.read(&mut [0; 1]))
+        .await
+        .expect("The connection was not dropped within the frame timeout");
+    assert!(matches!(read, Ok(0) | Err(_)), "{read:?}");
+    // Closed for stalling, not because reading the frame made the handler panic
+    assert_eq!(handle.metrics().handler_panics(), 0);
+
+    // Idling between requests is fine
+    let client = Client::new(handle.local_addr()).await;
+    client.set("ABC", "1234", None).await.unwrap();
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    assert_eq!(
+        client.get("ABC").await.unwrap().value(),
+        Some(b"1234".as_slice())
+    );
+}
+
+#[tokio::test]
+async fn test_zero_max_connections_means_unlimited() {
+    let server = Server::new()
+        .max_connections(0)
+        .bind("127.0.0.1:0")
+        .await
+        .unwrap();
+    let address = format!("127.0.0.1:{}", server.port());
+    tokio::spawn(server.run());
+    let clients = [
+        Client::new(&address).await,
+        Client::new(&address).await,
+        Client::new(&address).await,
+    ];
+
+    for client in &clients {
+        let resp = timeout(Duration::from_millis(500), client.get("ABC"))
+            .await
+            .expect("Server did not accept the connection")
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::KeyNotFound);
+    }
+}
+
+#[tokio::test]
+async fn test_rebinding_the_same_port_with_reuse_address_works() {
+    let server = Server::new()
+        .reuse_address(true)
+        .bind("127.0.0.1:0")
+        .await
+        .unwrap();
+    let port = server.port();
+    drop(server);
+
+    let server = Server::new()
+        .reuse_address(true)
+        .bind(format!("127.0.0.1:{port}"))
+        .await
+        .unwrap();
+    assert_eq!(server.port(), port);
+    let address = format!("127.0.0.1:{port}");
+    tokio::spawn(server.run());
+
+    let client = Client::new(address).await;
+    let resp = client.get("ABC").await.unwrap();
+    assert_eq!(resp.status(), StatusCode::KeyNotFound);
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_binding_the_same_port_twice_with_reuse_port_works() {
+    let server_1 = Server::new()
+        .reuse_port(true)
+        .bind("127.0.0.1:0")
+        .await
+        .unwrap();
+    let port = server_1.port();
+    let server_2 = Server::new()
+        .reuse_port(true)
+        .bind(format!("127.0.0.1:{port}"))
+        .await
+        .unwrap();
+    assert_eq!(server_2.port(), port);
+
+    // Without the option, the port is taken
+    assert!(Server::new()
+        .bind(format!("127.0.0.1:{port}"))
+        .await
+        .is_err());
+}
+
+#[tokio::test]
+async fn test_quiescing_stops_accepting_but_keeps_serving_existing_connections() {
+    let server = Server::new().bind("127.0.0.1:0").await.unwrap();
+    let address = format!("127.0.0.1:{}", server.port());
+    let handle = server.handle();
+    let server = tokio::spawn(server.run());
+    let client = Client::new(&address).await;
+    let resp = client.set("ABC", "1234", None).await.unwrap();
+    assert_eq!(resp, StatusCode::Ok);
+
+    handle.quiesce(None);
+
+    // New connections are refused once the server stopped listening
+    timeout(Duration::from_secs(1), async {
+        while TcpStream::connect(&address).await.is_ok() {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    })
+    .await
+    .expect("Server still accepts connections");
+
+    // The existing connection is still served
+    let resp = client.get("ABC").await.unwrap();
+    assert_eq!(resp.status(), StatusCode::Ok);
+    assert!(!server.is_finished());
+
+    // The server is done once the last connection is closed
+    drop(client);
+    let reason = timeout(Duration::from_secs(1), server)
+        .await
+        .expect("Server did not finish after draining")
+        .unwrap();
+    assert!(matches!(reason, ShutdownReason::Requested), "{reason:?}");
+}
+
+#[tokio::test]
+async fn test_draining_handlers_are_reported_while_quiescing() {
+    let handle = Server::new().bind("127.0.0.1:0").await.unwrap().spawn();
+    let address = handle.local_addr();
+    let mut clients = Vec::new();
+    for _ in 0..3 {
+        let client = Client::new(address).await;
+        let resp = client.get("ABC").await.unwrap();
+        assert_eq!(resp.status(), StatusCode::KeyNotFound);
+        clients.push(client);
+    }
+    // Nothing is draining while the server runs normally
+    assert_eq!(handle.draining_handlers(), None);
+
+    handle.quiesce(None);
+    assert_eq!(handle.draining_handlers(), Some(3));
+
+    drop(clients.pop());
+    timeout(Duration::from_secs(1), async {
+        while handle.draining_handlers() != Some(2) {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    })
+    .await
+    .expect("Closed connection was not reported as drained");
+
+    timeout(Duration::from_secs(1), handle.shutdown())
+        .await
+        .expect("Server did not shut down");
+    assert_eq!(handle.draining_handlers(), None);
+    assert_eq!(handle.active_connections(), 0);
+}
+
+#[tokio::test]
+async fn test_quiescing_shuts_down_remaining_connections_after_the_deadline() {
+    let server = Server::new().bind("127.0.0.1:0").await.unwrap();
+    let address = format!("127.0.0.1:{}", server.port());
+    let handle = server.handle();
+    let server = tokio::spawn(server.run());
+    let client = Client::new(&address).await;
+    let resp = client.get("ABC").await.unwrap();
+    assert_eq!(resp.status(), StatusCode::KeyNotFound);
+
+    handle.quiesce(Some(Duration::from_millis(50)));
+
+    let reason = timeout(Duration::from_secs(1), server)
+        .await
+        .expect("Server did not finish after the deadline")
+        .unwrap();
+    assert!(matches!(reason, ShutdownReason::Timeout), "{reason:?}");
+    assert!(client.get("ABC").await.is_err());
+}
+
+#[tokio::test]
+async fn test_renewing_a_connection_mid_session_moves_all_clients_to_a_new_stream() {
+    let handle = Server::new().bind("127.0.0.1:0").await.unwrap().spawn();
+    let conn = ClientConnection::new(handle.local_addr()).await;
+    let client_1 = Client::with_connection(&conn);
+    let client_2 = Client::with_connection(&conn);
+    client_1.set("ABC", "1234", None).await.unwrap();
+
+    // Requests in flight while renewing are still answered
+    let (before, renewed, after) = tokio::join!(
+        client_1.get("ABC"),
+        client_2.renew_connection(),
+        client_2.get("ABC")
+    );
+    renewed.unwrap();
+    assert_eq!(before.unwrap().value(), Some(b"1234".as_slice()));
+    assert_eq!(after.unwrap().value(), Some(b"1234".as_slice()));
+
+    assert_eq!(
+        client_1.set("DEF", "5678", None).await.unwrap(),
+        StatusCode::Ok
+    );
+    assert_eq!(
+        client_2.get("DEF").await.unwrap().value(),
+        Some(b"5678".as_slice())
+    );
+    assert_eq!(handle.metrics().accepted_connections(), 2);
+}
+
+#[tokio::test]
+async fn test_a_connection_with_keepalive_stays_usable_across_idle_periods() {
+    let handle = Server::new().bind("127.0.0.1:0").await.unwrap().spawn();
+    let conn = ClientConnection::new(handle.local_addr()).await;
+    conn.keepalive_interval(Duration::from_millis(20))
+        .await
+        .unwrap();
+    let client = Client::with_connection(&conn);
+    client.set("ABC", "1234", None).await.unwrap();
+
+    tokio::time::sleep(Duration::from_millis(150)).await;
+
+    assert_eq!(
+        client.get("ABC").await.unwrap().value(),
+        Some(b"1234".as_slice())
+    );
+    assert_eq!(
+        client.set("DEF", "5678", None).await.unwrap(),
+        StatusCode::Ok
+    );
+    assert_eq!(handle.metrics().accepted_connections(), 1);
+}
+
+#[tokio::test]
+async fn test_a_client_can_take_over_a_std_stream() {
+    let address = run_test_server().await;
+    let stream = std::net::TcpStream::connect(address).unwrap();
+
+    let client = Client::from_std_stream(stream).unwrap();
+    assert_eq!(
+        client.set("ABC", "1234", None).await.unwrap(),
+        StatusCode::Ok
+    );
+    assert_eq!(
+        client.get("ABC").await.unwrap().value(),
+        Some(b"1234".as_slice())
+    );
+}
+
+#[tokio::test]
+async fn test_a_raw_get_frame_is_answered_with_the_raw_response_frame() {
+    let address = run_test_server().await;
+    let conn = ClientConnection::new(address).await;
+    Client::with_connection(&conn)
+        .set("ABC", "1234", None)
+        .await
+        .unwrap();
+
+    let mut request = vec![OpCode::Get as u8, 0, 3];
+    request.extend_from_slice(&42u32.to_be_bytes());
+    request.extend_from_slice(&14u32.to_be_bytes());
+    request.extend_from_slice(b"ABC");
+    let raw = conn.send_raw(request.into()).await.unwrap();
+
+    let (response, length) = Frame::decode_response(&raw).unwrap().unwrap();
+    assert_eq!(length, raw.len());
+    assert_eq!(response.op_code, OpCode::Get);
+    assert_eq!(response.status, StatusCode::Ok);
+    assert_eq!(response.request_id, 42);
+    assert_eq!(response.key.as_deref(), Some("ABC"));
+    assert_eq!(response.value.as_deref(), Some(b"1234".as_slice()));
+}
+
+#[tokio::test]
+async fn test_connecting_ready_waits_for_a_server_starting_late() {
+    let address = TcpListener::bind("127.0.0.1:0")
+        .await
+        .unwrap()
+        .local_addr()
+        .unwrap();
+    tokio::spawn(async move {
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        Server::new().bind(address).await.unwrap().run().await
+    });
+
+    let client = Client::connect_ready(address, Duration::from_secs(5))
+        .await
+        .unwrap();
+    assert_eq!(
+        client.set("ABC", "1234", None).await.unwrap(),
+        StatusCode::Ok
+    );
+}
+
+#[tokio::test]
+async fn test_slow_sweeps_show_in_the_expiry_lag() {
+    let handle = Server::new()
+        .sweep_interval(Duration::from_millis(300))
+        .bind("127.0.0.1:0")
+        .await
+        .unwrap()
+        .spawn();
+    let client = Client::new(handle.local_addr()).await;
+    let ttl = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_millis()
+        + 10;
+    client.set("ABC", "1234", Some(ttl)).await.unwrap();
+
+    // The key is only reclaimed by the first sweep, long after it expired
+    timeout(Duration::from_secs(2), async {
+        while handle.metrics().expired_keys_reclaimed() == 0 {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    })
+    .await
+    .expect("The key was not reclaimed");
+    let metrics = handle.metrics();
+    assert_eq!(metrics.expired_keys_reclaimed(), 1);
+    assert!(
+        metrics.expiry_lag_sum() >= Duration::from_millis(100),
+        "{:?}",
+        metrics.expiry_lag_sum()
+    );
+    assert!(metrics
+        .to_prometheus()
+        .lines()
+        .any(|line| line == "cached_expiry_lag_seconds_count 1"));
+}
+
+#[tokio::test]
+async fn test_keys_expiring_together_are_evicted_in_a_single_batch() {
+    let handle = Server::new()
+        .sweep_interval(Duration::from_millis(500))
+        .bind("127.0.0.1:0")
+        .await
+        .unwrap()
+        .spawn();
+    let mut evictions = handle.subscribe_evictions();
+    let client = Client::new(handle.local_addr()).await;
+    let ttl = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_millis()
+        + 200;
+    let mut expected_keys: Vec<String> = (0..500).map(|i| format!("key-{i}")).collect();
+    let entries = expected_keys.iter().map(|key| (key.as_str(), "value"));
+    for (key, status) in client.set_many_pipelined(entries, Some(ttl)).await {
+        assert_eq!(status.unwrap(), StatusCode::Ok, "{key} was not set in time");
+    }
+    client.set("live", "value", None).await.unwrap();
+
+    // All keys expired before the first sweep, which removes them in one go
+    let batch = timeout(Duration::from_secs(2), evictions.recv())
+        .await
+        .expect("No batch was received")
+        .unwrap();
+    assert_eq!(batch.reason, EvictionReason::Expired);
+    let mut keys = batch.keys;
+    keys.sort();
+    expected_keys.sort();
+    assert_eq!(keys, expected_keys);
+    assert_eq!(evictions.missed_batches(), 0);
+    assert_eq!(handle.metrics().evictions(), 500);
+}
+
+#[tokio::test]
+async fn test_connection_stats_count_the_operations_of_the_connection() {
+    let address = run_test_server().await;
+    let before_connecting = SystemTime::now() - Duration::from_millis(1);
+    let client = Client::new(address).await;
+
+    client.set("ABC", "1234", None).await.unwrap();
+    client.get("ABC").await.unwrap();
+    client.get("DEF").await.unwrap();
+    client.delete("ABC").await.unwrap();
+
+    let stats = client.connection_stats().await.unwrap();
+    assert_eq!(stats.handled_requests(), 4);
+    assert!(stats.bytes_received() > 0);
+    assert!(stats.bytes_sent() > 0);
+    assert!(stats.connected_since() >= before_connecting);
+    assert!(stats.connected_since() <= SystemTime::now());
+
+    // Asking for the stats counts as a request afterwards
+    let next_stats = client.connection_stats().await.unwrap();
+    assert_eq!(next_stats.handled_requests(), 5);
+    assert!(next_stats.bytes_received() > stats.bytes_received());
+    assert!(next_stats.bytes_sent() > stats.bytes_sent());
+    assert_eq!(next_stats.connected_since(), stats.connected_since());
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn test_inflight_bytes_stay_within_their_limit() {
+    const MAX_INFLIGHT_BYTES: u64 = 1536 * 1024;
+    let handle = Server::new()
+        .max_inflight_bytes(MAX_INFLIGHT_BYTES)
+        .bind("127.0.0.1:0")
+        .await
+        .unwrap()
+        .spawn();
+    let address = handle.local_addr();
+
+    // Only a single one of these fits into the limit at a time
+    let value = "a".repeat(1024 * 1024);
+    let mut clients = vec![];
+    for i in 0..8 {
+        let client = Client::new(address).await;
+        let value = value.clone();
+        clients.push(tokio::spawn(async move {
+            let key = format!("key-{i}");
+            client.set(key.clone(), value.clone(), None).await.unwrap();
+            assert_eq!(
+                client.get(key).await.unwrap().into_value(),
+                Some(value.into_bytes())
+            );
+        }));
+    }
+    let all_answered = async {
+        for client in clients {
+            client.await.unwrap();
+        }
+    };
+    let mut peak = 0;
+    let watch = async {
+        loop {
+            let inflight_bytes = handle.metrics().inflight_bytes();
+            assert!(inflight_bytes <= MAX_INFLIGHT_BYTES);
+            peak = peak.max(inflight_bytes);
+            tokio::time::sleep(Duration::from_millis(1)).await;
+        }
+    };
+    tokio::select! {
+        _ = all_answered => {}
+        _ = watch => unreachable!(),
+        _ = tokio::time::sleep(Duration::from_secs(10)) => panic!("Requests were not answered"),
+    }
+    assert!(peak > 0);
+
+    // Answered requests don't hold anything once their connection waits for the next one
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    assert_eq!(handle.metrics().inflight_bytes(), 0);
+}
+
+#[tokio::test]
+async fn test_server_handle_reports_metrics_and_shuts_the_server_down() {
+    let handle = Server::new().bind("127.0.0.1:0").await.unwrap().spawn();
+    let address = handle.local_addr();
+    assert_eq!(handle.active_connections(), 0);
+
+    let client = Client::new(address).await;
+    let resp = client.set("ABC", "1234", None).await.unwrap();
+    assert_eq!(resp, StatusCode::Ok);
+    let resp = client.get("ABC").await.unwrap();
+    assert_eq!(resp.status(), StatusCode::Ok);
+    let resp = client.get("DEF").await.unwrap();
+    assert_eq!(resp.status(), StatusCode::KeyNotFound);
+
+    let metrics = handle.metrics();
+    assert_eq!(metrics.accepted_connections(), 1);
+    assert_eq!(metrics.active_connections(), 1);
+    assert_eq!(metrics.handled_requests(), 3);
+    assert_eq!(metrics.hits(), 1);
+    assert_eq!(metrics.misses(), 1);
+    assert!(metrics.bytes_received() > 0);
+    assert!(metrics.bytes_sent() > 0);
+    assert_eq!(handle.active_connections(), 1);
+    let prometheus = handle.metrics_prometheus();
+    assert!(prometheus.contains("cached_hits_total 1\n"));
+    assert!(prometheus.contains("cached_misses_total 1\n"));
+
+    timeout(Duration::from_secs(1), handle.shutdown())
+        .await
+        .expect("Server did not shut down");
+    assert_eq!(handle.active_connections(), 0);
+    assert!(client.get("ABC").await.is_err());
+    assert!(TcpStream::connect(address).await.is_err());
+}
+
+/// A server whose key validator panics for the key `panic`, and with it the connection handler.
+fn panicking_server() -> Server {
+    Server::new().key_validator(Arc::new(|key: &str| {
+        if key == "panic" {
+            panic!("key validator panicked");
+        }
+        true
+    }))
+}
+
+/// A get request for the key making the handlers of [`panicking_server`] panic.
+fn panicking_request() -> Vec<u8> {
+    let mut request = Frame::new(OpCode::Get, 1);
+    request.key = Some("panic".to_string());
+    let mut buf = bytes::BytesMut::new();
+    request.encode_request(&mut buf).unwrap();
+    buf.to_vec()
+}
+
+#[tokio::test]
+async fn test_a_panicking_handler_does_not_affect_other_connections() {
+    let handle = panicking_server()
+        .bind("127.0.0.1:0")
+        .await
+        .unwrap()
+        .spawn();
+    let address = handle.local_addr();
+    let client = Client::new(address).await;
+    let resp = client.set("ABC", "1234", None).await.unwrap();
+    assert_eq!(resp, StatusCode::Ok);
+
+    let mut stream = TcpStream::connect(address).await.unwrap();
+    stream.write_all(&panicking_request()).await.unwrap();
+    // The panicking connection is closed
+    let read = timeout(Duration::from_secs(1), stream.read(&mut [0; 16]))
+        .await
+        .expect("Connection was not closed");
+    assert!(matches!(read, Ok(0) | Err(_)));
+    assert_eq!(handle.metrics().handler_panics(), 1);
+
+    let resp = client.get("ABC").await.unwrap();
+    assert_eq!(resp.status(), StatusCode::Ok);
+    let other_client = Client::new(address).await;
+    let resp = other_client.get("ABC").await.unwrap();
+    assert_eq!(resp.status(), StatusCode::Ok);
+}
+
+#[tokio::test]
+async fn test_a_panicking_handler_is_restarted_up_to_the_limit() {
+    let handle = panicking_server()
+        .max_handler_restarts(2)
+        .bind("127.0.0.1:0")
+        .await
+        .unwrap()
+        .spawn();
+    let mut stream = TcpStream::connect(handle.local_addr()).await.unwrap();
+    // Every restart reads the next request and panics again
+    stream
+        .write_all(&panicking_request().repeat(3))
+        .await
+        .unwrap();
+    let read = timeout(Duration::from_secs(1), stream.read(&mut [0; 16]))
+        .await
+        .expect("Connection was not closed");
+    assert!(matches!(read, Ok(0) | Err(_)));
+    assert_eq!(handle.metrics().handler_panics(), 3);
+}
+
+#[tokio::test]
+async fn test_malformed_or_cut_off_requests_close_the_connection_without_a_panic() {
+    let handle = Server::new()
+        .max_handler_restarts(2)
+        .bind("127.0.0.1:0")
+        .await
+        .unwrap()
+        .spawn();
+    let address = handle.local_addr();
+
+    // An unknown op code, and a frame length shorter than the header
+    let mut unknown_op_code = [0; 11];
+    unknown_op_code[0] = 255;
+    unknown_op_code[7..].copy_from_slice(&11u32.to_be_bytes());
+    let mut too_short = [0; 11];
+    too_short[0] = OpCode::Get as u8;
+    too_short[7..].copy_from_slice(&5u32.to_be_bytes());
+    for malformed in [unknown_op_code, too_short] {
+        let mut stream = TcpStream::connect(address).await.unwrap();
+        stream.write_all(&malformed).await.unwrap();
+        let read = timeout(Duration::from_secs(1), stream.read(&mut [0; 16]))
+            .await
+            .expect("Connection was not closed");
+        assert!(matches!(read, Ok(0) | Err(_)));
+    }
+
+    // Going away in the middle of a frame
+    let mut stream = TcpStream::connect(address).await.unwrap();
+    let mut header = vec![OpCode::Get as u8, 0, 3];
+    header.extend_from_slice(&0u32.to_be_bytes());
+    header.extend_from_slice(&100u32.to_be_bytes());
+    stream.write_all(&header).await.unwrap();
+    drop(stream);
+
+    timeout(Duration::from_secs(1), async {
+        while handle.metrics().accepted_connections() < 3 || handle.active_connections() > 0 {
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        }
+    })
+    .await
+    .expect("Connections were not closed");
+    assert_eq!(handle.metrics().handler_panics(), 0);
+}
+
+#[tokio::test]
+async fn test_connection_error_is_reported_after_server_goes_away() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let address = listener.local_addr().unwrap();
+    // A server that reads the first request and then goes away without responding
+    let server = tokio::spawn(async move {
+        let (mut stream, _) = listener.accept().await.unwrap();
+        let mut buf = [0; 64];
+        let _ = stream.read(&mut buf).await.unwrap();
+    });
+    let client = Client::new(address).await;
+
+    let err = client.get("ABC").await.unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "connection closed while awaiting response: could not read response"
+    );
+    server.await.unwrap();
+
+    // Subsequent requests must report the original cause, not just a generic send failure
+    let err = client.get("ABC").await.unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "connection closed: could not read response"
+    );
+    let err = client.set("ABC", "1234", None).await.unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "connection closed: could not read response"
+    );
+}
+
+/// Runs a proxy in front of the server at `address` that drops its first connection
+/// after reading the first request, and passes all later connections through.
+async fn run_flaky_proxy(address: SocketAddr) -> SocketAddr {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let proxy_address = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        let (mut stream, _) = listener.accept().await.unwrap();
+        let _ = stream.read(&mut [0; 64]).await.unwrap();
+        drop(stream);
+        loop {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            tokio::spawn(async move {
+                let mut server = TcpStream::connect(address).await.unwrap();
+                let _ = tokio::io::copy_bidirectional(&mut stream, &mut server).await;
+            });
+        }
+    });
+    proxy_address
+}
+
+#[tokio::test]
+async fn test_get_retry_recovers_from_a_dropped_connection() {
+    let address = run_test_server().await;
+    Client::new(address)
+        .await
+        .set("ABC", "1234", None)
+        .await
+        .unwrap();
+    let client = Client::new(run_flaky_proxy(address).await).await;
+
+    let resp = client.get_retry("ABC", RetryPolicy::new(1)).await.unwrap();
+    assert_eq!(resp.value(), Some(b"1234".as_slice()));
+    // The connection was replaced for all later requests
+    assert_eq!(
+        client.get("ABC").await.unwrap().value(),
+        Some(b"1234".as_slice())
+    );
+}
+
+#[tokio::test]
+async fn test_set_retry_only_retries_if_opted_in() {
+    let address = run_test_server().await;
+    let client = Client::new(run_flaky_proxy(address).await).await;
+    let err = client
+        .set_retry("ABC", "1234", None, RetryPolicy::new(1))
+        .await
+        .unwrap_err();
+    assert!(err.is_connection_error());
+
+    let client = Client::new(run_flaky_proxy(address).await).await;
+    let status = client
+        .set_retry("ABC", "1234", None, RetryPolicy::new(1).retry_sets(true))
+        .await
+        .unwrap();
+    assert_eq!(status, StatusCode::Ok);
+}
+
+#[tokio::test]
+async fn test_full_server_evicts_per_its_eviction_policy() {
+    let handle = Server::new()
+        .shard_amount(1)
+        .max_entries(2)
+        .eviction_policy(EvictionPolicy::Lru)
+        .bind("127.0.0.1:0")
+        .await
+        .unwrap()
+        .spawn();
+    let client = Client::new(handle.local_addr()).await;
+    client.set("a", "1", None).await.unwrap();
+    client.set("b", "2", None).await.unwrap();
+    client.get("a").await.unwrap();
+
+    client.set("c", "3", None).await.unwrap();
+    assert_eq!(
+        client.get("b").await.unwrap().status(),
+        StatusCode::KeyNotFound
+    );
+    assert_eq!(
+        client.get("a").await.unwrap().value(),
+        Some(b"1".as_slice())
+    );
+    assert_eq!(
+        client.get("c").await.unwrap().value(),
+        Some(b"3".as_slice())
+    );
+}
+
+#[tokio::test]
+async fn test_requests_in_flight_when_the_server_goes_away_are_told_apart() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let address = listener.local_addr().unwrap();
+    // A server that waits for a request and then goes away without responding
+    let server = tokio::spawn(async move {
+        let (mut stream, _) = listener.accept().await.unwrap();
+        let mut buf = [0; 64];
+        let _ = stream.read(&mut buf).await.unwrap();
+    });
+    let client = Client::new(address).await;
+
+    let err = client.get("ABC").await.unwrap_err();
+    assert!(err.is_closed_while_awaiting());
+    assert!(err.status().is_none());
+    server.await.unwrap();
+
+    // Requests made once the connection is known to be closed never made it to the server
+    let err = client.get("ABC").await.unwrap_err();
+    assert!(!err.is_closed_while_awaiting());
+}
+
+#[tokio::test]
+async fn test_get_or_default_falls_back_for_missing_keys_only() {
+    let address = run_test_server().await;
+    let client = Client::new(address).await;
+    client.set("present", "1234", None).await.unwrap();
+    client
+        .set_negative("remembered", Duration::from_secs(10))
+        .await
+        .unwrap();
+    client.push("list", "1").await.unwrap();
+
+    let value = client
+        .get_or_default("present", "default".to_string())
+        .await;
+    assert_eq!(value.unwrap(), "1234");
+    let value = client
+        .get_or_default("missing", "default".to_string())
+        .await;
+    assert_eq!(value.unwrap(), "default");
+    let value = client
+        .get_or_default("remembered", "default".to_string())
+        .await;
+    assert_eq!(value.unwrap(), "default");
+    let err = client
+        .get_or_default("list", "default".to_string())
+        .await
+        .unwrap_err();
+    assert_eq!(err.status(), Some(StatusCode::WrongType));
+}
+
+#[tokio::test]
+async fn test_keys_rejected_by_the_key_validator_are_refused() {
+    let handle = Server::new()
+        .key_validator(Arc::new(|key: &str| key.starts_with("tenant-a:")))
+        .bind("127.0.0.1:0")
+        .await
+        .unwrap()
+        .spawn();
+    let client = Client::new(handle.local_addr()).await;
+
+    let status = client.set("tenant-a:foo", "1234", None).await.unwrap();
+    assert_eq!(status, StatusCode::Ok);
+    let status = client.set("tenant-b:foo", "1234", None).await.unwrap();
+    assert_eq!(status, StatusCode::InvalidKey);
+    let resp = client.get("foo").await.unwrap();
+    assert_eq!(resp.status(), StatusCode::InvalidKey);
+    let err = client
+        .exists_many(["tenant-a:foo", "tenant-b:foo"])
+        .await
+        .unwrap_err();
+    assert_eq!(err.status(), Some(StatusCode::InvalidKey));
+
+    let resp = client.get("tenant-a:foo").await.unwrap();
+    assert_eq!(resp.value(), Some(b"1234".as_slice()));
+}
+
+#[tokio::test]
+async fn test_keys_that_are_not_valid_utf8_are_refused_without_closing_the_connection() {
+    let address = run_test_server().await;
+    let conn = ClientConnection::new(address).await;
+
+    let mut request = vec![OpCode::Get as u8, 0, 2];
+    request.extend_from_slice(&1u32.to_be_bytes());
+    request.extend_from_slice(&13u32.to_be_bytes());
+    request.extend_from_slice(&[0xff, 0xfe]);
+    let raw = conn.send_raw(request.into()).await.unwrap();
+    let (response, _) = Frame::decode_response(&raw).unwrap().unwrap();
+    assert_eq!(response.op_code, OpCode::Get);
+    assert_eq!(response.status, StatusCode::InvalidKey);
+
+    // Keys carried in the value are checked too
+    let mut request = vec![OpCode::ExistsMany as u8, 0, 0];
+    request.extend_from_slice(&2u32.to_be_bytes());
+    request.extend_from_slice(&14u32.to_be_bytes());
+    request.extend_from_slice(&[2, 0xff, 0xfe]);
+    let raw = conn.send_raw(request.into()).await.unwrap();
+    let (response, _) = Frame::decode_response(&raw).unwrap().unwrap();
+    assert_eq!(response.op_code, OpCode::ExistsMany);
+    assert_eq!(response.status, StatusCode::InvalidKey);
+
+    let client = Client::with_connection(&conn);
+    assert_eq!(
+        client.set("ABC", "1234", None).await.unwrap(),
+        StatusCode::Ok
+    );
+    assert_eq!(
+        client.get("ABC").await.unwrap().value(),
+        Some(b"1234".as_slice())
+    );
+}
+
+#[tokio::test]
+async fn test_list_items_and_set_members_that_are_not_valid_utf8_are_refused() {
+    let address = run_test_server().await;
+    let conn = ClientConnection::new(address).await;
+
+    for op_code in [OpCode::LPush, OpCode::SAdd, OpCode::SIsMember, OpCode::SRem] {
+        let mut request = Frame::new(op_code, 1);
+        request.key = Some("ABC".to_string());
+        request.value = Some(vec![0xff, 0xfe].into());
+        let mut buf = bytes::BytesMut::new();
+        request.encode_request(&mut buf).unwrap();
+        let raw = conn.send_raw(buf.freeze()).await.unwrap();
+        let (response, _) = Frame::decode_response(&raw).unwrap().unwrap();
+        assert_eq!(response.op_code, op_code);
+        assert_eq!(response.status, StatusCode::InvalidValue);
+    }
+
+    let client = Client::with_connection(&conn);
+    assert_eq!(client.push("ABC", "1234").await.unwrap(), StatusCode::Ok);
+}
+
+#[tokio::test]
+async fn test_keys_outside_of_printable_ascii_are_refused_if_restricted() {
+    let handle = Server::new()
+        .printable_ascii_keys(true)
+        .bind("127.0.0.1:0")
+        .await
+        .unwrap()
+        .spawn();
+    let client = Client::new(handle.local_addr()).await;
+    assert!(client.capabilities().await.unwrap().validates_keys());
+
+    let status = client.set("foo\u{7}bar", "1234", None).await.unwrap();
+    assert_eq!(status, StatusCode::InvalidKey);
+    let status = client.set("f\u{f6}\u{f6}", "1234", None).await.unwrap();
+    assert_eq!(status, StatusCode::InvalidKey);
+    let status = client.set("foo bar:1", "1234", None).await.unwrap();
+    assert_eq!(status, StatusCode::Ok);
+    let resp = client.get("foo bar:1").await.unwrap();
+    assert_eq!(resp.value(), Some(b"1234".as_slice()));
+}
+
+#[tokio::test]
+async fn test_serving_forever_serves_until_shut_down() {
+    let server = Server::new().bind("127.0.0.1:0").await.unwrap();
+    let handle = server.handle();
+    let server = tokio::spawn(server.serve_forever());
+    let client = Client::new(handle.local_addr()).await;
+    client.set("ABC", "1234", None).await.unwrap();
+    assert_eq!(
+        client.get("ABC").await.unwrap().value(),
+        Some(b"1234".as_slice())
+    );
+
+    handle.shutdown().await;
+    let reason = timeout(Duration::from_secs(1), server)
+        .await
+        .expect("Server did not shut down")
+        .unwrap();
+    assert!(matches!(reason, ShutdownReason::Requested), "{reason:?}");
+}
+
+#[tokio::test]
+async fn test_run_reports_shutting_down_via_a_handle_as_requested() {
+    let server = Server::new().bind("127.0.0.1:0").await.unwrap();
+    let handle = server.handle();
+    let server = tokio::spawn(server.run());
+    let client = Client::new(handle.local_addr()).await;
+    client.get("ABC").await.unwrap();
+
+    handle.shutdown().await;
+    let reason = timeout(Duration::from_secs(1), server)
+        .await
+        .expect("Server did not shut down")
+        .unwrap();
+    assert!(matches!(reason, ShutdownReason::Requested), "{reason:?}");
+}
+
+#[tokio::test]
+async fn test_memoized_function_is_only_called_once_per_key() {
+    let address = run_test_server().await;
+    let client = Client::new(address).await;
+    let calls = Arc::new(Mutex::new(Vec::new()));
+    let recorded_calls = Arc::clone(&calls);
+    let double = Memoized::new(
+        client.clone(),
+        None,
+        |n: &u32| format!("double:{n}"),
+        move |n: u32| {
+            recorded_calls.lock().unwrap().push(n);
+            async move { (2 * n).to_string() }
+        },
+    );
+
+    assert_eq!(double.call(1).await.unwrap(), "2");
+    assert_eq!(double.call(2).await.unwrap(), "4");
+    assert_eq!(double.call(1).await.unwrap(), "2");
+    assert_eq!(double.call(2).await.unwrap(), "4");
+    assert_eq!(*calls.lock().unwrap(), vec![1, 2]);
+
+    // The results are stored like any other value
+    let resp = client.get("double:1").await.unwrap();
+    assert_eq!(resp.value(), Some(b"2".as_slice()));
+}
+
+#[tokio::test]
+async fn test_capabilities_match_the_server_configuration() {
+    let address = run_test_server().await;
+    let client = Client::new(address).await;
+    let capabilities = client.capabilities().await.unwrap();
+    assert_eq!(capabilities.max_key_length(), 255);
+    assert_eq!(capabilities.max_value_length(), 1024 * 1024);
+    assert_eq!(capabilities.max_entries(), None);
+    assert!(capabilities.requires_flush_confirmation());
+    assert!(!capabilities.validates_keys());
+    assert!(capabilities.allows(OpCode::KeysGlob));
+
+    let handle = Server::new()
+        .max_entries(1000)
+        .require_flush_confirmation(false)
+        .key_validator(Arc::new(|key: &str| !key.is_empty()))
+        .allowed_opcodes(HashSet::from([
+            OpCode::Get,
+            OpCode::Set,
+            OpCode::Capabilities,
+        ]))
+        .bind("127.0.0.1:0")
+        .await
+        .unwrap()
+        .spawn();
+    let client = Client::new(handle.local_addr()).await;
+    let capabilities = client.capabilities().await.unwrap();
+    assert_eq!(capabilities.max_entries(), Some(1000));
+    assert!(!capabilities.requires_flush_confirmation());
+    assert!(capabilities.validates_keys());
+    assert_eq!(
+        capabilities.allowed_opcodes(),
+        [OpCode::Set, OpCode::Get, OpCode::Capabilities]
+    );
+    assert!(!capabilities.allows(OpCode::Delete));
+}
+
+#[tokio::test]
+async fn test_server_version_matches_the_build() {
+    let address = run_test_server().await;
+    let client = Client::new(address).await;
+    let info = client.server_version().await.unwrap();
+    assert_eq!(info.version(), env!("CARGO_PKG_VERSION"));
+    assert_eq!(info.has_http(), cfg!(feature = "http"));
+    assert_eq!(info.has_resp(), cfg!(feature = "resp"));
+    assert!(client.capabilities().await.unwrap().allows(OpCode::Version));
+}
+
+#[tokio::test]
+async fn test_random_key_returns_one_of_the_keys_set() {
+    let address = run_test_server().await;
+    let client = Client::new(address).await;
+    assert_eq!(client.random_key().await.unwrap(), None);
+
+    let keys: HashSet<String> = (0..10).map(|idx| format!("key:{idx}")).collect();
+    for key in &keys {
+        client.set(key.as_str(), "value", None).await.unwrap();
+    }
+    for _ in 0..10 {
+        let key = client.random_key().await.unwrap().unwrap();
+        assert!(keys.contains(&key));
+    }
+}
+
+#[tokio::test]
+async fn test_inspect_returns_everything_known_about_a_key() {
+    let address = Server::new()
+        .shard_amount(4)
+        .bind("127.0.0.1:0")
+        .await
+        .unwrap()
+        .spawn()
+        .local_addr();
+    let client = Client::new(address).await;
+    assert_eq!(client.inspect("ABC").await.unwrap(), None);
+
+    let before = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_millis();
+    let ttl = before + 60_000;
+    client
+        .set_with_flags("ABC", "1234", Some(ttl), 42)
+        .await
+        .unwrap();
+    let info = client.inspect("ABC").await.unwrap().unwrap();
+    assert_eq!(info.value_length(), 4);
+    assert_eq!(info.ttl_since_unix_epoch_in_millis(), Some(ttl));
+    assert!(info.updated_at_in_millis() >= before);
+    assert_eq!(info.flags(), 42);
+    assert!(!info.is_negative_cached());
+    assert!(info.shard() < 4);
+
+    client
+        .set_negative("DEF", Duration::from_secs(60))
+        .await
+        .unwrap();
+    let info = client.inspect("DEF").await.unwrap().unwrap();
+    assert_eq!(info.value_length(), 0);
+    assert!(info.is_negative_cached());
+}
+
+#[tokio::test]
+async fn test_counters_are_incremented_and_decremented_atomically() {
+    let address = Server::new()
+        .bind("127.0.0.1:0")
+        .await
+        .unwrap()
+        .spawn()
+        .local_addr();
+    let client = Client::new(address).await;
+    assert_eq!(client.increment("counter", 10).await.unwrap(), 10);
+    assert_eq!(client.decrement("counter", 3).await.unwrap(), 7);
+    assert_eq!(client.decrement("other", 3).await.unwrap(), -3);
+
+    let tasks: Vec<_> = (0..10)
+        .map(|_| {
+            let client = client.clone();
+            tokio::spawn(async move {
+                for _ in 0..10 {
+                    client.increment("counter", 1).await.unwrap();
+                }
+            })
+        })
+        .collect();
+    for task in tasks {
+        task.await.unwrap();
+    }
+    assert_eq!(
+        client.get("counter").await.unwrap().value(),
+        Some(b"107".as_slice())
+    );
+
+    client.set("text", "abc", None).await.unwrap();
+    let err = client.increment("text", 1).await.unwrap_err();
+    assert_eq!(err.status(), Some(StatusCode::InvalidValue));
+    assert_eq!(
+        client.get("text").await.unwrap().value(),
+        Some(b"abc".as_slice())
+    );
+
+    // Results that don't fit are refused and leave the counter as it was
+    let err = client.decrement("counter", i64::MIN).await.unwrap_err();
+    assert_eq!(err.status(), Some(StatusCode::OutOfRange));
+    let err = client.increment("counter", i64::MAX).await.unwrap_err();
+    assert_eq!(err.status(), Some(StatusCode::OutOfRange));
+    assert_eq!(client.decrement("counter", 108).await.unwrap(), -1);
+    assert_eq!(
+        client.decrement("counter", i64::MIN).await.unwrap(),
+        i64::MAX
+    );
+}
+
+/// Resolves every name to the addresses it was last given.
+#[derive(Debug, Default)]
+struct MockResolver(Mutex<Vec<SocketAddr>>);
+
+#[async_trait::async_trait]
+impl Resolver for MockResolver {
+    async fn resolve(&self, _name: &str) -> std::io::Result<Vec<SocketAddr>> {
+        Ok(self.0.lock().unwrap().clone())
+    }
+}
+
+#[tokio::test]
+async fn test_a_balanced_client_sends_requests_to_all_resolved_servers() {
+    let first = Server::new().bind("127.0.0.1:0").await.unwrap().spawn();
+    let second = Server::new().bind("127.0.0.1:0").await.unwrap().spawn();
+    let resolver = Arc::new(MockResolver::default());
+    *resolver.0.lock().unwrap() = vec![first.local_addr(), second.local_addr()];
+    let client = Client::balanced_with_resolver(
+        "cache.internal:7777",
+        resolver.clone(),
+        Duration::from_millis(10),
+    )
+    .await
+    .unwrap();
+    let handled = || {
+        (
+            first.metrics().handled_requests(),
+            second.metrics().handled_requests(),
+        )
+    };
+
+    for i in 0..10 {
+        client.get(format!("key {i}")).await.unwrap();
+    }
+    timeout(Duration::from_secs(1), async {
+        while handled().0 + handled().1 < 10 {
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        }
+    })
+    .await
+    .unwrap();
+    let (on_first, on_second) = handled();
+    assert!(on_first > 0, "no request hit the first server");
+    assert!(on_second > 0, "no request hit the second server");
+
+    // The first server is no longer resolved and stops receiving requests
+    *resolver.0.lock().unwrap() = vec![second.local_addr()];
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    for i in 0..10 {
+        client.get(format!("key {i}")).await.unwrap();
+    }
+    timeout(Duration::from_secs(1), async {
+        while handled().1 < on_second + 10 {
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        }
+    })
+    .await
+    .unwrap();
+    assert_eq!(handled().0, on_first);
+}
+
+#[tokio::test]
+async fn test_a_balanced_client_reads_keys_from_the_server_it_wrote_them_to() {
+    let first = Server::new().bind("127.0.0.1:0").await.unwrap().spawn();
+    let second = Server::new().bind("127.0.0.1:0").await.unwrap().spawn();
+    let resolver = Arc::new(MockResolver::default());
+    *resolver.0.lock().unwrap() = vec![first.local_addr(), second.local_addr()];
+    let client = Client::balanced_with_resolver(
+        "cache.internal:7777",
+        resolver.clone(),
+        Duration::from_secs(60),
+    )
+    .await
+    .unwrap();
+
+    for i in 0..20 {
+        let key = format!("key {i}");
+        assert_eq!(
+            client.set(key.clone(), "1234", None).await.unwrap(),
+            StatusCode::Ok
+        );
+        assert_eq!(
+            client.get(key).await.unwrap().value(),
+            Some(b"1234".as_slice())
+        );
+    }
+    // The keys are spread across both servers
+    let on_first = Client::new(first.local_addr()).await;
+    let on_second = Client::new(second.local_addr()).await;
+    let mut stored = (0, 0);
+    for i in 0..20 {
+        let key = format!("key {i}");
+        let in_first = on_first.get(key.clone()).await.unwrap().status() == StatusCode::Ok;
+        let in_second = on_second.get(key).await.unwrap().status() == StatusCode::Ok;
+        assert!(
+            in_first != in_second,
+            "key {i} is not on exactly one server"
+        );
+        stored.0 += usize::from(in_first);
+        stored.1 += usize::from(in_second);
+    }
+    assert!(stored.0 > 0 && stored.1 > 0, "{stored:?}");
+}
+
+#[tokio::test]
+async fn test_a_balanced_client_subscribes_to_and_renews_resolved_servers_only() {
+    let first = Server::new().bind("127.0.0.1:0").await.unwrap().spawn();
+    let second = Server::new().bind("127.0.0.1:0").await.unwrap().spawn();
+    let resolver = Arc::new(MockResolver::default());
+    *resolver.0.lock().unwrap() = vec![first.local_addr(), second.local_addr()];
+    let client = Client::balanced_with_resolver(
+        "cache.internal:7777",
+        resolver.clone(),
+        Duration::from_millis(10),
+    )
+    .await
+    .unwrap();
+
+    // The first server is no longer resolved, but its connection was the first one handed out
+    *resolver.0.lock().unwrap() = vec![second.local_addr()];
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    first.shutdown().await;
+    client.renew_connection().await.unwrap();
+    let capabilities = client.capabilities().await.unwrap();
+    assert_eq!(client.known_capabilities(), Some(capabilities));
+
+    let mut evictions = client.subscribe_evictions().await.unwrap();
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_millis();
+    client.set("ABC", "1", Some(now + 10)).await.unwrap();
+    tokio::time::sleep(Duration::from_millis(20)).await;
+    client.flush_expired().await.unwrap();
+    let batch = timeout(Duration::from_secs(1), evictions.recv())
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(batch.keys, vec!["ABC".to_string()]);
+}
+
+#[tokio::test]
+async fn test_validating_against_known_capabilities_needs_no_server() {
+    let handle = Server::new()
+        .allowed_opcodes(HashSet::from([OpCode::Set, OpCode::Capabilities]))
+        .bind("127.0.0.1:0")
+        .await
+        .unwrap()
+        .spawn();
+    let client = Client::new(handle.local_addr()).await;
+    assert_eq!(client.known_capabilities(), None);
+    let capabilities = client.capabilities().await.unwrap();
+    assert_eq!(client.known_capabilities(), Some(capabilities.clone()));
+    handle.shutdown().await;
+
+    let too_long = "a".repeat(capabilities.max_value_length() + 1);
+    let e = client
+        .validate(&BatchOp::set("ABC", too_long.as_str(), None))
+        .unwrap_err();
+    assert_eq!(e.to_string(), "value too long");
+    let e = client.validate(&BatchOp::delete("ABC")).unwrap_err();
+    assert_eq!(e.status(), Some(StatusCode::OperationNotPermitted));
+    client.validate(&BatchOp::set("ABC", "1234", None)).unwrap();
+}
+
+/// Collects what is written to it, for all of its clones.
+#[derive(Clone, Default)]
+struct SharedBuffer(Arc<Mutex<Vec<u8>>>);
+
+impl std::io::Write for SharedBuffer {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+#[tokio::test]
+async fn test_the_access_log_has_a_line_per_request_with_hashed_keys() {
+    let buffer = SharedBuffer::default();
+    let handle = Server::new()
+        .access_log(Box::new(buffer.clone()))
+        .bind("127.0.0.1:0")
+        .await
+        .unwrap()
+        .spawn();
+    let client = Client::new(handle.local_addr()).await;
+    client.set("secret-key", "1234", None).await.unwrap();
+    client.get("missing-key").await.unwrap();
+
+    // Lines are written once the response was sent
+    let log = timeout(Duration::from_secs(2), async {
+        loop {
+            let log = String::from_utf8(buffer.0.lock().unwrap().clone()).unwrap();
+            if log.lines().count() >= 2 {
+                return log;
+            }
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        }
+    })
+    .await
+    .unwrap();
+    let lines: Vec<&str> = log.lines().collect();
+    assert_eq!(lines.len(), 2, "{log}");
+    for (line, op, status) in [(lines[0], "Set", "Ok"), (lines[1], "Get", "KeyNotFound")] {
+        let fields: HashMap<&str, &str> = line
+            .split(' ')
+            .map(|field| field.split_once('=').unwrap())
+            .collect();
+        assert!(fields["ts"].parse::<u128>().unwrap() > 0);
+        assert!(fields["peer"].starts_with("127.0.0.1:"));
+        assert_eq!(fields["op"], op);
+        assert_eq!(fields["key"].len(), 16);
+        assert_eq!(fields["status"], status);
+        assert!(fields["received"].parse::<u64>().unwrap() > 0);
+        assert!(fields["sent"].parse::<u64>().unwrap() > 0);
+        assert!(fields["latency_us"].parse::<u128>().is_ok());
+    }
+    assert!(!log.contains("secret-key"));
+}
+
+/// Sets a key that expires right away and removes it.
+async fn expire_key(client: &Client, key: String) {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_millis();
+    client
+        .set(key.clone(), "1".to_string(), Some(now + 10))
+        .await
+        .unwrap();
+    tokio::time::sleep(Duration::from_millis(20)).await;
+    client.flush_expired().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_the_eviction_stream_resumes_after_the_server_bounced() {
+    let handle = Server::new().bind("127.0.0.1:0").await.unwrap().spawn();
+    let address = handle.local_addr();
+    let client = Client::new(address).await;
+    let mut evictions = client.subscribe_evictions().await.unwrap();
+
+    expire_key(&client, "before".to_string()).await;
+    let batch = timeout(Duration::from_secs(2), evictions.recv())
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(batch.keys, vec!["before".to_string()]);
+    assert_eq!(batch.reason, EvictionReason::Expired);
+
+    handle.shutdown().await;
+    let handle = Server::new().bind(address).await.unwrap().spawn();
+    let client = Client::new(handle.local_addr()).await;
+    // Batches removed before the stream subscribed again are lost
+    for attempt in 0..50 {
+        expire_key(&client, format!("after-{attempt}")).await;
+        if let Ok(batch) = timeout(Duration::from_millis(100), evictions.recv()).await {
+            let keys = batch.unwrap().keys;
+            assert!(keys.iter().all(|key| key.starts_with("after-")), "{keys:?}");
+            return;
+        }
+    }
+    panic!("The eviction stream did not resume");
+}
+
+#[tokio::test]
+async fn test_empty_keys_are_rejected_consistently() {
+    let address = run_test_server().await;
+    let client = Client::new(address).await;
+
+    for err in [
+        client.set("", "1234", None).await.unwrap_err(),
+        client.get("").await.unwrap_err(),
+        client.delete("").await.unwrap_err(),
+        client.exists_many(["ABC", ""]).await.unwrap_err(),
+    ] {
+        assert_eq!(err.to_string(), "key empty");
+    }
+    // The connection is still usable
+    let resp = client.get("ABC").await.unwrap();
+    assert_eq!(resp.status(), StatusCode::KeyNotFound);
+}
+
+#[tokio::test]
+async fn test_request_tap_receives_every_answered_request() {
+    let (tap, mut logs) = tokio::sync::mpsc::channel::<RequestLog>(16);
+    let handle = Server::new()
+        .request_tap(tap)
+        .bind("127.0.0.1:0")
+        .await
+        .unwrap()
+        .spawn();
+    let client = Client::new(handle.local_addr()).await;
+    client.set("ABC", "1234", Some(u128::MAX)).await.unwrap();
+    client.get("ABC").await.unwrap();
+    client.delete("missing").await.unwrap();
+    client.exists_many(["ABC", "DEF"]).await.unwrap();
+
+    let log = logs.recv().await.unwrap();
+    assert_eq!(log.op_code(), OpCode::Set);
+    assert_eq!(log.keys(), ["ABC"]);
+    assert_eq!(log.value(), Some(&b"1234"[..]));
+    assert!(log.ttl_since_unix_epoch_in_millis().is_some());
+    assert_eq!(log.status(), StatusCode::Ok);
+
+    let log = logs.recv().await.unwrap();
+    assert_eq!(log.op_code(), OpCode::Get);
+    assert_eq!(log.keys(), ["ABC"]);
+    assert_eq!(log.value(), None);
+    assert_eq!(log.status(), StatusCode::Ok);
+
+    let log = logs.recv().await.unwrap();
+    assert_eq!(log.op_code(), OpCode::Delete);
+    assert_eq!(log.status(), StatusCode::KeyNotFound);
+
+    let log = logs.recv().await.unwrap();
+    assert_eq!(log.op_code(), OpCode::ExistsMany);
+    assert_eq!(log.keys(), ["ABC", "DEF"]);
+    assert!(logs.try_recv().is_err());
+}
+
+#[tokio::test]
+async fn test_full_request_tap_drops_logs_instead_of_blocking() {
+    let (tap, mut logs) = tokio::sync::mpsc::channel::<RequestLog>(1);
+    let handle = Server::new()
+        .request_tap(tap)
+        .bind("127.0.0.1:0")
+        .await
+        .unwrap()
+        .spawn();
+    let client = Client::new(handle.local_addr()).await;
+    for _ in 0..10 {
+        client.get("ABC").await.unwrap();
+    }
+
+    assert_eq!(logs.recv().await.unwrap().op_code(), OpCode::Get);
+    assert!(logs.try_recv().is_err());
+}
+
+async fn record_session() -> Vec<RequestLog> {
+    let (tap, mut logs) = tokio::sync::mpsc::channel::<RequestLog>(16);
+    let handle = Server::new()
+        .request_tap(tap)
+        .bind("127.0.0.1:0")
+        .await
+        .unwrap()
+        .spawn();
+    let client = Client::new(handle.local_addr()).await;
+    let in_a_minute = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_millis()
+        + 60_000;
+    client.set("a", "1", None).await.unwrap();
+    client.set("b", "2", Some(in_a_minute)).await.unwrap();
+    client.push("list", "x").await.unwrap();
+    client.set_add("set", "m").await.unwrap();
+    client.delete("a").await.unwrap();
+    client.get("b").await.unwrap();
+    client.set("c", "3", None).await.unwrap();
+    handle.shutdown().await;
+
+    let mut recorded = vec![];
+    while let Some(log) = logs.recv().await {
+        recorded.push(log);
+    }
+    recorded
+}
+
+#[tokio::test]
+async fn test_replaying_a_recorded_session_reproduces_the_cache_state() {
+    let recorded = record_session().await;
+    assert_eq!(recorded.len(), 7);
+
+    let handle = Server::new().bind("127.0.0.1:0").await.unwrap().spawn();
+    let client = Client::new(handle.local_addr()).await;
+    let mismatches = ReplayClient::new(client.clone(), recorded)
+        .speed(10.0)
+        .replay()
+        .await
+        .unwrap();
+
+    assert_eq!(mismatches, 0);
+    assert_eq!(
+        client.keys_matching("*").await.unwrap(),
+        vec!["b", "c", "list", "set"]
+    );
+    let b = client.get("b").await.unwrap();
+    assert_eq!(b.value(), Some(b"2".as_slice()));
+    assert!(b.ttl_since_unix_epoch_in_millis().is_some());
+    assert_eq!(
+        client.get("c").await.unwrap().value(),
+        Some(b"3".as_slice())
+    );
+    assert_eq!(client.pop("list").await.unwrap(), Some("x".to_string()));
+    assert!(client.set_contains("set", "m").await.unwrap());
+}
+
+#[tokio::test]
+async fn test_replaying_in_loops_reports_diverging_statuses() {
+    let recorded = record_session().await;
+    let handle = Server::new().bind("127.0.0.1:0").await.unwrap().spawn();
+    let client = Client::new(handle.local_addr()).await;
+
+    let mismatches = ReplayClient::new(client, recorded)
+        .speed(f64::INFINITY)
+        .loops(2)
+        .replay()
+        .await
+        .unwrap();
+    // Setting "b" and "c" and adding the member again, the list just grows
+    assert_eq!(mismatches, 3);
+}
+
+#[test]
+fn test_server_runs_on_the_runtime_it_was_given() {
+    let server_runtime = tokio::runtime::Builder::new_multi_thread()
+        .worker_threads(2)
+        .enable_all()
+        .build()
+        .unwrap();
+    let new_client_runtime = || {
+        tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap()
+    };
+
+    let handle = new_client_runtime().block_on(async {
+        let handle = Server::new()
+            .runtime(server_runtime.handle().clone())
+            .bind("127.0.0.1:0")
+            .await
+            .unwrap()
+            .spawn();
+        let client = Client::new(handle.local_addr()).await;
+        assert_eq!(
+            client.set("ABC", "1234", None).await.unwrap(),
+            StatusCode::Ok
+        );
+        handle
+    });
+
+    // The runtime the server was spawned from is gone, the server and its shards live on
+    new_client_runtime().block_on(async {
+        let client = Client::new(handle.local_addr()).await;
+        let resp = client.get("ABC").await.unwrap();
+        assert_eq!(resp.value(), Some(b"1234".as_slice()));
+    });
 }
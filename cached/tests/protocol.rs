@@ -0,0 +1,89 @@
+//! Exercises the wire format on its own, this also runs without the `runtime` feature.
+use bytes::BytesMut;
+use cached::protocol::{Frame, OpCode, StatusCode, MAX_KEY_LENGTH};
+
+#[test]
+fn test_requests_survive_encoding_and_decoding() {
+    let mut frame = Frame::new(OpCode::Set, 7);
+    frame.key = Some("ABC".to_string());
+    frame.value = Some("1234".into());
+    frame.ttl_since_unix_epoch_in_millis = Some(1_700_000_000_000);
+
+    let mut buf = BytesMut::new();
+    frame.encode_request(&mut buf).unwrap();
+    let (decoded, length) = Frame::decode_request(&buf).unwrap().unwrap();
+
+    assert_eq!(decoded, frame);
+    assert_eq!(length, buf.len());
+}
+
+#[test]
+fn test_responses_are_decoded_once_they_arrived_completely() {
+    let mut first = Frame::new(OpCode::Get, 1);
+    first.status = StatusCode::KeyNotFound;
+    let mut second = Frame::new(OpCode::Get, 2);
+    second.key = Some("ABC".to_string());
+    second.value = Some("1234".into());
+    let mut buf = BytesMut::new();
+    first.encode_response(&mut buf).unwrap();
+    second.encode_response(&mut buf).unwrap();
+
+    // Frames arriving in pieces are only decoded once they are complete
+    assert_eq!(Frame::decode_response(&buf[..5]).unwrap(), None);
+    let (decoded, length) = Frame::decode_response(&buf).unwrap().unwrap();
+    assert_eq!(decoded, first);
+    let (decoded, _) = Frame::decode_response(&buf[length..]).unwrap().unwrap();
+    assert_eq!(decoded, second);
+}
+
+#[test]
+fn test_only_frames_with_a_ttl_field_carry_a_ttl() {
+    let mut frame = Frame::new(OpCode::Delete, 1);
+    frame.key = Some("ABC".to_string());
+    frame.ttl_since_unix_epoch_in_millis = Some(1234);
+
+    let mut buf = BytesMut::new();
+    frame.encode_request(&mut buf).unwrap();
+    let (decoded, _) = Frame::decode_request(&buf).unwrap().unwrap();
+
+    assert_eq!(decoded.ttl_since_unix_epoch_in_millis, None);
+}
+
+#[test]
+fn test_frames_with_invalid_keys_are_not_encoded() {
+    let mut buf = BytesMut::new();
+    let mut frame = Frame::new(OpCode::Get, 1);
+    frame.key = Some("a".repeat(MAX_KEY_LENGTH + 1));
+    assert!(frame.encode_request(&mut buf).is_err());
+
+    frame.key = Some(String::new());
+    assert!(frame.encode_request(&mut buf).is_err());
+    assert!(buf.is_empty());
+}
+
+#[test]
+fn test_decoding_unknown_op_codes_fails() {
+    let mut buf = BytesMut::new();
+    Frame::new(OpCode::Get, 1).encode_request(&mut buf).unwrap();
+    buf[0] = 0;
+
+    assert!(Frame::decode_request(&buf).is_err());
+}
+
+#[test]
+fn test_only_frames_with_a_flags_field_carry_flags() {
+    let mut frame = Frame::new(OpCode::Get, 1);
+    frame.key = Some("ABC".to_string());
+    frame.value = Some("1234".into());
+    frame.flags = 0xdead_beef;
+
+    let mut buf = BytesMut::new();
+    frame.encode_response(&mut buf).unwrap();
+    let (decoded, _) = Frame::decode_response(&buf).unwrap().unwrap();
+    assert_eq!(decoded.flags, 0xdead_beef);
+
+    buf.clear();
+    frame.encode_request(&mut buf).unwrap();
+    let (decoded, _) = Frame::decode_request(&buf).unwrap().unwrap();
+    assert_eq!(decoded.flags, 0);
+}
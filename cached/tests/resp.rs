@@ -0,0 +1,246 @@
+use cached::{Client, Server, ServerHandle, StatusCode};
+use std::net::SocketAddr;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::TcpStream;
+use tokio::time::timeout;
+
+/// Returns the addresses of the wire protocol and of the RESP gateway.
+async fn run_test_server() -> (SocketAddr, SocketAddr, ServerHandle) {
+    let server = Server::new()
+        .require_flush_confirmation(false)
+        .bind("127.0.0.1:0")
+        .await
+        .unwrap()
+        .bind_resp("127.0.0.1:0")
+        .await
+        .unwrap();
+    let address: SocketAddr = format!("127.0.0.1:{}", server.port()).parse().unwrap();
+    let resp_address: SocketAddr = format!("127.0.0.1:{}", server.resp_port()).parse().unwrap();
+    let handle = server.spawn();
+    (address, resp_address, handle)
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum Reply {
+    Simple(String),
+    Error(String),
+    Integer(i64),
+    Bulk(Option<String>),
+}
+
+/// A minimal RESP client, as any Redis client library would use.
+struct RespClient {
+    stream: BufReader<TcpStream>,
+}
+
+impl RespClient {
+    async fn connect(address: SocketAddr) -> Self {
+        Self {
+            stream: BufReader::new(TcpStream::connect(address).await.unwrap()),
+        }
+    }
+
+    async fn send(&mut self, command: &[&str]) {
+        let mut encoded = format!("*{}\r\n", command.len());
+        for arg in command {
+            encoded.push_str(&format!("${}\r\n{arg}\r\n", arg.len()));
+        }
+        self.stream
+            .get_mut()
+            .write_all(encoded.as_bytes())
+            .await
+            .unwrap();
+    }
+
+    async fn read_reply(&mut self) -> Reply {
+        let mut line = String::new();
+        timeout(Duration::from_secs(5), self.stream.read_line(&mut line))
+            .await
+            .unwrap()
+            .unwrap();
+        let line = line.trim_end_matches("\r\n");
+        let (kind, rest) = line.split_at(1);
+        match kind {
+            "+" => Reply::Simple(rest.to_string()),
+            "-" => Reply::Error(rest.to_string()),
+            ":" => Reply::Integer(rest.parse().unwrap()),
+            "$" if rest == "-1" => Reply::Bulk(None),
+            "$" => {
+                let mut bulk = vec![0; rest.parse::<usize>().unwrap() + 2];
+                self.stream.read_exact(&mut bulk).await.unwrap();
+                bulk.truncate(bulk.len() - 2);
+                Reply::Bulk(Some(String::from_utf8(bulk).unwrap()))
+            }
+            _ => panic!("Unexpected reply {line}"),
+        }
+    }
+
+    async fn command(&mut self, command: &[&str]) -> Reply {
+        self.send(command).await;
+        self.read_reply().await
+    }
+}
+
+fn ok() -> Reply {
+    Reply::Simple("OK".to_string())
+}
+
+fn bulk(value: &str) -> Reply {
+    Reply::Bulk(Some(value.to_string()))
+}
+
+#[tokio::test]
+async fn test_values_can_be_set_and_got_over_resp() {
+    let (_, resp_address, _handle) = run_test_server().await;
+    let mut client = RespClient::connect(resp_address).await;
+
+    assert_eq!(
+        client.command(&["PING"]).await,
+        Reply::Simple("PONG".to_string())
+    );
+    assert_eq!(client.command(&["ping", "hi"]).await, bulk("hi"));
+
+    assert_eq!(client.command(&["GET", "foo"]).await, Reply::Bulk(None));
+    assert_eq!(client.command(&["SET", "foo", "bar"]).await, ok());
+    assert_eq!(client.command(&["GET", "foo"]).await, bulk("bar"));
+    // Unlike the wire protocol, existing values are replaced
+    assert_eq!(client.command(&["set", "foo", "baz"]).await, ok());
+    assert_eq!(client.command(&["get", "foo"]).await, bulk("baz"));
+    assert_eq!(
+        client.command(&["SET", "foo", "qux", "NX"]).await,
+        Reply::Bulk(None)
+    );
+    assert_eq!(client.command(&["GET", "foo"]).await, bulk("baz"));
+
+    assert_eq!(client.command(&["SET", "other", "1"]).await, ok());
+    assert_eq!(
+        client.command(&["DEL", "foo", "other", "missing"]).await,
+        Reply::Integer(2)
+    );
+    assert_eq!(client.command(&["GET", "foo"]).await, Reply::Bulk(None));
+}
+
+#[tokio::test]
+async fn test_resp_and_the_wire_protocol_share_the_cache() {
+    let (address, resp_address, _handle) = run_test_server().await;
+    let client = Client::new(address).await;
+    let mut resp_client = RespClient::connect(resp_address).await;
+
+    client.set("from client", "1", None).await.unwrap();
+    assert_eq!(
+        resp_client.command(&["GET", "from client"]).await,
+        bulk("1")
+    );
+
+    assert_eq!(
+        resp_client.command(&["SET", "from resp", "a\r\nb"]).await,
+        ok()
+    );
+    let resp = client.get("from resp").await.unwrap();
+    assert_eq!(resp.value(), Some(b"a\r\nb".as_slice()));
+
+    assert_eq!(resp_client.command(&["FLUSHALL"]).await, ok());
+    let resp = client.get("from client").await.unwrap();
+    assert_eq!(resp.status(), StatusCode::KeyNotFound);
+}
+
+#[tokio::test]
+async fn test_values_set_over_resp_expire() {
+    let (address, resp_address, _handle) = run_test_server().await;
+    let client = Client::new(address).await;
+    let mut resp_client = RespClient::connect(resp_address).await;
+
+    assert_eq!(
+        resp_client
+            .command(&["SET", "short", "1", "PX", "10"])
+            .await,
+        ok()
+    );
+    assert_eq!(
+        resp_client.command(&["SET", "long", "1", "EX", "60"]).await,
+        ok()
+    );
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    assert_eq!(
+        resp_client.command(&["GET", "short"]).await,
+        Reply::Bulk(None)
+    );
+    assert_eq!(resp_client.command(&["GET", "long"]).await, bulk("1"));
+
+    assert_eq!(resp_client.command(&["SET", "later", "1"]).await, ok());
+    assert_eq!(
+        resp_client.command(&["EXPIRE", "later", "60"]).await,
+        Reply::Integer(1)
+    );
+    let remaining = client.get("later").await.unwrap().remaining_ttl().unwrap();
+    assert!(remaining > Duration::from_secs(50) && remaining <= Duration::from_secs(60));
+    assert_eq!(
+        resp_client.command(&["EXPIRE", "later", "0"]).await,
+        Reply::Integer(1)
+    );
+    assert_eq!(
+        resp_client.command(&["GET", "later"]).await,
+        Reply::Bulk(None)
+    );
+    assert_eq!(
+        resp_client.command(&["EXPIRE", "missing", "60"]).await,
+        Reply::Integer(0)
+    );
+}
+
+#[tokio::test]
+async fn test_invalid_resp_commands_are_answered_with_errors() {
+    let (_, resp_address, _handle) = run_test_server().await;
+    let mut client = RespClient::connect(resp_address).await;
+
+    let errors = [
+        vec!["GET"],
+        vec!["GET", "a", "b"],
+        vec!["SET", "a", "1", "EX", "soon"],
+        vec!["SET", "a", "1", "KEEPTTL"],
+        vec!["EXPIRE", "a", "soon"],
+        vec!["GET", ""],
+        vec!["HGETALL", "a"],
+    ];
+    for command in errors {
+        let reply = client.command(&command).await;
+        assert!(matches!(reply, Reply::Error(_)), "{command:?}: {reply:?}");
+    }
+    // The connection is still usable after errors
+    assert_eq!(
+        client.command(&["PING"]).await,
+        Reply::Simple("PONG".to_string())
+    );
+
+    // Malformed commands close the connection
+    client
+        .stream
+        .get_mut()
+        .write_all(b"PING\r\n")
+        .await
+        .unwrap();
+    let reply = client.read_reply().await;
+    assert!(matches!(reply, Reply::Error(e) if e.starts_with("ERR Protocol error")));
+    let mut rest = vec![];
+    client.stream.read_to_end(&mut rest).await.unwrap();
+    assert!(rest.is_empty());
+}
+
+#[tokio::test]
+async fn test_pipelined_resp_commands_are_answered_in_order() {
+    let (_, resp_address, _handle) = run_test_server().await;
+    let mut client = RespClient::connect(resp_address).await;
+
+    for i in 0..10 {
+        client
+            .send(&["SET", &format!("key-{i}"), &i.to_string()])
+            .await;
+        client.send(&["GET", &format!("key-{i}")]).await;
+    }
+    for i in 0..10 {
+        assert_eq!(client.read_reply().await, ok());
+        assert_eq!(client.read_reply().await, bulk(&i.to_string()));
+    }
+    assert_eq!(client.command(&["QUIT"]).await, ok());
+}
@@ -0,0 +1,30 @@
+//! Kept apart from the other integration tests,
+//! as every server running in the process shuts down on Ctrl-C.
+#![cfg(unix)]
+
+use cached::{Client, Server, ShutdownReason};
+use std::process::Command;
+use std::time::Duration;
+use tokio::time::timeout;
+
+#[tokio::test]
+async fn test_run_reports_shutting_down_on_ctrl_c() {
+    let server = Server::new().bind("127.0.0.1:0").await.unwrap();
+    let handle = server.handle();
+    let server = tokio::spawn(server.run());
+    // Once a request was served, the server listens for Ctrl-C
+    let client = Client::new(handle.local_addr()).await;
+    client.get("ABC").await.unwrap();
+
+    let status = Command::new("kill")
+        .args(["-INT", &std::process::id().to_string()])
+        .status()
+        .unwrap();
+    assert!(status.success());
+
+    let reason = timeout(Duration::from_secs(5), server)
+        .await
+        .expect("Server did not shut down")
+        .unwrap();
+    assert!(matches!(reason, ShutdownReason::Signal), "{reason:?}");
+}